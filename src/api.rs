@@ -0,0 +1,181 @@
+use std::str::FromStr;
+use std::time::Duration;
+
+use axum::extract::{Path, State};
+use axum::http::{header, HeaderValue, Method};
+use axum::response::Html;
+use axum::routing::get;
+use axum::{Json, Router};
+use serde::Serialize;
+use tower_http::cors::{AllowOrigin, CorsLayer};
+use utoipa::openapi::Server;
+use utoipa::{OpenApi, ToSchema};
+
+use crate::collection::{Collection, Error, Movie, PathnameHash, Tag};
+use crate::reload::ReloadStatus;
+use crate::{routes, theme, AppState, RatingForm};
+
+#[derive(Debug, Serialize, ToSchema)]
+pub(crate) struct MovieSummary {
+    id: String,
+    /// Folder name
+    name: String,
+    title: String,
+    year: Option<u16>,
+    /// Tag directory names, like `genre/horror`
+    tags: Vec<String>,
+    /// Stars from 1 to 5
+    rating: Option<u8>,
+}
+
+impl MovieSummary {
+    pub(crate) fn new(movie: &Movie, collection: &Collection) -> Self {
+        Self {
+            id: movie.id(),
+            name: movie.name.clone(),
+            title: movie.title().to_string(),
+            year: movie.year(),
+            tags: movie.tags(collection).into_iter().map(|(name, _)| name.to_string()).collect(),
+            rating: movie.rating,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub(crate) struct TagSummary {
+    /// Directory name, including the group like `genre/horror`
+    name: String,
+    display_name: String,
+    group: Option<String>,
+    /// Other names accepted for the tag in filters and tag routes
+    aliases: Vec<String>,
+    /// Left off movie cards and tag lists in the web interface
+    hidden: bool,
+    /// Number of tagged movies
+    movies: usize,
+}
+
+impl TagSummary {
+    fn new(name: &str, tag: &Tag) -> Self {
+        Self {
+            name: name.to_string(),
+            display_name: tag.display_name().to_string(),
+            group: tag.group.clone(),
+            aliases: tag.meta.aliases.clone(),
+            hidden: tag.meta.hidden,
+            movies: tag.movies.len(),
+        }
+    }
+}
+
+/// OpenAPI document of the JSON API, served at `/api/openapi.json`.
+#[derive(OpenApi)]
+#[openapi(
+    info(title = "tagrs", description = "Movies and tags of the collection"),
+    paths(movies, movie, tags, routes::set_tags, routes::set_rating, routes::reload_status),
+    components(schemas(MovieSummary, TagSummary, routes::TagSet, RatingForm, ReloadStatus))
+)]
+pub(crate) struct ApiDoc;
+
+/// JSON API for scripts and other dashboards, served under `/api/v1`.
+pub(crate) fn router(state: &AppState) -> Router<AppState> {
+    let router = Router::new()
+        .route("/movies", get(movies))
+        .route("/movies/:id", get(movie))
+        .route("/tags", get(tags));
+    match cors_layer(&state.cors_origins) {
+        Some(cors) => router.layer(cors),
+        None => router,
+    }
+}
+
+/// Lets browsers on the given origins call the API. `*` allows any origin.
+fn cors_layer(origins: &[HeaderValue]) -> Option<CorsLayer> {
+    if origins.is_empty() {
+        return None;
+    }
+    let allow_origin = if origins.iter().any(|o| o == "*") {
+        AllowOrigin::any()
+    } else {
+        AllowOrigin::list(origins.iter().cloned())
+    };
+    Some(
+        CorsLayer::new()
+            .allow_origin(allow_origin)
+            .allow_methods([Method::GET, Method::POST, Method::PUT, Method::DELETE])
+            .allow_headers([header::CONTENT_TYPE, header::AUTHORIZATION])
+            .max_age(Duration::from_secs(60 * 60)),
+    )
+}
+
+#[utoipa::path(get, path = "/api/v1/movies", tag = "movies", responses((status = 200, description = "All movies sorted by folder name", body = [MovieSummary])))]
+async fn movies(State(state): State<AppState>) -> Json<Vec<MovieSummary>> {
+    let collection = state.collection.read().await;
+    let mut movies: Vec<MovieSummary> = collection
+        .movies
+        .values()
+        .map(|movie| MovieSummary::new(movie, &collection))
+        .collect();
+    movies.sort_by(|a, b| a.name.cmp(&b.name));
+    Json(movies)
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/movies/{id}",
+    tag = "movies",
+    params(("id" = String, Path, description = "Movie id")),
+    responses(
+        (status = 200, description = "The movie", body = MovieSummary),
+        (status = 404, description = "No movie with the id"),
+    )
+)]
+async fn movie(State(state): State<AppState>, Path(id): Path<String>) -> Result<Json<MovieSummary>, Error> {
+    let hash = PathnameHash::from_str(&id).map_err(|_| Error::NotFound)?;
+    let collection = state.collection.read().await;
+    let movie = collection.movies.get(&hash).ok_or(Error::NotFound)?;
+    Ok(Json(MovieSummary::new(movie, &collection)))
+}
+
+#[utoipa::path(get, path = "/api/v1/tags", tag = "tags", responses((status = 200, description = "All tags, pinned ones first, in the same order as on movie cards", body = [TagSummary])))]
+async fn tags(State(state): State<AppState>) -> Json<Vec<TagSummary>> {
+    let collection = state.collection.read().await;
+    let mut tags: Vec<(&String, &Tag)> = collection.tags.iter().collect();
+    tags.sort_by_cached_key(|(name, tag)| tag.sort_key(name));
+    Json(tags.into_iter().map(|(name, tag)| TagSummary::new(name, tag)).collect())
+}
+
+pub(crate) async fn openapi() -> Json<utoipa::openapi::OpenApi> {
+    let mut doc = ApiDoc::openapi();
+    // filled in from Cargo.toml, which names no license
+    doc.info.license = None;
+    // paths are absolute, as some JSON routes live outside `/api/v1`
+    let base_path = theme::url("");
+    if !base_path.is_empty() {
+        doc.servers = Some(vec![Server::new(base_path)]);
+    }
+    Json(doc)
+}
+
+/// Swagger UI for the OpenAPI document, if enabled with `--swagger-ui`.
+pub(crate) async fn swagger_ui(State(state): State<AppState>) -> Result<Html<&'static str>, Error> {
+    if !state.swagger_ui {
+        return Err(Error::NotFound);
+    }
+    Ok(Html(SWAGGER_UI))
+}
+
+const SWAGGER_UI: &str = r##"<!DOCTYPE html>
+<html>
+<head>
+  <meta charset="utf-8">
+  <title>tagrs API</title>
+  <link rel="stylesheet" href="https://unpkg.com/swagger-ui-dist@5/swagger-ui.css">
+</head>
+<body>
+  <div id="swagger-ui"></div>
+  <script src="https://unpkg.com/swagger-ui-dist@5/swagger-ui-bundle.js"></script>
+  <script>SwaggerUIBundle({ url: "openapi.json", dom_id: "#swagger-ui" });</script>
+</body>
+</html>
+"##;
@@ -0,0 +1,60 @@
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+use tokio::sync::RwLock;
+
+use crate::collection::{Error, Movie, PathnameHash};
+use crate::Jellyfin;
+
+/// Fallback artwork for movies without a local poster, downloaded from Jellyfin and kept in the
+/// cache directory.
+#[derive(Debug)]
+pub struct RemoteArtwork {
+    cache_dir: PathBuf,
+    /// Movies Jellyfin had no artwork for, so they are not looked up on every request.
+    misses: RwLock<HashSet<PathnameHash>>,
+}
+
+impl RemoteArtwork {
+    pub fn new<T: AsRef<Path>>(cache_dir: T) -> Self {
+        Self {
+            cache_dir: cache_dir.as_ref().to_path_buf(),
+            misses: RwLock::new(HashSet::new()),
+        }
+    }
+
+    fn cache_path(&self, movie: &Movie) -> PathBuf {
+        self.cache_dir.join(format!("jellyfin-{}.jpg", movie.id()))
+    }
+
+    /// Returns the path of the cached Jellyfin poster for `movie`, stored in `folder` as Jellyfin
+    /// sees it, downloading it if needed.
+    pub(crate) async fn poster(&self, jellyfin: &Jellyfin, movie: &Movie, folder: &Path) -> Result<Option<PathBuf>, Error> {
+        let cache_path = self.cache_path(movie);
+        if tokio::fs::try_exists(&cache_path).await? {
+            return Ok(Some(cache_path));
+        }
+        if self.misses.read().await.contains(&movie.hash) {
+            return Ok(None);
+        }
+        let image = match jellyfin.item_id(folder).await? {
+            Some(item_id) => jellyfin.api.get_item_image(&item_id).await?,
+            None => None,
+        };
+        let Some(image) = image else {
+            tracing::debug!("no jellyfin artwork for {}", movie.name);
+            self.misses.write().await.insert(movie.hash);
+            return Ok(None);
+        };
+        tokio::fs::create_dir_all(&self.cache_dir).await?;
+        let tmp = cache_path.with_extension(format!("{}.tmp", uuid::Uuid::new_v4()));
+        tokio::fs::write(&tmp, image).await?;
+        tokio::fs::rename(&tmp, &cache_path).await?;
+        Ok(Some(cache_path))
+    }
+
+    /// Forgets the known misses so newly added Jellyfin items are picked up.
+    pub(crate) async fn invalidate(&self) {
+        self.misses.write().await.clear();
+    }
+}
@@ -0,0 +1,53 @@
+use std::collections::HashMap;
+use std::sync::LazyLock;
+
+use axum::extract::Request;
+use axum::http::{header, HeaderValue};
+use axum::middleware::Next;
+use axum::response::Response;
+use sha1::{Digest, Sha1};
+
+use crate::theme::url;
+
+/// Directory the files under `/static` are served from.
+pub(crate) const STATIC_DIR: &str = "src/static";
+
+/// Content hashes of the static files by name, taken when first linked. Files changed while
+/// running keep their old hash until a restart.
+static HASHES: LazyLock<HashMap<String, String>> = LazyLock::new(|| {
+    let mut hashes = HashMap::new();
+    let entries = match std::fs::read_dir(STATIC_DIR) {
+        Ok(entries) => entries,
+        Err(e) => {
+            tracing::warn!("failed to hash static files in {}: {}", STATIC_DIR, e);
+            return hashes;
+        }
+    };
+    for entry in entries.flatten() {
+        if let Ok(contents) = std::fs::read(entry.path()) {
+            let hash = hex::encode(Sha1::digest(&contents));
+            hashes.insert(entry.file_name().to_string_lossy().to_string(), hash[..12].to_string());
+        }
+    }
+    hashes
+});
+
+/// URL of a static file, carrying its content hash so browsers can cache it for good.
+pub(crate) fn static_url(name: &str) -> String {
+    match HASHES.get(name) {
+        Some(hash) => url(&format!("/static/{}?v={}", name, hash)),
+        None => url(&format!("/static/{}", name)),
+    }
+}
+
+/// Middleware letting browsers keep static files linked with their hash, which changes with the
+/// file. Requests without the hash have to be revalidated.
+pub(crate) async fn cache_control(request: Request, next: Next) -> Response {
+    let versioned = request.uri().query().is_some_and(|query| query.starts_with("v="));
+    let mut response = next.run(request).await;
+    let value = if versioned { "public, max-age=31536000, immutable" } else { "no-cache" };
+    if response.status().is_success() {
+        response.headers_mut().insert(header::CACHE_CONTROL, HeaderValue::from_static(value));
+    }
+    response
+}
@@ -0,0 +1,235 @@
+use std::collections::VecDeque;
+use std::fmt::Display;
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use tokio::io::AsyncWriteExt;
+use tokio::sync::{Mutex, RwLock};
+
+use crate::collection::Error;
+use crate::updates::{Update, Updates};
+use crate::webhooks::Webhooks;
+
+/// Number of events kept in memory for the audit page.
+const RECENT_EVENTS: usize = 500;
+
+/// Number of rotated log files kept, as `<file>.1` (newest) to `<file>.5`.
+const ROTATED_LOGS: usize = 5;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub(crate) enum Action {
+    TagAdded { tag: String, movie: String },
+    TagRemoved { tag: String, movie: String },
+    TagCreated { tag: String },
+    TagDeleted { tag: String },
+    TagMerged { from: String, into: String, movies: usize },
+    TagLibraryCreated { tag: String, library: String },
+    TagLibraryDeleted { tag: String, library: String },
+    Reload,
+    LibraryScan,
+    LibraryEnabled { user: String, folder: String },
+    LibraryDisabled { user: String, folder: String },
+    PresetApplied { user: String, preset: String },
+    LibrariesCopied { user: String, source: String },
+    AllLibrariesEnabled { user: String },
+    AllLibrariesDisabled { user: String },
+    UserCreated { user: String },
+    UserDeleted { user: String },
+    UserEnabled { user: String },
+    UserDisabled { user: String },
+    PlaybackStopped { user: String, device: String, item: String },
+    DeviceRevoked { device: String, user: String },
+    TaskStarted { task: String },
+    ParentalControlsChanged { user: String },
+    RadarrSearch { movie: String },
+    PosterChanged { movie: String },
+    NoteChanged { movie: String },
+    RatingChanged { movie: String, rating: Option<u8> },
+    JellyfinTagsSet { movie: String, tags: Vec<String> },
+    CollectionExported { tag: String, collection: String },
+    SnapshotTaken { snapshot: String },
+    SnapshotRestored { snapshot: String },
+    TraktConnected,
+    TraktListSynced { list: String, tag: String, tagged: usize, added: usize, removed: usize },
+}
+
+impl Display for Action {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Action::TagAdded { tag, movie } => write!(f, "tagged {} as {}", movie, tag),
+            Action::TagRemoved { tag, movie } => write!(f, "removed tag {} from {}", tag, movie),
+            Action::TagCreated { tag } => write!(f, "created tag {}", tag),
+            Action::TagDeleted { tag } => write!(f, "deleted tag {}", tag),
+            Action::TagMerged { from, into, movies } => {
+                write!(f, "merged tag {} into {}, adding {} movies", from, into, movies)
+            }
+            Action::TagLibraryCreated { tag, library } => write!(f, "created Jellyfin library {} for tag {}", library, tag),
+            Action::TagLibraryDeleted { tag, library } => write!(f, "deleted Jellyfin library {} of tag {}", library, tag),
+            Action::Reload => write!(f, "reloaded the collection"),
+            Action::LibraryScan => write!(f, "started a Jellyfin library scan"),
+            Action::LibraryEnabled { user, folder } => write!(f, "gave {} access to {}", user, folder),
+            Action::LibraryDisabled { user, folder } => write!(f, "revoked {}'s access to {}", user, folder),
+            Action::PresetApplied { user, preset } => write!(f, "applied the {} preset to {}", preset, user),
+            Action::LibrariesCopied { user, source } => write!(f, "copied {}'s libraries to {}", source, user),
+            Action::AllLibrariesEnabled { user } => write!(f, "gave {} access to all libraries", user),
+            Action::AllLibrariesDisabled { user } => write!(f, "limited {} to the currently enabled libraries", user),
+            Action::UserCreated { user } => write!(f, "created user {}", user),
+            Action::UserDeleted { user } => write!(f, "deleted user {}", user),
+            Action::UserEnabled { user } => write!(f, "enabled user {}", user),
+            Action::UserDisabled { user } => write!(f, "disabled user {}", user),
+            Action::PlaybackStopped { user, device, item } => {
+                write!(f, "stopped {} playing {} on {}", user, item, device)
+            }
+            Action::DeviceRevoked { device, user } => write!(f, "revoked device {} of {}", device, user),
+            Action::TaskStarted { task } => write!(f, "started the Jellyfin task {}", task),
+            Action::ParentalControlsChanged { user } => write!(f, "changed parental controls for {}", user),
+            Action::RadarrSearch { movie } => write!(f, "started a Radarr search for {}", movie),
+            Action::PosterChanged { movie } => write!(f, "picked a new poster for {}", movie),
+            Action::NoteChanged { movie } => write!(f, "changed the note on {}", movie),
+            Action::RatingChanged { movie, rating: Some(rating) } => write!(f, "rated {} {} stars", movie, rating),
+            Action::RatingChanged { movie, rating: None } => write!(f, "cleared the rating of {}", movie),
+            Action::SnapshotTaken { snapshot } => write!(f, "took tag snapshot {}", snapshot),
+            Action::SnapshotRestored { snapshot } => write!(f, "restored tag snapshot {}", snapshot),
+            Action::TraktConnected => write!(f, "connected tagrs to Trakt"),
+            Action::TraktListSynced { list, tag, tagged, added, removed } => write!(
+                f,
+                "synced Trakt list {} with tag {}, tagging {} movies, adding {} and removing {} on Trakt",
+                list, tag, tagged, added, removed
+            ),
+            Action::CollectionExported { tag, collection } => {
+                write!(f, "exported tag {} as Jellyfin collection {}", tag, collection)
+            }
+            Action::JellyfinTagsSet { movie, tags } => {
+                write!(f, "set the Jellyfin tags of {} to [{}]", movie, tags.join(", "))
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct Event {
+    pub(crate) timestamp: DateTime<Utc>,
+    pub(crate) actor: String,
+    #[serde(flatten)]
+    pub(crate) action: Action,
+}
+
+/// Append-only record of every mutation, optionally persisted as JSON lines and delivered to
+/// webhooks.
+#[derive(Debug, Default)]
+pub struct AuditLog {
+    path: Option<PathBuf>,
+    file: Mutex<Option<tokio::fs::File>>,
+    recent: RwLock<VecDeque<Event>>,
+    updates: Updates,
+    webhooks: Webhooks,
+}
+
+impl AuditLog {
+    /// Opens (or creates) the JSONL file at `path`, loading its most recent events.
+    pub async fn open<T: AsRef<Path>>(path: T) -> anyhow::Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let mut recent = VecDeque::with_capacity(RECENT_EVENTS);
+        if let Ok(contents) = tokio::fs::read_to_string(&path).await {
+            for line in contents.lines().filter(|l| !l.trim().is_empty()) {
+                match serde_json::from_str(line) {
+                    Ok(event) => {
+                        if recent.len() == RECENT_EVENTS {
+                            recent.pop_front();
+                        }
+                        recent.push_back(event);
+                    }
+                    Err(e) => tracing::warn!("skipping malformed audit entry: {}", e),
+                }
+            }
+        }
+        let file = tokio::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .await?;
+        Ok(Self {
+            path: Some(path),
+            file: Mutex::new(Some(file)),
+            recent: RwLock::new(recent),
+            updates: Updates::default(),
+            webhooks: Webhooks::default(),
+        })
+    }
+
+    /// Posts every recorded event to `webhooks`.
+    pub fn sending_to(mut self, webhooks: Webhooks) -> Self {
+        self.webhooks = webhooks;
+        self
+    }
+
+    /// Tells open activity feeds about recorded events through `updates`.
+    pub(crate) fn notifying(mut self, updates: Updates) -> Self {
+        self.updates = updates;
+        self
+    }
+
+    pub(crate) async fn record(&self, actor: &str, action: Action) -> Result<(), Error> {
+        let event = Event {
+            timestamp: Utc::now(),
+            actor: actor.to_string(),
+            action,
+        };
+        tracing::info!(actor = %event.actor, "audit: {}", event.action);
+        self.webhooks.send(&event.actor, &event.action);
+        if let Some(file) = &mut *self.file.lock().await {
+            let mut line = serde_json::to_vec(&event)?;
+            line.push(b'\n');
+            file.write_all(&line).await?;
+            file.flush().await?;
+        }
+        let mut recent = self.recent.write().await;
+        if recent.len() == RECENT_EVENTS {
+            recent.pop_front();
+        }
+        recent.push_back(event);
+        self.updates.notify(Update::Activity);
+        Ok(())
+    }
+
+    /// Posts `action` to the webhooks without recording it, for details of a recorded event
+    /// that automation may react to, like the movies a merge added to a tag.
+    pub(crate) fn announce(&self, actor: &str, action: &Action) {
+        self.webhooks.send(actor, action);
+    }
+
+    /// Most recent events, newest first.
+    pub(crate) async fn recent(&self, limit: usize) -> Vec<Event> {
+        self.recent.read().await.iter().rev().take(limit).cloned().collect()
+    }
+
+    pub(crate) fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+
+    /// Moves the log file aside as `<file>.1`, shifting older ones along and dropping the
+    /// oldest, and continues in a new file. The audit page keeps showing the recent events.
+    pub(crate) async fn rotate(&self) -> Result<(), Error> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        let rotated = |n: usize| {
+            let mut name = path.clone().into_os_string();
+            name.push(format!(".{}", n));
+            PathBuf::from(name)
+        };
+        let mut file = self.file.lock().await;
+        for n in (1..ROTATED_LOGS).rev() {
+            match tokio::fs::rename(rotated(n), rotated(n + 1)).await {
+                Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e.into()),
+                _ => {}
+            }
+        }
+        tokio::fs::rename(path, rotated(1)).await?;
+        *file = Some(tokio::fs::OpenOptions::new().create(true).append(true).open(path).await?);
+        tracing::info!("rotated audit log {}", path.display());
+        Ok(())
+    }
+}
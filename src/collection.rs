@@ -1,17 +1,65 @@
 use axum::http::StatusCode;
 use axum::response::IntoResponse;
+use serde::Deserialize;
 use sha1::{Digest, Sha1};
-use std::collections::{HashMap, HashSet};
+use std::collections::hash_map::Entry;
+use std::collections::{BTreeSet, HashMap, HashSet};
 use std::fmt::Display;
+use std::future::Future;
 use std::path::{Path, PathBuf};
 use std::str::FromStr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Instant, SystemTime};
+
+use futures::StreamExt;
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::SeedableRng;
 
 use tokio::fs::read_dir;
 
-type Tags = HashMap<String, HashSet<PathnameHash>>;
+use crate::config::PathMappings;
+use crate::nfo::Metadata;
+use crate::storage::{FsStorage, MemoryStorage, Storage};
+
+/// Tags keyed by their path relative to the tag directory, e.g. `kids` or `genre/horror`.
+pub(crate) type Tags = HashMap<String, Tag>;
 type Movies = HashMap<PathnameHash, Movie>;
 
-#[derive(Debug, Clone, PartialEq, Eq, Hash, Copy)]
+/// Extensions of the files listed as a movie's video files.
+const VIDEO_EXTENSIONS: &[&str] = &[
+    "avi", "iso", "m2ts", "m4v", "mkv", "mov", "mp4", "mpeg", "mpg", "ts", "webm", "wmv",
+];
+
+/// How many movie or tag directories are scanned at once, which matters on slow network mounts.
+pub(crate) const SCAN_CONCURRENCY: usize = 16;
+
+/// How many movies and tags a running scan has loaded so far.
+#[derive(Debug, Default)]
+pub(crate) struct ScanProgress {
+    pub(crate) movies: AtomicUsize,
+    pub(crate) tags: AtomicUsize,
+}
+
+impl ScanProgress {
+    /// Starts counting again for another scan.
+    pub(crate) fn reset(&self) {
+        self.movies.store(0, Ordering::Relaxed);
+        self.tags.store(0, Ordering::Relaxed);
+    }
+}
+
+/// Artwork file names checked in each movie folder, in order of preference.
+pub(crate) const POSTER_CANDIDATES: &[(&str, &str)] = &[
+    ("poster.jpg", "image/jpeg"),
+    ("poster.png", "image/png"),
+    ("poster.webp", "image/webp"),
+    ("folder.jpg", "image/jpeg"),
+    ("cover.jpg", "image/jpeg"),
+];
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Copy)]
 pub(crate) struct PathnameHash([u8; 20]);
 
 impl FromStr for PathnameHash {
@@ -26,6 +74,11 @@ impl PathnameHash {
     pub(crate) fn as_slice(&self) -> &[u8] {
         &self.0
     }
+
+    /// The movie ID used in URLs.
+    pub(crate) fn id(&self) -> String {
+        hex::encode(self.0)
+    }
 }
 
 impl TryFrom<&[u8]> for PathnameHash {
@@ -41,20 +94,145 @@ impl TryFrom<&[u8]> for PathnameHash {
     }
 }
 
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Tag {
+    /// Name of the tag directory, without the group
+    pub(crate) name: String,
+    /// The group directory this tag is nested in, if any
+    pub(crate) group: Option<String>,
+    pub(crate) movies: HashSet<PathnameHash>,
+    pub(crate) meta: TagMeta,
+}
+
+/// Optional settings read from a `.tagrs.toml` file inside a tag directory.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct TagMeta {
+    /// Label shown instead of the directory name
+    pub(crate) display_name: Option<String>,
+    /// Any CSS color, used for the tag buttons
+    pub(crate) color: Option<String>,
+    pub(crate) description: Option<String>,
+    /// Pinned tags are listed first, lowest value first
+    pub(crate) pinned: Option<i32>,
+    /// Other names accepted for the tag, like "Science Fiction" for `sci-fi`
+    pub(crate) aliases: Vec<String>,
+    /// Machinery tags like `processing`, left off movie cards and tag lists
+    pub(crate) hidden: bool,
+}
+
+impl TagMeta {
+    pub(crate) const FILE_NAME: &'static str = ".tagrs.toml";
+
+    /// Reads the metadata file of a tag directory. A missing or malformed file leaves the tag
+    /// with default settings.
+    pub(crate) async fn load(tag_path: &Path) -> Self {
+        let path = tag_path.join(Self::FILE_NAME);
+        let Ok(contents) = tokio::fs::read_to_string(&path).await else {
+            return Self::default();
+        };
+        match toml::from_str::<Self>(&contents) {
+            Ok(mut meta) => {
+                if meta.color.as_deref().is_some_and(|color| !is_safe_css_color(color)) {
+                    tracing::warn!("ignoring invalid color in {}", path.display());
+                    meta.color = None;
+                }
+                meta
+            }
+            Err(e) => {
+                tracing::warn!("failed to parse {}: {}", path.display(), e);
+                Self::default()
+            }
+        }
+    }
+}
+
+/// Colors end up in a `style` attribute, so only allow what color values are made of.
+fn is_safe_css_color(color: &str) -> bool {
+    !color.is_empty()
+        && color
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "#(),.% -".contains(c))
+}
+
+/// A video file inside a movie folder.
+#[derive(Debug, Clone)]
+pub(crate) struct MovieFile {
+    /// Path relative to the movie folder
+    pub(crate) name: String,
+    pub(crate) size: u64,
+}
+
 #[derive(Debug, Clone)]
 pub(crate) struct Movie {
     pub(crate) name: String,
     pub(crate) path: PathBuf,
     pub(crate) hash: PathnameHash,
     pub(crate) poster_path: Option<PathBuf>,
+    pub(crate) poster_content_type: &'static str,
+    pub(crate) metadata: Option<Metadata>,
+    /// Reminder jotted down in tagrs, like "replace with remux"
+    pub(crate) note: Option<String>,
+    /// Stars from 1 to [`MAX_RATING`]
+    pub(crate) rating: Option<u8>,
+    /// Timestamps of the movie folder, not every filesystem records a creation time
+    pub(crate) created: Option<SystemTime>,
+    pub(crate) modified: Option<SystemTime>,
+}
+
+/// How a tagged movie is represented inside a tag directory.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum LinkMode {
+    /// A symlink to the movie folder
+    #[default]
+    Symlink,
+    /// A folder mirroring the movie folder with every file hard linked
+    Hardlink,
+    /// A full copy of the movie folder
+    Copy,
 }
 
+/// Where movie IDs, and so movie URLs and tag membership, come from.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum IdentityMode {
+    /// A hash of the folder name, which changes when the folder is renamed
+    #[default]
+    Name,
+    /// An ID file inside each movie folder, created on first scan. Renamed folders keep their
+    /// ID and their tags are relinked on the next reload.
+    File,
+}
+
+/// ID file written into each movie folder in the `file` identity mode.
+pub(crate) const ID_FILE: &str = ".tagrs-id";
+
+/// Free-text note kept in a movie folder.
+pub(crate) const NOTE_FILE: &str = ".tagrs-note.txt";
+
+/// Star rating kept in a movie folder, a single digit.
+pub(crate) const RATING_FILE: &str = ".tagrs-rating";
+
+/// Most stars a movie can be rated with.
+pub(crate) const MAX_RATING: u8 = 5;
+
+/// Folder name last seen for each movie ID, kept in the tag directory to detect renames.
+const ID_INDEX_FILE: &str = ".tagrs-ids.toml";
+
 #[derive(Debug, Clone)]
 pub struct Collection {
     pub(crate) tags: Tags,
     pub(crate) movies: Movies,
     pub(crate) movie_dir: PathBuf,
     pub(crate) tag_dir: PathBuf,
+    pub(crate) link_mode: LinkMode,
+    pub(crate) identity: IdentityMode,
+    pub(crate) path_mappings: PathMappings,
+    /// Counts changes made through [`crate::AppState::collection_mut`], so a reload scanning
+    /// without the lock notices it missed some.
+    pub(crate) generation: u64,
+    /// Keys of the tags by their aliases, lowercased
+    aliases: HashMap<String, String>,
+    storage: Arc<dyn Storage>,
 }
 
 impl Display for Collection {
@@ -63,7 +241,7 @@ impl Display for Collection {
     }
 }
 
-fn path_hash<T>(path: T) -> anyhow::Result<PathnameHash>
+pub(crate) fn path_hash<T>(path: T) -> anyhow::Result<PathnameHash>
 where
     T: AsRef<Path>,
 {
@@ -81,114 +259,569 @@ where
 impl Collection {
     pub async fn new<T>(movie_dir: T, tag_dir: T) -> anyhow::Result<Self>
     where
-        T: AsRef<Path> + Eq + std::hash::Hash,
+        T: AsRef<Path>,
     {
-        let mut ignore_paths = HashSet::new();
-        let abs_movie_dir = tokio::fs::canonicalize(movie_dir.as_ref()).await?;
-        let abs_tag_dir = tokio::fs::canonicalize(tag_dir.as_ref()).await?;
-        ignore_paths.insert(abs_movie_dir.clone());
+        let storage = FsStorage::new(movie_dir.as_ref(), tag_dir.as_ref()).await?;
+        Self::scan(Arc::new(storage), &ScanProgress::default()).await
+    }
+
+    /// A collection of the movie folders `movies` and empty tags `tags` that only lives in
+    /// memory.
+    pub async fn in_memory(movies: &[&str], tags: &[&str]) -> anyhow::Result<Self> {
+        Self::scan(Arc::new(MemoryStorage::new(movies, tags)), &ScanProgress::default()).await
+    }
+
+    /// Loads everything in `storage`, counting what has been loaded in `progress`.
+    pub(crate) async fn scan(storage: Arc<dyn Storage>, progress: &ScanProgress) -> anyhow::Result<Self> {
+        let (movies, tags) = tokio::join!(Self::load_movies(&*storage, progress), storage.load_tags(progress));
+        let mut tags = tags?;
+        let aliases = Self::index_aliases(&mut tags);
         Ok(Collection {
-            movies: Self::load_movies(&movie_dir).await?,
-            tags: Self::load_tags(&abs_tag_dir, &ignore_paths).await?,
-            movie_dir: abs_movie_dir,
-            tag_dir: abs_tag_dir,
+            movies: movies?,
+            tags,
+            movie_dir: storage.movie_dir().to_path_buf(),
+            tag_dir: storage.tag_dir().to_path_buf(),
+            link_mode: LinkMode::default(),
+            identity: IdentityMode::default(),
+            path_mappings: PathMappings::default(),
+            generation: 0,
+            aliases,
+            storage,
         })
     }
 
-    async fn load_movies<T>(movie_dir: T) -> anyhow::Result<Movies>
-    where
-        T: AsRef<Path>,
-    {
-        //tracing::debug!("loading movies from {:?}", movie_dir.as_ref());
-        let mut movies = HashMap::new();
-        let mut entries = read_dir(&movie_dir).await?;
-        while let Some(entry) = entries.next_entry().await? {
-            //tracing::debug!("entry: {:?}", entry);
-            if entry.file_type().await?.is_dir() {
-                let name = entry.file_name().to_string_lossy().to_string();
-                let path = entry.path();
-                let hash = path_hash(&path)?;
-                let possible_poster_path = path.join("poster.jpg");
-                let poster_path = if possible_poster_path.exists() {
-                    Some(possible_poster_path)
-                } else {
-                    None
-                };
-                let movie = Movie {
-                    name,
-                    hash,
-                    path,
-                    poster_path,
-                };
-                movies.insert(hash, movie);
+    /// Maps the lowercased aliases of `tags` to their keys. An alias another tag already has
+    /// would make names resolve to either of them, so it is dropped from all but the first tag
+    /// by key.
+    fn index_aliases(tags: &mut Tags) -> HashMap<String, String> {
+        let mut keys: Vec<String> = tags.keys().cloned().collect();
+        keys.sort();
+        let mut aliases = HashMap::new();
+        for key in keys {
+            let Some(tag) = tags.get_mut(&key) else { continue };
+            tag.meta.aliases.retain(|alias| match aliases.entry(alias.to_lowercase()) {
+                Entry::Vacant(entry) => {
+                    entry.insert(key.clone());
+                    true
+                }
+                Entry::Occupied(entry) if entry.get() == &key => true,
+                Entry::Occupied(entry) => {
+                    tracing::warn!("ignoring alias {} of tag {}, it is an alias of {} already", alias, key, entry.get());
+                    false
+                }
+            });
+        }
+        aliases
+    }
+
+    /// Loads the storage of this collection again with the same settings and generation,
+    /// without borrowing it while loading.
+    pub(crate) fn rescan<'a>(&self, progress: &'a ScanProgress) -> impl Future<Output = Result<Collection, Error>> + 'a {
+        let (storage, link_mode, identity, path_mappings, generation) = (self.storage(), self.link_mode, self.identity, self.path_mappings.clone(), self.generation);
+        async move {
+            let fresh = Self::scan(storage, progress)
+                .await?
+                .with_link_mode(link_mode)
+                .with_path_mappings(path_mappings)
+                .with_identity(identity)
+                .await?;
+            Ok(Collection { generation, ..fresh })
+        }
+    }
+
+    pub(crate) fn storage(&self) -> Arc<dyn Storage> {
+        self.storage.clone()
+    }
+
+    pub fn with_link_mode(mut self, link_mode: LinkMode) -> Self {
+        self.link_mode = link_mode;
+        self
+    }
+
+    /// Rewrites symlink targets so they resolve inside the Jellyfin container.
+    pub fn with_path_mappings(mut self, path_mappings: PathMappings) -> Self {
+        self.path_mappings = path_mappings;
+        self
+    }
+
+    /// Switches how movies are identified, writing missing ID files and repairing the tags of
+    /// renamed folders.
+    pub async fn with_identity(mut self, identity: IdentityMode) -> anyhow::Result<Self> {
+        self.identity = identity;
+        self.assign_ids().await?;
+        Ok(self)
+    }
+
+    /// Re-keys the freshly scanned movies and tags by the IDs of the identity mode. Scans key
+    /// everything by folder name, which is all the `name` mode needs.
+    async fn assign_ids(&mut self) -> anyhow::Result<()> {
+        if self.identity == IdentityMode::Name {
+            return Ok(());
+        }
+        let index_path = self.tag_dir.join(ID_INDEX_FILE);
+        let last_names: HashMap<String, String> = match tokio::fs::read_to_string(&index_path).await {
+            Ok(contents) => toml::from_str(&contents)
+                .map_err(|e| anyhow::anyhow!("invalid movie ID index {}: {}", index_path.display(), e))?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => HashMap::new(),
+            Err(e) => return Err(e.into()),
+        };
+        let by_name = std::mem::take(&mut self.movies);
+        // folder name hash to ID, for translating the tag directories
+        let mut ids = HashMap::new();
+        for (name_hash, mut movie) in by_name {
+            let mut id = read_movie_id(&movie.path).await.unwrap_or(name_hash);
+            if self.movies.contains_key(&id) {
+                tracing::warn!("{} has the ID of another movie, was it copied? using its name instead", movie.path.display());
+                id = name_hash;
             }
+            movie.hash = id;
+            ids.insert(name_hash, id);
+            self.movies.insert(id, movie);
         }
-        Ok(movies)
+        let renamed: Vec<(String, String)> = self
+            .movies
+            .values()
+            .filter_map(|movie| {
+                let old_name = last_names.get(&movie.id())?;
+                let renamed = *old_name != movie.name && !old_name.contains(['/', '\\']);
+                renamed.then(|| (old_name.clone(), movie.name.clone()))
+            })
+            .collect();
+        for (old_name, new_name) in renamed {
+            self.repair_rename(&old_name, &new_name).await;
+        }
+        for tag in self.tags.values_mut() {
+            tag.movies = tag.movies.iter().map(|hash| ids.get(hash).copied().unwrap_or(*hash)).collect();
+        }
+        let index: HashMap<String, &str> = self.movies.values().map(|m| (m.id(), m.name.as_str())).collect();
+        tokio::fs::write(&index_path, toml::to_string(&index)?).await?;
+        Ok(())
     }
 
-    async fn load_tags<D>(tag_index_dir: D, ignore: &HashSet<PathBuf>) -> anyhow::Result<Tags>
-    where
-        D: AsRef<Path>,
-    {
-        let mut tags = HashMap::new();
-        let mut entries = read_dir(&tag_index_dir).await?;
-        while let Some(entry) = entries.next_entry().await? {
-            if entry.file_type().await?.is_dir() {
-                if ignore.contains(&entry.path()) {
-                    continue;
+    /// Moves the tag entries of a movie folder renamed from `old_name` over to its new name.
+    /// The scan keyed those entries by the old name, so the tags are updated to the new one.
+    async fn repair_rename(&mut self, old_name: &str, new_name: &str) {
+        tracing::info!("{} was renamed to {}, relinking its tags", old_name, new_name);
+        let (old_hash, new_hash) = (path_hash(old_name), path_hash(new_name));
+        let (Ok(old_hash), Ok(new_hash)) = (old_hash, new_hash) else {
+            return;
+        };
+        for (key, tag) in self.tags.iter_mut() {
+            if !tag.movies.remove(&old_hash) {
+                continue;
+            }
+            let old_entry = self.tag_dir.join(key).join(old_name);
+            let new_entry = self.tag_dir.join(key).join(new_name);
+            let result = match tokio::fs::symlink_metadata(&old_entry).await {
+                Ok(metadata) if metadata.is_symlink() => match self.storage.unlink(key, old_name).await {
+                    Ok(()) => self.storage.link(key, new_name, LinkMode::Symlink, &self.path_mappings).await,
+                    Err(e) => Err(e),
+                },
+                // mirrored folders still hold the right files, only their name is stale
+                Ok(_) => tokio::fs::rename(&old_entry, &new_entry).await.map_err(Error::from),
+                Err(e) => Err(e.into()),
+            };
+            match result {
+                Ok(()) => {
+                    tag.movies.insert(new_hash);
                 }
-                let tag = entry
-                    .file_name()
-                    .to_str()
-                    .ok_or(anyhow::anyhow!("Invalid tag directory name"))?
-                    .to_string();
-                tags.insert(tag, HashSet::new());
+                Err(e) => tracing::warn!("failed to relink {} in {}: {}", new_name, key, e),
+            }
+        }
+    }
+
+    async fn load_movies(storage: &dyn Storage, progress: &ScanProgress) -> anyhow::Result<Movies> {
+        let start = Instant::now();
+        let movie_paths = storage.movie_folders().await?;
+        let loaded: Vec<_> = futures::stream::iter(movie_paths)
+            .map(|path| storage.load_movie(path))
+            .buffer_unordered(SCAN_CONCURRENCY)
+            .inspect(|_| {
+                progress.movies.fetch_add(1, Ordering::Relaxed);
+            })
+            .collect()
+            .await;
+        let mut movies = HashMap::new();
+        for movie in loaded {
+            let movie = movie?;
+            movies.insert(movie.hash, movie);
+        }
+        tracing::info!("loaded {} movies in {:?}", movies.len(), start.elapsed());
+        Ok(movies)
+    }
+
+    /// Things worth fixing by hand, like tag links to movies that are gone.
+    pub(crate) fn problems(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        for (key, tag) in &self.tags {
+            let dangling = tag.movies.iter().filter(|hash| !self.movies.contains_key(hash)).count();
+            if dangling > 0 {
+                problems.push(format!("tag {} links {} movies missing from {}", key, dangling, self.movie_dir.display()));
+            }
+        }
+        let missing_posters = self.missing_posters().len();
+        if missing_posters > 0 {
+            problems.push(format!("{} movies have no poster", missing_posters));
+        }
+        problems.sort();
+        problems
+    }
+
+    /// Every tag with the number of movies in the collection carrying it.
+    pub(crate) fn tag_counts(&self) -> Vec<(&str, &Tag, usize)> {
+        self.tags
+            .iter()
+            .map(|(key, tag)| {
+                let count = tag.movies.iter().filter(|hash| self.movies.contains_key(hash)).count();
+                (key.as_str(), tag, count)
+            })
+            .collect()
+    }
+
+    /// Movies not carrying any tag.
+    pub(crate) fn untagged(&self) -> Vec<&Movie> {
+        self.movies
+            .values()
+            .filter(|movie| !self.tags.values().any(|tag| tag.movies.contains(&movie.hash)))
+            .collect()
+    }
+
+    /// Picks a movie at random, among those tagged `tag` if given. The same seed picks the same
+    /// movie as long as the collection doesn't change.
+    pub(crate) fn random_movie(&self, tag: Option<&str>, seed: u64) -> Result<Option<&Movie>, Error> {
+        let mut candidates: Vec<&Movie> = match tag {
+            Some(tag) => {
+                let tag = self.resolve_tag(tag).and_then(|key| self.tags.get(key)).ok_or(Error::NotFound)?;
+                tag.movies.iter().filter_map(|hash| self.movies.get(hash)).collect()
             }
+            None => self.movies.values().collect(),
+        };
+        // hash map order differs between runs, so sort for the seed to mean something
+        candidates.sort_by_key(|movie| &movie.name);
+        let mut rng = StdRng::seed_from_u64(seed);
+        Ok(candidates.choose(&mut rng).copied())
+    }
+
+    /// Movies without poster artwork in their folder, by name.
+    pub(crate) fn missing_posters(&self) -> Vec<&Movie> {
+        let mut movies: Vec<_> = self.movies.values().filter(|m| m.poster_path.is_none()).collect();
+        movies.sort_by_key(|m| &m.name);
+        movies
+    }
+
+    /// Key of the tag named `name`, or of the tag listing `name` among its aliases. Aliases
+    /// match ignoring case.
+    pub(crate) fn resolve_tag(&self, name: &str) -> Option<&str> {
+        if let Some((key, _)) = self.tags.get_key_value(name) {
+            return Some(key);
         }
+        self.aliases.get(&name.to_lowercase()).map(String::as_str)
+    }
 
-        for (tag, movie_tags) in tags.iter_mut() {
-            let tag_dir = tag_index_dir.as_ref().join(tag);
-            let mut dir_entries = read_dir(&tag_dir).await?;
-            while let Some(entry) = dir_entries.next_entry().await? {
-                if entry.file_type().await?.is_symlink() {
-                    let hash = path_hash(entry.path())?;
-                    movie_tags.insert(hash);
+    /// Names of all tag groups, sorted.
+    pub(crate) fn groups(&self) -> BTreeSet<&str> {
+        self.tags.values().filter_map(|tag| tag.group.as_deref()).collect()
+    }
+
+    /// Creates an empty tag directory. `tag` may name a group, like `genre/horror`.
+    pub(crate) async fn create_tag(&mut self, tag: &str) -> Result<&Tag, Error> {
+        let (group, name) = match tag.split_once('/') {
+            Some((group, name)) => (Some(group), name),
+            None => (None, tag),
+        };
+        let valid_name = |name: &str| {
+            !name.is_empty() && !name.starts_with('.') && !name.contains(['/', '\\', '\0'])
+        };
+        if !group.is_none_or(valid_name) || !valid_name(name) {
+            return Err(Error::InvalidPath(format!("invalid tag name: {}", tag)));
+        }
+        let taken = self.tags.contains_key(tag)
+            || self.groups().contains(tag)
+            || group.is_some_and(|group| self.tags.contains_key(group));
+        if taken {
+            return Err(Error::InvalidPath(format!("tag already exists: {}", tag)));
+        }
+        self.storage.create_tag(tag).await?;
+        let new_tag = Tag {
+            name: name.to_string(),
+            group: group.map(str::to_string),
+            ..Tag::default()
+        };
+        Ok(self.tags.entry(tag.to_string()).or_insert(new_tag))
+    }
+
+    /// Deletes a tag directory, unlinking every movie in it. Movie folders are never touched.
+    pub(crate) async fn delete_tag(&mut self, tag: &str) -> Result<Tag, Error> {
+        if !self.tags.contains_key(tag) {
+            return Err(Error::NotFound);
+        }
+        self.storage.delete_tag(tag).await?;
+        let removed = self.tags.remove(tag).ok_or(Error::NotFound)?;
+        self.aliases.retain(|_, key| key != tag);
+        if let Some(group) = &removed.group {
+            if !self.tags.values().any(|t| t.group.as_ref() == Some(group)) {
+                if let Err(e) = self.storage.remove_group(group).await {
+                    tracing::warn!("failed to remove empty group {}: {}", group, e);
                 }
             }
         }
+        Ok(removed)
+    }
 
-        Ok(tags)
+    /// Links every movie of `from` into `into` and deletes `from`, returning the names of the
+    /// movies `into` didn't have yet. Movies already in both stay linked once.
+    pub(crate) async fn merge_tag(&mut self, from: &str, into: &str) -> Result<Vec<String>, Error> {
+        if from == into {
+            return Err(Error::InvalidPath(format!("can't merge tag {} into itself", from)));
+        }
+        let source = self.tags.get(from).ok_or(Error::NotFound)?;
+        let target = self.tags.get(into).ok_or(Error::NotFound)?;
+        let mut moved: Vec<(PathnameHash, String)> = source
+            .movies
+            .difference(&target.movies)
+            .filter_map(|hash| self.movies.get(hash))
+            .map(|movie| (movie.hash, movie.name.clone()))
+            .collect();
+        moved.sort_by(|(_, a), (_, b)| a.cmp(b));
+        for (hash, name) in &moved {
+            self.storage.link(into, name, self.link_mode, &self.path_mappings).await?;
+            // recorded right away, so a failing link leaves the target matching the disk
+            self.tags.get_mut(into).ok_or(Error::NotFound)?.movies.insert(*hash);
+        }
+        self.delete_tag(from).await?;
+        Ok(moved.into_iter().map(|(_, name)| name).collect())
     }
 
-    pub(crate) async fn toggle_tag(&mut self, tag: &str, movie: &Movie) -> Result<(), Error> {
-        let tag_movies = self.tags.get_mut(tag).ok_or(Error::NotFound)?;
-        let tag_path = self.tag_dir.join(tag).join(movie.path.file_name().unwrap());
-        let movie_path = self.movie_dir.join(movie.path.file_name().unwrap());
+    /// Adds or removes `tag` from `movie`, returning whether the movie is now tagged.
+    pub(crate) async fn toggle_tag(&mut self, tag: &str, movie: &Movie) -> Result<bool, Error> {
+        let tag_movies = &mut self.tags.get_mut(tag).ok_or(Error::NotFound)?.movies;
         if tag_movies.contains(&movie.hash) {
-            tracing::debug!("unlinking {} from {}", tag_path.display(), movie.path.display());
-            tokio::fs::remove_file(&tag_path).await?;
+            self.storage.unlink(tag, &movie.name).await?;
             tag_movies.remove(&movie.hash);
+            Ok(false)
         } else {
-            tracing::debug!("linking {} to {}", movie.path.display(), tag_path.display());
-            tokio::fs::symlink(movie_path, &tag_path).await?;
+            self.storage.link(tag, &movie.name, self.link_mode, &self.path_mappings).await?;
             tag_movies.insert(movie.hash);
+            Ok(true)
         }
-        Ok(())
     }
 
-    pub(crate) async fn reload(&mut self) -> Result<(), Error> {
-        self.movies = Self::load_movies(&self.movie_dir).await?;
-        let mut ignore_paths = HashSet::new();
-        ignore_paths.insert(self.movie_dir.clone());
-        self.tags = Self::load_tags(&self.tag_dir, &ignore_paths).await?;
-        tracing::debug!("Reloaded collections: {}", self);
-        Ok(())
+    /// Links `movie` into exactly `tags`, returning the tags it was added to and removed from. If
+    /// a link fails, the changes made so far are undone before returning the error.
+    pub(crate) async fn set_tags(
+        &mut self,
+        movie: &Movie,
+        tags: &HashSet<String>,
+    ) -> Result<(Vec<String>, Vec<String>), Error> {
+        if tags.iter().any(|tag| !self.tags.contains_key(tag)) {
+            return Err(Error::NotFound);
+        }
+        let (mut added, mut removed) = (Vec::new(), Vec::new());
+        for (name, tag) in &self.tags {
+            match (tag.movies.contains(&movie.hash), tags.contains(name)) {
+                (false, true) => added.push(name.clone()),
+                (true, false) => removed.push(name.clone()),
+                _ => {}
+            }
+        }
+        let mut applied: Vec<&String> = Vec::new();
+        for tag in removed.iter().chain(&added) {
+            if let Err(e) = self.toggle_tag(tag, movie).await {
+                for tag in applied.into_iter().rev() {
+                    if let Err(undo) = self.toggle_tag(tag, movie).await {
+                        tracing::warn!("failed to undo {} on {}: {}", tag, movie.name, undo);
+                    }
+                }
+                return Err(e);
+            }
+            applied.push(tag);
+        }
+        Ok((added, removed))
+    }
+
+    /// Replaces the note of the movie with ID `hash`, removing it if `note` is blank.
+    pub(crate) async fn set_note(&mut self, hash: &PathnameHash, note: &str) -> Result<&Movie, Error> {
+        let movie = self.movies.get_mut(hash).ok_or(Error::NotFound)?;
+        let note = Some(note.trim()).filter(|note| !note.is_empty());
+        self.storage.save_note(&movie.name, note).await?;
+        movie.note = note.map(str::to_string);
+        Ok(movie)
+    }
+
+    /// Rates the movie with ID `hash`, or clears its rating if `None`.
+    pub(crate) async fn set_rating(&mut self, hash: &PathnameHash, rating: Option<u8>) -> Result<&Movie, Error> {
+        let movie = self.movies.get_mut(hash).ok_or(Error::NotFound)?;
+        self.storage.save_rating(&movie.name, rating).await?;
+        movie.rating = rating;
+        Ok(movie)
+    }
+
+    /// Finds movie folders added to or removed from `storage` compared to the `known` movies,
+    /// loading the added ones. Doesn't need the collection, so it can run without its lock.
+    pub(crate) async fn scan_movie_changes(storage: &dyn Storage, known: &HashSet<PathnameHash>) -> Result<MovieChanges, Error> {
+        let mut on_disk = HashMap::new();
+        for path in storage.movie_folders().await? {
+            on_disk.insert(path_hash(&path)?, path);
+        }
+        let removed = known.iter().filter(|hash| !on_disk.contains_key(hash)).copied().collect();
+        let mut added = Vec::new();
+        for (hash, path) in on_disk {
+            if !known.contains(&hash) {
+                added.push(storage.load_movie(path).await?);
+            }
+        }
+        Ok(MovieChanges { added, removed })
+    }
+
+    /// Applies what [`Collection::scan_movie_changes`] found, leaving the tags and the movies
+    /// already loaded alone. Returns whether anything changed.
+    pub(crate) fn apply_movie_changes(&mut self, changes: MovieChanges) -> bool {
+        let mut changed = false;
+        for hash in &changes.removed {
+            changed |= self.movies.remove(hash).is_some();
+            for tag in self.tags.values_mut() {
+                tag.movies.remove(hash);
+            }
+        }
+        for movie in changes.added {
+            if let Entry::Vacant(entry) = self.movies.entry(movie.hash) {
+                entry.insert(movie);
+                changed = true;
+            }
+        }
+        if changed {
+            tracing::info!("movie folders changed, now {} movies", self.movies.len());
+        }
+        changed
+    }
+}
+
+/// Movie folders added or removed since a collection was loaded.
+#[derive(Debug, Default)]
+pub(crate) struct MovieChanges {
+    added: Vec<Movie>,
+    removed: Vec<PathnameHash>,
+}
+
+/// The ID stored in a movie folder, creating the file from the folder name hash if there is
+/// none yet so existing URLs keep working. `None` if the folder can't be written.
+async fn read_movie_id(movie_path: &Path) -> Option<PathnameHash> {
+    let id_path = movie_path.join(ID_FILE);
+    match tokio::fs::read_to_string(&id_path).await {
+        Ok(contents) => match PathnameHash::from_str(contents.trim()) {
+            Ok(id) => return Some(id),
+            Err(_) => tracing::warn!("ignoring invalid movie ID in {}", id_path.display()),
+        },
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+        Err(e) => {
+            tracing::warn!("failed to read {}: {}", id_path.display(), e);
+            return None;
+        }
+    }
+    let id = path_hash(movie_path).ok()?;
+    if let Err(e) = tokio::fs::write(&id_path, hex::encode(id.as_slice())).await {
+        tracing::warn!("failed to write {}: {}", id_path.display(), e);
+        return None;
+    }
+    Some(id)
+}
+
+/// Name of the Jellyfin library managed for a tag, flattening groups since library names can't
+/// contain slashes.
+pub(crate) fn tag_library_name(tag: &str) -> String {
+    tag.replace('/', " - ")
+}
+
+impl Tag {
+    /// Label for the tag buttons, from the tag metadata or the directory name.
+    pub(crate) fn display_name(&self) -> &str {
+        self.meta.display_name.as_deref().unwrap_or(&self.name)
+    }
+
+    /// Orders tags wherever they are listed: pinned tags first, lowest value first, then by
+    /// label ignoring case. Tags with the same label, e.g. in different groups, go by `key`.
+    pub(crate) fn sort_key<'a>(&'a self, key: &'a str) -> (bool, Option<i32>, String, &'a str) {
+        (self.meta.pinned.is_none(), self.meta.pinned, self.display_name().to_lowercase(), key)
     }
 }
 
 impl Movie {
+    /// Stores `image` as the movie's `poster.jpg`, converting it to JPEG if needed, and returns
+    /// its path.
+    pub(crate) async fn save_poster(&self, image: Vec<u8>) -> Result<PathBuf, Error> {
+        let path = self.path.join(POSTER_CANDIDATES[0].0);
+        let tmp = path.with_extension(format!("{}.tmp", uuid::Uuid::new_v4()));
+        let target = tmp.clone();
+        tokio::task::spawn_blocking(move || -> anyhow::Result<()> {
+            let image = image::load_from_memory(&image)?;
+            image.into_rgb8().save_with_format(&target, image::ImageFormat::Jpeg)?;
+            Ok(())
+        })
+        .await
+        .map_err(anyhow::Error::from)??;
+        tokio::fs::rename(&tmp, &path).await?;
+        Ok(path)
+    }
+
     pub(crate) fn id(&self) -> String {
-        hex::encode(self.hash.as_slice())
+        self.hash.id()
+    }
+
+    /// Title from the NFO metadata, falling back to the folder name.
+    pub(crate) fn title(&self) -> &str {
+        self.metadata
+            .as_ref()
+            .and_then(|m| m.title.as_deref())
+            .unwrap_or(&self.name)
+    }
+
+    pub(crate) fn year(&self) -> Option<u16> {
+        self.metadata.as_ref()?.year
+    }
+
+    /// Video files anywhere in the movie folder, by path.
+    pub(crate) async fn video_files(&self) -> Result<Vec<MovieFile>, Error> {
+        let mut files = Vec::new();
+        let mut dirs = vec![self.path.clone()];
+        while let Some(dir) = dirs.pop() {
+            let mut entries = read_dir(&dir).await?;
+            while let Some(entry) = entries.next_entry().await? {
+                let path = entry.path();
+                let metadata = tokio::fs::metadata(&path).await?;
+                if metadata.is_dir() {
+                    dirs.push(path);
+                    continue;
+                }
+                let is_video = path
+                    .extension()
+                    .and_then(|ext| ext.to_str())
+                    .is_some_and(|ext| VIDEO_EXTENSIONS.contains(&ext.to_ascii_lowercase().as_str()));
+                if is_video {
+                    let name = path.strip_prefix(&self.path).unwrap_or(&path).to_string_lossy().to_string();
+                    files.push(MovieFile { name, size: metadata.len() });
+                }
+            }
+        }
+        files.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(files)
+    }
+
+    /// When the movie folder was added, going by its creation time where the filesystem
+    /// records one and its modification time otherwise.
+    pub(crate) fn added(&self) -> Option<SystemTime> {
+        self.created.or(self.modified)
+    }
+
+    /// Keys of the tags this movie carries, sorted.
+    pub(crate) fn tags<'a>(&self, collection: &'a Collection) -> Vec<(&'a str, &'a Tag)> {
+        let mut tags: Vec<_> = collection
+            .tags
+            .iter()
+            .filter(|(_, tag)| tag.movies.contains(&self.hash))
+            .map(|(key, tag)| (key.as_str(), tag))
+            .collect();
+        tags.sort_by_cached_key(|(key, tag)| tag.sort_key(key));
+        tags
     }
 }
 
@@ -197,20 +830,27 @@ pub enum Error {
     NotFound,
     IO(std::io::Error),
     Other(anyhow::Error),
-    JellyfinError(String),
+    Jellyfin(String),
+    /// Jellyfin didn't answer, or recently failed to so often that it isn't asked for now
+    JellyfinUnreachable(String),
+    Radarr(String),
+    Trakt(String),
     InvalidPath(String),
-    JsonEncodingError(serde_json::Error),
+    JsonEncoding(serde_json::Error),
 }
 
 impl Display for Error {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            Error::JellyfinError(msg) => write!(f, "Jellyfin error: {}", msg),
+            Error::Jellyfin(msg) => write!(f, "Jellyfin error: {}", msg),
+            Error::JellyfinUnreachable(msg) => write!(f, "Jellyfin unreachable: {}", msg),
+            Error::Radarr(msg) => write!(f, "Radarr error: {}", msg),
+            Error::Trakt(msg) => write!(f, "Trakt error: {}", msg),
             Error::InvalidPath(msg) => write!(f, "Invalid path: {}", msg),
             Error::IO(e) => write!(f, "IO error: {}", e),
             Error::Other(e) => write!(f, "{}", e),
             Error::NotFound => write!(f, "Not found"),
-            Error::JsonEncodingError(e) => write!(f, "Json encoding error: {}", e),
+            Error::JsonEncoding(e) => write!(f, "Json encoding error: {}", e),
         }
     }
 }
@@ -235,7 +875,7 @@ impl IntoResponse for Error {
                 )
                     .into_response()
             }
-            Error::JellyfinError(e) => {
+            Error::Jellyfin(e) => {
                 tracing::error!("jellyfin error: {:?}", e);
                 (
                     StatusCode::INTERNAL_SERVER_ERROR,
@@ -243,6 +883,30 @@ impl IntoResponse for Error {
                 )
                     .into_response()
             }
+            Error::JellyfinUnreachable(e) => {
+                tracing::warn!("jellyfin unreachable: {}", e);
+                (
+                    StatusCode::SERVICE_UNAVAILABLE,
+                    format!("Jellyfin unreachable: {}", e)
+                )
+                    .into_response()
+            }
+            Error::Radarr(e) => {
+                tracing::error!("radarr error: {:?}", e);
+                (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    format!("Radarr error: {}", e)
+                )
+                    .into_response()
+            }
+            Error::Trakt(e) => {
+                tracing::error!("trakt error: {:?}", e);
+                (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    format!("Trakt error: {}", e)
+                )
+                    .into_response()
+            }
             Error::InvalidPath(e) => {
                 tracing::error!("invalid path: {:?}", e);
                 (
@@ -251,7 +915,7 @@ impl IntoResponse for Error {
                 )
                     .into_response()
             }
-            Error::JsonEncodingError(e) => {
+            Error::JsonEncoding(e) => {
                 tracing::error!("json encoding error: {:?}", e);
                 (
                     StatusCode::INTERNAL_SERVER_ERROR,
@@ -283,12 +947,12 @@ impl From<std::io::Error> for Error {
 
 impl From<reqwest::Error> for Error {
     fn from(e: reqwest::Error) -> Self {
-        Error::JellyfinError(format!("{}", e))
+        Error::Jellyfin(format!("{}", e))
     }
 }
 
 impl From<serde_json::Error> for Error {
     fn from(e: serde_json::Error) -> Self {
-        Error::JsonEncodingError(e)
+        Error::JsonEncoding(e)
     }
 }
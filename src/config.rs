@@ -0,0 +1,314 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use serde::Deserialize;
+
+use crate::collection::Collection;
+use crate::jellyfin_api::MediaFolders;
+use crate::media_server::ServerKind;
+use crate::preview::{library_content, LibraryContent};
+use crate::roles::Role;
+use crate::rules::Rule;
+use crate::scheduler::ScheduledJob;
+use crate::tag_sync::{ConflictPolicy, SyncDirection};
+use crate::views::View;
+
+/// Settings read from the optional TOML config file, for options that don't fit on the command
+/// line.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct Config {
+    /// Additional Jellyfin servers, managed alongside the one given on the command line.
+    #[serde(default)]
+    pub servers: Vec<ServerConfig>,
+    pub radarr: Option<RadarrConfig>,
+    pub trakt: Option<TraktConfig>,
+    /// Named sets of libraries that can be given to a user in one go.
+    #[serde(default)]
+    pub presets: Vec<Preset>,
+    /// Which libraries the user libraries page shows, and in which order.
+    #[serde(default)]
+    pub user_libraries: UserLibrariesConfig,
+    #[serde(default)]
+    pub theme: ThemeConfig,
+    /// Throttling of requests that change tags or users.
+    #[serde(default)]
+    pub limits: LimitsConfig,
+    /// Where the movie and tag directories are mounted in the Jellyfin container.
+    #[serde(default)]
+    pub path_mappings: PathMappings,
+    /// What each user reported by `--user-header` may do.
+    #[serde(default)]
+    pub roles: RolesConfig,
+    /// Named movie lists linked in the page header.
+    #[serde(default)]
+    pub views: Vec<View>,
+    /// Tags given automatically to movies matching a filter.
+    #[serde(default)]
+    pub rules: Vec<Rule>,
+    /// Syncing tags with the tags of Jellyfin items.
+    #[serde(default)]
+    pub jellyfin_tags: JellyfinTagsConfig,
+    /// URLs notified of tag and library changes.
+    #[serde(default)]
+    pub webhooks: Vec<WebhookConfig>,
+    /// Jobs run at times given by cron expressions.
+    #[serde(default)]
+    pub schedule: Vec<ScheduledJob>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct WebhookConfig {
+    pub url: String,
+    /// Signs each body with HMAC-SHA256 in the `X-Tagrs-Signature` header
+    pub secret: Option<String>,
+    pub secret_file: Option<PathBuf>,
+    /// Audit actions to send, like `tag_added`; all of them when empty
+    #[serde(default)]
+    pub events: Vec<String>,
+}
+
+impl WebhookConfig {
+    pub(crate) async fn secret(&self) -> anyhow::Result<Option<String>> {
+        match (&self.secret, &self.secret_file) {
+            (None, None) => Ok(None),
+            (secret, secret_file) => resolve_api_key(secret, secret_file)
+                .await
+                .map(Some)
+                .map_err(|e| anyhow::anyhow!("webhook {}: {}", self.url, e)),
+        }
+    }
+}
+
+/// Copying tags between tagrs and Jellyfin items on the default server, on every reload.
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct JellyfinTagsConfig {
+    pub sync: SyncDirection,
+    /// Which side wins when a movie has a tag on one side only
+    pub conflict: ConflictPolicy,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RolesConfig {
+    /// Role of users not listed, including anonymous ones
+    pub default: Role,
+    /// Treat Jellyfin administrators not listed in `users` as admins
+    pub jellyfin_admins: bool,
+    pub users: HashMap<String, Role>,
+}
+
+impl Default for RolesConfig {
+    fn default() -> Self {
+        // without roles configured everyone may do everything, as before roles existed
+        Self { default: Role::Admin, jellyfin_admins: false, users: HashMap::new() }
+    }
+}
+
+/// A directory mounted at `local` for tagrs and at `jellyfin` for Jellyfin.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PathMapping {
+    pub local: PathBuf,
+    pub jellyfin: PathBuf,
+}
+
+/// Prefix rewrites for paths handed to Jellyfin, which includes symlink targets Jellyfin
+/// follows. The longest matching prefix wins.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(transparent)]
+pub struct PathMappings(Vec<PathMapping>);
+
+impl PathMappings {
+    /// Where Jellyfin sees the local `path`, which is unchanged if no mapping applies.
+    pub(crate) fn to_jellyfin(&self, path: &Path) -> PathBuf {
+        self.0
+            .iter()
+            .filter_map(|mapping| Some((mapping, path.strip_prefix(&mapping.local).ok()?)))
+            .max_by_key(|(mapping, _)| mapping.local.components().count())
+            .map(|(mapping, rest)| mapping.jellyfin.join(rest))
+            .unwrap_or_else(|| path.to_path_buf())
+    }
+}
+
+/// Branding added to every page.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ThemeConfig {
+    /// Stylesheet loaded after the built-in ones
+    pub custom_css: Option<PathBuf>,
+    /// Image shown in the page header
+    pub logo: Option<PathBuf>,
+}
+
+/// Limits on requests that change something, i.e. everything but `GET`, `HEAD` and `OPTIONS`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LimitsConfig {
+    /// Changes handled at the same time, further ones wait for a slot
+    pub concurrent_changes: usize,
+    /// Sustained changes per second, requests above it are rejected with 429
+    pub changes_per_second: f64,
+    /// Changes allowed in a quick burst before the rate applies
+    pub burst: u32,
+}
+
+impl Default for LimitsConfig {
+    fn default() -> Self {
+        Self { concurrent_changes: 4, changes_per_second: 10.0, burst: 20 }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Preset {
+    pub name: String,
+    /// Jellyfin library ids or names
+    pub folders: Vec<String>,
+}
+
+impl Preset {
+    /// Ids of the libraries in this preset, and the entries matching no library on the server.
+    pub(crate) fn resolve<'a>(&'a self, folders: &[MediaFolders]) -> (Vec<String>, Vec<&'a str>) {
+        let mut ids = Vec::new();
+        let mut unknown = Vec::new();
+        for entry in &self.folders {
+            match folders.iter().find(|f| &f.id == entry || &f.name == entry) {
+                Some(folder) => ids.push(folder.id.clone()),
+                None => unknown.push(entry.as_str()),
+            }
+        }
+        (ids, unknown)
+    }
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct UserLibrariesConfig {
+    /// Library ids or names left out, e.g. `Music`
+    pub hidden: Vec<String>,
+    /// Collection types left out, e.g. `music`, `photos` or `homevideos`
+    pub hidden_types: Vec<String>,
+    /// Show the libraries of tags first, in the order of their tags
+    pub pin_tag_libraries: bool,
+}
+
+impl UserLibrariesConfig {
+    /// The libraries to show of `folders`. Hidden ones are only left out of the page, users keep
+    /// their access to them.
+    pub(crate) fn arrange(&self, collection: &Collection, folders: &[MediaFolders]) -> Vec<MediaFolders> {
+        let mut shown: Vec<_> = folders
+            .iter()
+            .filter(|f| !self.hidden.iter().any(|entry| entry == &f.id || entry == &f.name))
+            .filter(|f| !self.hidden_types.iter().any(|kind| kind.eq_ignore_ascii_case(&f.collection_type)))
+            .collect();
+        if self.pin_tag_libraries {
+            // the sort is stable, so other libraries keep Jellyfin's order
+            shown.sort_by_cached_key(|folder| match library_content(collection, folder) {
+                LibraryContent::Tag(key) => (false, Some(collection.tags[key].sort_key(key))),
+                _ => (true, None),
+            });
+        }
+        shown.into_iter().cloned().collect()
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ServerConfig {
+    pub name: String,
+    pub base_url: String,
+    /// "jellyfin" (default) or "emby"
+    #[serde(default)]
+    pub kind: ServerKind,
+    pub api_key: Option<String>,
+    /// Read the API key from this file instead, e.g. a Docker secret
+    pub api_key_file: Option<PathBuf>,
+    /// Address of the web UI for links opened in the browser, if not `base_url`
+    pub public_url: Option<String>,
+}
+
+impl ServerConfig {
+    pub async fn api_key(&self) -> anyhow::Result<String> {
+        resolve_api_key(&self.api_key, &self.api_key_file)
+            .await
+            .map_err(|e| anyhow::anyhow!("server {}: {}", self.name, e))
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct RadarrConfig {
+    pub base_url: String,
+    pub api_key: Option<String>,
+    pub api_key_file: Option<PathBuf>,
+}
+
+impl RadarrConfig {
+    pub async fn api_key(&self) -> anyhow::Result<String> {
+        resolve_api_key(&self.api_key, &self.api_key_file)
+            .await
+            .map_err(|e| anyhow::anyhow!("radarr: {}", e))
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TraktConfig {
+    /// Client ID of an API app created at https://trakt.tv/oauth/applications
+    pub client_id: String,
+    pub client_secret: Option<String>,
+    pub client_secret_file: Option<PathBuf>,
+    /// Where the access token is kept between restarts, readable only by tagrs
+    pub token_file: PathBuf,
+    #[serde(default)]
+    pub lists: Vec<TraktListConfig>,
+}
+
+impl TraktConfig {
+    pub async fn client_secret(&self) -> anyhow::Result<String> {
+        resolve_api_key(&self.client_secret, &self.client_secret_file)
+            .await
+            .map_err(|e| anyhow::anyhow!("trakt: {}", e))
+    }
+}
+
+/// A Trakt list of the connected user kept in step with a tag.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TraktListConfig {
+    /// Slug of the list as in its URL, or `watchlist`
+    pub list: String,
+    /// Tag name or alias, created when missing
+    pub tag: String,
+    /// `pull` tags the movies on the list, `push` makes the list match the tag and `both` adds
+    /// what either side is missing
+    pub sync: SyncDirection,
+}
+
+async fn resolve_api_key(api_key: &Option<String>, api_key_file: &Option<PathBuf>) -> anyhow::Result<String> {
+    match (api_key, api_key_file) {
+        (Some(api_key), None) => Ok(api_key.clone()),
+        (None, Some(path)) => read_secret(path).await,
+        _ => anyhow::bail!("needs exactly one of api_key or api_key_file"),
+    }
+}
+
+/// Reads a secret from a file such as `/run/secrets/<name>`, ignoring surrounding whitespace.
+pub async fn read_secret<T: AsRef<Path>>(path: T) -> anyhow::Result<String> {
+    let path = path.as_ref();
+    let secret = tokio::fs::read_to_string(path)
+        .await
+        .map_err(|e| anyhow::anyhow!("failed to read secret from {}: {}", path.display(), e))?;
+    Ok(secret.trim().to_string())
+}
+
+impl Config {
+    pub async fn load<T: AsRef<Path>>(path: T) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        let contents = tokio::fs::read_to_string(path)
+            .await
+            .map_err(|e| anyhow::anyhow!("failed to read config file {}: {}", path.display(), e))?;
+        let config = toml::from_str(&contents)
+            .map_err(|e| anyhow::anyhow!("invalid config file {}: {}", path.display(), e))?;
+        Ok(config)
+    }
+}
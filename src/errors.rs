@@ -0,0 +1,81 @@
+use axum::extract::Request;
+use axum::http::{header, HeaderMap, HeaderValue};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use http_body_util::BodyExt;
+
+use crate::templates;
+
+/// Largest error message read back from a response.
+const MAX_MESSAGE: usize = 64 * 1024;
+
+/// How a failed request is answered, following what the client asked for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ErrorFormat {
+    /// A full page, for navigating the browser
+    Page,
+    /// A dismissible message added to the page, for htmx requests
+    Fragment,
+    /// The plain text message, for scripts and the APIs
+    Plain,
+}
+
+impl ErrorFormat {
+    fn negotiate(headers: &HeaderMap) -> Self {
+        if headers.contains_key("hx-request") {
+            return ErrorFormat::Fragment;
+        }
+        let accepts_html = headers
+            .get(header::ACCEPT)
+            .and_then(|accept| accept.to_str().ok())
+            .is_some_and(|accept| accept.contains("text/html"));
+        if accepts_html {
+            ErrorFormat::Page
+        } else {
+            ErrorFormat::Plain
+        }
+    }
+}
+
+/// Middleware turning plain text error responses, like the ones of [`crate::collection::Error`],
+/// into an error page for browsers or an error message htmx adds to `#errors` instead of
+/// swapping it into its target.
+pub(crate) async fn error_pages(request: Request, next: Next) -> Response {
+    let format = ErrorFormat::negotiate(request.headers());
+    let response = next.run(request).await;
+    let status = response.status();
+    let is_text = response
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .is_none_or(|content_type| content_type.as_bytes().starts_with(b"text/plain"));
+    if format == ErrorFormat::Plain || !(status.is_client_error() || status.is_server_error()) || !is_text {
+        return response;
+    }
+    let (parts, body) = response.into_parts();
+    let message = match http_body_util::Limited::new(body, MAX_MESSAGE).collect().await {
+        Ok(body) => String::from_utf8_lossy(&body.to_bytes()).into_owned(),
+        Err(_) => String::new(),
+    };
+    let message = match message.trim() {
+        "" => status.canonical_reason().unwrap_or_default().to_string(),
+        message => message.to_string(),
+    };
+    let mut response = match format {
+        ErrorFormat::Page => templates::error_page(status.as_u16(), status.canonical_reason(), &message).into_response(),
+        _ => {
+            let mut response = templates::error_message(&message).into_response();
+            let headers = response.headers_mut();
+            headers.insert("hx-retarget", HeaderValue::from_static("#errors"));
+            headers.insert("hx-reswap", HeaderValue::from_static("beforeend"));
+            response
+        }
+    };
+    *response.status_mut() = status;
+    // keep headers like Retry-After, but not those describing the old body
+    for (name, value) in parts.headers.iter() {
+        if name != header::CONTENT_TYPE && name != header::CONTENT_LENGTH {
+            response.headers_mut().insert(name, value.clone());
+        }
+    }
+    response
+}
@@ -0,0 +1,287 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, LazyLock};
+
+use async_graphql::http::GraphiQLSource;
+use async_graphql::{Context, EmptyMutation, EmptySubscription, Object, Schema, SimpleObject};
+use axum::extract::State;
+use axum::response::Html;
+use axum::Json;
+use tokio::sync::{Mutex, OwnedRwLockReadGuard};
+
+use crate::collection::{Collection, Movie, Tag};
+use crate::jellyfin_api::{self, MediaFolders};
+use crate::media_server::MediaServer;
+use crate::roles::{self, Role};
+use crate::templates::MovieSources;
+use crate::views::Filter;
+use crate::{Actor, AppState};
+
+/// The schema is the same for every query, the data comes with each request.
+static SCHEMA: LazyLock<Schema<Query, EmptyMutation, EmptySubscription>> =
+    LazyLock::new(|| Schema::build(Query, EmptyMutation, EmptySubscription).finish());
+
+/// Everything a query reads, shared by its resolvers. The collection stays locked for reading
+/// until the query is answered so all fields see the same state.
+struct QueryData {
+    state: AppState,
+    collection: OwnedRwLockReadGuard<Collection>,
+    sources: MovieSources,
+    role: Role,
+    /// Users and libraries of each server, fetched once per query
+    servers: Mutex<HashMap<String, Arc<ServerData>>>,
+}
+
+struct ServerData {
+    users: Vec<jellyfin_api::User>,
+    folders: Vec<MediaFolders>,
+}
+
+impl QueryData {
+    fn from_context<'a>(ctx: &Context<'a>) -> &'a QueryData {
+        ctx.data_unchecked::<QueryData>()
+    }
+
+    /// The named server, or the default one.
+    fn client(&self, server: Option<&str>) -> async_graphql::Result<(String, Arc<MediaServer>)> {
+        match server {
+            Some(name) => Ok((name.to_string(), self.state.jellyfin.get(name).ok_or("unknown server")?.clone())),
+            None => {
+                let (name, api) = self.state.jellyfin.default_server().ok_or("no Jellyfin server configured")?;
+                Ok((name.to_string(), api.clone()))
+            }
+        }
+    }
+
+    async fn server(&self, server: Option<&str>) -> async_graphql::Result<(String, Arc<ServerData>)> {
+        if self.role < Role::Admin {
+            return Err("Admin role required for Jellyfin users".into());
+        }
+        let (name, api) = self.client(server)?;
+        // held while fetching so resolvers asking at the same time share one fetch
+        let mut servers = self.servers.lock().await;
+        if let Some(data) = servers.get(&name) {
+            return Ok((name, data.clone()));
+        }
+        let (users, folders) = tokio::try_join!(api.get_users(), api.get_libraries())?;
+        let data = Arc::new(ServerData { users, folders });
+        servers.insert(name.clone(), data.clone());
+        Ok((name, data))
+    }
+}
+
+pub(crate) struct Query;
+
+#[Object]
+impl Query {
+    /// Movies sorted by name, optionally limited to those matching a filter expression as used
+    /// by views, like `tag:kids and not tag:scary`.
+    async fn movies(&self, ctx: &Context<'_>, filter: Option<String>) -> async_graphql::Result<Vec<MovieObject>> {
+        let data = QueryData::from_context(ctx);
+        let filter = filter.map(Filter::try_from).transpose()?;
+        let collection = &*data.collection;
+        let mut movies: Vec<&Movie> = collection
+            .movies
+            .values()
+            .filter(|movie| {
+                let resolution = data.sources.media_info(movie).and_then(|m| m.resolution());
+                filter.as_ref().is_none_or(|filter| filter.matches(collection, movie, resolution))
+            })
+            .collect();
+        movies.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(movies.into_iter().map(|movie| MovieObject(movie.clone())).collect())
+    }
+
+    /// A movie by id.
+    async fn movie(&self, ctx: &Context<'_>, id: String) -> Option<MovieObject> {
+        let data = QueryData::from_context(ctx);
+        data.collection.movies.values().find(|movie| movie.id() == id).cloned().map(MovieObject)
+    }
+
+    /// Tags, pinned ones first, in the same order as on movie cards.
+    async fn tags(&self, ctx: &Context<'_>) -> Vec<TagObject> {
+        let data = QueryData::from_context(ctx);
+        let mut tags: Vec<_> = data.collection.tags.iter().collect();
+        tags.sort_by_cached_key(|(key, tag)| tag.sort_key(key));
+        tags.into_iter().map(|(key, _)| TagObject(key.clone())).collect()
+    }
+
+    /// A tag by name, like `genre/horror`, or by one of its aliases.
+    async fn tag(&self, ctx: &Context<'_>, name: String) -> Option<TagObject> {
+        let data = QueryData::from_context(ctx);
+        data.collection.resolve_tag(&name).map(|key| TagObject(key.to_string()))
+    }
+
+    /// Users of a Jellyfin server, the default one if none is named. Needs the admin role.
+    async fn users(&self, ctx: &Context<'_>, server: Option<String>) -> async_graphql::Result<Vec<UserObject>> {
+        let data = QueryData::from_context(ctx);
+        let (server, users) = data.server(server.as_deref()).await?;
+        Ok(users.users.iter().map(|user| UserObject { user: user.clone(), server: server.clone() }).collect())
+    }
+}
+
+pub(crate) struct MovieObject(Movie);
+
+#[Object(name = "Movie")]
+impl MovieObject {
+    async fn id(&self) -> String {
+        self.0.id()
+    }
+
+    /// Folder name
+    async fn name(&self) -> &str {
+        &self.0.name
+    }
+
+    async fn title(&self) -> &str {
+        self.0.title()
+    }
+
+    async fn year(&self) -> Option<u16> {
+        self.0.year()
+    }
+
+    async fn genres(&self) -> Vec<String> {
+        self.0.metadata.as_ref().map(|m| m.genres.clone()).unwrap_or_default()
+    }
+
+    /// Stars from 1 to 5
+    async fn rating(&self) -> Option<u8> {
+        self.0.rating
+    }
+
+    /// Note jotted down on the movie page
+    async fn note(&self) -> Option<&str> {
+        self.0.note.as_deref()
+    }
+
+    async fn tags(&self, ctx: &Context<'_>) -> Vec<TagObject> {
+        let data = QueryData::from_context(ctx);
+        self.0.tags(&data.collection).into_iter().map(|(key, _)| TagObject(key.to_string())).collect()
+    }
+
+    /// Whether a Jellyfin user can see the movie through one of their libraries: the movie
+    /// directory or the directory of one of its tags. Needs the admin role.
+    async fn visible_to(&self, ctx: &Context<'_>, user: String, server: Option<String>) -> async_graphql::Result<bool> {
+        let data = QueryData::from_context(ctx);
+        let (_, server) = data.server(server.as_deref()).await?;
+        let user = server.users.iter().find(|u| u.name == user || u.id == user).ok_or("unknown user")?;
+        let collection = &*data.collection;
+        let mut paths: Vec<PathBuf> = vec![collection.movie_dir.join(&self.0.name)];
+        for (key, _) in self.0.tags(collection) {
+            paths.push(collection.tag_dir.join(key).join(&self.0.name));
+        }
+        let paths: Vec<PathBuf> = paths.iter().map(|path| collection.path_mappings.to_jellyfin(path)).collect();
+        let accessible = user.accessible_folders(&server.folders);
+        Ok(server
+            .folders
+            .iter()
+            .filter(|folder| accessible.contains(&folder.id))
+            .any(|folder| paths.iter().any(|path| path.starts_with(Path::new(&folder.path)))))
+    }
+}
+
+pub(crate) struct TagObject(String);
+
+impl TagObject {
+    fn tag<'a>(&self, ctx: &Context<'a>) -> Option<&'a Tag> {
+        QueryData::from_context(ctx).collection.tags.get(&self.0)
+    }
+}
+
+#[Object(name = "Tag")]
+impl TagObject {
+    /// Directory name, including the group like `genre/horror`
+    async fn name(&self) -> &str {
+        &self.0
+    }
+
+    async fn display_name(&self, ctx: &Context<'_>) -> Option<String> {
+        self.tag(ctx).map(|tag| tag.display_name().to_string())
+    }
+
+    async fn group(&self, ctx: &Context<'_>) -> Option<String> {
+        self.tag(ctx)?.group.clone()
+    }
+
+    /// Left off movie cards and tag lists in the web interface
+    async fn hidden(&self, ctx: &Context<'_>) -> bool {
+        self.tag(ctx).is_some_and(|tag| tag.meta.hidden)
+    }
+
+    /// Tagged movies sorted by name.
+    async fn movies(&self, ctx: &Context<'_>) -> Vec<MovieObject> {
+        let collection = &*QueryData::from_context(ctx).collection;
+        let Some(tag) = self.tag(ctx) else {
+            return Vec::new();
+        };
+        let mut movies: Vec<&Movie> = tag.movies.iter().filter_map(|hash| collection.movies.get(hash)).collect();
+        movies.sort_by(|a, b| a.name.cmp(&b.name));
+        movies.into_iter().map(|movie| MovieObject(movie.clone())).collect()
+    }
+}
+
+pub(crate) struct UserObject {
+    user: jellyfin_api::User,
+    server: String,
+}
+
+#[derive(SimpleObject)]
+struct Library {
+    id: String,
+    name: String,
+    path: String,
+}
+
+#[Object(name = "User")]
+impl UserObject {
+    async fn id(&self) -> &str {
+        &self.user.id
+    }
+
+    async fn name(&self) -> &str {
+        &self.user.name
+    }
+
+    async fn is_admin(&self) -> bool {
+        self.user.is_admin()
+    }
+
+    async fn is_disabled(&self) -> bool {
+        self.user.is_disabled()
+    }
+
+    /// Libraries the user can see.
+    async fn libraries(&self, ctx: &Context<'_>) -> async_graphql::Result<Vec<Library>> {
+        let data = QueryData::from_context(ctx);
+        let (_, server) = data.server(Some(&self.server)).await?;
+        let accessible = self.user.accessible_folders(&server.folders);
+        Ok(server
+            .folders
+            .iter()
+            .filter(|folder| accessible.contains(&folder.id))
+            .map(|folder| Library { id: folder.id.clone(), name: folder.name.clone(), path: folder.path.clone() })
+            .collect())
+    }
+}
+
+/// Answers a query posted as JSON.
+pub(crate) async fn query(
+    State(state): State<AppState>,
+    actor: Actor,
+    Json(request): Json<async_graphql::Request>,
+) -> Json<async_graphql::Response> {
+    let data = QueryData {
+        role: roles::role(&state, &actor).await,
+        sources: state.movie_sources().await,
+        collection: state.collection.clone().read_owned().await,
+        servers: Default::default(),
+        state: state.clone(),
+    };
+    Json(SCHEMA.execute(request.data(data)).await)
+}
+
+/// GraphiQL, for trying out queries in the browser.
+pub(crate) async fn graphiql() -> Html<String> {
+    Html(GraphiQLSource::build().endpoint(&crate::theme::url("/graphql")).finish())
+}
@@ -0,0 +1,194 @@
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::path::Path;
+use std::sync::{Arc, LazyLock};
+
+use axum::extract::{Request, State};
+use axum::http::header::ACCEPT_LANGUAGE;
+use axum::middleware::Next;
+use axum::response::Response;
+use maud::{html, Markup};
+
+use crate::AppState;
+
+/// Translated strings keyed by dotted names like `users.create_user`.
+pub(crate) type Messages = HashMap<String, String>;
+
+/// Locales compiled into the binary.
+const BUILTIN: &[(&str, &str)] = &[
+    ("en", include_str!("locales/en.toml")),
+    ("de", include_str!("locales/de.toml")),
+];
+
+const FALLBACK: &str = "en";
+
+/// English strings, used for keys a locale doesn't translate.
+static FALLBACK_MESSAGES: LazyLock<Messages> =
+    LazyLock::new(|| parse(BUILTIN[0].1).expect("the built-in English locale is valid"));
+
+tokio::task_local! {
+    /// Locale of the request being handled.
+    static LOCALE: Locale;
+}
+
+/// Flattens nested tables into dotted keys, so locale files can group strings in sections.
+fn flatten(prefix: &str, table: toml::Table, messages: &mut Messages) -> anyhow::Result<()> {
+    for (key, value) in table {
+        let key = if prefix.is_empty() { key } else { format!("{}.{}", prefix, key) };
+        match value {
+            toml::Value::String(message) => {
+                messages.insert(key, message);
+            }
+            toml::Value::Table(table) => flatten(&key, table, messages)?,
+            _ => anyhow::bail!("{} must be a string", key),
+        }
+    }
+    Ok(())
+}
+
+fn parse(contents: &str) -> anyhow::Result<Messages> {
+    let mut messages = Messages::new();
+    flatten("", toml::from_str(contents)?, &mut messages)?;
+    Ok(messages)
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct Locale {
+    pub(crate) lang: String,
+    messages: Arc<Messages>,
+}
+
+impl Locale {
+    fn get(&self, key: &str) -> String {
+        self.messages
+            .get(key)
+            .or_else(|| FALLBACK_MESSAGES.get(key))
+            .cloned()
+            .unwrap_or_else(|| key.to_string())
+    }
+}
+
+/// All available locales, and the one to use regardless of the browser's preference if set.
+#[derive(Debug, Clone)]
+pub struct Translations {
+    locales: HashMap<String, Arc<Messages>>,
+    fixed: Option<String>,
+}
+
+impl Default for Translations {
+    fn default() -> Self {
+        let locales = BUILTIN
+            .iter()
+            .map(|(lang, contents)| {
+                let messages = parse(contents).unwrap_or_else(|e| panic!("invalid built-in locale {}: {}", lang, e));
+                (lang.to_string(), Arc::new(messages))
+            })
+            .collect();
+        Self { locales, fixed: None }
+    }
+}
+
+impl Translations {
+    /// Adds the `<lang>.toml` files in `dir`, overriding built-in strings of the same locale.
+    pub async fn load_dir<T: AsRef<Path>>(mut self, dir: T) -> anyhow::Result<Self> {
+        let dir = dir.as_ref();
+        let mut entries = tokio::fs::read_dir(dir)
+            .await
+            .map_err(|e| anyhow::anyhow!("failed to read locale directory {}: {}", dir.display(), e))?;
+        while let Some(entry) = entries.next_entry().await? {
+            let path = entry.path();
+            if path.extension().is_none_or(|ext| ext != "toml") {
+                continue;
+            }
+            let Some(lang) = path.file_stem().map(|s| s.to_string_lossy().to_lowercase()) else {
+                continue;
+            };
+            let contents = tokio::fs::read_to_string(&path).await?;
+            let messages =
+                parse(&contents).map_err(|e| anyhow::anyhow!("invalid locale file {}: {}", path.display(), e))?;
+            let locale = self.locales.entry(lang).or_default();
+            Arc::make_mut(locale).extend(messages);
+        }
+        Ok(self)
+    }
+
+    /// Always use `lang` instead of following the browser's `Accept-Language`.
+    pub fn with_locale(mut self, lang: &str) -> anyhow::Result<Self> {
+        let lang = lang.to_lowercase();
+        anyhow::ensure!(self.locales.contains_key(&lang), "unknown locale {}", lang);
+        self.fixed = Some(lang);
+        Ok(self)
+    }
+
+    fn locale(&self, lang: &str) -> Option<Locale> {
+        let messages = self.locales.get(lang)?.clone();
+        Some(Locale { lang: lang.to_string(), messages })
+    }
+
+    /// Picks the configured locale, or the best match for an `Accept-Language` header.
+    fn negotiate(&self, accept_language: Option<&str>) -> Locale {
+        if let Some(locale) = self.fixed.as_deref().and_then(|lang| self.locale(lang)) {
+            return locale;
+        }
+        let mut preferred: Vec<(&str, f32)> = accept_language
+            .unwrap_or_default()
+            .split(',')
+            .filter_map(|entry| {
+                let mut parts = entry.split(';');
+                let lang = parts.next()?.trim();
+                let quality = parts
+                    .find_map(|p| p.trim().strip_prefix("q="))
+                    .and_then(|q| q.parse().ok())
+                    .unwrap_or(1.0);
+                Some((lang, quality))
+            })
+            .collect();
+        preferred.sort_by(|a, b| b.1.total_cmp(&a.1));
+        preferred
+            .into_iter()
+            .filter_map(|(lang, _)| {
+                // only the primary language matters, `de-AT` gets German
+                let primary = lang.split('-').next()?.to_lowercase();
+                self.locale(&primary)
+            })
+            .next()
+            .or_else(|| self.locale(FALLBACK))
+            .unwrap_or_else(|| Locale { lang: FALLBACK.to_string(), messages: Arc::default() })
+    }
+}
+
+/// Middleware rendering the request's pages in the negotiated locale.
+pub(crate) async fn localize(State(state): State<AppState>, request: Request, next: Next) -> Response {
+    let accept_language = request.headers().get(ACCEPT_LANGUAGE).and_then(|h| h.to_str().ok());
+    let locale = state.translations.negotiate(accept_language);
+    LOCALE.scope(locale, next.run(request)).await
+}
+
+/// Language of the current request, for the `lang` attribute.
+pub(crate) fn lang() -> String {
+    LOCALE.try_with(|l| l.lang.clone()).unwrap_or_else(|_| FALLBACK.to_string())
+}
+
+/// Translates `key` into the current request's language.
+pub(crate) fn t(key: &str) -> String {
+    LOCALE.try_with(|l| l.get(key)).unwrap_or_else(|_| {
+        FALLBACK_MESSAGES.get(key).cloned().unwrap_or_else(|| key.to_string())
+    })
+}
+
+/// Translates `key`, filling in `{name}` placeholders.
+pub(crate) fn tf(key: &str, args: &[(&str, &dyn Display)]) -> String {
+    args.iter().fold(t(key), |message, (name, value)| {
+        message.replace(&format!("{{{}}}", name), &value.to_string())
+    })
+}
+
+/// Translates `key`, putting `markup` where its `{name}` placeholder is.
+pub(crate) fn tm(key: &str, name: &str, markup: Markup) -> Markup {
+    let message = t(key);
+    let placeholder = format!("{{{}}}", name);
+    match message.split_once(&placeholder) {
+        Some((before, after)) => html! { (before) (markup) (after) },
+        None => html! { (message) },
+    }
+}
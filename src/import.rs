@@ -0,0 +1,238 @@
+use std::collections::BTreeSet;
+
+use serde::Deserialize;
+
+use crate::collection::{Collection, Movie};
+use crate::rules::Change;
+
+/// A movie listed in an imported file, e.g. a Letterboxd watchlist export.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub(crate) struct Row {
+    /// Letterboxd names the column `Name`, other exports `Title`
+    #[serde(alias = "Name", alias = "name", alias = "Title", alias = "title")]
+    pub(crate) title: String,
+    #[serde(alias = "Year", default, deserialize_with = "csv::invalid_option")]
+    pub(crate) year: Option<u16>,
+}
+
+/// Reads the movies from a CSV file with a header row naming at least a `Name` or `Title`
+/// column, like the `watchlist.csv` of a Letterboxd export. Other columns are ignored.
+pub(crate) fn parse_csv(data: &[u8]) -> Result<Vec<Row>, csv::Error> {
+    let mut reader = csv::ReaderBuilder::new().flexible(true).trim(csv::Trim::All).from_reader(data);
+    reader
+        .deserialize()
+        .filter(|row: &Result<Row, csv::Error>| !row.as_ref().is_ok_and(|row| row.title.is_empty()))
+        .collect()
+}
+
+/// What importing a file into a tag would change.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Plan {
+    pub(crate) changes: Vec<Change>,
+    /// Matched movies carrying the tag already
+    pub(crate) already_tagged: Vec<String>,
+    /// Rows matching no movie, or several equally well
+    pub(crate) unmatched: Vec<Row>,
+}
+
+/// Matches `rows` against the movies of the collection to give them `tag`.
+pub(crate) fn plan(collection: &Collection, rows: Vec<Row>, tag: &str) -> Plan {
+    let tag = collection.resolve_tag(tag).unwrap_or(tag).to_string();
+    let matcher = Matcher::new(collection);
+    let tagged = collection.tags.get(&tag).map(|tag| &tag.movies);
+
+    let mut plan = Plan::default();
+    let mut changes = BTreeSet::new();
+    for row in rows {
+        let Some(movie) = matcher.find(&row.title, row.year) else {
+            plan.unmatched.push(row);
+            continue;
+        };
+        if tagged.is_some_and(|movies| movies.contains(&movie.hash)) {
+            plan.already_tagged.push(movie.name.clone());
+        } else {
+            changes.insert(Change { tag: tag.clone(), movie_name: movie.name.clone(), movie: movie.hash });
+        }
+    }
+    plan.changes = changes.into_iter().collect();
+    plan.already_tagged.sort();
+    plan.already_tagged.dedup();
+    plan
+}
+
+/// Finds movies of the collection by title and year as written elsewhere.
+pub(crate) struct Matcher<'a> {
+    /// Each movie with its normalized titles and year
+    candidates: Vec<(&'a Movie, Vec<String>, Option<u16>)>,
+}
+
+impl<'a> Matcher<'a> {
+    pub(crate) fn new(collection: &'a Collection) -> Self {
+        let candidates = collection
+            .movies
+            .values()
+            .map(|movie| {
+                let (folder_title, folder_year) = split_year(&movie.name);
+                let mut titles = vec![normalize(movie.title()), normalize(folder_title)];
+                titles.dedup();
+                (movie, titles, movie.year().or(folder_year))
+            })
+            .collect();
+        Self { candidates }
+    }
+
+    /// The movie closest to `title`, whose year is at most one off when both are known since
+    /// release dates differ between countries. Ties match nothing rather than a guess.
+    pub(crate) fn find(&self, title: &str, year: Option<u16>) -> Option<&'a Movie> {
+        best_match(&self.candidates, title, year)
+    }
+}
+
+fn best_match<'a>(candidates: &[(&'a Movie, Vec<String>, Option<u16>)], title: &str, year: Option<u16>) -> Option<&'a Movie> {
+    let title = normalize(title);
+    // a typo in titles from six characters on, two from fourteen
+    let max_distance = (title.chars().count() + 2) / 8;
+    let mut best: Option<((usize, u16), &Movie)> = None;
+    let mut tied = false;
+    for (movie, titles, candidate_year) in candidates {
+        let year_offset = match (year, candidate_year) {
+            (Some(a), Some(b)) if a.abs_diff(*b) > 1 => continue,
+            (Some(a), Some(b)) => a.abs_diff(*b),
+            _ => 1,
+        };
+        let Some(distance) = titles.iter().map(|candidate| distance(&title, candidate)).min() else {
+            continue;
+        };
+        if distance > max_distance {
+            continue;
+        }
+        let score = (distance, year_offset);
+        match &best {
+            Some((best_score, _)) if score > *best_score => {}
+            Some((best_score, _)) if score == *best_score => tied = true,
+            _ => {
+                best = Some((score, movie));
+                tied = false;
+            }
+        }
+    }
+    best.filter(|_| !tied).map(|(_, movie)| movie)
+}
+
+/// Splits a folder name like `Alien (1979)` into title and year.
+fn split_year(name: &str) -> (&str, Option<u16>) {
+    let Some((title, rest)) = name.rsplit_once('(') else {
+        return (name, None);
+    };
+    match rest.strip_suffix(')').and_then(|year| year.parse().ok()) {
+        Some(year) => (title.trim_end(), Some(year)),
+        None => (name, None),
+    }
+}
+
+/// Lowercase words of `title` without punctuation or a leading article, so that e.g.
+/// "The Lord of the Rings: The Two Towers" and "Lord of the Rings - The Two Towers" are equal.
+fn normalize(title: &str) -> String {
+    let title = title.to_lowercase().replace('&', " and ");
+    let mut words = title
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .peekable();
+    if words.peek().is_some_and(|word| matches!(*word, "the" | "a" | "an")) {
+        words.next();
+    }
+    words.collect::<Vec<_>>().join(" ")
+}
+
+/// Levenshtein distance between `a` and `b` in characters.
+fn distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    let mut current = vec![0; b.len() + 1];
+    for (i, a) in a.chars().enumerate() {
+        current[0] = i + 1;
+        for (j, b) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(a != *b);
+            current[j + 1] = substitution.min(previous[j + 1] + 1).min(current[j] + 1);
+        }
+        std::mem::swap(&mut previous, &mut current);
+    }
+    previous[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn normalize_drops_case_punctuation_and_leading_articles() {
+        let cases = [
+            ("The Lord of the Rings: The Two Towers", "lord of the rings the two towers"),
+            ("Lord of the Rings - The Two Towers", "lord of the rings the two towers"),
+            ("A Quiet Place", "quiet place"),
+            ("An American Werewolf in London", "american werewolf in london"),
+            ("Fast & Furious", "fast and furious"),
+            ("WALL·E", "wall e"),
+            ("Amélie", "amélie"),
+            ("  Heat!  ", "heat"),
+            ("", ""),
+        ];
+        for (title, expected) in cases {
+            assert_eq!(normalize(title), expected, "{:?}", title);
+        }
+    }
+
+    #[test]
+    fn distance_counts_edits_in_characters() {
+        let cases = [
+            ("", "", 0),
+            ("heat", "", 4),
+            ("alien", "aliens", 1),
+            ("kitten", "sitting", 3),
+            ("flaw", "lawn", 2),
+            ("amélie", "amelie", 1),
+        ];
+        for (a, b, expected) in cases {
+            assert_eq!(distance(a, b), expected, "{:?} {:?}", a, b);
+            assert_eq!(distance(b, a), expected, "{:?} {:?}", b, a);
+        }
+    }
+
+    #[test]
+    fn split_year_takes_a_trailing_year_only() {
+        let cases = [
+            ("Alien (1979)", ("Alien", Some(1979))),
+            ("Alien", ("Alien", None)),
+            ("Blade Runner 2049 (2017)", ("Blade Runner 2049", Some(2017))),
+            ("(500) Days of Summer (2009)", ("(500) Days of Summer", Some(2009))),
+            ("(500) Days of Summer", ("(500) Days of Summer", None)),
+            ("Nope (Director's Cut)", ("Nope (Director's Cut)", None)),
+        ];
+        for (name, expected) in cases {
+            assert_eq!(split_year(name), expected, "{:?}", name);
+        }
+    }
+
+    #[tokio::test]
+    async fn find_allows_a_year_off_by_one_and_refuses_ties() {
+        let movies = ["Alien (1979)", "Aliens (1986)", "The Thing (1982)", "The Thing (2011)", "Dune (1984)", "Dune (2021)"];
+        let collection = Collection::in_memory(&movies, &[]).await.unwrap();
+        let matcher = Matcher::new(&collection);
+        let cases = [
+            ("Alien", Some(1979), Some("Alien (1979)")),
+            ("Alien", Some(1980), Some("Alien (1979)")),
+            ("Alien", Some(1981), None),
+            ("Aliens", None, Some("Aliens (1986)")),
+            ("Alienz", Some(1986), Some("Aliens (1986)")),
+            ("Thing", Some(2011), Some("The Thing (2011)")),
+            ("The Thing", None, None),
+            ("Dune", None, None),
+            ("Dune", Some(2020), Some("Dune (2021)")),
+            ("Dune", Some(2000), None),
+        ];
+        for (title, year, expected) in cases {
+            let found = matcher.find(title, year).map(|movie| movie.name.as_str());
+            assert_eq!(found, expected, "{:?} {:?}", title, year);
+        }
+    }
+}
@@ -0,0 +1,111 @@
+use axum::body::{Body, Bytes};
+use axum::extract::Request;
+use axum::http::{header, HeaderValue};
+use axum::middleware::Next;
+use axum::response::Response;
+use axum::Router;
+use axum_insights::{AppInsights, AppInsightsError, AppInsightsLayer};
+use http_body_util::BodyExt;
+use serde::{Deserialize, Serialize};
+use tracing::Subscriber;
+use tracing_subscriber::filter::LevelFilter;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::registry::LookupSpan;
+
+use crate::AppState;
+
+/// An error response as reported to Application Insights.
+#[derive(Default, Serialize, Deserialize, Clone)]
+pub(crate) struct WebError {
+    message: String,
+}
+
+impl AppInsightsError for WebError {
+    fn message(&self) -> Option<String> {
+        Some(self.message.clone())
+    }
+
+    fn backtrace(&self) -> Option<String> {
+        None
+    }
+}
+
+/// Request telemetry for Azure Application Insights, added to the router when configured.
+#[derive(Clone)]
+pub struct Insights(AppInsightsLayer<(), WebError>);
+
+impl std::fmt::Debug for Insights {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Insights").finish_non_exhaustive()
+    }
+}
+
+impl Insights {
+    /// Sends the spans and events of `subscriber` to Application Insights and installs it as the
+    /// global subscriber.
+    pub(crate) fn init<S>(connection_string: String, level: LevelFilter, subscriber: S) -> anyhow::Result<Self>
+    where
+        S: SubscriberExt + Subscriber + for<'span> LookupSpan<'span> + Send + Sync + 'static,
+    {
+        let insights = AppInsights::default()
+            .with_connection_string(connection_string)
+            .with_service_config(env!("CARGO_PKG_NAME"), env!("CARGO_PKG_NAME"))
+            .with_minimum_level(level)
+            .with_subscriber(subscriber)
+            .with_catch_panic(true)
+            .with_error_type::<WebError>()
+            .build_and_set_global_default()
+            .map_err(|e| anyhow::anyhow!("failed to set up Application Insights: {}", e))?;
+        Ok(Self(insights.layer()))
+    }
+
+    /// Adds the telemetry layer to every route. Error responses are plain text or HTML, while the
+    /// layer reads the error from a JSON [`WebError`] body, so they are turned into one for the
+    /// layer and back for the client.
+    pub(crate) fn apply(&self, router: Router<AppState>) -> Router<AppState> {
+        router
+            .layer(axum::middleware::from_fn(encode_error))
+            .layer(self.0.clone())
+            .layer(axum::middleware::from_fn(restore_error))
+    }
+}
+
+/// The error response as the handler returned it.
+#[derive(Clone)]
+struct OriginalError {
+    content_type: Option<HeaderValue>,
+    body: Bytes,
+}
+
+async fn encode_error(request: Request, next: Next) -> Response {
+    let response = next.run(request).await;
+    if !(response.status().is_client_error() || response.status().is_server_error()) {
+        return response;
+    }
+    let (mut parts, body) = response.into_parts();
+    let body = match body.collect().await {
+        Ok(body) => body.to_bytes(),
+        Err(e) => {
+            tracing::warn!("failed to read error response: {}", e);
+            Bytes::new()
+        }
+    };
+    let error = WebError { message: String::from_utf8_lossy(&body).into_owned() };
+    let content_type = parts.headers.insert(header::CONTENT_TYPE, HeaderValue::from_static("application/json"));
+    parts.extensions.insert(OriginalError { content_type, body });
+    Response::from_parts(parts, Body::from(serde_json::to_vec(&error).unwrap_or_default()))
+}
+
+async fn restore_error(request: Request, next: Next) -> Response {
+    let mut response = next.run(request).await;
+    if let Some(original) = response.extensions_mut().remove::<OriginalError>() {
+        let headers = response.headers_mut();
+        headers.remove(header::CONTENT_LENGTH);
+        match original.content_type {
+            Some(content_type) => headers.insert(header::CONTENT_TYPE, content_type),
+            None => headers.remove(header::CONTENT_TYPE),
+        };
+        *response.body_mut() = Body::from(original.body);
+    }
+    response
+}
@@ -1,15 +1,21 @@
-use reqwest::{IntoUrl, Method};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use percent_encoding::{utf8_percent_encode, NON_ALPHANUMERIC};
+use reqwest::{Method, StatusCode};
 use serde::{Deserialize, Serialize};
+use tracing::Instrument;
 
 use crate::collection::Error;
+use crate::logging::{inject_request_id, inject_trace_context};
+use crate::media_server::{MediaServer, ServerKind};
 
 
 #[derive(Debug, Deserialize, Clone)]
 #[serde(rename_all = "PascalCase")]
 struct APIList<T> {
     items: Vec<T>,
-    total_record_count: usize,
-    start_index: usize,
 }
 
 #[derive(Debug, Deserialize, Clone)]
@@ -17,23 +23,69 @@ struct APIList<T> {
 pub(crate) struct User {
     pub(crate) id: String,
     pub(crate) name: String,
-    pub(crate) policy: serde_json::Value,
+    pub(crate) policy: UserPolicy,
+}
+
+/// A user's access policy. Only the fields tagrs works with are typed, everything else is kept
+/// in `extra` so it is sent back to Jellyfin unchanged when the policy is updated.
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+#[serde(rename_all = "PascalCase")]
+pub(crate) struct UserPolicy {
+    #[serde(default)]
+    pub(crate) is_administrator: bool,
+    #[serde(default)]
+    pub(crate) is_disabled: bool,
+    #[serde(default)]
+    pub(crate) enable_all_folders: bool,
+    #[serde(default)]
+    pub(crate) enabled_folders: Vec<String>,
+    #[serde(default)]
+    pub(crate) max_parental_rating: Option<i32>,
+    #[serde(default)]
+    pub(crate) block_unrated_items: Vec<String>,
+    #[serde(flatten)]
+    pub(crate) extra: serde_json::Map<String, serde_json::Value>,
 }
 
 impl User {
-    pub(crate) fn is_admin(&self) -> Option<bool> {
-        self.policy["IsAdministrator"].as_bool()
+    pub(crate) fn is_admin(&self) -> bool {
+        self.policy.is_administrator
     }
 
-    pub(crate) fn is_disabled(&self) -> Option<bool> {
-        self.policy["IsDisabled"].as_bool()
+    pub(crate) fn is_disabled(&self) -> bool {
+        self.policy.is_disabled
     }
 
-    pub(crate) fn enabled_folders(&self) -> Result<Vec<String>, serde_json::Error> {
-        serde_json::from_value(self.policy["EnabledFolders"].clone())
+    pub(crate) fn enabled_folders(&self) -> &[String] {
+        &self.policy.enabled_folders
+    }
+
+    /// Whether the user can see every library, including ones added later. `EnabledFolders` is
+    /// ignored by Jellyfin in that case.
+    pub(crate) fn has_all_folders(&self) -> bool {
+        self.policy.enable_all_folders
+    }
+
+    /// Ids of the libraries the user can actually see.
+    pub(crate) fn accessible_folders(&self, folders: &[MediaFolders]) -> Vec<String> {
+        if self.has_all_folders() {
+            folders.iter().map(|f| f.id.clone()).collect()
+        } else {
+            self.policy.enabled_folders.clone()
+        }
     }
 }
 
+/// Item kinds that can be blocked when they have no parental rating.
+pub(crate) const UNRATED_ITEM_TYPES: &[&str] = &["Movie", "Series", "Trailer", "Book", "Music", "LiveTvProgram"];
+
+#[derive(Debug, Deserialize, Clone)]
+#[serde(rename_all = "PascalCase")]
+pub(crate) struct ParentalRating {
+    pub(crate) name: String,
+    pub(crate) value: Option<i32>,
+}
+
 #[derive(Debug, Deserialize, Clone)]
 #[serde(rename_all = "PascalCase")]
 pub(crate) struct MediaFolders {
@@ -41,26 +93,364 @@ pub(crate) struct MediaFolders {
     pub(crate) name: String,
     pub(crate) path: String,
     pub(crate) collection_type: String,
-    pub(crate) etag: String,
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Deserialize, Clone)]
+#[serde(rename_all = "PascalCase")]
+pub(crate) struct Item {
+    pub(crate) id: String,
+    pub(crate) name: String,
+    pub(crate) path: Option<String>,
+    pub(crate) user_data: Option<UserItemData>,
+    #[serde(default)]
+    pub(crate) tags: Vec<String>,
+}
+
+/// Artwork for an item offered by one of Jellyfin's metadata providers.
+#[derive(Debug, Deserialize, Clone)]
+#[serde(rename_all = "PascalCase")]
+pub(crate) struct RemoteImage {
+    pub(crate) provider_name: Option<String>,
+    pub(crate) url: String,
+    pub(crate) thumbnail_url: Option<String>,
+    pub(crate) width: Option<u32>,
+    pub(crate) height: Option<u32>,
+    pub(crate) language: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct RemoteImageResult {
+    #[serde(default)]
+    images: Vec<RemoteImage>,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+#[serde(rename_all = "PascalCase")]
+pub(crate) struct UserItemData {
+    #[serde(default)]
+    pub(crate) play_count: u32,
+    #[serde(default)]
+    pub(crate) played: bool,
+    pub(crate) last_played_date: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+impl Item {
+    /// The movie folder holding the item, whose path is either the folder or a video file in it.
+    pub(crate) fn folder(&self) -> Option<&Path> {
+        let path = Path::new(self.path.as_deref()?);
+        if path.extension().is_some() {
+            path.parent()
+        } else {
+            Some(path)
+        }
+    }
+
+    /// Name of the folder holding the item, which is what tagrs uses to identify movies.
+    pub(crate) fn folder_name(&self) -> Option<&str> {
+        self.folder()?.file_name()?.to_str()
+    }
+}
+
+#[derive(Debug, Deserialize, Clone)]
+#[serde(rename_all = "PascalCase")]
+pub(crate) struct Session {
+    pub(crate) id: String,
+    pub(crate) user_name: Option<String>,
+    pub(crate) client: Option<String>,
+    pub(crate) device_name: Option<String>,
+    pub(crate) now_playing_item: Option<NowPlayingItem>,
+    pub(crate) play_state: Option<PlayState>,
+    pub(crate) transcoding_info: Option<TranscodingInfo>,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+#[serde(rename_all = "PascalCase")]
+pub(crate) struct NowPlayingItem {
+    pub(crate) name: String,
+    pub(crate) series_name: Option<String>,
+    pub(crate) run_time_ticks: Option<u64>,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+#[serde(rename_all = "PascalCase")]
+pub(crate) struct PlayState {
+    pub(crate) position_ticks: Option<u64>,
+    #[serde(default)]
+    pub(crate) is_paused: bool,
+    pub(crate) play_method: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+#[serde(rename_all = "PascalCase")]
+pub(crate) struct TranscodingInfo {
+    pub(crate) video_codec: Option<String>,
+    pub(crate) audio_codec: Option<String>,
+    pub(crate) bitrate: Option<u64>,
+    #[serde(default)]
+    pub(crate) transcode_reasons: Vec<String>,
+}
+
+/// A job the server runs on a schedule or on demand, such as the library scan.
+#[derive(Debug, Deserialize, Clone)]
+#[serde(rename_all = "PascalCase")]
+pub(crate) struct ScheduledTask {
+    pub(crate) id: String,
+    pub(crate) name: String,
+    pub(crate) description: Option<String>,
+    #[serde(default)]
+    pub(crate) category: String,
+    /// "Idle", "Running" or "Cancelling"
+    pub(crate) state: String,
+    pub(crate) current_progress_percentage: Option<f64>,
+    pub(crate) last_execution_result: Option<TaskResult>,
+    #[serde(default)]
+    pub(crate) is_hidden: bool,
+}
+
+impl ScheduledTask {
+    pub(crate) fn is_running(&self) -> bool {
+        self.state != "Idle"
+    }
+}
+
+#[derive(Debug, Deserialize, Clone)]
+#[serde(rename_all = "PascalCase")]
+pub(crate) struct TaskResult {
+    pub(crate) end_time_utc: Option<chrono::DateTime<chrono::Utc>>,
+    /// "Completed", "Failed", "Cancelled" or "Aborted"
+    pub(crate) status: String,
+}
+
+/// What the server reports about itself.
+#[derive(Debug, Deserialize, Clone)]
+#[serde(rename_all = "PascalCase")]
+pub(crate) struct SystemInfo {
+    pub(crate) server_name: String,
+    pub(crate) version: String,
+    /// "Jellyfin Server" or "Emby Server"
+    pub(crate) product_name: Option<String>,
+    pub(crate) operating_system_display_name: Option<String>,
+    #[serde(default)]
+    pub(crate) has_pending_restart: bool,
+    #[serde(default)]
+    pub(crate) has_update_available: bool,
+    #[serde(default)]
+    pub(crate) is_shutting_down: bool,
+}
+
+/// A device that has signed in to the server, which stays signed in until it is deleted.
+#[derive(Debug, Deserialize, Clone)]
+#[serde(rename_all = "PascalCase")]
+pub(crate) struct Device {
+    pub(crate) id: String,
+    pub(crate) name: String,
+    pub(crate) app_name: Option<String>,
+    pub(crate) app_version: Option<String>,
+    pub(crate) last_user_name: Option<String>,
+    pub(crate) date_last_activity: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+/// Jellyfin measures durations in 100ns ticks.
+pub(crate) const TICKS_PER_SECOND: u64 = 10_000_000;
+
+#[derive(Serialize, Deserialize, Clone)]
 pub struct JellyfinClient {
     base_url: String,
     api_key: String,
+    #[serde(skip)]
+    dialect: Dialect,
+    /// Where browsers reach the web UI, if not at `base_url`
+    #[serde(default)]
+    public_url: Option<String>,
+    #[serde(skip)]
+    client: reqwest::Client,
+    #[serde(skip)]
+    breaker: Arc<CircuitBreaker>,
+}
+
+/// How a server expects to be addressed. Jellyfin and Emby each have their own, see
+/// [`ServerKind::dialect`].
+#[derive(Clone, Copy)]
+pub(crate) struct Dialect {
+    /// Prefix of every API path
+    pub(crate) api_prefix: &'static str,
+    /// Header carrying the API key, and its value for a key
+    pub(crate) auth_header: &'static str,
+    pub(crate) auth_value: fn(&str) -> String,
+    /// Web UI path showing an item, followed by its id
+    pub(crate) item_page: &'static str,
+}
+
+impl Default for Dialect {
+    fn default() -> Self {
+        ServerKind::Jellyfin.dialect()
+    }
+}
+
+/// Stops asking a Jellyfin server that failed several times in a row for a while, so pages
+/// needing it fail fast instead of waiting for timeouts. After the pause one request is let
+/// through to find out whether it's back.
+#[derive(Debug, Default)]
+struct CircuitBreaker {
+    state: Mutex<BreakerState>,
+}
+
+#[derive(Debug, Default)]
+struct BreakerState {
+    failures: u32,
+    open_until: Option<Instant>,
+}
+
+impl CircuitBreaker {
+    /// Failures in a row after which requests are refused
+    const THRESHOLD: u32 = 3;
+    /// How long requests are refused before trying again
+    const PAUSE: Duration = Duration::from_secs(30);
+
+    fn check(&self) -> Result<(), Error> {
+        let mut state = self.state.lock().unwrap();
+        match state.open_until {
+            Some(open_until) if open_until > Instant::now() => {
+                Err(Error::JellyfinUnreachable("not asking again yet after repeated failures".to_string()))
+            }
+            Some(_) => {
+                // let this request try, and refuse the others until it's done
+                state.open_until = Some(Instant::now() + Self::PAUSE);
+                Ok(())
+            }
+            None => Ok(()),
+        }
+    }
+
+    fn succeeded(&self) {
+        let mut state = self.state.lock().unwrap();
+        if state.open_until.is_some() {
+            tracing::info!("Jellyfin is reachable again");
+        }
+        *state = BreakerState::default();
+    }
+
+    fn failed(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.failures += 1;
+        if state.failures >= Self::THRESHOLD {
+            if state.open_until.is_none() {
+                tracing::warn!("Jellyfin failed {} times in a row, pausing requests for {:?}", state.failures, Self::PAUSE);
+            }
+            state.open_until = Some(Instant::now() + Self::PAUSE);
+        }
+    }
+}
+
+/// Sends requests to Jellyfin in a span of their own, passing the trace on when exported, and
+/// keeping `breaker` informed about whether Jellyfin answers.
+trait SendTraced {
+    fn send_traced(self, breaker: &CircuitBreaker) -> impl std::future::Future<Output = Result<reqwest::Response, Error>> + Send;
+}
+
+impl SendTraced for reqwest::RequestBuilder {
+    async fn send_traced(self, breaker: &CircuitBreaker) -> Result<reqwest::Response, Error> {
+        breaker.check()?;
+        let (client, request) = self.build_split();
+        let mut request = request?;
+        let span = tracing::info_span!(
+            "jellyfin request",
+            otel.kind = "client",
+            http.request.method = %request.method(),
+            url.path = request.url().path(),
+            http.response.status_code = tracing::field::Empty,
+        );
+        span.in_scope(|| inject_trace_context(request.headers_mut()));
+        inject_request_id(request.headers_mut());
+        let response = match client.execute(request).instrument(span.clone()).await {
+            Ok(response) => response,
+            Err(e) => {
+                breaker.failed();
+                return Err(Error::JellyfinUnreachable(e.to_string()));
+            }
+        };
+        span.record("http.response.status_code", response.status().as_u16());
+        // a proxy in front of Jellyfin answers these while it's down
+        match response.status() {
+            StatusCode::BAD_GATEWAY | StatusCode::SERVICE_UNAVAILABLE | StatusCode::GATEWAY_TIMEOUT => breaker.failed(),
+            _ => breaker.succeeded(),
+        }
+        Ok(response)
+    }
+}
+
+impl std::fmt::Debug for JellyfinClient {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("JellyfinClient")
+            .field("base_url", &self.base_url)
+            .field("api_prefix", &self.dialect.api_prefix)
+            .field("public_url", &self.public_url)
+            .field("api_key", &"<redacted>")
+            .finish()
+    }
+}
+
+/// A pooled HTTP client giving up on requests after `timeout`, for Jellyfin and the other
+/// services tagrs talks to.
+pub fn http_client(timeout: Duration, connect_timeout: Duration) -> reqwest::Client {
+    reqwest::Client::builder()
+        .timeout(timeout)
+        .connect_timeout(connect_timeout)
+        .pool_idle_timeout(Duration::from_secs(90))
+        .build()
+        .expect("failed to initialize the HTTP client")
 }
 
 impl JellyfinClient {
-    pub fn new(mut base_url: String, api_key: String) -> Self {
+    pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+    pub const DEFAULT_CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+
+    pub fn new(base_url: String, api_key: String) -> Self {
+        Self::with_timeouts(base_url, api_key, Self::DEFAULT_TIMEOUT, Self::DEFAULT_CONNECT_TIMEOUT)
+    }
+
+    pub fn with_timeouts(base_url: String, api_key: String, timeout: Duration, connect_timeout: Duration) -> Self {
+        Self::with_client(base_url, api_key, http_client(timeout, connect_timeout))
+    }
+
+    /// Talks to Jellyfin through `client`, so several servers can share its connection pool.
+    pub fn with_client(mut base_url: String, api_key: String, client: reqwest::Client) -> Self {
         if base_url.ends_with('/') {
             base_url.pop();
         }
-        Self { base_url, api_key }
+        Self { base_url, api_key, dialect: Dialect::default(), public_url: None, client, breaker: Arc::default() }
+    }
+
+    /// Addresses the server the way `dialect` says, e.g. to talk to Emby instead of Jellyfin.
+    pub(crate) fn with_dialect(mut self, dialect: Dialect) -> Self {
+        // the API prefix is added to every path, so don't repeat it when it was configured too
+        let prefix = dialect.api_prefix;
+        if !prefix.is_empty() && self.base_url.ends_with(prefix) {
+            self.base_url.truncate(self.base_url.len() - prefix.len());
+        }
+        self.dialect = dialect;
+        self
+    }
+
+    /// Links to the web UI go to `public_url`, e.g. when tagrs reaches Jellyfin on an internal
+    /// address browsers can't.
+    pub fn with_public_url(mut self, mut public_url: String) -> Self {
+        if public_url.ends_with('/') {
+            public_url.pop();
+        }
+        self.public_url = Some(public_url);
+        self
+    }
+
+    /// Page of the web UI showing the item `item_id`.
+    pub(crate) fn web_url(&self, item_id: &str) -> String {
+        let base_url = self.public_url.as_deref().unwrap_or(&self.base_url);
+        format!("{}{}{}", base_url, self.dialect.item_page, item_id)
     }
 
     fn base_request(
         &self,
-        client: &reqwest::Client,
         method: reqwest::Method,
         path: &str,
     ) -> Result<reqwest::RequestBuilder, Error> {
@@ -69,32 +459,128 @@ impl JellyfinClient {
                 "Path must start with \"/\": {path}"
             )));
         }
-        let url = format!("{}{}", self.base_url, path);
-        Ok(client
+        let url = format!("{}{}{}", self.base_url, self.dialect.api_prefix, path);
+        Ok(self
+            .client
             .request(method, url)
-            .header(
-                "Authorization",
-                format!(r#"MediaBrowser Token="{}""#, self.api_key),
-            )
+            .header(self.dialect.auth_header, (self.dialect.auth_value)(&self.api_key))
             .header("Accept", "application/json"))
     }
 
     #[tracing::instrument]
     pub(crate) async fn get_users(&self) -> Result<Vec<User>, Error> {
         tracing::debug!("Getting users");
-        let resp = self.base_request(&reqwest::Client::new(), Method::GET, "/Users")?.send().await?;
+        let resp = self.base_request(Method::GET, "/Users")?.send_traced(&self.breaker).await?;
         let text = resp.text().await?;
         tracing::debug!("Users response: {}", text);
         let users: Vec<User> = serde_json::from_str(&text)?;
         Ok(users)
     }
 
+    #[tracing::instrument]
+    pub(crate) async fn get_user(&self, user_id: &str) -> Result<User, Error> {
+        let path = format!("/Users/{}", user_id);
+        let resp = self.base_request(Method::GET, &path)?.send_traced(&self.breaker).await?;
+        match resp.status() {
+            StatusCode::NOT_FOUND => Err(Error::NotFound),
+            status if status.is_success() => Ok(resp.json().await?),
+            status => Err(Error::Jellyfin(format!(
+                "Failed to get user: User id = {}, {}",
+                user_id, status,
+            ))),
+        }
+    }
+
+    #[tracing::instrument]
+    pub(crate) async fn get_sessions(&self) -> Result<Vec<Session>, Error> {
+        let resp = self.base_request(Method::GET, "/Sessions")?.send_traced(&self.breaker).await?;
+        let text = resp.text().await?;
+        Ok(serde_json::from_str(&text)?)
+    }
+
+    #[tracing::instrument]
+    pub(crate) async fn get_system_info(&self) -> Result<SystemInfo, Error> {
+        let resp = self.base_request(Method::GET, "/System/Info")?.send_traced(&self.breaker).await?;
+        let status = resp.status();
+        if !status.is_success() {
+            return Err(Error::Jellyfin(format!("Failed to get system info: {}", status)));
+        }
+        Ok(resp.json().await?)
+    }
+
+    #[tracing::instrument]
+    pub(crate) async fn get_scheduled_tasks(&self) -> Result<Vec<ScheduledTask>, Error> {
+        let resp = self.base_request(Method::GET, "/ScheduledTasks")?.send_traced(&self.breaker).await?;
+        let text = resp.text().await?;
+        Ok(serde_json::from_str(&text)?)
+    }
+
+    /// Starts a scheduled task now, unless it is running already.
+    #[tracing::instrument]
+    pub(crate) async fn start_task(&self, task_id: &str) -> Result<(), Error> {
+        let path = format!("/ScheduledTasks/Running/{}", task_id);
+        let resp = self.base_request(Method::POST, &path)?.send_traced(&self.breaker).await?;
+        match resp.status() {
+            StatusCode::NOT_FOUND => Err(Error::NotFound),
+            status if status.is_success() => Ok(()),
+            status => Err(Error::Jellyfin(format!(
+                "Failed to start task: Task id = {}, {}",
+                task_id, status,
+            ))),
+        }
+    }
+
+    /// Tells the client playing in `session_id` to stop.
+    #[tracing::instrument]
+    pub(crate) async fn stop_playback(&self, session_id: &str) -> Result<(), Error> {
+        let path = format!("/Sessions/{}/Playing/Stop", session_id);
+        let resp = self.base_request(Method::POST, &path)?.send_traced(&self.breaker).await?;
+        match resp.status() {
+            StatusCode::NOT_FOUND => Err(Error::NotFound),
+            status if status.is_success() => Ok(()),
+            status => Err(Error::Jellyfin(format!(
+                "Failed to stop playback: Session id = {}, {}",
+                session_id, status,
+            ))),
+        }
+    }
+
+    #[tracing::instrument]
+    pub(crate) async fn get_devices(&self) -> Result<Vec<Device>, Error> {
+        let resp = self.base_request(Method::GET, "/Devices")?.send_traced(&self.breaker).await?;
+        let text = resp.text().await?;
+        let devices: APIList<Device> = serde_json::from_str(&text)?;
+        Ok(devices.items)
+    }
+
+    /// Deletes a device, which signs it out and ends its sessions.
+    #[tracing::instrument]
+    pub(crate) async fn delete_device(&self, device_id: &str) -> Result<(), Error> {
+        let path = format!("/Devices?id={}", utf8_percent_encode(device_id, NON_ALPHANUMERIC));
+        let resp = self.base_request(Method::DELETE, &path)?.send_traced(&self.breaker).await?;
+        match resp.status() {
+            StatusCode::NOT_FOUND => Err(Error::NotFound),
+            status if status.is_success() => Ok(()),
+            status => Err(Error::Jellyfin(format!(
+                "Failed to delete device: Device id = {}, {}",
+                device_id, status,
+            ))),
+        }
+    }
+
+    #[tracing::instrument]
+    pub(crate) async fn get_parental_ratings(&self) -> Result<Vec<ParentalRating>, Error> {
+        let resp = self.base_request(Method::GET, "/Localization/ParentalRatings")?.send_traced(&self.breaker).await?;
+        let text = resp.text().await?;
+        Ok(serde_json::from_str(&text)?)
+    }
+
     #[tracing::instrument]
     pub(crate) async fn get_media_folders(&self) -> Result<Vec<MediaFolders>, Error> {
         tracing::debug!("Getting media folders");
         let resp = self
-            .base_request(&reqwest::Client::new(), Method::GET, "/Library/MediaFolders")?
-            .send()
+            .base_request(Method::GET, "/Library/MediaFolders")?
+            .send_traced(&self.breaker)
             .await?;
         let text = resp.text().await?;
         let folders: APIList<MediaFolders> = serde_json::from_str(&text)?;
@@ -102,31 +588,335 @@ impl JellyfinClient {
     }
 
     #[tracing::instrument]
-    pub(crate) async fn set_user_media_folders(&self, user: &User, folders: &[String]) -> Result<(), Error> {
+    pub(crate) async fn get_movies(&self) -> Result<Vec<Item>, Error> {
+        tracing::debug!("Getting movies");
+        let resp = self
+            .base_request(Method::GET, "/Items?Recursive=true&IncludeItemTypes=Movie&Fields=Path,Tags")?
+            .send_traced(&self.breaker)
+            .await?;
+        let text = resp.text().await?;
+        let items: APIList<Item> = serde_json::from_str(&text)?;
+        Ok(items.items)
+    }
+
+    /// The movie stored in the folder `path` as Jellyfin sees it, whose own path is either that
+    /// folder or its video file.
+    #[tracing::instrument]
+    pub(crate) async fn find_item_by_path(&self, path: &Path) -> Result<Option<Item>, Error> {
+        let movies = self.get_movies().await?;
+        Ok(movies.into_iter().find(|item| item.folder() == Some(path)))
+    }
+
+    /// Movies `user_id` has played, including their play counts.
+    #[tracing::instrument]
+    pub(crate) async fn get_played_movies(&self, user_id: &str) -> Result<Vec<Item>, Error> {
+        let path = format!(
+            "/Users/{}/Items?Recursive=true&IncludeItemTypes=Movie&Filters=IsPlayed&Fields=Path",
+            user_id
+        );
+        let resp = self.base_request(Method::GET, &path)?.send_traced(&self.breaker).await?;
+        let text = resp.text().await?;
+        let items: APIList<Item> = serde_json::from_str(&text)?;
+        Ok(items.items)
+    }
+
+    /// Downloads the primary image of an item as a JPEG, returning `None` if it has none.
+    #[tracing::instrument]
+    pub(crate) async fn get_item_image(&self, item_id: &str) -> Result<Option<Vec<u8>>, Error> {
+        let path = format!("/Items/{}/Images/Primary?format=Jpg", item_id);
+        let resp = self
+            .base_request(Method::GET, &path)?
+            .send_traced(&self.breaker)
+            .await?;
+        match resp.status() {
+            StatusCode::NOT_FOUND => Ok(None),
+            status if status.is_success() => Ok(Some(resp.bytes().await?.to_vec())),
+            status => Err(Error::Jellyfin(format!(
+                "Failed to get image: Item id = {}, {}",
+                item_id, status,
+            ))),
+        }
+    }
+
+    /// Posters the metadata providers have for an item.
+    #[tracing::instrument]
+    pub(crate) async fn get_remote_images(&self, item_id: &str) -> Result<Vec<RemoteImage>, Error> {
+        let path = format!("/Items/{}/RemoteImages?type=Primary&includeAllLanguages=true", item_id);
+        let resp = self.base_request(Method::GET, &path)?.send_traced(&self.breaker).await?;
+        match resp.status() {
+            StatusCode::NOT_FOUND => Err(Error::NotFound),
+            status if status.is_success() => Ok(resp.json::<RemoteImageResult>().await?.images),
+            status => Err(Error::Jellyfin(format!(
+                "Failed to get remote images: Item id = {}, {}",
+                item_id, status,
+            ))),
+        }
+    }
+
+    /// Replaces the tags of an item. Jellyfin updates items from a complete item, so it is
+    /// fetched and sent back with only its tags changed.
+    #[tracing::instrument]
+    pub(crate) async fn set_item_tags(&self, item_id: &str, tags: &[String]) -> Result<(), Error> {
+        let path = format!("/Items/{}", item_id);
+        let resp = self.base_request(Method::GET, &path)?.send_traced(&self.breaker).await?;
+        let mut item: serde_json::Map<String, serde_json::Value> = match resp.status() {
+            StatusCode::NOT_FOUND => return Err(Error::NotFound),
+            status if status.is_success() => resp.json().await?,
+            status => {
+                return Err(Error::Jellyfin(format!("Failed to get item: Item id = {}, {}", item_id, status)))
+            }
+        };
+        item.insert("Tags".to_string(), serde_json::json!(tags));
+        let resp = self.base_request(Method::POST, &path)?.json(&item).send_traced(&self.breaker).await?;
+        if resp.status().is_success() {
+            Ok(())
+        } else {
+            Err(Error::Jellyfin(format!(
+                "Failed to update item tags: Item id = {}, {}",
+                item_id,
+                resp.text().await?,
+            )))
+        }
+    }
+
+    /// Downloads an image found with [`Self::get_remote_images`] from its provider.
+    #[tracing::instrument]
+    pub(crate) async fn download_remote_image(&self, url: &str) -> Result<Vec<u8>, Error> {
+        let resp = self.client.get(url).send().await?;
+        let status = resp.status();
+        if !status.is_success() {
+            return Err(Error::Jellyfin(format!("Failed to download image {}: {}", url, status)));
+        }
+        Ok(resp.bytes().await?.to_vec())
+    }
+
+    #[tracing::instrument(skip(password))]
+    pub(crate) async fn create_user(&self, name: &str, password: &str) -> Result<User, Error> {
+        tracing::debug!("Creating user");
+        let body = serde_json::json!({ "Name": name, "Password": password });
+        let resp = self.base_request(Method::POST, "/Users/New")?.json(&body).send_traced(&self.breaker).await?;
+        if resp.status().is_success() {
+            Ok(resp.json().await?)
+        } else {
+            Err(Error::Jellyfin(format!(
+                "Failed to create user: Name = {}, {}",
+                name,
+                resp.text().await?,
+            )))
+        }
+    }
+
+    #[tracing::instrument]
+    pub(crate) async fn delete_user(&self, user_id: &str) -> Result<(), Error> {
+        let path = format!("/Users/{}", user_id);
+        let resp = self.base_request(Method::DELETE, &path)?.send_traced(&self.breaker).await?;
+        if resp.status().is_success() {
+            Ok(())
+        } else {
+            Err(Error::Jellyfin(format!(
+                "Failed to delete user: User id = {}, {}",
+                user_id,
+                resp.text().await?,
+            )))
+        }
+    }
+
+    #[tracing::instrument]
+    pub(crate) async fn refresh_library(&self) -> Result<(), Error> {
+        tracing::debug!("Starting library scan");
+        let resp = self.base_request(Method::POST, "/Library/Refresh")?.send_traced(&self.breaker).await?;
+        if resp.status().is_success() {
+            Ok(())
+        } else {
+            Err(Error::Jellyfin(format!(
+                "Failed to start library scan: {}",
+                resp.text().await?,
+            )))
+        }
+    }
+
+    /// Creates a movie library named `name` containing the folder at `path`.
+    #[tracing::instrument]
+    pub(crate) async fn add_virtual_folder(&self, name: &str, path: &str) -> Result<(), Error> {
+        let resp = self
+            .base_request(Method::POST, "/Library/VirtualFolders")?
+            .query(&[("name", name), ("collectionType", "movies"), ("paths", path), ("refreshLibrary", "true")])
+            .json(&serde_json::json!({ "LibraryOptions": {} }))
+            .send_traced(&self.breaker)
+            .await?;
+        if resp.status().is_success() {
+            Ok(())
+        } else {
+            Err(Error::Jellyfin(format!(
+                "Failed to create library: Name = {}, {}",
+                name,
+                resp.text().await?,
+            )))
+        }
+    }
+
+    /// Collections (box sets) on the server.
+    #[tracing::instrument]
+    pub(crate) async fn get_collections(&self) -> Result<Vec<Item>, Error> {
+        let resp = self
+            .base_request(Method::GET, "/Items?Recursive=true&IncludeItemTypes=BoxSet")?
+            .send_traced(&self.breaker)
+            .await?;
+        let items: APIList<Item> = resp.json().await?;
+        Ok(items.items)
+    }
+
+    /// Creates a collection holding `item_ids`, returning its id.
+    #[tracing::instrument]
+    pub(crate) async fn create_collection(&self, name: &str, item_ids: &[String]) -> Result<String, Error> {
+        #[derive(Deserialize)]
+        #[serde(rename_all = "PascalCase")]
+        struct Created {
+            id: String,
+        }
+        let resp = self
+            .base_request(Method::POST, "/Collections")?
+            .query(&[("name", name), ("ids", &item_ids.join(","))])
+            .send_traced(&self.breaker)
+            .await?;
+        if resp.status().is_success() {
+            Ok(resp.json::<Created>().await?.id)
+        } else {
+            Err(Error::Jellyfin(format!(
+                "Failed to create collection: Name = {}, {}",
+                name,
+                resp.text().await?,
+            )))
+        }
+    }
+
+    #[tracing::instrument]
+    pub(crate) async fn add_to_collection(&self, collection_id: &str, item_ids: &[String]) -> Result<(), Error> {
+        let resp = self
+            .base_request(Method::POST, &format!("/Collections/{}/Items", collection_id))?
+            .query(&[("ids", item_ids.join(","))])
+            .send_traced(&self.breaker)
+            .await?;
+        match resp.status() {
+            StatusCode::NOT_FOUND => Err(Error::NotFound),
+            status if status.is_success() => Ok(()),
+            _ => Err(Error::Jellyfin(format!(
+                "Failed to add to collection: Collection id = {}, {}",
+                collection_id,
+                resp.text().await?,
+            ))),
+        }
+    }
+
+    #[tracing::instrument]
+    pub(crate) async fn remove_virtual_folder(&self, name: &str) -> Result<(), Error> {
+        let resp = self
+            .base_request(Method::DELETE, "/Library/VirtualFolders")?
+            .query(&[("name", name), ("refreshLibrary", "true")])
+            .send_traced(&self.breaker)
+            .await?;
+        match resp.status() {
+            StatusCode::NOT_FOUND => Err(Error::NotFound),
+            status if status.is_success() => Ok(()),
+            _ => Err(Error::Jellyfin(format!(
+                "Failed to delete library: Name = {}, {}",
+                name,
+                resp.text().await?,
+            ))),
+        }
+    }
+
+    /// Restricts the user to exactly `folders`, switching off access to all libraries. Returns
+    /// the policy as it was sent to Jellyfin.
+    #[tracing::instrument]
+    pub(crate) async fn set_user_media_folders(&self, user: &User, folders: &[String]) -> Result<UserPolicy, Error> {
         let mut policy = user.policy.clone();
-        policy["EnabledFolders"] = serde_json::to_value(folders)?;
-        let path = format!("/Users/{}/Policy", user.id);
+        policy.enable_all_folders = false;
+        policy.enabled_folders = folders.to_vec();
+        self.set_user_policy(&user.id, &policy).await?;
+        Ok(policy)
+    }
+
+    /// Gives the user access to every library, including ones added later.
+    #[tracing::instrument]
+    pub(crate) async fn set_user_all_folders(&self, user: &User) -> Result<UserPolicy, Error> {
+        let mut policy = user.policy.clone();
+        policy.enable_all_folders = true;
+        policy.enabled_folders.clear();
+        self.set_user_policy(&user.id, &policy).await?;
+        Ok(policy)
+    }
+
+    /// Overwrites individual policy fields, keyed by their Jellyfin names, leaving the rest of
+    /// the user's policy untouched. Returns the policy as it was sent to Jellyfin.
+    #[tracing::instrument]
+    pub(crate) async fn update_policy_fields(
+        &self,
+        user: &User,
+        fields: serde_json::Map<String, serde_json::Value>,
+    ) -> Result<UserPolicy, Error> {
+        let serde_json::Value::Object(mut policy) = serde_json::to_value(&user.policy)? else {
+            unreachable!("policies serialize to JSON objects");
+        };
+        policy.extend(fields);
+        let policy: UserPolicy = serde_json::from_value(serde_json::Value::Object(policy))?;
+        self.set_user_policy(&user.id, &policy).await?;
+        Ok(policy)
+    }
+
+    #[tracing::instrument]
+    pub(crate) async fn set_user_policy(&self, user_id: &str, policy: &UserPolicy) -> Result<(), Error> {
+        let path = format!("/Users/{}/Policy", user_id);
         let resp = self
-            .base_request(&reqwest::Client::new(), Method::POST, &path)?
-            .json(&policy)
-            .send()
+            .base_request(Method::POST, &path)?
+            .json(policy)
+            .send_traced(&self.breaker)
             .await?;
         if resp.status().is_success() {
             Ok(())
         } else {
-            Err(Error::JellyfinError(format!(
-                "Failed to set media folders: User id = {}, {}",
-                user.id,
+            Err(Error::Jellyfin(format!(
+                "Failed to set user policy: User id = {}, {}",
+                user_id,
                 resp.text().await?,
             )))
         }
     }
 }
 
-fn to_json_array<T: Serialize>(items: &[T]) -> Result<serde_json::Value, serde_json::Error> {
-    let mut values = Vec::new();
-    for item in items {
-        values.push(serde_json::to_value(item)?);
+/// The Jellyfin servers being managed, in configuration order. The first is the default.
+#[derive(Debug, Clone, Default)]
+pub struct JellyfinServers {
+    servers: Vec<(String, Arc<MediaServer>)>,
+}
+
+impl JellyfinServers {
+    /// Adds the server reached through `client`, running the software `kind`.
+    pub fn insert(&mut self, name: String, kind: ServerKind, client: JellyfinClient) -> anyhow::Result<()> {
+        if self.get(&name).is_some() {
+            anyhow::bail!("duplicate Jellyfin server name: {}", name);
+        }
+        self.servers.push((name, Arc::new(MediaServer::new(kind, client))));
+        Ok(())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.servers.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.servers.len()
+    }
+
+    pub(crate) fn get(&self, name: &str) -> Option<&Arc<MediaServer>> {
+        self.servers.iter().find(|(n, _)| n == name).map(|(_, server)| server)
+    }
+
+    pub(crate) fn default_server(&self) -> Option<(&str, &Arc<MediaServer>)> {
+        self.servers.first().map(|(name, server)| (name.as_str(), server))
+    }
+
+    pub(crate) fn iter(&self) -> impl Iterator<Item = (&str, &Arc<MediaServer>)> {
+        self.servers.iter().map(|(name, server)| (name.as_str(), server))
     }
-    Ok(serde_json::Value::Array(values))
 }
@@ -0,0 +1,167 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::collection::Error;
+use crate::jellyfin_api::{JellyfinClient, MediaFolders, User};
+use crate::media_server::MediaServer;
+
+/// Users, media folders and movie item ids of each Jellyfin server, kept for `ttl` so pages
+/// don't ask Jellyfin for them on every click. Changes made through tagrs invalidate them right away.
+/// While Jellyfin is unreachable, the last known ones are served and marked stale.
+#[derive(Debug)]
+pub struct JellyfinCache {
+    ttl: Duration,
+    servers: Mutex<HashMap<String, Cached>>,
+}
+
+/// Jellyfin item ids by movie folder.
+type ItemIds = HashMap<PathBuf, String>;
+
+#[derive(Debug, Default)]
+struct Cached {
+    /// Bumped on invalidation, so fetches started before it aren't stored
+    generation: u64,
+    /// With when they were fetched, or `None` once they're known to be outdated
+    users: Option<(Option<Instant>, Vec<User>)>,
+    folders: Option<(Option<Instant>, Vec<MediaFolders>)>,
+    /// Ids of the movie items by the folder they are stored in
+    items: Option<(Option<Instant>, Arc<ItemIds>)>,
+    /// Whether expired data was served because Jellyfin didn't answer
+    stale: bool,
+}
+
+impl JellyfinCache {
+    pub fn new(ttl: Duration) -> Self {
+        Self { ttl, servers: Mutex::new(HashMap::new()) }
+    }
+
+    pub(crate) async fn users(&self, server: &str, api: &MediaServer) -> Result<Vec<User>, Error> {
+        let generation = {
+            let mut servers = self.servers.lock().unwrap();
+            let cached = servers.entry(server.to_string()).or_default();
+            if let Some(users) = self.fresh(&cached.users) {
+                return Ok(users);
+            }
+            cached.generation
+        };
+        match api.get_users().await {
+            Ok(users) => {
+                self.store(server, generation, |cached| cached.users = Some((Some(Instant::now()), users.clone())));
+                Ok(users)
+            }
+            Err(e) => self.fallback(server, e, |cached| cached.users.as_ref().map(|(_, users)| users.clone())),
+        }
+    }
+
+    pub(crate) async fn media_folders(&self, server: &str, api: &MediaServer) -> Result<Vec<MediaFolders>, Error> {
+        let generation = {
+            let mut servers = self.servers.lock().unwrap();
+            let cached = servers.entry(server.to_string()).or_default();
+            if let Some(folders) = self.fresh(&cached.folders) {
+                return Ok(folders);
+            }
+            cached.generation
+        };
+        match api.get_libraries().await {
+            Ok(folders) => {
+                self.store(server, generation, |cached| cached.folders = Some((Some(Instant::now()), folders.clone())));
+                Ok(folders)
+            }
+            Err(e) => self.fallback(server, e, |cached| cached.folders.as_ref().map(|(_, folders)| folders.clone())),
+        }
+    }
+
+    /// Jellyfin's id of the movie stored in `folder` as Jellyfin sees it, if it knew the movie
+    /// when its movies were last fetched.
+    pub(crate) async fn movie_id(&self, server: &str, api: &JellyfinClient, folder: &Path) -> Result<Option<String>, Error> {
+        Ok(self.movie_ids(server, api).await?.get(folder).cloned())
+    }
+
+    /// Jellyfin's ids of the movies by the folder they are stored in, as Jellyfin sees it.
+    async fn movie_ids(&self, server: &str, api: &JellyfinClient) -> Result<Arc<ItemIds>, Error> {
+        let generation = {
+            let mut servers = self.servers.lock().unwrap();
+            let cached = servers.entry(server.to_string()).or_default();
+            if let Some(items) = self.fresh(&cached.items) {
+                return Ok(items);
+            }
+            cached.generation
+        };
+        match api.get_movies().await {
+            Ok(movies) => {
+                let items: Arc<ItemIds> = Arc::new(
+                    movies
+                        .into_iter()
+                        .filter_map(|item| Some((item.folder()?.to_path_buf(), item.id)))
+                        .collect(),
+                );
+                self.store(server, generation, |cached| cached.items = Some((Some(Instant::now()), items.clone())));
+                Ok(items)
+            }
+            Err(e) => self.fallback(server, e, |cached| cached.items.as_ref().map(|(_, items)| items.clone())),
+        }
+    }
+
+    /// Whether data of `server` was served from before Jellyfin became unreachable.
+    pub(crate) fn is_stale(&self, server: &str) -> bool {
+        self.servers.lock().unwrap().get(server).is_some_and(|cached| cached.stale)
+    }
+
+    /// Marks what is known about `server` as outdated after changing its users, libraries or
+    /// items.
+    /// It is still served if Jellyfin becomes unreachable.
+    pub(crate) fn invalidate(&self, server: &str) {
+        let mut servers = self.servers.lock().unwrap();
+        let cached = servers.entry(server.to_string()).or_default();
+        cached.generation += 1;
+        if let Some((fetched, _)) = &mut cached.users {
+            *fetched = None;
+        }
+        if let Some((fetched, _)) = &mut cached.folders {
+            *fetched = None;
+        }
+        if let Some((fetched, _)) = &mut cached.items {
+            *fetched = None;
+        }
+    }
+
+    pub(crate) fn invalidate_all(&self) {
+        let servers: Vec<String> = self.servers.lock().unwrap().keys().cloned().collect();
+        for server in servers {
+            self.invalidate(&server);
+        }
+    }
+
+    fn fresh<T: Clone>(&self, entry: &Option<(Option<Instant>, T)>) -> Option<T> {
+        entry
+            .as_ref()
+            .filter(|(fetched, _)| fetched.is_some_and(|fetched| fetched.elapsed() < self.ttl))
+            .map(|(_, value)| value.clone())
+    }
+
+    fn store(&self, server: &str, generation: u64, update: impl FnOnce(&mut Cached)) {
+        let mut servers = self.servers.lock().unwrap();
+        let cached = servers.entry(server.to_string()).or_default();
+        cached.stale = false;
+        if cached.generation == generation {
+            update(cached);
+        }
+    }
+
+    /// The last known data from `last` if `error` means Jellyfin is unreachable, else `error`.
+    fn fallback<T>(&self, server: &str, error: Error, last: impl FnOnce(&Cached) -> Option<T>) -> Result<T, Error> {
+        if !matches!(error, Error::JellyfinUnreachable(_)) {
+            return Err(error);
+        }
+        let mut servers = self.servers.lock().unwrap();
+        let cached = servers.entry(server.to_string()).or_default();
+        let Some(value) = last(cached) else {
+            return Err(error);
+        };
+        tracing::debug!("serving stale data of {}: {}", server, error);
+        cached.stale = true;
+        Ok(value)
+    }
+}
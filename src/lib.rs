@@ -1,89 +1,972 @@
+use std::convert::Infallible;
 use std::str::FromStr;
 use std::sync::Arc;
 
-use axum::http::Request;
+use axum::extract::{DefaultBodyLimit, FromRequestParts};
+use axum::http::request::Parts;
+use axum::http::{HeaderName, Request};
 use axum::response::IntoResponse;
-use axum::routing::{get, post};
+use axum::routing::{delete, get, post, put};
 use axum::Router;
-use axum_insights::AppInsightsError;
-use clap::Parser;
+use tower::ServiceExt;
+use clap::{ArgGroup, Args, CommandFactory, Parser, Subcommand};
+use percent_encoding::{utf8_percent_encode, NON_ALPHANUMERIC};
 use serde::{Deserialize, Serialize};
-use tokio::io::AsyncReadExt;
-use tokio::sync::RwLock;
+use tokio::sync::{RwLock, RwLockWriteGuard};
+use tokio_util::io::ReaderStream;
+use tower_http::compression::CompressionLayer;
+use tower_http::limit::RequestBodyLimitLayer;
 use tower_http::services::ServeDir;
+use tower_http::timeout::TimeoutLayer;
 use tower_http::trace::TraceLayer;
 
+mod api;
+mod artwork;
+mod assets;
+mod audit;
 mod collection;
+mod config;
+mod errors;
+mod graphql;
+mod i18n;
+mod import;
+mod insights;
+mod jellyfin_cache;
+mod limits;
+mod logging;
+mod media_info;
+mod media_server;
+mod nfo;
+mod overrides;
+mod poster_cache;
+mod preview;
+mod radarr;
+mod refresh;
+mod reload;
+mod roles;
+mod rules;
+mod scheduler;
+mod signing;
+mod snapshots;
+mod stats;
+mod storage;
+mod systemd;
+mod tag_sync;
 mod templates;
+mod theme;
+mod thumbnails;
+mod trakt;
+mod updates;
+mod views;
+mod watched;
+mod webhooks;
 pub mod jellyfin_api;
 
-pub use collection::Collection;
+pub use artwork::RemoteArtwork;
+pub use audit::AuditLog;
+pub use collection::{Collection, IdentityMode, LinkMode};
+pub use config::{Config, JellyfinTagsConfig, LimitsConfig, PathMapping, PathMappings, Preset, RolesConfig, ThemeConfig, UserLibrariesConfig, WebhookConfig};
+pub use i18n::Translations;
+pub use insights::Insights;
+pub use limits::ChangeLimiter;
+pub use logging::{init_logging, LogFormat, LogRotation, Logging};
+pub use jellyfin_api::JellyfinServers;
+pub use jellyfin_cache::JellyfinCache;
+pub use media_info::MediaInfoCache;
+pub use media_server::ServerKind;
+pub use overrides::TemplateOverrides;
+pub use poster_cache::PosterCache;
+pub use radarr::{Radarr, RadarrClient};
+pub use trakt::Trakt;
+pub use refresh::LibraryRefresh;
+pub use signing::CookieKey;
+pub use reload::Reloads;
+pub use roles::Role;
+pub use rules::Rule;
+pub use scheduler::ScheduledJob;
+pub use systemd::{activated_socket, notify_ready, ActivatedSocket};
 use collection::Error;
 use collection::PathnameHash;
+use collection::ScanProgress;
+use media_server::MediaServer;
+use snapshots::Snapshot;
 use templates::MISSING_POSTER;
+pub use thumbnails::Thumbnailer;
+pub use updates::Updates;
+pub use views::View;
+pub use webhooks::Webhooks;
 
 /// Admin dashboard for managing your Jellyfin collection
 #[derive(Debug, Parser)]
 #[command(version, about)]
+#[command(subcommand_negates_reqs = true, args_conflicts_with_subcommands = true)]
 pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+    /// Running the server, when no subcommand is given
+    #[command(flatten)]
+    pub serve: Option<ServeArgs>,
+}
+
+/// Tools around tagrs that exit instead of serving
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Print a completion script for the shell, e.g. `tagrs completions bash`
+    Completions { shell: clap_complete::Shell },
+    /// Print the man page in roff, e.g. `tagrs man > tagrs.1`
+    Man,
+}
+
+impl Command {
+    pub fn run(&self) -> anyhow::Result<()> {
+        let mut cli = Cli::command();
+        let mut out = std::io::stdout().lock();
+        match self {
+            Command::Completions { shell } => {
+                let name = cli.get_name().to_string();
+                clap_complete::generate(*shell, &mut cli, name, &mut out);
+            }
+            Command::Man => clap_mangen::Man::new(cli).render(&mut out)?,
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Args)]
+#[command(group(ArgGroup::new("jellyfin_key").args(["jellyfin_api_key", "jellyfin_api_key_file"])))]
+#[command(group(ArgGroup::new("app_insights").args(["app_insights_connection_string", "app_insights_connection_string_file"])))]
+#[command(group(ArgGroup::new("cookie_key").args(["cookie_secret", "cookie_secret_file"])))]
+pub struct ServeArgs {
+    /// Address to listen on, or a unix socket like `unix:/run/tagrs.sock`
     #[clap(short, long, default_value = "127.0.0.1:3000")]
     pub bind: String,
+    /// Permissions of the unix socket, in octal like 660
+    #[clap(long, env, value_parser = parse_mode)]
+    pub socket_mode: Option<u32>,
+    /// Path the dashboard is served below by a reverse proxy, like `/tagrs`
+    #[clap(long, env, value_parser = parse_base_path, default_value = "")]
+    pub base_path: String,
+    /// PEM certificate chain to serve HTTPS with, reloaded when the file changes
+    #[clap(long, env, requires = "tls_key")]
+    pub tls_cert: Option<std::path::PathBuf>,
+    /// PEM private key of the certificate
+    #[clap(long, env, requires = "tls_cert")]
+    pub tls_key: Option<std::path::PathBuf>,
     #[clap(short, long, env)]
     pub movie_dir: String,
     #[clap(short, long, env)]
     pub tag_dir: String,
+    /// How movies are placed into tag directories
+    #[clap(long, env, value_enum, default_value_t)]
+    pub link_mode: LinkMode,
+    /// How movies are identified, `file` keeps tags and URLs working across folder renames
+    #[clap(long, env, value_enum, default_value_t)]
+    pub identity: IdentityMode,
     #[clap(short, long, default_value = "info")]
     pub log_level: tracing::Level,
-    #[clap(short = 'j', long, env)]
-    pub jellyfin_base_url: String,
-    #[clap(short = 'a', long, env)]
-    pub jellyfin_api_key: String,
+    /// How log lines are written
+    #[clap(long, env, value_enum, default_value_t)]
+    pub log_format: LogFormat,
+    /// Also write the log to this file, with the date appended and rotated
+    #[clap(long, env)]
+    pub log_file: Option<std::path::PathBuf>,
+    /// How often the log file is rotated
+    #[clap(long, env, value_enum, default_value_t)]
+    pub log_rotation: LogRotation,
+    /// OpenTelemetry collector receiving traces over OTLP/HTTP, e.g. http://tempo:4318
+    #[clap(long, env)]
+    pub otlp_endpoint: Option<String>,
+    /// Connection string of an Azure Application Insights resource receiving request telemetry
+    #[clap(long, env = "APPLICATIONINSIGHTS_CONNECTION_STRING", hide_env_values = true)]
+    pub app_insights_connection_string: Option<String>,
+    /// Read the Application Insights connection string from a file, e.g. a Docker secret
+    #[clap(long, env = "APPLICATIONINSIGHTS_CONNECTION_STRING_FILE")]
+    pub app_insights_connection_string_file: Option<String>,
+    #[clap(short = 'j', long, env, requires = "jellyfin_key")]
+    pub jellyfin_base_url: Option<String>,
+    #[clap(short = 'a', long, env, requires = "jellyfin_base_url", hide_env_values = true)]
+    pub jellyfin_api_key: Option<String>,
+    /// Read the Jellyfin API key from a file, e.g. a Docker secret
+    #[clap(long, env, requires = "jellyfin_base_url")]
+    pub jellyfin_api_key_file: Option<String>,
+    /// Whether the server at --jellyfin-base-url runs Jellyfin or Emby
+    #[clap(long, env, value_enum, default_value_t, requires = "jellyfin_base_url")]
+    pub server_kind: ServerKind,
+    /// Address of the Jellyfin web UI for links opened in the browser [default: --jellyfin-base-url]
+    #[clap(long, env, requires = "jellyfin_base_url")]
+    pub jellyfin_public_url: Option<String>,
+    /// User of the default Jellyfin server whose played movies are marked as watched [default: the
+    /// first administrator]
+    #[clap(long, env)]
+    pub watched_user: Option<String>,
+    /// TOML file with additional settings, such as extra Jellyfin servers
+    #[clap(short, long, env = "TAGRS_CONFIG")]
+    pub config: Option<String>,
+    /// Total time allowed for a Jellyfin or Radarr request, e.g. "30s"
+    #[clap(long, env, default_value = "30s")]
+    pub jellyfin_timeout: humantime::Duration,
+    /// Time allowed to establish a connection to Jellyfin or Radarr
+    #[clap(long, env, default_value = "5s")]
+    pub jellyfin_connect_timeout: humantime::Duration,
+    /// Directory for generated poster thumbnails [default: <tmp>/tagrs]
+    #[clap(long, env)]
+    pub cache_dir: Option<String>,
+    /// Start a Jellyfin library scan after tags have been changed
+    #[clap(long, env)]
+    pub auto_refresh: bool,
+    /// How long to wait after the last tag change before scanning
+    #[clap(long, env, default_value = "10s")]
+    pub auto_refresh_delay: humantime::Duration,
+    /// How long Jellyfin users and media folders are reused before asking Jellyfin again
+    #[clap(long, env, default_value = "30s")]
+    pub jellyfin_cache_ttl: humantime::Duration,
+    /// Time allowed to handle a request before answering 408, e.g. "60s"
+    #[clap(long, env, default_value = "60s")]
+    pub request_timeout: humantime::Duration,
+    /// Largest request body accepted, in bytes
+    #[clap(long, env, default_value_t = 1024 * 1024)]
+    pub max_body_size: usize,
+    /// Memory for keeping served posters, in MiB, or 0 to always read them from disk
+    #[clap(long, env, default_value_t = 100)]
+    pub poster_cache_size: usize,
+    /// Origins allowed to call the JSON API from a browser, comma separated, or "*" for any
+    #[clap(long, env, value_delimiter = ',')]
+    pub cors_allowed_origins: Vec<axum::http::HeaderValue>,
+    /// Language of the pages, e.g. "de", instead of following the browser's preference
+    #[clap(long, env)]
+    pub locale: Option<String>,
+    /// Directory of `<lang>.toml` files adding languages or overriding built-in strings
+    #[clap(long, env)]
+    pub locale_dir: Option<std::path::PathBuf>,
+    /// Directory of Jinja templates replacing parts of the pages, e.g. `movie.html`
+    #[clap(long, env)]
+    pub templates_dir: Option<std::path::PathBuf>,
+    /// Rescan the movie and tag directories this often, e.g. 15m
+    #[clap(long, env)]
+    pub reload_interval: Option<humantime::Duration>,
+    /// Create and delete a Jellyfin library along with each tag created or deleted here
+    #[clap(long, env)]
+    pub tag_libraries: bool,
+    /// Apply the tagging rules of the config file after every reload
+    #[clap(long, env)]
+    pub rules_on_reload: bool,
+    /// Serve Swagger UI for the JSON API at `/api/docs`
+    #[clap(long, env)]
+    pub swagger_ui: bool,
+    /// Token the Jellyfin Webhook plugin must pass as `?token=` to `/hooks/jellyfin`, which is
+    /// only served with one
+    #[clap(long, env)]
+    pub jellyfin_webhook_token: Option<String>,
+    /// ffprobe binary used to show resolution and codecs of movies
+    #[clap(long, env)]
+    pub ffprobe: Option<std::path::PathBuf>,
+    /// JSON lines file recording every change made through the dashboard
+    #[clap(long, env)]
+    pub audit_log: Option<String>,
+    /// Secret signing the cookies remembering how the movie list was shown, so they survive
+    /// restarts [default: a new one on every start]
+    #[clap(long, env, hide_env_values = true)]
+    pub cookie_secret: Option<String>,
+    /// Read the cookie secret from a file, e.g. a Docker secret
+    #[clap(long, env)]
+    pub cookie_secret_file: Option<String>,
+    /// Request header set by an authenticating reverse proxy that names the current user. Only
+    /// read with --trust-remote-user
+    #[clap(long, env, default_value = "Remote-User")]
+    pub user_header: axum::http::HeaderName,
+    /// Take the user named in --user-header at its word. Only set this when every request passes
+    /// through the proxy, which must replace the header sent by clients
+    #[clap(long, env)]
+    pub trust_remote_user: bool,
 }
 
-#[derive(Default, Serialize, Deserialize, Clone)]
-struct WebError {
-    message: String,
+fn parse_mode(mode: &str) -> Result<u32, String> {
+    u32::from_str_radix(mode, 8).map_err(|_| format!("{} is not an octal mode like 660", mode))
 }
 
-impl IntoResponse for WebError {
-    fn into_response(self) -> axum::http::Response<axum::body::Body> {
-        axum::http::Response::builder()
-            .status(400)
-            .header("content-type", "text/plain")
-            .body(self.message.into())
-            .unwrap()
+/// `/tagrs/` and `tagrs` become `/tagrs`, `/` becomes empty.
+fn parse_base_path(path: &str) -> Result<String, String> {
+    let path = path.trim_matches('/');
+    if path.is_empty() {
+        return Ok(String::new());
+    }
+    let path = format!("/{}", path);
+    match path.parse::<axum::http::uri::PathAndQuery>() {
+        Ok(parsed) if parsed.query().is_none() => Ok(path),
+        _ => Err(format!("{} is not a URL path", path)),
     }
 }
 
-impl AppInsightsError for WebError {
-    fn message(&self) -> Option<String> {
-        Some(self.message.clone())
+impl ServeArgs {
+    pub async fn jellyfin_api_key(&self) -> anyhow::Result<Option<String>> {
+        match (&self.jellyfin_api_key, &self.jellyfin_api_key_file) {
+            (Some(api_key), _) => Ok(Some(api_key.clone())),
+            (None, Some(path)) => Ok(Some(config::read_secret(path).await?)),
+            (None, None) => Ok(None),
+        }
+    }
+
+    pub async fn cookie_secret(&self) -> anyhow::Result<Option<String>> {
+        match (&self.cookie_secret, &self.cookie_secret_file) {
+            (Some(secret), _) => Ok(Some(secret.clone())),
+            (None, Some(path)) => Ok(Some(config::read_secret(path).await?)),
+            (None, None) => Ok(None),
+        }
+    }
+
+    pub async fn app_insights_connection_string(&self) -> anyhow::Result<Option<String>> {
+        match (&self.app_insights_connection_string, &self.app_insights_connection_string_file) {
+            (Some(connection_string), _) => Ok(Some(connection_string.clone())),
+            (None, Some(path)) => Ok(Some(config::read_secret(path).await?)),
+            (None, None) => Ok(None),
+        }
     }
 
-    fn backtrace(&self) -> Option<String> {
-        None
+    pub fn cache_dir(&self) -> std::path::PathBuf {
+        match &self.cache_dir {
+            Some(dir) => dir.into(),
+            None => std::env::temp_dir().join("tagrs"),
+        }
     }
 }
 
 #[derive(Debug, Clone)]
 pub struct AppState {
     collection: Arc<RwLock<Collection>>,
-    jellyfin_api: Arc<jellyfin_api::JellyfinClient>,
+    jellyfin: Arc<JellyfinServers>,
+    jellyfin_cache: Arc<JellyfinCache>,
+    thumbnailer: Arc<Thumbnailer>,
+    posters: Arc<PosterCache>,
+    artwork: Arc<RemoteArtwork>,
+    audit: Arc<AuditLog>,
+    user_header: Option<HeaderName>,
+    library_refresh: Option<Arc<LibraryRefresh>>,
+    media_info: Arc<MediaInfoCache>,
+    folder_sizes: Arc<stats::FolderSizes>,
+    radarr: Option<Arc<Radarr>>,
+    trakt: Option<Arc<Trakt>>,
+    watched: Option<Arc<watched::Watched>>,
+    tag_libraries: bool,
+    presets: Arc<Vec<Preset>>,
+    user_libraries: Arc<UserLibrariesConfig>,
+    limiter: Arc<ChangeLimiter>,
+    request_timeout: std::time::Duration,
+    max_body_size: usize,
+    cors_origins: Vec<axum::http::HeaderValue>,
+    swagger_ui: bool,
+    base_path: String,
+    insights: Option<Insights>,
+    translations: Arc<Translations>,
+    theme: Arc<ThemeConfig>,
+    overrides: Option<Arc<TemplateOverrides>>,
+    roles: Arc<RolesConfig>,
+    views: Arc<Vec<View>>,
+    rules: Arc<Vec<Rule>>,
+    rules_on_reload: bool,
+    jellyfin_tags: JellyfinTagsConfig,
+    updates: Updates,
+    reloads: Reloads,
+    jellyfin_webhook_token: Option<String>,
+    cookie_key: CookieKey,
 }
 
 impl AppState {
-    pub fn new(collection: Collection, jellyfin_api: jellyfin_api::JellyfinClient) -> Self {
+    pub fn new(collection: Collection, jellyfin: JellyfinServers) -> Self {
+        let cache_dir = std::env::temp_dir().join("tagrs");
+        let updates = Updates::default();
         Self {
             collection: Arc::new(RwLock::new(collection)),
-            jellyfin_api: Arc::new(jellyfin_api),
+            jellyfin: Arc::new(jellyfin),
+            jellyfin_cache: Arc::new(JellyfinCache::new(std::time::Duration::ZERO)),
+            thumbnailer: Arc::new(Thumbnailer::new(&cache_dir)),
+            posters: Arc::new(PosterCache::new(0)),
+            artwork: Arc::new(RemoteArtwork::new(&cache_dir)),
+            audit: Arc::new(AuditLog::default().notifying(updates.clone())),
+            user_header: None,
+            library_refresh: None,
+            media_info: Arc::new(MediaInfoCache::default()),
+            folder_sizes: Arc::new(stats::FolderSizes::default()),
+            radarr: None,
+            trakt: None,
+            watched: None,
+            tag_libraries: false,
+            presets: Arc::new(Vec::new()),
+            user_libraries: Arc::default(),
+            limiter: Arc::new(ChangeLimiter::default()),
+            request_timeout: std::time::Duration::from_secs(60),
+            max_body_size: 1024 * 1024,
+            cors_origins: Vec::new(),
+            swagger_ui: false,
+            base_path: String::new(),
+            insights: None,
+            translations: Arc::new(Translations::default()),
+            theme: Arc::new(ThemeConfig::default()),
+            overrides: None,
+            roles: Arc::new(RolesConfig::default()),
+            views: Arc::new(Vec::new()),
+            rules: Arc::new(Vec::new()),
+            rules_on_reload: false,
+            jellyfin_tags: JellyfinTagsConfig::default(),
+            updates,
+            reloads: Reloads::default(),
+            jellyfin_webhook_token: None,
+            cookie_key: CookieKey::random(),
+        }
+    }
+
+    /// Scan the Jellyfin library `delay` after the last tag change.
+    pub fn with_auto_refresh(mut self, delay: std::time::Duration) -> Self {
+        self.library_refresh = Some(Arc::new(LibraryRefresh::spawn(self.jellyfin.clone(), delay)));
+        self
+    }
+
+    /// Reloads the collection in the background of requests, see [`AppState::rescan_collection`].
+    /// Does nothing if a reload is running already.
+    pub async fn reload_collection(&self) -> Result<(), Error> {
+        let reloaded = self.reloads.run(|progress| async move { self.rescan_collection(&progress).await });
+        reloaded.await.unwrap_or(Ok(()))
+    }
+
+    /// Locks the collection to change it, counting the change so a running reload knows its
+    /// scan is out of date.
+    pub(crate) async fn collection_mut(&self) -> RwLockWriteGuard<'_, Collection> {
+        let mut collection = self.collection.write().await;
+        collection.generation += 1;
+        collection
+    }
+
+    /// Rescans the movie and tag directories without holding the collection lock, then swaps
+    /// in the result, counting scanned movies and tags in `progress`. If the collection changed
+    /// during the scan, it scans again, and the last attempt holds the lock to not lose changes.
+    pub(crate) async fn rescan_collection(&self, progress: &ScanProgress) -> Result<(), Error> {
+        let mut attempt = 1;
+        loop {
+            let (generation, rescan) = {
+                let collection = self.collection.read().await;
+                (collection.generation, collection.rescan(progress))
+            };
+            let fresh = rescan.await?;
+            let mut collection = self.collection.write().await;
+            if collection.generation == generation {
+                *collection = fresh;
+            } else if attempt < RELOAD_ATTEMPTS {
+                tracing::debug!("collection changed during reload attempt {}, scanning again", attempt);
+                attempt += 1;
+                progress.reset();
+                continue;
+            } else {
+                progress.reset();
+                *collection = collection.rescan(progress).await?;
+            }
+            tracing::debug!("Reloaded collections: {}", collection);
+            break;
+        }
+        self.artwork.invalidate().await;
+        if let Some(radarr) = &self.radarr {
+            radarr.invalidate().await;
+        }
+        if let Some(watched) = &self.watched {
+            watched.invalidate().await;
+        }
+        match self.sync_jellyfin_tags().await {
+            // the tags are synced on the next reload instead
+            Err(Error::JellyfinUnreachable(e)) => tracing::warn!("skipping the Jellyfin tag sync: {}", e),
+            result => result?,
+        }
+        if self.rules_on_reload {
+            self.apply_rules(RULES_ACTOR).await?;
+        }
+        self.updates.notify(updates::Update::Collection);
+        Ok(())
+    }
+
+    /// Picks up movie folders added or removed since the last scan, looking at the disk without
+    /// holding the collection lock. New folders need IDs with `--identity file`, so that mode
+    /// reloads everything instead.
+    pub(crate) async fn refresh_movies(&self) -> Result<(), Error> {
+        let (storage, identity, known) = {
+            let collection = self.collection.read().await;
+            (collection.storage(), collection.identity, collection.movies.keys().copied().collect())
+        };
+        if identity != IdentityMode::Name {
+            return self.reload_collection().await;
+        }
+        let changes = Collection::scan_movie_changes(&*storage, &known).await?;
+        if self.collection_mut().await.apply_movie_changes(changes) {
+            if self.rules_on_reload {
+                self.apply_rules(RULES_ACTOR).await?;
+            }
+            self.updates.notify(updates::Update::Collection);
+        }
+        Ok(())
+    }
+
+    /// Copies tags between the collection and the items of the default Jellyfin server as
+    /// configured, recording the changes as made by Jellyfin.
+    pub(crate) async fn sync_jellyfin_tags(&self) -> Result<(), Error> {
+        let JellyfinTagsConfig { sync, conflict } = self.jellyfin_tags;
+        if sync == tag_sync::SyncDirection::Off {
+            return Ok(());
+        }
+        let Some((_, api)) = self.jellyfin.default_server() else {
+            return Ok(());
+        };
+        let items = api.client().get_movies().await?;
+        let (pulled, created, pushes) = {
+            let mut collection = self.collection_mut().await;
+            let (pulls, pushes) = tag_sync::plan(&collection, &items, sync, conflict);
+            let (pulled, created) = tag_sync::apply(&mut collection, pulls).await?;
+            (pulled, created, pushes)
+        };
+        for tag in created {
+            self.audit.record(JELLYFIN_ACTOR, audit::Action::TagCreated { tag }).await?;
+        }
+        for pull in &pulled {
+            let (tag, movie) = (pull.tag.clone(), pull.movie_name.clone());
+            let action = if pull.add {
+                audit::Action::TagAdded { tag, movie }
+            } else {
+                audit::Action::TagRemoved { tag, movie }
+            };
+            self.audit.record(JELLYFIN_ACTOR, action).await?;
+        }
+        for push in &pushes {
+            api.client().set_item_tags(&push.item_id, &push.tags).await?;
+            let action = audit::Action::JellyfinTagsSet { movie: push.movie_name.clone(), tags: push.tags.clone() };
+            self.audit.record(JELLYFIN_ACTOR, action).await?;
+        }
+        if !pulled.is_empty() || !pushes.is_empty() {
+            tracing::info!("synced Jellyfin tags: {} pulled, {} items updated", pulled.len(), pushes.len());
+        }
+        if !pulled.is_empty() {
+            if let Some(library_refresh) = &self.library_refresh {
+                library_refresh.schedule();
+            }
+        }
+        Ok(())
+    }
+
+    /// Tags the rules would add right now.
+    async fn plan_rules(&self) -> Vec<rules::Change> {
+        let sources = self.movie_sources().await;
+        let collection = self.collection.read().await;
+        rules::plan(&self.rules, &collection, |movie| sources.media_info(movie).and_then(|m| m.resolution()))
+    }
+
+    /// Applies the tagging rules, recording the changes as made by `actor`.
+    pub(crate) async fn apply_rules(&self, actor: &str) -> Result<Vec<rules::Change>, Error> {
+        let sources = self.movie_sources().await;
+        let changes = {
+            let collection = self.collection.read().await;
+            rules::plan(&self.rules, &collection, |movie| sources.media_info(movie).and_then(|m| m.resolution()))
+        };
+        let applied = self.apply_changes(actor, changes).await?;
+        if !applied.is_empty() {
+            tracing::info!("tagging rules made {} changes", applied.len());
+        }
+        Ok(applied)
+    }
+
+    /// Tags movies as planned by rules or an import, creating missing tags along with their
+    /// Jellyfin libraries. Returns the changes made.
+    async fn apply_changes(&self, actor: &str, changes: Vec<rules::Change>) -> Result<Vec<rules::Change>, Error> {
+        let (applied, created) = rules::apply(&mut *self.collection_mut().await, changes).await?;
+        for tag in created {
+            self.audit.record(actor, audit::Action::TagCreated { tag: tag.clone() }).await?;
+            if self.tag_libraries {
+                let library = collection::tag_library_name(&tag);
+                let tag_path = {
+                    let collection = self.collection.read().await;
+                    collection.path_mappings.to_jellyfin(&collection.tag_dir.join(&tag))
+                };
+                let Some((server, api)) = self.jellyfin.default_server() else {
+                    continue;
+                };
+                api.client().add_virtual_folder(&library, &tag_path.to_string_lossy()).await?;
+                self.jellyfin_cache.invalidate(server);
+                self.audit.record(actor, audit::Action::TagLibraryCreated { tag, library }).await?;
+            }
+        }
+        for change in &applied {
+            let action = audit::Action::TagAdded { tag: change.tag.clone(), movie: change.movie_name.clone() };
+            self.audit.record(actor, action).await?;
+        }
+        if !applied.is_empty() {
+            if let Some(library_refresh) = &self.library_refresh {
+                library_refresh.schedule();
+            }
+        }
+        Ok(applied)
+    }
+
+    /// The libraries shown on the user libraries page.
+    async fn listed_folders(&self, folders: &[jellyfin_api::MediaFolders]) -> Vec<jellyfin_api::MediaFolders> {
+        self.user_libraries.arrange(&*self.collection.read().await, folders)
+    }
+
+    fn view(&self, name: &str) -> Result<Arc<View>, Error> {
+        let view = self.views.iter().find(|view| view.name == name).ok_or(Error::NotFound)?;
+        Ok(Arc::new(view.clone()))
+    }
+
+    /// Snapshot of the per-movie details from optional integrations, for rendering movie cards.
+    /// An unreachable Radarr or Jellyfin only hides its details.
+    async fn movie_sources(&self) -> templates::MovieSources {
+        let radarr = match &self.radarr {
+            Some(radarr) => radarr
+                .index()
+                .await
+                .inspect_err(|e| tracing::warn!("failed to load Radarr movies: {}", e))
+                .ok(),
+            None => None,
+        };
+        let watched = match &self.watched {
+            Some(watched) => watched
+                .played()
+                .await
+                .inspect_err(|e| tracing::warn!("failed to load watched movies: {}", e))
+                .ok(),
+            None => None,
+        };
+        templates::MovieSources {
+            media: self.media_info.all().await,
+            radarr,
+            watched,
+        }
+    }
+
+    pub fn with_limits(mut self, limits: &LimitsConfig) -> Self {
+        self.limiter = Arc::new(ChangeLimiter::new(limits));
+        self
+    }
+
+    /// Bound how long a request may take and how large its body may be, so a stalled upstream
+    /// or an oversized upload can't tie up the server.
+    pub fn with_request_limits(mut self, timeout: std::time::Duration, max_body_size: usize) -> Self {
+        self.request_timeout = timeout;
+        self.max_body_size = max_body_size;
+        self
+    }
+
+    /// Keep up to `max_bytes` of posters in memory.
+    pub fn with_poster_cache(mut self, max_bytes: usize) -> Self {
+        self.posters = Arc::new(PosterCache::new(max_bytes));
+        self
+    }
+
+    pub fn with_cors_origins(mut self, origins: Vec<axum::http::HeaderValue>) -> Self {
+        self.cors_origins = origins;
+        self
+    }
+
+    pub fn with_translations(mut self, translations: Translations) -> Self {
+        self.translations = Arc::new(translations);
+        self
+    }
+
+    pub fn with_theme(mut self, theme: ThemeConfig) -> Self {
+        self.theme = Arc::new(theme);
+        self
+    }
+
+    pub fn with_template_overrides(mut self, overrides: TemplateOverrides) -> Self {
+        self.overrides = Some(Arc::new(overrides));
+        self
+    }
+
+    pub fn with_cache_dir<T: AsRef<std::path::Path>>(mut self, cache_dir: T) -> Self {
+        self.thumbnailer = Arc::new(Thumbnailer::new(&cache_dir));
+        self.artwork = Arc::new(RemoteArtwork::new(&cache_dir));
+        self
+    }
+
+    /// Reuse Jellyfin users and media folders for `ttl`.
+    pub fn with_jellyfin_cache_ttl(mut self, ttl: std::time::Duration) -> Self {
+        self.jellyfin_cache = Arc::new(JellyfinCache::new(ttl));
+        self
+    }
+
+    pub fn with_audit_log(mut self, audit: AuditLog) -> Self {
+        self.audit = Arc::new(audit.notifying(self.updates.clone()));
+        self
+    }
+
+    /// Probe movies with the given ffprobe binary, starting with the whole collection in the
+    /// background.
+    pub fn with_ffprobe<T: AsRef<std::path::Path>>(mut self, ffprobe: T) -> Self {
+        self.media_info = Arc::new(MediaInfoCache::new(ffprobe));
+        self.media_info.spawn_probe_all(self.collection.clone());
+        self
+    }
+
+    /// Manage a Jellyfin library for each tag created or deleted through the dashboard.
+    pub fn with_tag_libraries(mut self) -> Self {
+        self.tag_libraries = true;
+        self
+    }
+
+    pub fn with_presets(mut self, presets: Vec<Preset>) -> Self {
+        self.presets = Arc::new(presets);
+        self
+    }
+
+    pub fn with_user_libraries(mut self, user_libraries: UserLibrariesConfig) -> Self {
+        self.user_libraries = Arc::new(user_libraries);
+        self
+    }
+
+    pub fn with_radarr(mut self, radarr: Radarr) -> Self {
+        self.radarr = Some(Arc::new(radarr));
+        self
+    }
+
+    pub fn with_trakt(mut self, trakt: Trakt) -> Self {
+        self.trakt = Some(Arc::new(trakt));
+        self
+    }
+
+    /// Mark the movies played by `user` of the default Jellyfin server, or its first
+    /// administrator, on movie cards. They are fetched again after `ttl`.
+    pub fn with_watched(mut self, user: Option<String>, ttl: std::time::Duration) -> Self {
+        self.watched = self
+            .jellyfin
+            .default_server()
+            .map(|(_, server)| Arc::new(watched::Watched::new(server.client().clone(), user, ttl)));
+        self
+    }
+
+    pub fn with_user_header(mut self, user_header: HeaderName) -> Self {
+        self.user_header = Some(user_header);
+        self
+    }
+
+    pub fn with_roles(mut self, roles: RolesConfig) -> Self {
+        self.roles = Arc::new(roles);
+        self
+    }
+
+    pub fn with_views(mut self, views: Vec<View>) -> Self {
+        self.views = Arc::new(views);
+        self
+    }
+
+    pub fn with_rules(mut self, rules: Vec<Rule>) -> Self {
+        self.rules = Arc::new(rules);
+        self
+    }
+
+    /// Sync tags with the tags of Jellyfin items whenever the collection is reloaded.
+    pub fn with_jellyfin_tags(mut self, jellyfin_tags: JellyfinTagsConfig) -> Self {
+        self.jellyfin_tags = jellyfin_tags;
+        self
+    }
+
+    /// Runs each job whenever its cron expression matches, until the process exits.
+    pub fn start_scheduler(&self, jobs: Vec<ScheduledJob>) {
+        scheduler::spawn(self.clone(), jobs);
+    }
+
+    /// Require `token` from calls to the Jellyfin webhook receiver.
+    pub fn with_jellyfin_webhook_token(mut self, token: String) -> Self {
+        self.jellyfin_webhook_token = Some(token);
+        self
+    }
+
+    /// Signs cookies with `key` instead of one made up for this process.
+    pub fn with_cookie_key(mut self, key: CookieKey) -> Self {
+        self.cookie_key = key;
+        self
+    }
+
+    /// Serve everything below `base_path`, which is empty or starts with a slash.
+    pub fn with_base_path(mut self, base_path: String) -> Self {
+        self.base_path = base_path;
+        self
+    }
+
+    /// Report requests and failed responses to Application Insights.
+    pub fn with_insights(mut self, insights: Insights) -> Self {
+        self.insights = Some(insights);
+        self
+    }
+
+    /// Serve Swagger UI at `/api/docs`.
+    pub fn with_swagger_ui(mut self) -> Self {
+        self.swagger_ui = true;
+        self
+    }
+
+    /// Apply the tagging rules whenever the collection is reloaded.
+    pub fn with_rules_on_reload(mut self) -> Self {
+        self.rules_on_reload = true;
+        self
+    }
+}
+
+/// Name recorded in the audit log for tags given by rules on reload.
+const RULES_ACTOR: &str = "rules";
+
+/// Scans a reload makes without the collection lock before holding it for the last one.
+const RELOAD_ATTEMPTS: usize = 3;
+
+/// Name recorded in the audit log for changes made by syncing Jellyfin tags.
+const JELLYFIN_ACTOR: &str = "jellyfin";
+
+/// The user making a request, as reported by the authenticating reverse proxy.
+#[derive(Debug, Clone)]
+pub struct Actor(String);
+
+impl Actor {
+    const ANONYMOUS: &'static str = "anonymous";
+
+    pub fn name(&self) -> &str {
+        &self.0
+    }
+}
+
+#[axum::async_trait]
+impl FromRequestParts<AppState> for Actor {
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, state: &AppState) -> Result<Self, Self::Rejection> {
+        let name = state
+            .user_header
+            .as_ref()
+            .and_then(|header| parts.headers.get(header))
+            .and_then(|value| value.to_str().ok())
+            .filter(|value| !value.is_empty())
+            .unwrap_or(Self::ANONYMOUS);
+        Ok(Actor(name.to_string()))
+    }
+}
+
+/// Returns the value of the cookie called `name`, if the request carries it.
+fn cookie<'a>(headers: &'a axum::http::HeaderMap, name: &str) -> Option<&'a str> {
+    headers
+        .get_all(axum::http::header::COOKIE)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(';'))
+        .filter_map(|pair| pair.trim().split_once('='))
+        .find(|(key, _)| *key == name)
+        .map(|(_, value)| value)
+}
+
+/// `set-cookie` value keeping `name` for a year, or clearing it if `value` is `None`. It's only
+/// sent to pages below `base_path`, so apps sharing the host don't see it.
+fn set_cookie(base_path: &str, name: &str, value: Option<&str>) -> String {
+    let path = if base_path.is_empty() { "/" } else { base_path };
+    match value {
+        Some(value) => format!("{}={}; Path={}; SameSite=Lax; Max-Age=31536000", name, value, path),
+        None => format!("{}=; Path={}; SameSite=Lax; Max-Age=0", name, path),
+    }
+}
+
+const SERVER_COOKIE: &str = "jellyfin_server";
+
+/// The Jellyfin server picked with the server selector, or the default server.
+#[derive(Debug, Clone)]
+pub struct Jellyfin {
+    name: String,
+    server: Arc<MediaServer>,
+    /// The client of `server`, for the requests Jellyfin and Emby answer alike
+    api: Arc<jellyfin_api::JellyfinClient>,
+    cache: Arc<JellyfinCache>,
+}
+
+impl Jellyfin {
+    fn new(name: &str, server: &Arc<MediaServer>, cache: &Arc<JellyfinCache>) -> Self {
+        Jellyfin { name: name.to_string(), server: server.clone(), api: server.client().clone(), cache: cache.clone() }
+    }
+
+    async fn users(&self) -> Result<Vec<jellyfin_api::User>, Error> {
+        self.cache.users(&self.name, self.server.as_ref()).await
+    }
+
+    async fn media_folders(&self) -> Result<Vec<jellyfin_api::MediaFolders>, Error> {
+        self.cache.media_folders(&self.name, self.server.as_ref()).await
+    }
+
+    /// Id of the movie in the folder `path` as Jellyfin sees it. Movies unknown when the
+    /// movies of the server were cached are asked for, in case Jellyfin added them since.
+    async fn item_id(&self, path: &std::path::Path) -> Result<Option<String>, Error> {
+        if let Some(id) = self.cache.movie_id(&self.name, &self.api, path).await? {
+            return Ok(Some(id));
         }
+        Ok(self.api.find_item_by_path(path).await?.map(|item| item.id))
+    }
+
+    /// Whether the users and media folders are from before Jellyfin became unreachable.
+    fn is_stale(&self) -> bool {
+        self.cache.is_stale(&self.name)
+    }
+
+    /// Forgets the cached users and media folders after changing them.
+    fn invalidate(&self) {
+        self.cache.invalidate(&self.name);
+    }
+}
+
+#[axum::async_trait]
+impl FromRequestParts<AppState> for Jellyfin {
+    type Rejection = Error;
+
+    async fn from_request_parts(parts: &mut Parts, state: &AppState) -> Result<Self, Self::Rejection> {
+        let selected = cookie(&parts.headers, SERVER_COOKIE)
+            .and_then(|name| Some((name, state.jellyfin.get(name)?)));
+        let (name, server) = selected
+            .or_else(|| state.jellyfin.default_server())
+            .ok_or_else(|| anyhow::anyhow!("no Jellyfin server is configured"))?;
+        Ok(Jellyfin::new(name, server, &state.jellyfin_cache))
+    }
+}
+
+/// Routes needing a Jellyfin server, left out when none is configured.
+fn jellyfin_routes(state: &AppState) -> Router<AppState> {
+    if state.jellyfin.is_empty() {
+        return Router::new();
+    }
+    let router = Router::new()
+        .route("/tag/:tag/collection", post(routes::export_collection))
+        .route("/movie/:id/posters", get(routes::poster_candidates))
+        .route("/movie/:id/jellyfin", get(routes::open_in_jellyfin))
+        .route("/movie/:id/poster", post(routes::set_poster))
+        .route("/user-libraries", get(routes::user_libraries))
+        .route("/users", post(routes::create_user))
+        .route("/users/libraries", post(routes::batch_user_libraries))
+        .route("/user/:user_id/all-libraries", post(routes::toggle_all_user_libraries))
+        .route("/user/:user_id", delete(routes::delete_user))
+        .route("/user/:user_id/disabled", post(routes::toggle_user_disabled))
+        .route(
+            "/user/:user_id/parental",
+            get(routes::parental_controls).post(routes::set_parental_controls),
+        )
+        .route("/user/:user_id/library/:folder_id", post(routes::toggle_user_library))
+        .route("/user/:user_id/preset", post(routes::apply_preset))
+        .route("/user/:user_id/copy", post(routes::copy_user_libraries))
+        .route("/user/:user_id/preview", get(routes::user_preview))
+        .route("/presets", get(routes::presets))
+        .route("/server", post(routes::select_server))
+        .route("/sessions", get(routes::sessions))
+        .route("/server/status", get(routes::server_status))
+        .route("/tasks", get(routes::tasks))
+        .route("/tasks/list", get(routes::task_list))
+        .route("/tasks/:task_id/run", post(routes::run_task))
+        .route("/sessions/:session_id/stop", post(routes::stop_session))
+        .route("/devices/:device_id", delete(routes::revoke_device))
+        .route("/stats/playback", get(routes::playback_stats))
+        .route("/jellyfin/refresh", post(routes::refresh_jellyfin));
+    // the hook is reachable by anyone, so it's only served when it can check a token
+    match state.jellyfin_webhook_token {
+        Some(_) => router.route("/hooks/jellyfin", post(routes::jellyfin_hook)),
+        None => router,
     }
-    
 }
 
 pub fn router(state: AppState) -> anyhow::Result<Router> {
     let trace_layer = TraceLayer::new_for_http().make_span_with(|req: &Request<_>| {
-        let request_id = uuid::Uuid::new_v4();
+        // set by `logging::request_id` before
+        let request_id = req.headers().get(logging::REQUEST_ID_HEADER).and_then(|id| id.to_str().ok()).unwrap_or_default();
         tracing::info_span!(
             "request",
             %request_id,
@@ -95,16 +978,85 @@ pub fn router(state: AppState) -> anyhow::Result<Router> {
     let router = Router::new()
         .route("/", get(routes::index))
         .route("/movies", get(routes::movie_list))
+        .route("/random", get(routes::random_movie))
+        .route("/tags", get(routes::tags).post(routes::create_tag))
+        .route("/tags/hidden", post(routes::show_hidden_tags))
+        .route("/tag/:tag", get(routes::tag_page).delete(routes::delete_tag))
+        .route("/tag/:tag/movies", get(routes::tag_movie_list))
+        .route("/tag/:from/merge/:into", post(routes::merge_tag))
+        .route("/view/:name", get(routes::view_page))
+        .route("/view/:name/movies", get(routes::view_movie_list))
         .route("/movie/:id/poster.jpg", get(routes::movie_poster))
+        .route("/movie/:id/note", put(routes::set_note))
+        .route("/movie/:id/rating", put(routes::set_rating))
         .route("/movie/:id", get(routes::movie))
-        .route("/movie/:id/tag/:tag", post(routes::toggle_tag))
-        .route("/user-libraries", get(routes::user_libraries))
-        .route("/user/:user_id/library/:folder_id", post(routes::toggle_user_library))
+        .route("/movie/:id/details", get(routes::movie_details))
+        .route("/movie/:id/radarr/search", post(routes::radarr_search))
+        .route("/movie/:id/tags", put(routes::set_tags))
+        .route("/movie/:id/tag/:tag", post(routes::toggle_tag).put(routes::set_tag).delete(routes::unset_tag))
+        .route("/theme", post(routes::select_color_scheme))
+        .route("/theme/custom.css", get(routes::custom_css))
+        .route("/theme/logo", get(routes::logo))
+        .route("/stats", get(routes::stats))
+        .route("/stats/disk", get(routes::disk_usage))
         .route("/reload", post(routes::reload))
-        .nest_service("/static", ServeDir::new("src/static"))
-        .layer(trace_layer)
-        .with_state(state);
-    Ok(router)
+        .route("/reload/status", get(routes::reload_status))
+        .route("/reload/events", get(routes::reload_events))
+        .route("/updates", get(routes::updates))
+        .route("/metrics", get(routes::metrics))
+        .route("/graphql", get(graphql::graphiql).post(graphql::query))
+        .route("/audit", get(routes::audit))
+        .route("/activity", get(routes::activity))
+        .route("/activity/feed", get(routes::activity_feed))
+        .route("/rules", get(routes::rules))
+        .route("/rules/run", post(routes::run_rules))
+        .route("/import", get(routes::import))
+        .route("/import/letterboxd", post(routes::import_letterboxd))
+        .route("/trakt", get(routes::trakt))
+        .route("/trakt/connection", get(routes::trakt_connection))
+        .route("/trakt/connect", post(routes::trakt_connect))
+        .route("/trakt/sync", post(routes::trakt_sync))
+        .route("/snapshots", get(routes::snapshots).post(routes::take_snapshot))
+        .route("/snapshots/:id/restore", post(routes::restore_snapshot))
+        .nest("/api/v1", api::router(&state))
+        .route("/api/openapi.json", get(api::openapi))
+        .route("/api/docs", get(api::swagger_ui))
+        .merge(jellyfin_routes(&state))
+        .nest(
+            "/static",
+            Router::new()
+                .fallback_service(ServeDir::new(assets::STATIC_DIR))
+                .layer(axum::middleware::from_fn(assets::cache_control)),
+        )
+        .fallback(routes::not_found)
+        .layer(axum::middleware::from_fn_with_state(state.clone(), limits::limit_changes))
+        .layer(axum::middleware::from_fn_with_state(state.clone(), roles::authorize))
+        .layer(axum::middleware::from_fn(errors::error_pages))
+        .layer(axum::middleware::from_fn_with_state(state.clone(), theme::apply_theme))
+        .layer(axum::middleware::from_fn_with_state(state.clone(), i18n::localize))
+        .layer(DefaultBodyLimit::disable())
+        .layer(RequestBodyLimitLayer::new(state.max_body_size))
+        .layer(TimeoutLayer::new(state.request_timeout));
+    let router = match &state.insights {
+        Some(insights) => insights.apply(router),
+        None => router,
+    };
+    let router = router.layer(CompressionLayer::new()).with_state(state.clone());
+    let router = match state.base_path.as_str() {
+        "" => router,
+        base_path => {
+            let nested = Router::new().nest(base_path, router);
+            // the nested router only matches `/tagrs`, but links and proxies use `/tagrs/`
+            let base = base_path.to_string();
+            let index = nested.clone().map_request(move |mut request: Request<axum::body::Body>| {
+                let query = request.uri().query().map(|q| format!("?{}", q)).unwrap_or_default();
+                *request.uri_mut() = format!("{}{}", base, query).parse().expect("base path is a valid URI");
+                request
+            });
+            nested.route_service(&format!("{}/", base_path), index)
+        }
+    };
+    Ok(router.layer(trace_layer).layer(axum::middleware::from_fn(logging::request_id)))
 }
 
 mod routes {
@@ -113,126 +1065,1646 @@ mod routes {
     use axum::extract::Path as PathExtractor;
     use axum::extract::Query;
     use axum::extract::State;
-    use axum::response::Response;
-    use maud::html;
-    use maud::Markup;
+    use axum::http::{HeaderMap, StatusCode};
+    use axum::response::{Redirect, Response};
+    use axum::Form;
+    use maud::{html, Markup};
+    use std::collections::HashSet;
 
-    //#[tracing::instrument]
-    pub async fn index(State(state): State<AppState>, Query(paging): Query<OptionalPaging>) -> impl IntoResponse {
-        templates::index(&*state.collection.read().await, paging.into())
+    #[tracing::instrument(skip_all)]
+    pub async fn index(
+        State(state): State<AppState>,
+        preferences: ListPreferences,
+        Query(paging): Query<OptionalPaging>,
+        Query(filter): Query<MovieFilter>,
+    ) -> impl IntoResponse {
+        let (paging, filter) = preferences.apply(paging, filter);
+        let sources = state.movie_sources().await;
+        let page = templates::index(&*state.collection.read().await, &sources, paging, &filter);
+        (preferences.remember(&state, paging, &filter), page)
     }
 
-    //#[tracing::instrument]
+    #[tracing::instrument(skip_all, fields(movie_id = %id))]
     pub async fn movie_poster(
         State(state): State<AppState>,
         PathExtractor(id): PathExtractor<String>,
+        Query(options): Query<PosterOptions>,
+        headers: HeaderMap,
     ) -> Result<Response, Error> {
-        let hash = PathnameHash::from_str(&id)?;
         let collection = state.collection.read().await;
-        let movie = collection.movies.get(&hash).unwrap();
-        let body = match &movie.poster_path {
+        let movie = PathnameHash::from_str(&id).ok().and_then(|hash| collection.movies.get(&hash).cloned());
+        let folder = movie.as_ref().map(|movie| collection.path_mappings.to_jellyfin(&movie.path));
+        drop(collection);
+        let (Some(movie), Some(folder)) = (movie, folder) else {
+            // pages rendered before a reload may still link posters of renamed or removed movies
+            return Ok((StatusCode::NOT_FOUND, [("content-type", "image/jpeg")], MISSING_POSTER).into_response());
+        };
+        let content_type = movie.poster_content_type;
+        let poster_path = match &movie.poster_path {
+            Some(poster_path) => Some(poster_path.clone()),
+            None => match state.jellyfin.default_server() {
+                Some((name, server)) => state
+                    .artwork
+                    .poster(&Jellyfin::new(name, server, &state.jellyfin_cache), &movie, &folder)
+                    .await
+                    .unwrap_or_else(|e| {
+                        tracing::warn!("failed to fetch jellyfin poster for {}: {}", movie.name, e);
+                        None
+                    }),
+                None => None,
+            },
+        };
+        // thumbnails are always encoded as JPEG regardless of the source format
+        let (poster_path, content_type) = match (poster_path, options.w) {
+            (Some(poster_path), Some(width)) => match state.thumbnailer.thumbnail(&poster_path, width).await {
+                Ok(thumbnail) => (Some(thumbnail), "image/jpeg"),
+                // a poster that can't be decoded is still worth showing to browsers that can
+                Err(e) => {
+                    tracing::warn!("failed to create a thumbnail of {}: {}", poster_path.display(), e);
+                    (Some(poster_path), content_type)
+                }
+            },
+            (poster_path, _) => (poster_path, content_type),
+        };
+        let response = Response::builder()
+            .header("content-type", content_type)
+            .header("cache-control", POSTER_CACHE_CONTROL);
+        let response = match &poster_path {
             Some(poster_path) => {
                 let metadata = tokio::fs::metadata(poster_path).await?;
-                let mut file = tokio::fs::File::open(poster_path).await?;
-                let mut image_data = Vec::with_capacity(metadata.len() as usize);
-                file.read_to_end(&mut image_data).await?;
-                Body::from(image_data)
+                let modified = metadata.modified()?;
+                let etag = file_etag(&metadata)?;
+                let response = response
+                    .header("etag", &etag)
+                    .header("last-modified", httpdate::fmt_http_date(modified));
+                if is_not_modified(&headers, &etag, Some(modified)) {
+                    response.status(304).body(Body::empty())
+                } else if state.posters.enabled() {
+                    let poster = state.posters.read(poster_path, modified).await?;
+                    response.header("content-length", poster.len()).body(Body::from(poster))
+                } else {
+                    let file = tokio::fs::File::open(poster_path).await?;
+                    response
+                        .header("content-length", metadata.len())
+                        .body(Body::from_stream(ReaderStream::new(file)))
+                }
+            }
+            None => {
+                let response = response.header("etag", MISSING_POSTER_ETAG);
+                if is_not_modified(&headers, MISSING_POSTER_ETAG, None) {
+                    response.status(304).body(Body::empty())
+                } else {
+                    response.body(Body::from(MISSING_POSTER))
+                }
             }
-            None => Body::from(MISSING_POSTER),
         };
-        let response = Response::builder()
-            .header("content-type", "image/jpeg")
-            .body(body)
-            .unwrap();
-        Ok(response)
+        Ok(response.unwrap())
     }
 
-    //#[tracing::instrument]
+    const POSTER_CACHE_CONTROL: &str = "public, max-age=3600";
+    const MISSING_POSTER_ETAG: &str = concat!("\"missing-", env!("CARGO_PKG_VERSION"), "\"");
+
+    fn file_etag(metadata: &std::fs::Metadata) -> Result<String, Error> {
+        let modified = metadata
+            .modified()?
+            .duration_since(std::time::UNIX_EPOCH)
+            .map_err(anyhow::Error::from)?;
+        Ok(format!("\"{:x}-{:x}\"", modified.as_secs(), metadata.len()))
+    }
+
+    /// Evaluates the conditional request headers, giving `If-None-Match` precedence over
+    /// `If-Modified-Since` as described in RFC 9110.
+    fn is_not_modified(headers: &HeaderMap, etag: &str, modified: Option<std::time::SystemTime>) -> bool {
+        if let Some(if_none_match) = headers.get("if-none-match").and_then(|v| v.to_str().ok()) {
+            return if_none_match
+                .split(',')
+                .map(|tag| tag.trim().trim_start_matches("W/"))
+                .any(|tag| tag == "*" || tag == etag);
+        }
+        let since = headers
+            .get("if-modified-since")
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| httpdate::parse_http_date(v).ok());
+        match (since, modified) {
+            // HTTP dates only have second precision
+            (Some(since), Some(modified)) => modified
+                .duration_since(since)
+                .map_or(true, |newer_by| newer_by.as_secs() == 0),
+            _ => false,
+        }
+    }
+
+    #[tracing::instrument(skip_all, fields(movie_id = %id))]
     pub async fn movie(
         State(state): State<AppState>,
         PathExtractor(id): PathExtractor<String>,
+        Query(filter): Query<MovieFilter>,
     ) -> Result<Markup, Error> {
-        let hash = PathnameHash::from_str(&id)?;
+        let hash = PathnameHash::from_str(&id).map_err(|_| Error::NotFound)?;
+        let sources = state.movie_sources().await;
         let collection = state.collection.read().await;
         let movie = collection.movies.get(&hash).ok_or(Error::NotFound)?;
-        Ok(templates::movie(&collection, movie))
+        Ok(templates::movie(&collection, movie, &sources, &filter))
     }
 
-    pub async fn toggle_tag(
+    #[tracing::instrument(skip_all, fields(movie_id = %id))]
+    pub async fn movie_details(
         State(state): State<AppState>,
-        PathExtractor((id, tag)): PathExtractor<(String, String)>,
+        PathExtractor(id): PathExtractor<String>,
     ) -> Result<Markup, Error> {
-        let hash = PathnameHash::from_str(&id)?;
-        let mut collection = state.collection.write().await;
-        let movie = collection.movies.get(&hash).ok_or(Error::NotFound)?.clone();
-        collection.toggle_tag(&tag, &movie).await?;
-        Ok(templates::movie(&collection, &movie))
+        let hash = PathnameHash::from_str(&id).map_err(|_| Error::NotFound)?;
+        let movie = state.collection.read().await.movies.get(&hash).ok_or(Error::NotFound)?.clone();
+        // walking the folder and running ffprobe take a while, so don't hold up changes meanwhile
+        let files = movie.video_files().await?;
+        let media_info = state.media_info.probe(&movie).await.unwrap_or_else(|e| {
+            tracing::warn!("failed to probe {}: {}", movie.name, e);
+            None
+        });
+        let sources = state.movie_sources().await;
+        let collection = state.collection.read().await;
+        Ok(templates::movie_details(&collection, &movie, &files, media_info.as_ref(), sources.radarr(&movie)))
     }
 
-    pub async fn reload(
+    #[tracing::instrument(skip_all, fields(movie_id = %id, user = %actor.name()))]
+    pub async fn set_note(
+        State(state): State<AppState>,
+        actor: Actor,
+        PathExtractor(id): PathExtractor<String>,
+        Form(form): Form<NoteForm>,
+    ) -> Result<Markup, Error> {
+        let hash = PathnameHash::from_str(&id).map_err(|_| Error::NotFound)?;
+        let mut collection = state.collection_mut().await;
+        let movie = collection.set_note(&hash, &form.note).await?;
+        let action = audit::Action::NoteChanged { movie: movie.name.clone() };
+        state.audit.record(actor.name(), action).await?;
+        Ok(templates::movie_note(movie, true))
+    }
+
+    /// Rates a movie from a form, where 0 clears the rating, or from JSON like `{"rating": 4}`,
+    /// where `null` clears it.
+    #[utoipa::path(
+        put,
+        path = "/movie/{id}/rating",
+        tag = "movies",
+        params(("id" = String, Path, description = "Movie id")),
+        request_body(content = RatingForm),
+        responses(
+            (status = 200, description = "The rated movie", body = MovieSummary),
+            (status = 404, description = "No movie with the id"),
+            (status = 422, description = "A rating above 5"),
+        )
+    )]
+    #[tracing::instrument(skip_all, fields(movie_id = %id, user = %actor.name()))]
+    pub async fn set_rating(
         State(state): State<AppState>,
+        actor: Actor,
+        PathExtractor(id): PathExtractor<String>,
+        headers: HeaderMap,
+        body: axum::body::Bytes,
     ) -> Result<Response, Error> {
-        let mut collection = state.collection.write().await;
-        collection.reload().await?;
-        let response = Response::builder()
-            .status(303)
-            .header("location", "/")
-            .body(Body::empty())
-            .unwrap();
-        Ok(response)
+        let json = headers
+            .get("content-type")
+            .and_then(|value| value.to_str().ok())
+            .is_some_and(|value| value.starts_with("application/json"));
+        let rating = if json {
+            match axum::Json::<RatingForm>::from_bytes(&body) {
+                Ok(axum::Json(form)) => form.rating,
+                Err(rejection) => return Ok(rejection.into_response()),
+            }
+        } else {
+            serde_urlencoded::from_bytes::<RatingForm>(&body).map_err(anyhow::Error::from)?.rating.filter(|&r| r > 0)
+        };
+        // forms clear the rating with 0, JSON clients with null
+        if rating.is_some_and(|rating| rating == 0 || rating > collection::MAX_RATING) {
+            let message = format!("ratings go from 1 to {}", collection::MAX_RATING);
+            return Ok((StatusCode::UNPROCESSABLE_ENTITY, message).into_response());
+        }
+        let hash = PathnameHash::from_str(&id).map_err(|_| Error::NotFound)?;
+        let mut collection = state.collection_mut().await;
+        let movie = collection.set_rating(&hash, rating).await?.clone();
+        let action = audit::Action::RatingChanged { movie: movie.name.clone(), rating };
+        state.audit.record(actor.name(), action).await?;
+        if json {
+            return Ok(axum::Json(api::MovieSummary::new(&movie, &collection)).into_response());
+        }
+        Ok(templates::rating(&movie).into_response())
     }
 
-    pub async fn user_libraries(
+    /// Posters Jellyfin's metadata providers offer for a movie.
+    #[tracing::instrument(skip_all, fields(movie_id = %id))]
+    pub async fn poster_candidates(
         State(state): State<AppState>,
+        jellyfin: Jellyfin,
+        PathExtractor(id): PathExtractor<String>,
     ) -> Result<Markup, Error> {
-        let users = state.jellyfin_api.get_users().await?;
-        let folders = state.jellyfin_api.get_media_folders().await?;
-        templates::user_libraries_page(&users, &folders)
+        let hash = PathnameHash::from_str(&id).map_err(|_| Error::NotFound)?;
+        let (movie, folder) = {
+            let collection = state.collection.read().await;
+            let movie = collection.movies.get(&hash).ok_or(Error::NotFound)?.clone();
+            let folder = collection.path_mappings.to_jellyfin(&movie.path);
+            (movie, folder)
+        };
+        let item_id = jellyfin.item_id(&folder).await?.ok_or(Error::NotFound)?;
+        let images = jellyfin.api.get_remote_images(&item_id).await?;
+        Ok(templates::poster_candidates(&movie, &images))
     }
 
-    pub async fn toggle_user_library(
+    /// Sends the browser to the movie's page in the web UI of the selected server.
+    #[tracing::instrument(skip_all, fields(movie_id = %id))]
+    pub async fn open_in_jellyfin(
         State(state): State<AppState>,
-        PathExtractor((user_id, folder_id)): PathExtractor<(String, String)>,
+        jellyfin: Jellyfin,
+        PathExtractor(id): PathExtractor<String>,
+    ) -> Result<Redirect, Error> {
+        let hash = PathnameHash::from_str(&id).map_err(|_| Error::NotFound)?;
+        let path = {
+            let collection = state.collection.read().await;
+            let movie = collection.movies.get(&hash).ok_or(Error::NotFound)?;
+            collection.path_mappings.to_jellyfin(&movie.path)
+        };
+        let item_id = jellyfin.item_id(&path).await?.ok_or(Error::NotFound)?;
+        Ok(Redirect::to(&jellyfin.api.web_url(&item_id)))
+    }
+
+    #[tracing::instrument(skip_all, fields(movie_id = %id, user = %actor.name()))]
+    pub async fn set_poster(
+        State(state): State<AppState>,
+        jellyfin: Jellyfin,
+        actor: Actor,
+        PathExtractor(id): PathExtractor<String>,
+        Form(choice): Form<PosterChoice>,
     ) -> Result<Markup, Error> {
-        let api1 = Arc::unwrap_or_clone(state.jellyfin_api.clone());
-        let api2 = Arc::unwrap_or_clone(state.jellyfin_api.clone());
-        let users_handle = tokio::spawn(async move {api1.get_users().await});
-        let folders_handle = tokio::spawn(async move {api2.get_media_folders().await});
-        let users: Vec<jellyfin_api::User> = users_handle.await.map_err(anyhow::Error::from)??;
-        let folders: Vec<jellyfin_api::MediaFolders> = folders_handle.await.map_err(anyhow::Error::from)??;
-
-        let mut user = users.iter().find(|u| u.id == user_id).ok_or(Error::NotFound)?.clone();
-        let mut user_folders = user.enabled_folders()?;
-        if user_folders.contains(&folder_id) {
-            user_folders.retain(|f| f != &folder_id);
-        } else {
-            user_folders.push(folder_id);
+        let hash = PathnameHash::from_str(&id).map_err(|_| Error::NotFound)?;
+        let (movie, folder) = {
+            let collection = state.collection.read().await;
+            let movie = collection.movies.get(&hash).ok_or(Error::NotFound)?.clone();
+            let folder = collection.path_mappings.to_jellyfin(&movie.path);
+            (movie, folder)
+        };
+        // only download what Jellyfin offered for this movie, not arbitrary URLs
+        let item_id = jellyfin.item_id(&folder).await?.ok_or(Error::NotFound)?;
+        let images = jellyfin.api.get_remote_images(&item_id).await?;
+        if !images.iter().any(|image| image.url == choice.url) {
+            return Err(Error::NotFound);
         }
-        tracing::debug!("Setting user folders: {:?}", &user_folders);
-        state.jellyfin_api.set_user_media_folders(&user, &user_folders).await?;
-        user.policy["EnabledFolders"] = serde_json::to_value(&user_folders)?;
-        templates::user_libraries_entry(&user, &folders)
+        let image = jellyfin.api.download_remote_image(&choice.url).await?;
+        let path = movie.save_poster(image).await?;
+        if let Some(movie) = state.collection_mut().await.movies.get_mut(&hash) {
+            movie.poster_path = Some(path);
+            movie.poster_content_type = "image/jpeg";
+        }
+        state.audit.record(actor.name(), audit::Action::PosterChanged { movie: movie.name.clone() }).await?;
+        Ok(templates::details_poster(&movie, Some(&uuid::Uuid::new_v4().to_string())))
     }
 
-    pub async fn movie_list(
+    #[tracing::instrument(skip_all, fields(movie_id = %id, user = %actor.name()))]
+    pub async fn radarr_search(
         State(state): State<AppState>,
-        Query(paging): Query<OptionalPaging>,
-    ) -> Markup {
-        let collection = state.collection.read().await;
-        templates::movie_list(&collection, paging.into())
+        actor: Actor,
+        PathExtractor(id): PathExtractor<String>,
+    ) -> Result<Markup, Error> {
+        let radarr = state.radarr.as_ref().ok_or(Error::NotFound)?;
+        let hash = PathnameHash::from_str(&id).map_err(|_| Error::NotFound)?;
+        let movie = state.collection.read().await.movies.get(&hash).ok_or(Error::NotFound)?.clone();
+        radarr.search(&movie).await?;
+        state.audit.record(actor.name(), audit::Action::RadarrSearch { movie: movie.name }).await?;
+        Ok(templates::radarr_search_started())
     }
-}
 
-#[derive(Debug, Serialize, Deserialize, Copy, Clone, Eq, PartialEq)]
-pub struct OptionalPaging {
-    page: Option<usize>,
-    per_page: Option<usize>,
-}
+    pub async fn toggle_tag(
+        State(state): State<AppState>,
+        actor: Actor,
+        PathExtractor((id, tag)): PathExtractor<(String, String)>,
+        Query(filter): Query<MovieFilter>,
+    ) -> Result<Response, Error> {
+        change_tag(state, actor, id, tag, filter, None).await
+    }
+
+    /// Tags a movie. Answers 409 Conflict with the current movie card if it already carries the
+    /// tag, e.g. because someone else just tagged it.
+    pub async fn set_tag(
+        State(state): State<AppState>,
+        actor: Actor,
+        PathExtractor((id, tag)): PathExtractor<(String, String)>,
+        Query(filter): Query<MovieFilter>,
+    ) -> Result<Response, Error> {
+        change_tag(state, actor, id, tag, filter, Some(true)).await
+    }
+
+    /// Untags a movie. Answers 409 Conflict with the current movie card if it doesn't carry the
+    /// tag.
+    pub async fn unset_tag(
+        State(state): State<AppState>,
+        actor: Actor,
+        PathExtractor((id, tag)): PathExtractor<(String, String)>,
+        Query(filter): Query<MovieFilter>,
+    ) -> Result<Response, Error> {
+        change_tag(state, actor, id, tag, filter, Some(false)).await
+    }
+
+    #[tracing::instrument(skip_all, fields(movie_id = %id, tag = %tag, user = %actor.name()))]
+    async fn change_tag(
+        state: AppState,
+        actor: Actor,
+        id: String,
+        tag: String,
+        filter: MovieFilter,
+        expected: Option<bool>,
+    ) -> Result<Response, Error> {
+        let hash = PathnameHash::from_str(&id).map_err(|_| Error::NotFound)?;
+        // Radarr and Jellyfin may be slow to answer, so ask them before blocking other changes
+        let sources = state.movie_sources().await;
+        let mut collection = state.collection_mut().await;
+        let movie = collection.movies.get(&hash).ok_or(Error::NotFound)?.clone();
+        let tag = collection.resolve_tag(&tag).ok_or(Error::NotFound)?.to_string();
+        if let Some(expected) = expected {
+            let current = collection.tags.get(&tag).ok_or(Error::NotFound)?.movies.contains(&hash);
+            if current == expected {
+                let card = templates::movie(&collection, &movie, &sources, &filter);
+                return Ok((StatusCode::CONFLICT, card).into_response());
+            }
+        }
+        let tagged = collection.toggle_tag(&tag, &movie).await?;
+        let movie_name = movie.name.clone();
+        let action = if tagged {
+            audit::Action::TagAdded { tag, movie: movie_name }
+        } else {
+            audit::Action::TagRemoved { tag, movie: movie_name }
+        };
+        state.audit.record(actor.name(), action).await?;
+        if let Some(library_refresh) = &state.library_refresh {
+            library_refresh.schedule();
+        }
+        Ok(templates::movie(&collection, &movie, &sources, &filter).into_response())
+    }
+
+    #[derive(Deserialize, utoipa::ToSchema)]
+    pub struct TagSet {
+        /// Tag directory names or aliases, like `genre/horror`
+        tags: HashSet<String>,
+    }
+
+    /// Sets all tags of a movie at once from `{"tags": [...]}` or a form with repeated `tags`
+    /// fields. JSON requests get the movie's JSON summary back, forms the movie card.
+    #[utoipa::path(
+        put,
+        path = "/movie/{id}/tags",
+        tag = "movies",
+        params(("id" = String, Path, description = "Movie id")),
+        request_body(content = TagSet, description = "Every tag the movie should have"),
+        responses(
+            (status = 200, description = "The movie with its new tags", body = MovieSummary),
+            (status = 404, description = "No movie with the id, or an unknown tag"),
+        )
+    )]
+    #[tracing::instrument(skip_all, fields(movie_id = %id, user = %actor.name()))]
+    pub async fn set_tags(
+        State(state): State<AppState>,
+        actor: Actor,
+        PathExtractor(id): PathExtractor<String>,
+        Query(filter): Query<MovieFilter>,
+        headers: HeaderMap,
+        body: axum::body::Bytes,
+    ) -> Result<Response, Error> {
+        let json = headers
+            .get("content-type")
+            .and_then(|value| value.to_str().ok())
+            .is_some_and(|value| value.starts_with("application/json"));
+        let tags = if json {
+            match axum::Json::<TagSet>::from_bytes(&body) {
+                Ok(axum::Json(set)) => set.tags,
+                Err(rejection) => return Ok(rejection.into_response()),
+            }
+        } else {
+            serde_urlencoded::from_bytes::<Vec<(String, String)>>(&body)
+                .map_err(anyhow::Error::from)?
+                .into_iter()
+                .filter(|(key, _)| key == "tags")
+                .map(|(_, tag)| tag)
+                .collect()
+        };
+        let hash = PathnameHash::from_str(&id).map_err(|_| Error::NotFound)?;
+        let sources = state.movie_sources().await;
+        let mut collection = state.collection_mut().await;
+        let movie = collection.movies.get(&hash).ok_or(Error::NotFound)?.clone();
+        // unknown names are left for set_tags to reject
+        let tags: HashSet<String> = tags
+            .into_iter()
+            .map(|tag| collection.resolve_tag(&tag).map(str::to_string).unwrap_or(tag))
+            .collect();
+        let (added, removed) = collection.set_tags(&movie, &tags).await?;
+        let actions = added
+            .into_iter()
+            .map(|tag| audit::Action::TagAdded { tag, movie: movie.name.clone() })
+            .chain(removed.into_iter().map(|tag| audit::Action::TagRemoved { tag, movie: movie.name.clone() }))
+            .collect::<Vec<_>>();
+        for action in actions {
+            state.audit.record(actor.name(), action).await?;
+        }
+        if let Some(library_refresh) = &state.library_refresh {
+            library_refresh.schedule();
+        }
+        if json {
+            return Ok(axum::Json(api::MovieSummary::new(&movie, &collection)).into_response());
+        }
+        Ok(templates::movie(&collection, &movie, &sources, &filter).into_response())
+    }
+
+    pub async fn not_found() -> Error {
+        Error::NotFound
+    }
+
+    /// Starts reloading the collection in the background, unless a reload is running already.
+    /// Open pages refresh when it's done.
+    #[tracing::instrument(skip_all, fields(user = %actor.name()))]
+    pub async fn reload(
+        State(state): State<AppState>,
+        actor: Actor,
+    ) -> Result<Response, Error> {
+        tokio::spawn(async move {
+            let reloaded = state.reloads.run(|progress| {
+                let state = state.clone();
+                async move { state.rescan_collection(&progress).await }
+            });
+            match reloaded.await {
+                Some(Ok(())) => {
+                    if let Err(e) = state.audit.record(actor.name(), audit::Action::Reload).await {
+                        tracing::error!("failed to record reload: {}", e);
+                    }
+                }
+                Some(Err(e)) => tracing::error!("reload failed: {}", e),
+                None => tracing::debug!("reload already running"),
+            }
+        });
+        let response = Response::builder()
+            .status(303)
+            .header("location", theme::url("/"))
+            .body(Body::empty())
+            .unwrap();
+        Ok(response)
+    }
+
+    #[derive(Debug, Deserialize)]
+    pub struct HookToken {
+        token: Option<String>,
+    }
+
+    /// The parts of a Jellyfin Webhook plugin notification we act on.
+    #[derive(Debug, Deserialize)]
+    #[serde(rename_all = "PascalCase")]
+    struct JellyfinNotification {
+        notification_type: String,
+        task_name: Option<String>,
+    }
+
+    /// Receives notifications of the Jellyfin Webhook plugin. New or deleted items and finished
+    /// library scans pick up added and removed movie folders in the background, and tell open
+    /// pages to refresh. Stopped playbacks and changed played states refetch the watched movies.
+    pub async fn jellyfin_hook(
+        State(state): State<AppState>,
+        Query(query): Query<HookToken>,
+        body: axum::body::Bytes,
+    ) -> Result<StatusCode, Error> {
+        if query.token.is_none() || query.token != state.jellyfin_webhook_token {
+            return Ok(StatusCode::UNAUTHORIZED);
+        }
+        let notification: JellyfinNotification = match serde_json::from_slice(&body) {
+            Ok(notification) => notification,
+            Err(e) => {
+                tracing::info!("ignoring malformed Jellyfin notification: {}", e);
+                return Ok(StatusCode::BAD_REQUEST);
+            }
+        };
+        if let ("PlaybackStop" | "UserDataSaved", Some(watched)) = (notification.notification_type.as_str(), &state.watched) {
+            watched.invalidate().await;
+            return Ok(StatusCode::NO_CONTENT);
+        }
+        let relevant = match notification.notification_type.as_str() {
+            "ItemAdded" | "ItemDeleted" => true,
+            "TaskCompleted" => notification.task_name.is_some_and(|name| name.to_lowercase().contains("scan")),
+            _ => false,
+        };
+        if !relevant {
+            return Ok(StatusCode::NO_CONTENT);
+        }
+        tracing::debug!("Jellyfin reported {}", notification.notification_type);
+        tokio::spawn(async move {
+            if let Err(e) = state.refresh_movies().await {
+                tracing::error!("failed to pick up changed movie folders: {}", e);
+            }
+        });
+        Ok(StatusCode::ACCEPTED)
+    }
+
+    #[utoipa::path(
+        get,
+        path = "/reload/status",
+        tag = "reload",
+        responses((status = 200, description = "Progress of the running or last reload", body = ReloadStatus))
+    )]
+    pub async fn reload_status(State(state): State<AppState>) -> axum::Json<reload::ReloadStatus> {
+        axum::Json(state.reloads.status())
+    }
+
+    /// Server-sent events with the progress of reloads, see [`Reloads`].
+    pub async fn reload_events(State(state): State<AppState>) -> impl IntoResponse {
+        state.reloads.subscribe()
+    }
+
+    /// Counters in the Prometheus text format.
+    pub async fn metrics(State(state): State<AppState>) -> Response {
+        Response::builder()
+            .header("content-type", "text/plain; version=0.0.4")
+            .body(Body::from(state.posters.metrics()))
+            .unwrap()
+    }
+
+    /// Server-sent events telling pages about changes, see [`Updates`].
+    pub async fn updates(State(state): State<AppState>) -> impl IntoResponse {
+        state.updates.subscribe()
+    }
+
+    #[tracing::instrument(skip_all, fields(user = %actor.name()))]
+    pub async fn refresh_jellyfin(
+        State(state): State<AppState>,
+        jellyfin: Jellyfin,
+        actor: Actor,
+    ) -> Result<Markup, Error> {
+        jellyfin.server.refresh().await?;
+        state.audit.record(actor.name(), audit::Action::LibraryScan).await?;
+        Ok(templates::scan_started())
+    }
+
+    pub async fn select_server(
+        State(state): State<AppState>,
+        Form(selection): Form<ServerSelection>,
+    ) -> Result<Response, Error> {
+        state.jellyfin.get(&selection.server).ok_or(Error::NotFound)?;
+        let response = Response::builder()
+            .header("set-cookie", set_cookie(&state.base_path, SERVER_COOKIE, Some(&selection.server)))
+            .header("hx-refresh", "true")
+            .body(Body::empty())
+            .unwrap();
+        Ok(response)
+    }
+
+    pub async fn select_color_scheme(State(state): State<AppState>, Form(selection): Form<SchemeSelection>) -> Response {
+        // anything else goes back to following the system preference
+        let scheme = theme::ColorScheme::from_name(&selection.scheme);
+        let cookie = set_cookie(&state.base_path, theme::SCHEME_COOKIE, scheme.as_ref().map(theme::ColorScheme::as_str));
+        Response::builder()
+            .header("set-cookie", cookie)
+            .header("hx-refresh", "true")
+            .body(Body::empty())
+            .unwrap()
+    }
+
+    pub async fn custom_css(State(state): State<AppState>) -> Result<Response, Error> {
+        let path = state.theme.custom_css.as_ref().ok_or(Error::NotFound)?;
+        let css = tokio::fs::read(path).await?;
+        Ok(Response::builder()
+            .header("content-type", "text/css")
+            .body(Body::from(css))
+            .unwrap())
+    }
+
+    pub async fn logo(State(state): State<AppState>) -> Result<Response, Error> {
+        let path = state.theme.logo.as_ref().ok_or(Error::NotFound)?;
+        let image = tokio::fs::read(path).await?;
+        Ok(Response::builder()
+            .header("content-type", theme::image_content_type(path))
+            .body(Body::from(image))
+            .unwrap())
+    }
+
+    pub async fn sessions(State(state): State<AppState>, jellyfin: Jellyfin) -> Result<Markup, Error> {
+        let (mut sessions, mut devices) = tokio::try_join!(jellyfin.api.get_sessions(), jellyfin.api.get_devices())?;
+        sessions.sort_by_key(|s| s.now_playing_item.is_none());
+        devices.sort_by_key(|d| std::cmp::Reverse(d.date_last_activity));
+        let server_picker = templates::server_picker(&state.jellyfin, &jellyfin.name);
+        Ok(templates::sessions_page(&sessions, &devices, server_picker))
+    }
+
+    /// Version and state of the selected server for the page header, which notes when the
+    /// server can't be reached instead of failing.
+    pub async fn server_status(jellyfin: Jellyfin) -> Markup {
+        let info = jellyfin
+            .api
+            .get_system_info()
+            .await
+            .inspect_err(|e| tracing::warn!("failed to get the status of {}: {}", jellyfin.name, e))
+            .ok();
+        templates::server_status(&jellyfin.name, info.as_ref())
+    }
+
+    /// Jellyfin's scheduled tasks, with buttons running them.
+    pub async fn tasks(State(state): State<AppState>, jellyfin: Jellyfin) -> Result<Markup, Error> {
+        let tasks = jellyfin.api.get_scheduled_tasks().await?;
+        let server_picker = templates::server_picker(&state.jellyfin, &jellyfin.name);
+        Ok(templates::tasks_page(&tasks, server_picker))
+    }
+
+    /// The task table, polled by the tasks page while tasks are running.
+    pub async fn task_list(jellyfin: Jellyfin) -> Result<Markup, Error> {
+        let tasks = jellyfin.api.get_scheduled_tasks().await?;
+        Ok(templates::task_list(&tasks))
+    }
+
+    #[tracing::instrument(skip_all, fields(task_id = %task_id, user = %actor.name()))]
+    pub async fn run_task(
+        State(state): State<AppState>,
+        jellyfin: Jellyfin,
+        actor: Actor,
+        PathExtractor(task_id): PathExtractor<String>,
+    ) -> Result<Markup, Error> {
+        let tasks = jellyfin.api.get_scheduled_tasks().await?;
+        let task = tasks.iter().find(|task| task.id == task_id).ok_or(Error::NotFound)?;
+        jellyfin.api.start_task(&task.id).await?;
+        state.audit.record(actor.name(), audit::Action::TaskStarted { task: task.name.clone() }).await?;
+        // Jellyfin starts the task in the background, so it may not show as running yet
+        let tasks = jellyfin.api.get_scheduled_tasks().await?;
+        Ok(templates::task_list(&tasks))
+    }
+
+    #[tracing::instrument(skip_all, fields(session_id = %session_id, user = %actor.name()))]
+    pub async fn stop_session(
+        State(state): State<AppState>,
+        jellyfin: Jellyfin,
+        actor: Actor,
+        PathExtractor(session_id): PathExtractor<String>,
+    ) -> Result<Markup, Error> {
+        let sessions = jellyfin.api.get_sessions().await?;
+        let session = sessions.iter().find(|s| s.id == session_id).ok_or(Error::NotFound)?;
+        let item = session.now_playing_item.as_ref().ok_or(Error::NotFound)?;
+        jellyfin.api.stop_playback(&session.id).await?;
+        let action = audit::Action::PlaybackStopped {
+            user: session.user_name.clone().unwrap_or_default(),
+            device: session.device_name.clone().unwrap_or_default(),
+            item: item.name.clone(),
+        };
+        state.audit.record(actor.name(), action).await?;
+        Ok(templates::playback_stopped())
+    }
+
+    /// Signs a device out of Jellyfin, so it needs the password again to come back.
+    #[tracing::instrument(skip_all, fields(device_id = %device_id, user = %actor.name()))]
+    pub async fn revoke_device(
+        State(state): State<AppState>,
+        jellyfin: Jellyfin,
+        actor: Actor,
+        PathExtractor(device_id): PathExtractor<String>,
+    ) -> Result<Markup, Error> {
+        let devices = jellyfin.api.get_devices().await?;
+        let device = devices.iter().find(|d| d.id == device_id).ok_or(Error::NotFound)?;
+        jellyfin.api.delete_device(&device.id).await?;
+        let action = audit::Action::DeviceRevoked {
+            device: device.name.clone(),
+            user: device.last_user_name.clone().unwrap_or_default(),
+        };
+        state.audit.record(actor.name(), action).await?;
+        // the row goes away
+        Ok(html! {})
+    }
+
+    /// Redirects to the details of a random movie, for when nobody can decide what to watch.
+    pub async fn random_movie(
+        State(state): State<AppState>,
+        Query(options): Query<RandomOptions>,
+    ) -> Result<Redirect, Error> {
+        let seed = options.seed.unwrap_or_else(|| {
+            std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .map_or(0, |elapsed| elapsed.as_nanos() as u64)
+        });
+        let collection = state.collection.read().await;
+        let movie = collection.random_movie(options.tag.as_deref(), seed)?.ok_or(Error::NotFound)?;
+        Ok(Redirect::to(&theme::url(&format!("/movie/{}/details", movie.id()))))
+    }
+
+    pub async fn tags(State(state): State<AppState>) -> Markup {
+        templates::tag_cloud(&*state.collection.read().await)
+    }
+
+    /// Shows or hides tags marked hidden for this browser. Only admins may see them.
+    pub async fn show_hidden_tags(State(state): State<AppState>, Form(form): Form<HiddenTagsForm>) -> Response {
+        let cookie = set_cookie(&state.base_path, theme::HIDDEN_TAGS_COOKIE, form.show.then_some("true"));
+        Response::builder()
+            .header("set-cookie", cookie)
+            .header("hx-refresh", "true")
+            .body(Body::empty())
+            .unwrap()
+    }
+
+    #[derive(Deserialize)]
+    pub struct HiddenTagsForm {
+        show: bool,
+    }
+
+    pub async fn stats(
+        State(state): State<AppState>,
+        jellyfin: Option<Jellyfin>,
+        Query(options): Query<StatsOptions>,
+    ) -> Markup {
+        let collection = state.collection.read().await;
+        let server_picker = jellyfin.and_then(|jellyfin| templates::server_picker(&state.jellyfin, &jellyfin.name));
+        templates::stats_page(&collection, options.tag_sort.unwrap_or_default(), server_picker)
+    }
+
+    pub async fn playback_stats(State(state): State<AppState>, jellyfin: Jellyfin) -> Result<Markup, Error> {
+        let movie_names = state.collection.read().await.movies.values().map(|m| m.name.clone()).collect();
+        let playback = stats::PlaybackStats::collect(&jellyfin.api, movie_names).await?;
+        Ok(templates::playback_stats(&playback))
+    }
+
+    #[tracing::instrument(skip_all)]
+    pub async fn disk_usage(State(state): State<AppState>, Query(options): Query<DiskUsageOptions>) -> Markup {
+        let usage = stats::DiskUsage::collect(&state.folder_sizes, &state.collection).await;
+        templates::disk_usage(&usage, options.sort.unwrap_or_default())
+    }
+
+    pub async fn user_libraries(
+        State(state): State<AppState>,
+        jellyfin: Jellyfin,
+        Query(filter): Query<UserFilter>,
+    ) -> Result<Markup, Error> {
+        let start = std::time::Instant::now();
+        let (users, folders) = tokio::try_join!(
+            jellyfin.users(),
+            jellyfin.media_folders(),
+        )?;
+        tracing::debug!(elapsed = ?start.elapsed(), "fetched users and media folders");
+        let server_picker = templates::server_picker(&state.jellyfin, &jellyfin.name);
+        let folders = state.listed_folders(&folders).await;
+        let shown: Vec<_> = users.iter().filter(|user| filter.matches(user)).collect();
+        templates::user_libraries_page(&users, &shown, &filter, &folders, &state.presets, server_picker, jellyfin.is_stale())
+    }
+
+    #[tracing::instrument(skip_all, fields(user = %actor.name()))]
+    pub async fn create_user(
+        State(state): State<AppState>,
+        jellyfin: Jellyfin,
+        actor: Actor,
+        Form(new_user): Form<NewUser>,
+    ) -> Result<Markup, Error> {
+        let name = new_user.name.trim();
+        if name.is_empty() {
+            return Err(anyhow::anyhow!("user name must not be empty").into());
+        }
+        let user = jellyfin.api.create_user(name, &new_user.password).await?;
+        jellyfin.invalidate();
+        let folders = jellyfin.media_folders().await?;
+        state
+            .audit
+            .record(actor.name(), audit::Action::UserCreated { user: user.name.clone() })
+            .await?;
+        templates::user_libraries_entry(&user, &state.listed_folders(&folders).await, &state.presets)
+    }
+
+    #[tracing::instrument(skip_all, fields(user_id = %user_id, user = %actor.name()))]
+    pub async fn delete_user(
+        State(state): State<AppState>,
+        jellyfin: Jellyfin,
+        actor: Actor,
+        PathExtractor(user_id): PathExtractor<String>,
+    ) -> Result<Markup, Error> {
+        let users = jellyfin.users().await?;
+        let user = users.iter().find(|u| u.id == user_id).ok_or(Error::NotFound)?;
+        jellyfin.api.delete_user(&user.id).await?;
+        jellyfin.invalidate();
+        state
+            .audit
+            .record(actor.name(), audit::Action::UserDeleted { user: user.name.clone() })
+            .await?;
+        Ok(html! {})
+    }
+
+    #[tracing::instrument(skip_all, fields(user_id = %user_id, user = %actor.name()))]
+    pub async fn toggle_user_disabled(
+        State(state): State<AppState>,
+        jellyfin: Jellyfin,
+        actor: Actor,
+        PathExtractor(user_id): PathExtractor<String>,
+    ) -> Result<Markup, Error> {
+        // the policy is written back whole, so start from Jellyfin's rather than a cached one
+        let (mut user, folders) = tokio::try_join!(
+            jellyfin.api.get_user(&user_id),
+            jellyfin.media_folders(),
+        )?;
+        user.policy.is_disabled = !user.policy.is_disabled;
+        jellyfin.api.set_user_policy(&user.id, &user.policy).await?;
+        jellyfin.invalidate();
+        let action = if user.policy.is_disabled {
+            audit::Action::UserDisabled { user: user.name.clone() }
+        } else {
+            audit::Action::UserEnabled { user: user.name.clone() }
+        };
+        state.audit.record(actor.name(), action).await?;
+        templates::user_libraries_entry(&user, &state.listed_folders(&folders).await, &state.presets)
+    }
+
+    pub async fn parental_controls(
+        jellyfin: Jellyfin,
+        PathExtractor(user_id): PathExtractor<String>,
+    ) -> Result<Markup, Error> {
+        let (user, ratings) = tokio::try_join!(
+            jellyfin.api.get_user(&user_id),
+            jellyfin.api.get_parental_ratings(),
+        )?;
+        Ok(templates::parental_controls(&user, &ratings))
+    }
+
+    /// The movies a user can currently see through their libraries.
+    #[tracing::instrument(skip_all, fields(user_id = %user_id))]
+    pub async fn user_preview(
+        State(state): State<AppState>,
+        jellyfin: Jellyfin,
+        PathExtractor(user_id): PathExtractor<String>,
+    ) -> Result<Markup, Error> {
+        let (user, folders) = tokio::try_join!(jellyfin.api.get_user(&user_id), jellyfin.media_folders())?;
+        let collection = state.collection.read().await;
+        let preview = preview::LibraryPreview::new(&collection, &user, &folders);
+        Ok(templates::user_preview(&collection, &user, &preview))
+    }
+
+    #[tracing::instrument(skip_all, fields(user_id = %user_id, user = %actor.name()))]
+    pub async fn set_parental_controls(
+        State(state): State<AppState>,
+        jellyfin: Jellyfin,
+        actor: Actor,
+        PathExtractor(user_id): PathExtractor<String>,
+        Form(form): Form<Vec<(String, String)>>,
+    ) -> Result<Markup, Error> {
+        let (mut user, ratings) = tokio::try_join!(
+            jellyfin.api.get_user(&user_id),
+            jellyfin.api.get_parental_ratings(),
+        )?;
+        let mut max_rating = serde_json::Value::Null;
+        let mut block_unrated = Vec::new();
+        for (key, value) in form {
+            match key.as_str() {
+                "max_parental_rating" if !value.is_empty() => {
+                    let rating: i32 = value.parse().map_err(anyhow::Error::from)?;
+                    max_rating = rating.into();
+                }
+                "block_unrated_items" => block_unrated.push(serde_json::Value::String(value)),
+                _ => {}
+            }
+        }
+        let mut fields = serde_json::Map::new();
+        fields.insert("MaxParentalRating".to_string(), max_rating);
+        fields.insert("BlockUnratedItems".to_string(), block_unrated.into());
+        user.policy = jellyfin.api.update_policy_fields(&user, fields).await?;
+        jellyfin.invalidate();
+        state
+            .audit
+            .record(actor.name(), audit::Action::ParentalControlsChanged { user: user.name.clone() })
+            .await?;
+        Ok(templates::parental_controls(&user, &ratings))
+    }
+
+    #[tracing::instrument(skip_all, fields(user_id = %user_id, folder_id = %folder_id, user = %actor.name()))]
+    pub async fn toggle_user_library(
+        State(state): State<AppState>,
+        jellyfin: Jellyfin,
+        actor: Actor,
+        PathExtractor((user_id, folder_id)): PathExtractor<(String, String)>,
+    ) -> Result<Markup, Error> {
+        let (mut user, folders) = tokio::try_join!(jellyfin.api.get_user(&user_id), jellyfin.media_folders())?;
+        // switching a library off for a user with access to everything restricts them to the
+        // remaining libraries
+        let mut user_folders = user.accessible_folders(&folders);
+        let folder_name = folders
+            .iter()
+            .find(|f| f.id == folder_id)
+            .map_or_else(|| folder_id.clone(), |f| f.name.clone());
+        let action = if user_folders.contains(&folder_id) {
+            user_folders.retain(|f| f != &folder_id);
+            audit::Action::LibraryDisabled { user: user.name.clone(), folder: folder_name }
+        } else {
+            user_folders.push(folder_id);
+            audit::Action::LibraryEnabled { user: user.name.clone(), folder: folder_name }
+        };
+        tracing::debug!("Setting user folders: {:?}", &user_folders);
+        user.policy = jellyfin.server.set_user_libraries(&user, Some(&user_folders)).await?;
+        jellyfin.invalidate();
+        state.audit.record(actor.name(), action).await?;
+        templates::user_libraries_entry(&user, &state.listed_folders(&folders).await, &state.presets)
+    }
+
+    #[tracing::instrument(skip_all, fields(user_id = %user_id, user = %actor.name()))]
+    pub async fn toggle_all_user_libraries(
+        State(state): State<AppState>,
+        jellyfin: Jellyfin,
+        actor: Actor,
+        PathExtractor(user_id): PathExtractor<String>,
+    ) -> Result<Markup, Error> {
+        let (mut user, folders) = tokio::try_join!(
+            jellyfin.api.get_user(&user_id),
+            jellyfin.media_folders(),
+        )?;
+        let action = if user.has_all_folders() {
+            // keep what the user sees now, but stop granting libraries added later
+            let user_folders = user.accessible_folders(&folders);
+            user.policy = jellyfin.server.set_user_libraries(&user, Some(&user_folders)).await?;
+            audit::Action::AllLibrariesDisabled { user: user.name.clone() }
+        } else {
+            user.policy = jellyfin.server.set_user_libraries(&user, None).await?;
+            audit::Action::AllLibrariesEnabled { user: user.name.clone() }
+        };
+        jellyfin.invalidate();
+        state.audit.record(actor.name(), action).await?;
+        templates::user_libraries_entry(&user, &state.listed_folders(&folders).await, &state.presets)
+    }
+
+    #[tracing::instrument(skip_all, fields(user = %actor.name()))]
+    pub async fn batch_user_libraries(
+        State(state): State<AppState>,
+        jellyfin: Jellyfin,
+        actor: Actor,
+        Form(form): Form<Vec<(String, String)>>,
+    ) -> Result<Response, Error> {
+        let mut grant = None;
+        let mut folder_id = None;
+        let mut user_ids = Vec::new();
+        for (key, value) in form {
+            match key.as_str() {
+                "action" => grant = Some(value == "grant"),
+                "folder" => folder_id = Some(value),
+                "user_ids" => user_ids.push(value),
+                _ => {}
+            }
+        }
+        let (Some(grant), Some(folder_id)) = (grant, folder_id) else {
+            return Err(anyhow::anyhow!("action and folder are required").into());
+        };
+        let (users, folders) = tokio::try_join!(
+            jellyfin.users(),
+            jellyfin.media_folders(),
+        )?;
+        let folder = folders.iter().find(|f| f.id == folder_id).ok_or(Error::NotFound)?;
+        let selected = users.iter().filter(|u| user_ids.contains(&u.id));
+        let results = futures::future::join_all(selected.map(|user| async {
+            // the cached user only names them, their policy may have changed since
+            let fresh = match jellyfin.api.get_user(&user.id).await {
+                Ok(fresh) => fresh,
+                Err(e) => return (user.name.as_str(), Err(e)),
+            };
+            if grant && fresh.has_all_folders() {
+                return (user.name.as_str(), Ok(()));
+            }
+            let mut user_folders = fresh.accessible_folders(&folders);
+            user_folders.retain(|f| f != &folder.id);
+            if grant {
+                user_folders.push(folder.id.clone());
+            }
+            let result = jellyfin.server.set_user_libraries(&fresh, Some(&user_folders)).await.map(|_| ());
+            if result.is_ok() {
+                let (user, folder) = (user.name.clone(), folder.name.clone());
+                let action = if grant {
+                    audit::Action::LibraryEnabled { user, folder }
+                } else {
+                    audit::Action::LibraryDisabled { user, folder }
+                };
+                if let Err(e) = state.audit.record(actor.name(), action).await {
+                    tracing::error!("failed to record audit event: {}", e);
+                }
+            }
+            (user.name.as_str(), result)
+        }))
+        .await;
+        jellyfin.invalidate();
+        let response = Response::builder()
+            // reload the user list to show the new state
+            .header("hx-trigger", "users-changed")
+            .body(Body::from(templates::batch_results(folder, grant, &results).into_string()))
+            .unwrap();
+        Ok(response)
+    }
+
+    #[tracing::instrument(skip_all, fields(user_id = %user_id, user = %actor.name()))]
+    pub async fn apply_preset(
+        State(state): State<AppState>,
+        jellyfin: Jellyfin,
+        actor: Actor,
+        PathExtractor(user_id): PathExtractor<String>,
+        Form(selection): Form<PresetSelection>,
+    ) -> Result<Response, Error> {
+        let preset = state.presets.iter().find(|p| p.name == selection.preset).ok_or(Error::NotFound)?;
+        let (mut user, folders) = tokio::try_join!(
+            jellyfin.api.get_user(&user_id),
+            jellyfin.media_folders(),
+        )?;
+        let (folder_ids, unknown) = preset.resolve(&folders);
+        // applying the rest would take away libraries the preset was meant to grant
+        if !unknown.is_empty() {
+            let message = format!("preset {} names unknown libraries: {}", preset.name, unknown.join(", "));
+            return Ok((StatusCode::UNPROCESSABLE_ENTITY, message).into_response());
+        }
+        user.policy = jellyfin.server.set_user_libraries(&user, Some(&folder_ids)).await?;
+        jellyfin.invalidate();
+        let action = audit::Action::PresetApplied { user: user.name.clone(), preset: preset.name.clone() };
+        state.audit.record(actor.name(), action).await?;
+        Ok(templates::user_libraries_entry(&user, &state.listed_folders(&folders).await, &state.presets)?.into_response())
+    }
+
+    #[tracing::instrument(skip_all, fields(user_id = %user_id, user = %actor.name()))]
+    pub async fn copy_user_libraries(
+        State(state): State<AppState>,
+        jellyfin: Jellyfin,
+        actor: Actor,
+        PathExtractor(user_id): PathExtractor<String>,
+        Form(form): Form<CopyLibraries>,
+    ) -> Result<Markup, Error> {
+        let (users, folders) = tokio::try_join!(
+            jellyfin.users(),
+            jellyfin.media_folders(),
+        )?;
+        let source = users
+            .iter()
+            .find(|u| u.name == form.source || u.id == form.source)
+            .ok_or(Error::NotFound)?;
+        // cached users only find the source, both policies come from Jellyfin
+        let (mut user, source) = tokio::try_join!(
+            jellyfin.api.get_user(&user_id),
+            jellyfin.api.get_user(&source.id),
+        )?;
+        user.policy = if source.has_all_folders() {
+            jellyfin.server.set_user_libraries(&user, None).await?
+        } else {
+            jellyfin.server.set_user_libraries(&user, Some(source.enabled_folders())).await?
+        };
+        jellyfin.invalidate();
+        let action = audit::Action::LibrariesCopied { user: user.name.clone(), source: source.name.clone() };
+        state.audit.record(actor.name(), action).await?;
+        templates::user_libraries_entry(&user, &state.listed_folders(&folders).await, &state.presets)
+    }
+
+    pub async fn presets(State(state): State<AppState>, jellyfin: Jellyfin) -> Result<Markup, Error> {
+        let folders = jellyfin.media_folders().await?;
+        let server_picker = templates::server_picker(&state.jellyfin, &jellyfin.name);
+        Ok(templates::presets_page(&state.presets, &folders, server_picker))
+    }
+
+    pub async fn audit(State(state): State<AppState>) -> Markup {
+        let events = state.audit.recent(AUDIT_PAGE_EVENTS).await;
+        templates::audit_page(&events, state.audit.path())
+    }
+
+    const AUDIT_PAGE_EVENTS: usize = 200;
+
+    pub async fn activity(State(state): State<AppState>) -> Markup {
+        templates::activity_page(&state.audit.recent(ACTIVITY_EVENTS).await)
+    }
+
+    /// The timeline of the activity page, fetched again whenever a change is recorded.
+    pub async fn activity_feed(State(state): State<AppState>) -> Markup {
+        templates::activity_feed(&state.audit.recent(ACTIVITY_EVENTS).await)
+    }
+
+    const ACTIVITY_EVENTS: usize = 100;
+
+    pub async fn movie_list(
+        State(state): State<AppState>,
+        preferences: ListPreferences,
+        Query(paging): Query<OptionalPaging>,
+        Query(filter): Query<MovieFilter>,
+        Query(cursor): Query<ScrollCursor>,
+    ) -> impl IntoResponse {
+        let (paging, filter) = preferences.apply(paging, filter);
+        let sources = state.movie_sources().await;
+        let collection = state.collection.read().await;
+        let list = match &cursor.after {
+            Some(after) => templates::movies_after(&collection, &sources, paging, &filter, after),
+            None => templates::movie_list(&collection, &sources, paging, &filter),
+        };
+        (preferences.remember(&state, paging, &filter), list)
+    }
+
+    #[tracing::instrument(skip_all, fields(tag = %tag))]
+    pub async fn tag_page(
+        State(state): State<AppState>,
+        PathExtractor(tag): PathExtractor<String>,
+        preferences: ListPreferences,
+        Query(paging): Query<OptionalPaging>,
+        Query(mut filter): Query<MovieFilter>,
+    ) -> Result<impl IntoResponse, Error> {
+        let sources = state.movie_sources().await;
+        let collection = state.collection.read().await;
+        let tag = collection.resolve_tag(&tag).ok_or(Error::NotFound)?.to_string();
+        let tag_name = collection.tags[&tag].display_name().to_string();
+        filter.tag = Some(tag);
+        let (paging, filter) = preferences.apply(paging, filter);
+        let page = templates::tag_page(&collection, &sources, &tag_name, paging, &filter, state.tag_libraries);
+        Ok((preferences.remember(&state, paging, &filter), page))
+    }
+
+    #[tracing::instrument(skip_all, fields(user = %actor.name()))]
+    pub async fn create_tag(
+        State(state): State<AppState>,
+        jellyfin: Option<Jellyfin>,
+        actor: Actor,
+        Form(new_tag): Form<NewTag>,
+    ) -> Result<Response, Error> {
+        let tag = new_tag.name.trim();
+        let tag_path = {
+            let mut collection = state.collection_mut().await;
+            collection.create_tag(tag).await?;
+            collection.path_mappings.to_jellyfin(&collection.tag_dir.join(tag))
+        };
+        state.audit.record(actor.name(), audit::Action::TagCreated { tag: tag.to_string() }).await?;
+        if let Some(jellyfin) = jellyfin.filter(|_| state.tag_libraries) {
+            let library = collection::tag_library_name(tag);
+            jellyfin.api.add_virtual_folder(&library, &tag_path.to_string_lossy()).await?;
+            jellyfin.invalidate();
+            state.audit.record(actor.name(), audit::Action::TagLibraryCreated { tag: tag.to_string(), library }).await?;
+        }
+        Ok(Response::builder()
+            .header("hx-refresh", "true")
+            .body(Body::empty())
+            .unwrap())
+    }
+
+    #[tracing::instrument(skip_all, fields(tag = %tag, user = %actor.name()))]
+    pub async fn delete_tag(
+        State(state): State<AppState>,
+        jellyfin: Option<Jellyfin>,
+        actor: Actor,
+        PathExtractor(tag): PathExtractor<String>,
+    ) -> Result<Response, Error> {
+        let tag = {
+            let mut collection = state.collection_mut().await;
+            let tag = collection.resolve_tag(&tag).ok_or(Error::NotFound)?.to_string();
+            collection.delete_tag(&tag).await?;
+            tag
+        };
+        state.audit.record(actor.name(), audit::Action::TagDeleted { tag: tag.clone() }).await?;
+        remove_tag_library(&state, jellyfin, &actor, tag).await?;
+        Ok(Response::builder()
+            .header("hx-redirect", theme::url("/"))
+            .body(Body::empty())
+            .unwrap())
+    }
+
+    /// Moves every movie of one tag into another and deletes the first, for duplicates like
+    /// `sci-fi` and `scifi`. Redirects to the remaining tag.
+    #[tracing::instrument(skip_all, fields(tag = %from, into = %into, user = %actor.name()))]
+    pub async fn merge_tag(
+        State(state): State<AppState>,
+        jellyfin: Option<Jellyfin>,
+        actor: Actor,
+        PathExtractor((from, into)): PathExtractor<(String, String)>,
+    ) -> Result<Response, Error> {
+        let (from, into, moved) = {
+            let mut collection = state.collection_mut().await;
+            let from = collection.resolve_tag(&from).ok_or(Error::NotFound)?.to_string();
+            let into = collection.resolve_tag(&into).ok_or(Error::NotFound)?.to_string();
+            // either may be an alias of the other
+            if from == into {
+                return Ok((StatusCode::BAD_REQUEST, "can't merge a tag into itself").into_response());
+            }
+            let moved = collection.merge_tag(&from, &into).await?;
+            (from, into, moved)
+        };
+        for movie in &moved {
+            let action = audit::Action::TagAdded { tag: into.clone(), movie: movie.clone() };
+            state.audit.announce(actor.name(), &action);
+        }
+        let action = audit::Action::TagMerged { from: from.clone(), into: into.clone(), movies: moved.len() };
+        state.audit.record(actor.name(), action).await?;
+        remove_tag_library(&state, jellyfin, &actor, from).await?;
+        if let Some(library_refresh) = &state.library_refresh {
+            library_refresh.schedule();
+        }
+        state.updates.notify(updates::Update::Collection);
+        Ok(Response::builder()
+            .header("hx-redirect", format!("{}{}", theme::url("/tag/"), utf8_percent_encode(&into, NON_ALPHANUMERIC)))
+            .body(Body::empty())
+            .unwrap())
+    }
+
+    /// Removes the Jellyfin library of a deleted tag when tag libraries are managed.
+    async fn remove_tag_library(
+        state: &AppState,
+        jellyfin: Option<Jellyfin>,
+        actor: &Actor,
+        tag: String,
+    ) -> Result<(), Error> {
+        let Some(jellyfin) = jellyfin.filter(|_| state.tag_libraries) else {
+            return Ok(());
+        };
+        let library = collection::tag_library_name(&tag);
+        let removed = jellyfin.api.remove_virtual_folder(&library).await;
+        jellyfin.invalidate();
+        match removed {
+            // the library may never have been created or was removed by hand
+            Err(Error::NotFound) => tracing::info!("no Jellyfin library named {}", library),
+            result => {
+                result?;
+                state.audit.record(actor.name(), audit::Action::TagLibraryDeleted { tag, library }).await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Fills the Jellyfin collection named after the tag with its movies, creating it first if
+    /// needed. Movies Jellyfin hasn't scanned yet are left out.
+    #[tracing::instrument(skip_all, fields(tag = %tag, user = %actor.name()))]
+    pub async fn export_collection(
+        State(state): State<AppState>,
+        jellyfin: Jellyfin,
+        actor: Actor,
+        PathExtractor(tag): PathExtractor<String>,
+    ) -> Result<Markup, Error> {
+        let (tag, name, folders) = {
+            let collection = state.collection.read().await;
+            let tag = collection.resolve_tag(&tag).ok_or(Error::NotFound)?;
+            let tagged = &collection.tags[tag];
+            let folders: HashSet<String> = tagged
+                .movies
+                .iter()
+                .filter_map(|hash| collection.movies.get(hash))
+                .map(|movie| movie.name.clone())
+                .collect();
+            (tag.to_string(), tagged.display_name().to_string(), folders)
+        };
+        let mut found = HashSet::new();
+        let mut item_ids = Vec::new();
+        for item in jellyfin.api.get_movies().await? {
+            if let Some(folder) = item.folder_name().filter(|folder| folders.contains(*folder)) {
+                found.insert(folder.to_string());
+                item_ids.push(item.id);
+            }
+        }
+        let existing = jellyfin.api.get_collections().await?.into_iter().find(|c| c.name == name);
+        match existing {
+            Some(existing) => jellyfin.api.add_to_collection(&existing.id, &item_ids).await?,
+            None => {
+                jellyfin.api.create_collection(&name, &item_ids).await?;
+            }
+        }
+        let action = audit::Action::CollectionExported { tag, collection: name.clone() };
+        state.audit.record(actor.name(), action).await?;
+        Ok(templates::collection_exported(&name, found.len(), folders.len() - found.len()))
+    }
+
+    #[tracing::instrument(skip_all, fields(tag = %tag))]
+    pub async fn tag_movie_list(
+        State(state): State<AppState>,
+        PathExtractor(tag): PathExtractor<String>,
+        preferences: ListPreferences,
+        Query(paging): Query<OptionalPaging>,
+        Query(mut filter): Query<MovieFilter>,
+        Query(cursor): Query<ScrollCursor>,
+    ) -> Result<impl IntoResponse, Error> {
+        let sources = state.movie_sources().await;
+        let collection = state.collection.read().await;
+        let tag = collection.resolve_tag(&tag).ok_or(Error::NotFound)?.to_string();
+        filter.tag = Some(tag);
+        let (paging, filter) = preferences.apply(paging, filter);
+        let list = match &cursor.after {
+            Some(after) => templates::movies_after(&collection, &sources, paging, &filter, after),
+            None => templates::movie_list(&collection, &sources, paging, &filter),
+        };
+        Ok((preferences.remember(&state, paging, &filter), list))
+    }
+
+    /// The tagging rules and what running them would change.
+    pub async fn rules(State(state): State<AppState>) -> Markup {
+        let pending = state.plan_rules().await;
+        templates::rules_page(&state.rules, &pending, None)
+    }
+
+    #[tracing::instrument(skip_all, fields(user = %actor.name()))]
+    pub async fn run_rules(State(state): State<AppState>, actor: Actor) -> Result<Markup, Error> {
+        let applied = state.apply_rules(actor.name()).await?;
+        let pending = state.plan_rules().await;
+        Ok(templates::rules_page(&state.rules, &pending, Some(&applied)))
+    }
+
+    pub async fn import() -> Markup {
+        templates::import_page(None)
+    }
+
+    /// Tags the movies listed in an uploaded Letterboxd export or other CSV file, reporting the
+    /// rows matching no movie.
+    #[tracing::instrument(skip_all, fields(user = %actor.name(), tag))]
+    pub async fn import_letterboxd(
+        State(state): State<AppState>,
+        actor: Actor,
+        multipart: axum::extract::Multipart,
+    ) -> Result<Response, Error> {
+        let (tag, data) = match read_import_form(multipart).await {
+            Ok(form) => form,
+            Err(rejection) => return Ok(rejection.into_response()),
+        };
+        let tag = tag.trim();
+        if tag.is_empty() {
+            return Ok((StatusCode::BAD_REQUEST, "a tag to import into is required").into_response());
+        }
+        tracing::Span::current().record("tag", tag);
+        let rows = match import::parse_csv(&data) {
+            Ok(rows) => rows,
+            Err(e) => return Ok((StatusCode::UNPROCESSABLE_ENTITY, format!("invalid CSV file: {}", e)).into_response()),
+        };
+        let plan = import::plan(&*state.collection.read().await, rows, tag);
+        let applied = state.apply_changes(actor.name(), plan.changes.clone()).await?;
+        if !applied.is_empty() {
+            state.updates.notify(updates::Update::Collection);
+        }
+        Ok(templates::import_page(Some((&plan, &applied))).into_response())
+    }
+
+    /// The `tag` and `file` fields of the import form.
+    async fn read_import_form(
+        mut multipart: axum::extract::Multipart,
+    ) -> Result<(String, axum::body::Bytes), Response> {
+        let (mut tag, mut data) = (None, None);
+        while let Some(field) = multipart.next_field().await.map_err(IntoResponse::into_response)? {
+            match field.name() {
+                Some("tag") => tag = Some(field.text().await.map_err(IntoResponse::into_response)?),
+                Some("file") => data = Some(field.bytes().await.map_err(IntoResponse::into_response)?),
+                _ => {}
+            }
+        }
+        match (tag, data) {
+            (Some(tag), Some(data)) => Ok((tag, data)),
+            _ => Err((StatusCode::BAD_REQUEST, "the tag and file fields are required").into_response()),
+        }
+    }
+
+    pub async fn trakt(State(state): State<AppState>) -> Result<Markup, Error> {
+        let trakt = state.trakt.as_ref().ok_or(Error::NotFound)?;
+        let connection = trakt.connection().await;
+        let last_sync = trakt.last_sync.read().await.clone();
+        Ok(templates::trakt_page(&trakt.lists, &connection, last_sync.as_ref()))
+    }
+
+    /// The connection section of the Trakt page, polled while waiting for the user to enter the
+    /// code.
+    pub async fn trakt_connection(State(state): State<AppState>) -> Result<Markup, Error> {
+        let trakt = state.trakt.as_ref().ok_or(Error::NotFound)?;
+        Ok(templates::trakt_connection(&trakt.connection().await))
+    }
+
+    #[tracing::instrument(skip_all, fields(user = %actor.name()))]
+    pub async fn trakt_connect(State(state): State<AppState>, actor: Actor) -> Result<Markup, Error> {
+        let trakt = state.trakt.as_ref().ok_or(Error::NotFound)?;
+        trakt.connect(state.audit.clone(), actor.name().to_string()).await?;
+        Ok(templates::trakt_connection(&trakt.connection().await))
+    }
+
+    #[tracing::instrument(skip_all, fields(user = %actor.name()))]
+    pub async fn trakt_sync(State(state): State<AppState>, actor: Actor) -> Result<Redirect, Error> {
+        let trakt = state.trakt.as_ref().ok_or(Error::NotFound)?;
+        let reports = trakt::sync(&state, trakt, actor.name()).await?;
+        if reports.iter().any(|report| !report.tagged.is_empty()) {
+            state.updates.notify(updates::Update::Collection);
+        }
+        Ok(Redirect::to(&theme::url("/trakt")))
+    }
+
+    pub async fn snapshots(State(state): State<AppState>) -> Result<Markup, Error> {
+        let tag_dir = state.collection.read().await.tag_dir.clone();
+        let snapshots = Snapshot::list(&tag_dir).await?;
+        Ok(templates::snapshots_page(&snapshots, None))
+    }
+
+    #[tracing::instrument(skip_all, fields(user = %actor.name()))]
+    pub async fn take_snapshot(State(state): State<AppState>, actor: Actor) -> Result<Redirect, Error> {
+        let (snapshot, tag_dir) = {
+            let collection = state.collection.read().await;
+            (Snapshot::take(&collection), collection.tag_dir.clone())
+        };
+        snapshot.save(&tag_dir).await?;
+        state.audit.record(actor.name(), audit::Action::SnapshotTaken { snapshot: snapshot.id }).await?;
+        Ok(Redirect::to(&theme::url("/snapshots")))
+    }
+
+    #[tracing::instrument(skip_all, fields(snapshot = %id, user = %actor.name()))]
+    pub async fn restore_snapshot(
+        State(state): State<AppState>,
+        actor: Actor,
+        PathExtractor(id): PathExtractor<String>,
+    ) -> Result<Markup, Error> {
+        let (snapshot, restored, tag_dir) = {
+            let mut collection = state.collection_mut().await;
+            let snapshot = Snapshot::load(&collection.tag_dir, &id).await?;
+            let restored = snapshot.restore(&mut collection).await?;
+            (snapshot, restored, collection.tag_dir.clone())
+        };
+        for tag in &restored.created_tags {
+            state.audit.record(actor.name(), audit::Action::TagCreated { tag: tag.clone() }).await?;
+        }
+        for (tag, movie) in &restored.added {
+            state.audit.record(actor.name(), audit::Action::TagAdded { tag: tag.clone(), movie: movie.clone() }).await?;
+        }
+        for (tag, movie) in &restored.removed {
+            state.audit.record(actor.name(), audit::Action::TagRemoved { tag: tag.clone(), movie: movie.clone() }).await?;
+        }
+        state.audit.record(actor.name(), audit::Action::SnapshotRestored { snapshot: id }).await?;
+        if let Some(library_refresh) = &state.library_refresh {
+            library_refresh.schedule();
+        }
+        state.updates.notify(updates::Update::Collection);
+        let snapshots = Snapshot::list(&tag_dir).await?;
+        Ok(templates::snapshots_page(&snapshots, Some((&snapshot, &restored))))
+    }
+
+    pub async fn view_page(
+        State(state): State<AppState>,
+        PathExtractor(name): PathExtractor<String>,
+        preferences: ListPreferences,
+        Query(paging): Query<OptionalPaging>,
+        Query(mut filter): Query<MovieFilter>,
+    ) -> Result<impl IntoResponse, Error> {
+        filter.view = Some(state.view(&name)?);
+        let (paging, filter) = preferences.apply(paging, filter);
+        let sources = state.movie_sources().await;
+        let collection = state.collection.read().await;
+        let page = templates::view_page(&collection, &sources, paging, &filter);
+        Ok((preferences.remember(&state, paging, &filter), page))
+    }
+
+    pub async fn view_movie_list(
+        State(state): State<AppState>,
+        PathExtractor(name): PathExtractor<String>,
+        preferences: ListPreferences,
+        Query(paging): Query<OptionalPaging>,
+        Query(mut filter): Query<MovieFilter>,
+        Query(cursor): Query<ScrollCursor>,
+    ) -> Result<impl IntoResponse, Error> {
+        filter.view = Some(state.view(&name)?);
+        let (paging, filter) = preferences.apply(paging, filter);
+        let sources = state.movie_sources().await;
+        let collection = state.collection.read().await;
+        let list = match &cursor.after {
+            Some(after) => templates::movies_after(&collection, &sources, paging, &filter, after),
+            None => templates::movie_list(&collection, &sources, paging, &filter),
+        };
+        Ok((preferences.remember(&state, paging, &filter), list))
+    }
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct ServerSelection {
+    server: String,
+}
+
+#[derive(Debug, Default, Deserialize, Clone)]
+pub struct RandomOptions {
+    /// Only pick among movies carrying this tag
+    tag: Option<String>,
+    /// Pick the same movie again, e.g. for sharing a suggestion
+    seed: Option<u64>,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct PosterChoice {
+    url: String,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct NoteForm {
+    note: String,
+}
+
+#[derive(Debug, Deserialize, Clone, utoipa::ToSchema)]
+pub struct RatingForm {
+    /// Stars from 1 to 5, or `null` to clear the rating
+    rating: Option<u8>,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct SchemeSelection {
+    /// `light`, `dark`, or anything else to follow the system
+    scheme: String,
+}
+
+/// Narrows the user libraries page down to some users.
+#[derive(Debug, Default, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct UserFilter {
+    /// Part of the user name, ignoring case
+    #[serde(skip_serializing_if = "String::is_empty")]
+    q: String,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    hide_disabled: bool,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    hide_admins: bool,
+}
+
+impl UserFilter {
+    fn matches(&self, user: &jellyfin_api::User) -> bool {
+        (!self.hide_disabled || !user.is_disabled())
+            && (!self.hide_admins || !user.is_admin())
+            && user.name.to_lowercase().contains(&self.q.trim().to_lowercase())
+    }
+
+    /// Query string reproducing this filter, without the leading `?`. Empty if nothing is set.
+    pub fn query(&self) -> String {
+        serde_urlencoded::to_string(self).unwrap_or_default()
+    }
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct PresetSelection {
+    preset: String,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct CopyLibraries {
+    /// Name or id of the user whose libraries are copied
+    source: String,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct NewTag {
+    name: String,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct NewUser {
+    name: String,
+    #[serde(default)]
+    password: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Copy, Clone, Eq, PartialEq)]
+pub struct PosterOptions {
+    w: Option<u32>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize, Copy, Clone, Eq, PartialEq, PartialOrd, Ord)]
+#[serde(rename_all = "lowercase")]
+pub enum Sort {
+    #[default]
+    Name,
+    Year,
+    /// Highest resolution first, needs ffprobe
+    Resolution,
+    /// Most recently added folders first
+    Added,
+    /// Most stars first
+    Rating,
+}
+
+impl Sort {
+    pub const ALL: [Sort; 5] = [Sort::Name, Sort::Year, Sort::Resolution, Sort::Added, Sort::Rating];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Sort::Name => "name",
+            Sort::Year => "year",
+            Sort::Resolution => "resolution",
+            Sort::Added => "added",
+            Sort::Rating => "rating",
+        }
+    }
+}
+
+#[derive(Debug, Default, Serialize, Deserialize, Copy, Clone, Eq, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum TagSort {
+    Name,
+    /// Most used first
+    #[default]
+    Count,
+}
+
+#[derive(Debug, Serialize, Deserialize, Copy, Clone, Eq, PartialEq)]
+pub struct StatsOptions {
+    tag_sort: Option<TagSort>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize, Copy, Clone, Eq, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum DiskSort {
+    Name,
+    /// Largest first
+    #[default]
+    Size,
+}
+
+#[derive(Debug, Serialize, Deserialize, Copy, Clone, Eq, PartialEq)]
+pub struct DiskUsageOptions {
+    sort: Option<DiskSort>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Copy, Clone, Eq, PartialEq)]
+pub struct OptionalPaging {
+    page: Option<usize>,
+    per_page: Option<usize>,
+    sort: Option<Sort>,
+    scroll: Option<bool>,
+}
 
 #[derive(Debug, Serialize, Deserialize, Copy, Clone, Eq, PartialEq, PartialOrd, Ord)]
 pub struct Paging {
     page: usize,
     per_page: usize,
+    sort: Sort,
+    /// Load more movies when scrolling to the end of the list instead of showing pages
+    scroll: bool,
 }
 
 impl From<OptionalPaging> for Paging {
@@ -240,7 +2712,9 @@ impl From<OptionalPaging> for Paging {
         let default = Self::default();
         Self {
             page: paging.page.unwrap_or(default.page),
-            per_page: paging.per_page.unwrap_or(default.per_page),
+            per_page: paging.per_page.map_or(default.per_page, |per_page| per_page.clamp(*Self::PER_PAGE.start(), *Self::PER_PAGE.end())),
+            sort: paging.sort.unwrap_or(default.sort),
+            scroll: paging.scroll.unwrap_or(default.scroll),
         }
     }
 }
@@ -250,16 +2724,155 @@ impl Default for Paging {
         Self {
             page: 1,
             per_page: 50,
+            sort: Sort::default(),
+            scroll: false,
         }
     }
 }
 
 impl Paging {
+    /// How many movies a page can show
+    const PER_PAGE: std::ops::RangeInclusive<usize> = 1..=500;
+
     pub fn offset(&self) -> usize {
-        self.page.saturating_sub(1) * self.per_page
+        self.page.saturating_sub(1).saturating_mul(self.per_page)
     }
 
     pub fn last_page(&self, total: usize) -> usize {
-        (total + self.per_page - 1) / self.per_page
+        total.div_ceil(self.per_page).max(1)
+    }
+
+    pub fn with_page(self, page: usize) -> Self {
+        Self { page, ..self }
+    }
+
+    /// Query string reproducing this paging state, without the leading `?`.
+    pub fn query(&self) -> String {
+        let query = format!("page={}&per_page={}&sort={}", self.page, self.per_page, self.sort.as_str());
+        if self.scroll {
+            format!("{}&scroll=true", query)
+        } else {
+            query
+        }
+    }
+}
+
+/// Id of the last movie already shown in infinite scroll mode, to load the ones after it.
+#[derive(Debug, Default, Deserialize, Clone)]
+pub struct ScrollCursor {
+    after: Option<String>,
+}
+
+/// Narrows down what the movie list shows. Carried in the query string next to [`Paging`].
+#[derive(Debug, Default, Serialize, Deserialize, Clone, Eq, PartialEq)]
+pub struct MovieFilter {
+    /// Only show movies carrying this tag. Taken from the `/tag/:name` path.
+    #[serde(skip)]
+    tag: Option<String>,
+    /// Only show movies in this saved view. Taken from the `/view/:name` path.
+    #[serde(skip)]
+    view: Option<Arc<View>>,
+    /// Only show the tags of this group on each movie
+    group: Option<String>,
+    filter: Option<Subset>,
+    resolution: Option<media_info::Resolution>,
+}
+
+const LIST_VIEW_COOKIE: &str = "list_view";
+
+/// How the movie list was last shown, kept in a signed cookie so a plain visit picks up where
+/// the previous one left off. The page number itself isn't remembered.
+#[derive(Debug, Default, Serialize, Deserialize, Clone)]
+pub struct ListPreferences {
+    per_page: Option<usize>,
+    sort: Option<Sort>,
+    scroll: Option<bool>,
+    group: Option<String>,
+    filter: Option<Subset>,
+    resolution: Option<media_info::Resolution>,
+}
+
+#[axum::async_trait]
+impl FromRequestParts<AppState> for ListPreferences {
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, state: &AppState) -> Result<Self, Self::Rejection> {
+        let preferences = cookie(&parts.headers, LIST_VIEW_COOKIE)
+            .and_then(|value| state.cookie_key.verify(value))
+            .and_then(|value| serde_urlencoded::from_str(value).ok())
+            .unwrap_or_default();
+        Ok(preferences)
+    }
+}
+
+impl ListPreferences {
+    /// Fills in what the query leaves out. Links within the list always carry the page, and
+    /// then the filter in the query is complete, so picking "All movies" clears a remembered
+    /// one.
+    fn apply(&self, paging: OptionalPaging, mut filter: MovieFilter) -> (Paging, MovieFilter) {
+        if paging.page.is_none() {
+            filter.group = filter.group.or_else(|| self.group.clone());
+            filter.resolution = filter.resolution.or(self.resolution);
+            // tag and view pages already show a subset
+            if filter.tag.is_none() && filter.view.is_none() {
+                filter.filter = filter.filter.or(self.filter);
+            }
+        }
+        let paging = OptionalPaging {
+            // the cookie may be from a version accepting any number
+            per_page: paging.per_page.or(self.per_page.filter(|per_page| Paging::PER_PAGE.contains(per_page))),
+            sort: paging.sort.or(self.sort),
+            scroll: paging.scroll.or(self.scroll),
+            ..paging
+        };
+        (paging.into(), filter)
+    }
+
+    /// `set-cookie` header remembering the list as shown on the pages of `state`.
+    fn remember(&self, state: &AppState, paging: Paging, filter: &MovieFilter) -> [(axum::http::HeaderName, String); 1] {
+        let preferences = Self {
+            per_page: Some(paging.per_page),
+            sort: Some(paging.sort),
+            scroll: Some(paging.scroll),
+            group: filter.group.clone(),
+            filter: if filter.tag.is_some() || filter.view.is_some() { self.filter } else { filter.filter },
+            resolution: filter.resolution,
+        };
+        let value = state.cookie_key.sign(&serde_urlencoded::to_string(&preferences).unwrap_or_default());
+        [(axum::http::header::SET_COOKIE, set_cookie(&state.base_path, LIST_VIEW_COOKIE, Some(&value)))]
+    }
+}
+
+/// Subsets of the collection the movie list can be limited to.
+#[derive(Debug, Serialize, Deserialize, Copy, Clone, Eq, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Subset {
+    /// Movies without any tag, waiting to be sorted
+    Untagged,
+    /// Movies the Jellyfin user behind the watched badges hasn't played
+    Unwatched,
+}
+
+impl MovieFilter {
+    /// Query string reproducing this filter, without the leading `?`. Empty if nothing is set.
+    pub fn query(&self) -> String {
+        serde_urlencoded::to_string(self).unwrap_or_default()
+    }
+
+    /// Path of the page listing the filtered movies.
+    pub fn page_url(&self) -> String {
+        match (&self.tag, &self.view) {
+            (Some(tag), _) => format!("/tag/{}", utf8_percent_encode(tag, NON_ALPHANUMERIC)),
+            (None, Some(view)) => format!("/view/{}", utf8_percent_encode(&view.name, NON_ALPHANUMERIC)),
+            (None, None) => "/".to_string(),
+        }
+    }
+
+    /// Path of the movie list fragment swapped in by the paging controls.
+    pub fn list_url(&self) -> String {
+        match (&self.tag, &self.view) {
+            (None, None) => "/movies".to_string(),
+            _ => format!("{}/movies", self.page_url()),
+        }
     }
 }
@@ -0,0 +1,84 @@
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use axum::body::Body;
+use axum::extract::{Request, State};
+use axum::http::{Method, StatusCode};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use tokio::sync::Semaphore;
+
+use crate::config::LimitsConfig;
+use crate::AppState;
+
+/// Token bucket refilled at the configured rate.
+#[derive(Debug)]
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+#[derive(Debug)]
+pub struct ChangeLimiter {
+    permits: Semaphore,
+    bucket: Mutex<Bucket>,
+    rate: f64,
+    burst: f64,
+}
+
+impl ChangeLimiter {
+    pub fn new(config: &LimitsConfig) -> Self {
+        let burst = f64::from(config.burst.max(1));
+        Self {
+            permits: Semaphore::new(config.concurrent_changes.max(1)),
+            bucket: Mutex::new(Bucket { tokens: burst, updated: Instant::now() }),
+            rate: config.changes_per_second,
+            burst,
+        }
+    }
+
+    /// Takes a token, or returns how long until the next one is available.
+    fn take(&self) -> Result<(), Duration> {
+        let mut bucket = self.bucket.lock().unwrap();
+        let now = Instant::now();
+        let refill = now.duration_since(bucket.updated).as_secs_f64() * self.rate;
+        bucket.tokens = (bucket.tokens + refill).min(self.burst);
+        bucket.updated = now;
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else if self.rate > 0.0 {
+            Err(Duration::from_secs_f64((1.0 - bucket.tokens) / self.rate))
+        } else {
+            Err(Duration::MAX)
+        }
+    }
+}
+
+impl Default for ChangeLimiter {
+    fn default() -> Self {
+        Self::new(&LimitsConfig::default())
+    }
+}
+
+/// Middleware keeping double clicks and runaway scripts from hammering the filesystem and
+/// Jellyfin.
+pub(crate) async fn limit_changes(State(state): State<AppState>, request: Request, next: Next) -> Response {
+    if matches!(*request.method(), Method::GET | Method::HEAD | Method::OPTIONS) {
+        return next.run(request).await;
+    }
+    let limiter = state.limiter.clone();
+    if let Err(wait) = limiter.take() {
+        tracing::warn!("rate limited {} {}", request.method(), request.uri());
+        let retry_after = wait.as_secs().saturating_add(1).to_string();
+        return Response::builder()
+            .status(StatusCode::TOO_MANY_REQUESTS)
+            .header("retry-after", retry_after)
+            .body(Body::from("Too many changes, try again shortly"))
+            .unwrap();
+    }
+    let Ok(_permit) = limiter.permits.acquire().await else {
+        return StatusCode::SERVICE_UNAVAILABLE.into_response();
+    };
+    next.run(request).await
+}
@@ -0,0 +1,267 @@
+use std::fmt;
+use std::path::Path;
+
+use opentelemetry::trace::TracerProvider as _;
+use opentelemetry::KeyValue;
+use opentelemetry_otlp::WithExportConfig;
+use opentelemetry_sdk::propagation::TraceContextPropagator;
+use opentelemetry_sdk::trace::TracerProvider;
+use opentelemetry_sdk::Resource;
+use serde_json::{Map, Value};
+use tracing::field::{Field, Visit};
+use tracing::{Event, Level, Subscriber};
+use tracing_appender::non_blocking::WorkerGuard;
+use tracing_appender::rolling::{self, Rotation};
+use tracing_subscriber::filter::LevelFilter;
+use tracing_subscriber::fmt::format::{JsonFields, Writer};
+use tracing_subscriber::fmt::{FmtContext, FormatEvent, FormatFields, FormattedFields, MakeWriter};
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::{Layer, Registry};
+
+use crate::insights::Insights;
+use crate::ServeArgs;
+
+/// Rotated log files kept next to the current one.
+const KEPT_LOG_FILES: usize = 7;
+
+/// Header carrying the ID of a request, from the reverse proxy to Jellyfin and back.
+pub(crate) const REQUEST_ID_HEADER: &str = "x-request-id";
+
+/// Longest request ID accepted from a reverse proxy.
+const MAX_REQUEST_ID_LEN: usize = 128;
+
+tokio::task_local! {
+    static REQUEST_ID: String;
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum LogFormat {
+    /// Human readable lines
+    #[default]
+    Pretty,
+    /// One JSON object per line, with the fields of the enclosing spans like `request_id`
+    Json,
+}
+
+/// How often `--log-file` starts a new file.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum LogRotation {
+    Hourly,
+    #[default]
+    Daily,
+    Never,
+}
+
+impl From<LogRotation> for Rotation {
+    fn from(rotation: LogRotation) -> Self {
+        match rotation {
+            LogRotation::Hourly => Rotation::HOURLY,
+            LogRotation::Daily => Rotation::DAILY,
+            LogRotation::Never => Rotation::NEVER,
+        }
+    }
+}
+
+type BoxedLayer = Box<dyn Layer<Registry> + Send + Sync>;
+
+/// Where logs and traces go, kept until the end of `main` so the log file is flushed.
+#[derive(Debug)]
+pub struct Logging {
+    _guard: Option<WorkerGuard>,
+    /// Exports spans with `--otlp-endpoint`
+    tracer_provider: Option<TracerProvider>,
+    /// Request telemetry layer, with `--app-insights-connection-string`
+    pub insights: Option<Insights>,
+}
+
+/// Logs to stdout and, if given, to `--log-file` with the date appended, rotated as configured.
+/// With `--otlp-endpoint`, spans are also exported as traces, and with
+/// `--app-insights-connection-string` sent to Application Insights.
+pub async fn init_logging(args: &ServeArgs) -> anyhow::Result<Logging> {
+    let (level, format) = (args.log_level, args.log_format);
+    let mut layers = vec![layer(format, std::io::stdout, true, level)];
+    let mut guard = None;
+    let mut tracer_provider = None;
+    if let Some(file) = &args.log_file {
+        let name = file.file_name().ok_or_else(|| anyhow::anyhow!("{} is not a file", file.display()))?;
+        let dir = file.parent().filter(|dir| !dir.as_os_str().is_empty()).unwrap_or(Path::new("."));
+        let appender = rolling::Builder::new()
+            .rotation(args.log_rotation.into())
+            .filename_prefix(name.to_string_lossy())
+            .max_log_files(KEPT_LOG_FILES + 1)
+            .build(dir)?;
+        let (writer, file_guard) = tracing_appender::non_blocking(appender);
+        layers.push(layer(format, writer, false, level));
+        guard = Some(file_guard);
+    }
+    if let Some(endpoint) = &args.otlp_endpoint {
+        let (layer, provider) = otlp_layer(endpoint, level)?;
+        layers.push(layer);
+        tracer_provider = Some(provider);
+    }
+    let subscriber = tracing_subscriber::registry().with(layers);
+    let insights = match args.app_insights_connection_string().await? {
+        Some(connection_string) => Some(Insights::init(connection_string, LevelFilter::from_level(level), subscriber)?),
+        None => {
+            subscriber.try_init()?;
+            None
+        }
+    };
+    Ok(Logging { _guard: guard, tracer_provider, insights })
+}
+
+impl Drop for Logging {
+    /// Exports the spans still waiting for the next batch. The tracer of the tracing layer keeps
+    /// the provider alive, so dropping the global one alone wouldn't.
+    fn drop(&mut self) {
+        if let Some(provider) = &self.tracer_provider {
+            if let Err(e) = provider.shutdown() {
+                eprintln!("failed to export the remaining spans: {}", e);
+            }
+            opentelemetry::global::shutdown_tracer_provider();
+        }
+    }
+}
+
+fn layer<W>(format: LogFormat, writer: W, ansi: bool, level: Level) -> BoxedLayer
+where
+    W: for<'w> MakeWriter<'w> + Send + Sync + 'static,
+{
+    let layer = tracing_subscriber::fmt::layer().with_writer(writer);
+    match format {
+        LogFormat::Pretty => layer.with_target(false).with_ansi(ansi).with_filter(LevelFilter::from_level(level)).boxed(),
+        LogFormat::Json => layer
+            .fmt_fields(JsonFields::new())
+            .event_format(FlatJson)
+            .with_filter(LevelFilter::from_level(level))
+            .boxed(),
+    }
+}
+
+/// Exports spans to an OpenTelemetry collector over OTLP/HTTP, e.g. `http://tempo:4318`, and
+/// passes the trace on to Jellyfin in `traceparent` headers.
+fn otlp_layer(endpoint: &str, level: Level) -> anyhow::Result<(BoxedLayer, TracerProvider)> {
+    let exporter = opentelemetry_otlp::new_exporter()
+        .http()
+        .with_endpoint(format!("{}/v1/traces", endpoint.trim_end_matches('/')));
+    let config = opentelemetry_sdk::trace::Config::default()
+        .with_resource(Resource::new([KeyValue::new("service.name", env!("CARGO_PKG_NAME"))]));
+    let provider = opentelemetry_otlp::new_pipeline()
+        .tracing()
+        .with_exporter(exporter)
+        .with_trace_config(config)
+        .install_batch(opentelemetry_sdk::runtime::Tokio)?;
+    let tracer = provider.tracer(env!("CARGO_PKG_NAME"));
+    opentelemetry::global::set_tracer_provider(provider.clone());
+    opentelemetry::global::set_text_map_propagator(TraceContextPropagator::new());
+    let layer = tracing_opentelemetry::layer().with_tracer(tracer).with_filter(LevelFilter::from_level(level)).boxed();
+    Ok((layer, provider))
+}
+
+/// Middleware giving every request an ID: the `X-Request-Id` of the reverse proxy if it sent
+/// a sane one, a new UUID otherwise. The ID is put on the request for the request span, returned
+/// in the response and passed on to Jellyfin by [`inject_request_id`].
+pub(crate) async fn request_id(mut request: axum::extract::Request, next: axum::middleware::Next) -> axum::response::Response {
+    let incoming = request
+        .headers()
+        .get(REQUEST_ID_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .filter(|id| !id.is_empty() && id.len() <= MAX_REQUEST_ID_LEN && id.bytes().all(|b| b.is_ascii_graphic()));
+    let id = incoming.map_or_else(|| uuid::Uuid::new_v4().to_string(), str::to_string);
+    let value = axum::http::HeaderValue::from_str(&id).expect("request IDs are visible ASCII");
+    request.headers_mut().insert(REQUEST_ID_HEADER, value.clone());
+    let mut response = REQUEST_ID.scope(id, next.run(request)).await;
+    response.headers_mut().insert(REQUEST_ID_HEADER, value);
+    response
+}
+
+/// Adds the ID of the request being handled, if any, to the headers of an outgoing request.
+pub(crate) fn inject_request_id(headers: &mut reqwest::header::HeaderMap) {
+    let Ok(Ok(value)) = REQUEST_ID.try_with(|id| reqwest::header::HeaderValue::from_str(id)) else {
+        return;
+    };
+    headers.insert(REQUEST_ID_HEADER, value);
+}
+
+/// Adds the current trace, if exported, to the headers of an outgoing request.
+pub(crate) fn inject_trace_context(headers: &mut reqwest::header::HeaderMap) {
+    use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+    struct Injector<'a>(&'a mut reqwest::header::HeaderMap);
+
+    impl opentelemetry::propagation::Injector for Injector<'_> {
+        fn set(&mut self, key: &str, value: String) {
+            let name = reqwest::header::HeaderName::from_bytes(key.as_bytes());
+            let value = reqwest::header::HeaderValue::from_str(&value);
+            if let (Ok(name), Ok(value)) = (name, value) {
+                self.0.insert(name, value);
+            }
+        }
+    }
+
+    let context = tracing::Span::current().context();
+    opentelemetry::global::get_text_map_propagator(|propagator| {
+        propagator.inject_context(&context, &mut Injector(headers))
+    });
+}
+
+/// Formats events as JSON objects holding the event fields next to the fields of every span
+/// the event happened in, so `request_id` can be queried like any other field.
+struct FlatJson;
+
+impl<S, N> FormatEvent<S, N> for FlatJson
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    N: for<'a> FormatFields<'a> + 'static,
+{
+    fn format_event(&self, ctx: &FmtContext<'_, S, N>, mut writer: Writer<'_>, event: &Event<'_>) -> fmt::Result {
+        let metadata = event.metadata();
+        let mut fields = Map::new();
+        fields.insert("timestamp".to_string(), chrono::Utc::now().to_rfc3339().into());
+        fields.insert("level".to_string(), metadata.level().as_str().into());
+        fields.insert("target".to_string(), metadata.target().into());
+        if let Some(scope) = ctx.event_scope() {
+            for span in scope.from_root() {
+                let extensions = span.extensions();
+                let Some(formatted) = extensions.get::<FormattedFields<N>>() else {
+                    continue;
+                };
+                if let Ok(Value::Object(span_fields)) = serde_json::from_str(&formatted.fields) {
+                    fields.extend(span_fields);
+                }
+            }
+        }
+        event.record(&mut JsonVisitor(&mut fields));
+        writeln!(writer, "{}", Value::Object(fields))
+    }
+}
+
+struct JsonVisitor<'a>(&'a mut Map<String, Value>);
+
+impl Visit for JsonVisitor<'_> {
+    fn record_f64(&mut self, field: &Field, value: f64) {
+        self.0.insert(field.name().to_string(), value.into());
+    }
+
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.0.insert(field.name().to_string(), value.into());
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.0.insert(field.name().to_string(), value.into());
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.0.insert(field.name().to_string(), value.into());
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.0.insert(field.name().to_string(), value.into());
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        self.0.insert(field.name().to_string(), format!("{:?}", value).into());
+    }
+}
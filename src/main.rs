@@ -1,17 +1,390 @@
-use tagrs::{Collection, Cli, router, jellyfin_api, AppState};
+use tagrs::{Collection, Cli, CookieKey, router, init_logging, jellyfin_api, activated_socket, notify_ready, ActivatedSocket, AppState, AuditLog, Config, JellyfinServers, Radarr, RadarrClient, TemplateOverrides, Trakt, Translations, Webhooks};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use axum_server::tls_rustls::RustlsConfig;
 use clap::Parser;
+use futures::future::{BoxFuture, Shared};
+use futures::FutureExt;
+
+/// How often the TLS certificate files are checked for changes.
+const TLS_RELOAD_INTERVAL: std::time::Duration = std::time::Duration::from_secs(60);
+
+/// How long open connections, like the live update streams of open pages, may take to finish
+/// after a shutdown signal before they are dropped.
+const SHUTDOWN_GRACE: std::time::Duration = std::time::Duration::from_secs(10);
+
+/// Resolves once tagrs is asked to shut down, for every server and connection waiting on it.
+type Stopping = Shared<BoxFuture<'static, ()>>;
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
-    let args = Cli::parse();
-    tracing_subscriber::fmt().with_max_level(args.log_level).with_target(false).init();
-    let collection = Collection::new(&args.movie_dir, &args.tag_dir).await?;
+    let cli = Cli::parse();
+    if let Some(command) = cli.command {
+        return command.run();
+    }
+    let args = cli.serve.expect("clap requires the server arguments without a subcommand");
+    let logging = init_logging(&args).await?;
+    let config = match &args.config {
+        Some(path) => Config::load(path).await?,
+        None => Config::default(),
+    };
+    let collection = Collection::new(&args.movie_dir, &args.tag_dir)
+        .await?
+        .with_link_mode(args.link_mode)
+        .with_path_mappings(config.path_mappings.clone())
+        .with_identity(args.identity)
+        .await?;
     tracing::debug!("{}", &collection);
-    let jellyfin_api = jellyfin_api::JellyfinClient::new(args.jellyfin_base_url, args.jellyfin_api_key);
-    tracing::debug!("{:?}", &jellyfin_api);
-    let state = AppState::new(collection, jellyfin_api);
-    let listener = tokio::net::TcpListener::bind(&args.bind).await?;
-    tracing::info!("Starting server on {}", args.bind);
-    axum::serve(listener, router(state)?).await?;
+    let cache_dir = args.cache_dir();
+    let audit = match &args.audit_log {
+        Some(path) => AuditLog::open(path).await?,
+        None => AuditLog::default(),
+    }
+    .sending_to(Webhooks::spawn(config.webhooks).await?);
+    let mut translations = Translations::default();
+    if let Some(dir) = &args.locale_dir {
+        translations = translations.load_dir(dir).await?;
+    }
+    if let Some(locale) = &args.locale {
+        translations = translations.with_locale(locale)?;
+    }
+    let http_client = jellyfin_api::http_client(args.jellyfin_timeout.into(), args.jellyfin_connect_timeout.into());
+    let jellyfin_client = |base_url: String, api_key: String, public_url: Option<String>| {
+        let client = jellyfin_api::JellyfinClient::with_client(base_url, api_key, http_client.clone());
+        match public_url {
+            Some(public_url) => client.with_public_url(public_url),
+            None => client,
+        }
+    };
+    let mut jellyfin = JellyfinServers::default();
+    if let (Some(base_url), Some(api_key)) = (&args.jellyfin_base_url, args.jellyfin_api_key().await?) {
+        let client = jellyfin_client(base_url.clone(), api_key, args.jellyfin_public_url.clone());
+        jellyfin.insert("default".to_string(), args.server_kind, client)?;
+    }
+    for server in &config.servers {
+        let api_key = server.api_key().await?;
+        let client = jellyfin_client(server.base_url.clone(), api_key, server.public_url.clone());
+        jellyfin.insert(server.name.clone(), server.kind, client)?;
+    }
+    let radarr = match &config.radarr {
+        Some(radarr) => {
+            let client = RadarrClient::new(radarr.base_url.clone(), radarr.api_key().await?, http_client.clone());
+            Some(Radarr::new(client))
+        }
+        None => None,
+    };
+    let trakt = match &config.trakt {
+        Some(trakt) => Some(Trakt::load(trakt).await?),
+        None => None,
+    };
+    if jellyfin.is_empty() {
+        anyhow::ensure!(!args.tag_libraries, "--tag-libraries needs a Jellyfin server");
+        tracing::info!("no Jellyfin server configured, only tagging movies");
+    }
+    tracing::debug!("{:?}", &jellyfin);
+    let cookie_secret = args.cookie_secret().await?;
+    let mut state = AppState::new(collection, jellyfin)
+        .with_cache_dir(cache_dir)
+        .with_jellyfin_cache_ttl(args.jellyfin_cache_ttl.into())
+        .with_watched(args.watched_user.clone(), args.jellyfin_cache_ttl.into())
+        .with_audit_log(audit)
+        .with_request_limits(args.request_timeout.into(), args.max_body_size)
+        .with_poster_cache(args.poster_cache_size * 1024 * 1024)
+        .with_cors_origins(args.cors_allowed_origins)
+        .with_base_path(args.base_path)
+        .with_translations(translations)
+        .with_theme(config.theme);
+    if args.trust_remote_user {
+        state = state.with_user_header(args.user_header);
+    } else if !config.roles.users.is_empty() {
+        tracing::warn!("roles of users are ignored without --trust-remote-user, everyone is anonymous");
+    }
+    state = state.with_presets(config.presets).with_user_libraries(config.user_libraries).with_limits(&config.limits).with_roles(config.roles).with_views(config.views).with_rules(config.rules);
+    state = state.with_jellyfin_tags(config.jellyfin_tags);
+    if let Some(secret) = cookie_secret {
+        state = state.with_cookie_key(CookieKey::new(&secret));
+    }
+    if let Some(token) = args.jellyfin_webhook_token {
+        state = state.with_jellyfin_webhook_token(token);
+    }
+    if args.rules_on_reload {
+        state = state.with_rules_on_reload();
+    }
+    if let Some(insights) = &logging.insights {
+        state = state.with_insights(insights.clone());
+    }
+    if args.swagger_ui {
+        state = state.with_swagger_ui();
+    }
+    if args.tag_libraries {
+        state = state.with_tag_libraries();
+    }
+    if let Some(dir) = &args.templates_dir {
+        state = state.with_template_overrides(TemplateOverrides::load(dir).await?);
+    }
+    if let Some(radarr) = radarr {
+        state = state.with_radarr(radarr);
+    }
+    if let Some(trakt) = trakt {
+        state = state.with_trakt(trakt);
+    }
+    if let Some(ffprobe) = &args.ffprobe {
+        state = state.with_ffprobe(ffprobe);
+    }
+    if args.auto_refresh {
+        state = state.with_auto_refresh(args.auto_refresh_delay.into());
+    }
+    if let Some(interval) = args.reload_interval {
+        let state = state.clone();
+        let mut interval = tokio::time::interval(interval.into());
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        tokio::spawn(async move {
+            // the first tick completes immediately and the collection was just loaded
+            interval.tick().await;
+            loop {
+                interval.tick().await;
+                if let Err(e) = state.reload_collection().await {
+                    tracing::warn!("periodic reload failed: {}", e);
+                }
+            }
+        });
+    }
+    state.start_scheduler(config.schedule);
+    let app = router(state)?;
+    let tls = args.tls_cert.as_ref().zip(args.tls_key.as_ref());
+    let stopping = shutdown_signal().boxed().shared();
+    let grace = stopping.clone().then(|()| tokio::time::sleep(SHUTDOWN_GRACE));
+    tokio::select! {
+        served = serve(&args.bind, tls, args.socket_mode, app, stopping) => served?,
+        () = grace => tracing::warn!("dropping connections still open {:?} after shutting down", SHUTDOWN_GRACE),
+    }
+    // returning drops `logging`, flushing logs and traces
+    Ok(())
+}
+
+/// Serves `app` on the socket from systemd if there is one, else on `bind`, until `stopping`
+/// and the requests being answered then are done.
+async fn serve(
+    bind: &str,
+    tls: Option<(&PathBuf, &PathBuf)>,
+    socket_mode: Option<u32>,
+    app: axum::Router,
+    stopping: Stopping,
+) -> anyhow::Result<()> {
+    if let Some(socket) = activated_socket()? {
+        return serve_activated(socket, tls, app, stopping).await;
+    }
+    if let Some(path) = bind.strip_prefix("unix:") {
+        anyhow::ensure!(tls.is_none(), "TLS is not supported on unix sockets");
+        return serve_unix(path.as_ref(), socket_mode, app, stopping).await;
+    }
+    if let Some((cert, key)) = tls {
+        let addr = tokio::net::lookup_host(bind)
+            .await?
+            .next()
+            .ok_or_else(|| anyhow::anyhow!("{} does not resolve to an address", bind))?;
+        let tls = RustlsConfig::from_pem_file(cert, key).await?;
+        reload_certificate(tls.clone(), cert.clone(), key.clone());
+        tracing::info!("Starting server on https://{}", addr);
+        notify_ready();
+        axum_server::bind_rustls(addr, tls).handle(server_handle(stopping)).serve(app.into_make_service()).await?;
+        return Ok(());
+    }
+    let listener = tokio::net::TcpListener::bind(bind).await?;
+    tracing::info!("Starting server on {}", bind);
+    notify_ready();
+    axum::serve(listener, app).with_graceful_shutdown(stopping).await?;
+    Ok(())
+}
+
+/// Handle of an axum-server that stops accepting connections once `stopping`, letting the open
+/// ones finish.
+fn server_handle(stopping: Stopping) -> axum_server::Handle {
+    let handle = axum_server::Handle::new();
+    let shutdown = handle.clone();
+    tokio::spawn(async move {
+        stopping.await;
+        shutdown.graceful_shutdown(None);
+    });
+    handle
+}
+
+/// Resolves on Ctrl+C, or on SIGTERM as sent by systemd and docker to stop tagrs.
+async fn shutdown_signal() {
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut signal) => {
+                signal.recv().await;
+            }
+            Err(e) => {
+                tracing::warn!("can't listen for SIGTERM: {}", e);
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+    tokio::select! {
+        _ = tokio::signal::ctrl_c() => {}
+        () = terminate => {}
+    }
+    tracing::info!("Shutting down");
+}
+
+/// Serves `app` on the socket systemd opened for tagrs, in place of `--bind`.
+async fn serve_activated(
+    socket: ActivatedSocket,
+    tls: Option<(&PathBuf, &PathBuf)>,
+    app: axum::Router,
+    stopping: Stopping,
+) -> anyhow::Result<()> {
+    match socket {
+        ActivatedSocket::Tcp(listener) => {
+            let addr = listener.local_addr()?;
+            if let Some((cert, key)) = tls {
+                let tls = RustlsConfig::from_pem_file(cert, key).await?;
+                reload_certificate(tls.clone(), cert.clone(), key.clone());
+                tracing::info!("Starting server on https://{} from systemd", addr);
+                notify_ready();
+                axum_server::from_tcp_rustls(listener, tls)
+                    .handle(server_handle(stopping))
+                    .serve(app.into_make_service())
+                    .await?;
+                return Ok(());
+            }
+            tracing::info!("Starting server on {} from systemd", addr);
+            notify_ready();
+            axum::serve(tokio::net::TcpListener::from_std(listener)?, app)
+                .with_graceful_shutdown(stopping)
+                .await?;
+            Ok(())
+        }
+        #[cfg(unix)]
+        ActivatedSocket::Unix(listener) => {
+            anyhow::ensure!(tls.is_none(), "TLS is not supported on unix sockets");
+            tracing::info!("Starting server on a unix socket from systemd");
+            accept_unix(tokio::net::UnixListener::from_std(listener)?, app, stopping).await
+        }
+    }
+}
+
+/// Reloads the certificate whenever one of its files is modified, e.g. renewed by certbot.
+/// A failed reload is logged and the previous certificate is kept.
+fn reload_certificate(tls: RustlsConfig, cert: PathBuf, key: PathBuf) {
+    async fn modified(cert: &Path, key: &Path) -> Option<(SystemTime, SystemTime)> {
+        let cert = tokio::fs::metadata(cert).await.ok()?.modified().ok()?;
+        let key = tokio::fs::metadata(key).await.ok()?.modified().ok()?;
+        Some((cert, key))
+    }
+
+    tokio::spawn(async move {
+        let mut loaded = modified(&cert, &key).await;
+        loop {
+            tokio::time::sleep(TLS_RELOAD_INTERVAL).await;
+            let current = modified(&cert, &key).await;
+            if current.is_none() || current == loaded {
+                continue;
+            }
+            match tls.reload_from_pem_file(&cert, &key).await {
+                Ok(()) => {
+                    tracing::info!("reloaded TLS certificate {}", cert.display());
+                    loaded = current;
+                }
+                Err(e) => tracing::warn!("failed to reload TLS certificate {}: {}", cert.display(), e),
+            }
+        }
+    });
+}
+
+/// Serves `app` on a unix socket at `path`, replacing a socket left behind by an earlier run.
+/// Anything else at `path` is left alone.
+#[cfg(unix)]
+async fn serve_unix(path: &Path, mode: Option<u32>, app: axum::Router, stopping: Stopping) -> anyhow::Result<()> {
+    use std::os::unix::fs::FileTypeExt;
+
+    match tokio::fs::symlink_metadata(path).await {
+        Ok(metadata) if metadata.file_type().is_socket() => tokio::fs::remove_file(path).await?,
+        Ok(_) => anyhow::bail!("{} exists and is not a socket", path.display()),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+        Err(e) => return Err(e.into()),
+    }
+    let listener = match mode {
+        Some(mode) => bind_with_mode(path, mode).await?,
+        None => tokio::net::UnixListener::bind(path)?,
+    };
+    tracing::info!("Starting server on {}", path.display());
+    accept_unix(listener, app, stopping).await
+}
+
+/// Binds a socket only reachable with `mode` from the start: it's created in a directory only
+/// the owner may enter, given its mode there and then moved to `path`.
+#[cfg(unix)]
+async fn bind_with_mode(path: &Path, mode: u32) -> anyhow::Result<tokio::net::UnixListener> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let file_name = path.file_name().ok_or_else(|| anyhow::anyhow!("{} is not a file path", path.display()))?;
+    let mut private_name = std::ffi::OsString::from(".");
+    private_name.push(file_name);
+    private_name.push(format!(".{}", std::process::id()));
+    let private_dir = path.with_file_name(private_name);
+    tokio::fs::DirBuilder::new().mode(0o700).create(&private_dir).await?;
+    let staged = private_dir.join(file_name);
+    let bound = async {
+        let listener = tokio::net::UnixListener::bind(&staged)?;
+        tokio::fs::set_permissions(&staged, std::fs::Permissions::from_mode(mode)).await?;
+        tokio::fs::rename(&staged, path).await?;
+        anyhow::Ok(listener)
+    }
+    .await;
+    if let Err(e) = tokio::fs::remove_dir_all(&private_dir).await {
+        tracing::warn!("failed to remove {}: {}", private_dir.display(), e);
+    }
+    bound
+}
+
+/// Serves `app` on each connection to `listener` until `stopping`, then waits for the open
+/// connections to finish the requests they are answering.
+#[cfg(unix)]
+async fn accept_unix(listener: tokio::net::UnixListener, app: axum::Router, stopping: Stopping) -> anyhow::Result<()> {
+    use hyper_util::rt::{TokioExecutor, TokioIo};
+    use hyper_util::server::conn::auto;
+    use hyper_util::server::graceful::GracefulShutdown;
+    use hyper_util::service::TowerToHyperService;
+
+    notify_ready();
+    let builder = auto::Builder::new(TokioExecutor::new());
+    let graceful = GracefulShutdown::new();
+    loop {
+        let accepted = tokio::select! {
+            accepted = listener.accept() => accepted,
+            () = stopping.clone() => break,
+        };
+        let stream = match accepted {
+            Ok((stream, _)) => stream,
+            // e.g. running out of file descriptors, which passes once connections are closed
+            Err(e) => {
+                tracing::warn!("failed to accept a connection: {}", e);
+                tokio::time::sleep(std::time::Duration::from_secs(1)).await;
+                continue;
+            }
+        };
+        let service = TowerToHyperService::new(app.clone());
+        let connection = builder.serve_connection_with_upgrades(TokioIo::new(stream), service).into_owned();
+        let connection = graceful.watch(connection);
+        tokio::spawn(async move {
+            if let Err(e) = connection.await {
+                tracing::debug!("connection failed: {}", e);
+            }
+        });
+    }
+    drop(listener);
+    graceful.shutdown().await;
     Ok(())
 }
+
+#[cfg(not(unix))]
+async fn serve_unix(_path: &Path, _mode: Option<u32>, _app: axum::Router, _stopping: Stopping) -> anyhow::Result<()> {
+    anyhow::bail!("unix sockets are not supported on this platform")
+}
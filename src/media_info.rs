@@ -0,0 +1,204 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+use futures::StreamExt;
+use serde::{Deserialize, Serialize};
+use tokio::sync::RwLock;
+
+use crate::collection::{Collection, Error, Movie};
+
+/// How many ffprobe processes run at once while probing the whole collection.
+const PROBE_CONCURRENCY: usize = 4;
+
+/// Stream details of a movie's primary video file, as reported by ffprobe.
+#[derive(Debug, Clone)]
+pub(crate) struct MediaInfo {
+    /// Path of the probed file relative to the movie folder
+    pub(crate) file: String,
+    /// Modification time of the probed file, to notice when it's replaced
+    modified: SystemTime,
+    pub(crate) width: Option<u32>,
+    pub(crate) height: Option<u32>,
+    pub(crate) video_codec: Option<String>,
+    pub(crate) audio_codecs: Vec<String>,
+    pub(crate) duration: Option<Duration>,
+}
+
+/// Resolution classes the movie list can be filtered on.
+#[derive(Debug, Serialize, Deserialize, Copy, Clone, Eq, PartialEq, PartialOrd, Ord)]
+pub enum Resolution {
+    #[serde(rename = "sd")]
+    Sd,
+    #[serde(rename = "720p")]
+    Hd,
+    #[serde(rename = "1080p")]
+    FullHd,
+    #[serde(rename = "4k")]
+    Uhd,
+}
+
+impl Resolution {
+    pub const ALL: [Resolution; 4] = [Resolution::Sd, Resolution::Hd, Resolution::FullHd, Resolution::Uhd];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Resolution::Sd => "SD",
+            Resolution::Hd => "720p",
+            Resolution::FullHd => "1080p",
+            Resolution::Uhd => "4K",
+        }
+    }
+
+    /// Classifies by width, since cropped widescreen movies are much less tall than their
+    /// resolution's name suggests.
+    fn from_width(width: u32) -> Self {
+        match width {
+            0..=1199 => Resolution::Sd,
+            1200..=1799 => Resolution::Hd,
+            1800..=3199 => Resolution::FullHd,
+            _ => Resolution::Uhd,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct Probe {
+    #[serde(default)]
+    streams: Vec<ProbeStream>,
+    format: Option<ProbeFormat>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ProbeStream {
+    codec_type: Option<String>,
+    codec_name: Option<String>,
+    width: Option<u32>,
+    height: Option<u32>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ProbeFormat {
+    duration: Option<String>,
+}
+
+impl MediaInfo {
+    fn from_probe(file: String, modified: SystemTime, probe: Probe) -> Self {
+        let video = probe.streams.iter().find(|s| s.codec_type.as_deref() == Some("video"));
+        let audio_codecs = probe
+            .streams
+            .iter()
+            .filter(|s| s.codec_type.as_deref() == Some("audio"))
+            .filter_map(|s| s.codec_name.clone())
+            .collect();
+        let duration = probe
+            .format
+            .and_then(|f| f.duration)
+            .and_then(|d| d.parse::<f64>().ok())
+            .map(Duration::from_secs_f64);
+        Self {
+            file,
+            modified,
+            width: video.and_then(|v| v.width),
+            height: video.and_then(|v| v.height),
+            video_codec: video.and_then(|v| v.codec_name.clone()),
+            audio_codecs,
+            duration,
+        }
+    }
+
+    pub(crate) fn resolution(&self) -> Option<Resolution> {
+        self.width.map(Resolution::from_width)
+    }
+}
+
+/// Media info of every probed movie keyed by movie folder.
+pub(crate) type MediaInfoMap = HashMap<PathBuf, MediaInfo>;
+
+/// Runs ffprobe on movies' primary video files, keeping the results in memory until the file
+/// changes. Does nothing unless an ffprobe binary was configured.
+#[derive(Debug, Default)]
+pub struct MediaInfoCache {
+    ffprobe: Option<PathBuf>,
+    infos: RwLock<Arc<MediaInfoMap>>,
+}
+
+impl MediaInfoCache {
+    pub fn new<T: AsRef<Path>>(ffprobe: T) -> Self {
+        Self {
+            ffprobe: Some(ffprobe.as_ref().to_path_buf()),
+            ..Self::default()
+        }
+    }
+
+    pub(crate) fn enabled(&self) -> bool {
+        self.ffprobe.is_some()
+    }
+
+    /// Everything probed so far, for filtering and sorting the movie list.
+    pub(crate) async fn all(&self) -> Arc<MediaInfoMap> {
+        self.infos.read().await.clone()
+    }
+
+    /// Media info of the largest video file in the movie folder, probing it if it isn't cached
+    /// or has changed since.
+    pub(crate) async fn probe(&self, movie: &Movie) -> Result<Option<MediaInfo>, Error> {
+        let Some(ffprobe) = &self.ffprobe else {
+            return Ok(None);
+        };
+        let Some(primary) = movie.video_files().await?.into_iter().max_by_key(|f| f.size) else {
+            return Ok(None);
+        };
+        let file = movie.path.join(&primary.name);
+        let modified = tokio::fs::metadata(&file).await?.modified()?;
+        if let Some(info) = self.infos.read().await.get(&movie.path) {
+            if info.file == primary.name && info.modified == modified {
+                return Ok(Some(info.clone()));
+            }
+        }
+        let output = tokio::process::Command::new(ffprobe)
+            .args(["-v", "error", "-print_format", "json", "-show_streams", "-show_format"])
+            .arg(&file)
+            .kill_on_drop(true)
+            .output()
+            .await?;
+        if !output.status.success() {
+            return Err(anyhow::anyhow!(
+                "ffprobe failed on {}: {}",
+                file.display(),
+                String::from_utf8_lossy(&output.stderr).trim()
+            )
+            .into());
+        }
+        let probe: Probe = serde_json::from_slice(&output.stdout)?;
+        let info = MediaInfo::from_probe(primary.name, modified, probe);
+        Arc::make_mut(&mut *self.infos.write().await).insert(movie.path.clone(), info.clone());
+        Ok(Some(info))
+    }
+
+    /// Probes every movie in the collection in the background so the list can be filtered by
+    /// resolution.
+    pub(crate) fn spawn_probe_all(self: &Arc<Self>, collection: Arc<RwLock<Collection>>) {
+        if !self.enabled() {
+            return;
+        }
+        let cache = self.clone();
+        tokio::spawn(async move {
+            let start = std::time::Instant::now();
+            let movies: Vec<Movie> = collection.read().await.movies.values().cloned().collect();
+            let count = movies.len();
+            futures::stream::iter(movies)
+                .for_each_concurrent(PROBE_CONCURRENCY, |movie| {
+                    let cache = cache.clone();
+                    async move {
+                        if let Err(e) = cache.probe(&movie).await {
+                            tracing::warn!("failed to probe {}: {}", movie.name, e);
+                        }
+                    }
+                })
+                .await;
+            tracing::info!("probed {} movies in {:?}", count, start.elapsed());
+        });
+    }
+}
@@ -0,0 +1,74 @@
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+
+use crate::collection::Error;
+use crate::jellyfin_api::{Dialect, JellyfinClient, MediaFolders, User, UserPolicy};
+
+/// Which media server runs at a configured address, picking the dialect to talk to it in.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum ServerKind {
+    #[default]
+    Jellyfin,
+    Emby,
+}
+
+impl ServerKind {
+    pub(crate) fn dialect(self) -> Dialect {
+        match self {
+            ServerKind::Jellyfin => Dialect {
+                api_prefix: "",
+                auth_header: "Authorization",
+                auth_value: |api_key| format!(r#"MediaBrowser Token="{}""#, api_key),
+                item_page: "/web/index.html#!/details?id=",
+            },
+            // Emby routes its API below `/emby` and takes the API key in a header of its own
+            ServerKind::Emby => Dialect {
+                api_prefix: "/emby",
+                auth_header: "X-Emby-Token",
+                auth_value: |api_key| api_key.to_string(),
+                item_page: "/web/index.html#!/item?id=",
+            },
+        }
+    }
+}
+
+/// A Jellyfin or Emby server. Emby was forked from Jellyfin and still answers the same API, so
+/// both only differ in the [`Dialect`] their client is set up with.
+#[derive(Debug)]
+pub(crate) struct MediaServer {
+    client: Arc<JellyfinClient>,
+}
+
+impl MediaServer {
+    pub(crate) fn new(kind: ServerKind, client: JellyfinClient) -> Self {
+        Self { client: Arc::new(client.with_dialect(kind.dialect())) }
+    }
+
+    pub(crate) fn client(&self) -> &Arc<JellyfinClient> {
+        &self.client
+    }
+
+    pub(crate) async fn get_users(&self) -> Result<Vec<User>, Error> {
+        self.client.get_users().await
+    }
+
+    pub(crate) async fn get_libraries(&self) -> Result<Vec<MediaFolders>, Error> {
+        self.client.get_media_folders().await
+    }
+
+    /// Restricts `user` to `libraries`, or gives them every library including ones added later
+    /// if `None`. Returns the policy as it was sent.
+    pub(crate) async fn set_user_libraries(&self, user: &User, libraries: Option<&[String]>) -> Result<UserPolicy, Error> {
+        match libraries {
+            Some(libraries) => self.client.set_user_media_folders(user, libraries).await,
+            None => self.client.set_user_all_folders(user).await,
+        }
+    }
+
+    /// Starts a scan of all libraries.
+    pub(crate) async fn refresh(&self) -> Result<(), Error> {
+        self.client.refresh_library().await
+    }
+}
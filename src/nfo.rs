@@ -0,0 +1,92 @@
+use std::path::Path;
+
+use serde::Deserialize;
+
+/// Movie details read from a Kodi/Jellyfin style `movie.nfo` file.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Metadata {
+    pub(crate) title: Option<String>,
+    pub(crate) year: Option<u16>,
+    pub(crate) genres: Vec<String>,
+    /// Runtime in minutes
+    pub(crate) runtime: Option<u32>,
+    pub(crate) plot: Option<String>,
+    /// IMDb ID like `tt0113277`
+    pub(crate) imdb_id: Option<String>,
+    pub(crate) tmdb_id: Option<u64>,
+}
+
+#[derive(Debug, Deserialize)]
+struct MovieNfo {
+    title: Option<String>,
+    year: Option<String>,
+    premiered: Option<String>,
+    #[serde(default, rename = "genre")]
+    genres: Vec<String>,
+    runtime: Option<String>,
+    plot: Option<String>,
+    imdbid: Option<String>,
+    tmdbid: Option<String>,
+    /// Newer scrapers write `<uniqueid type="imdb">` instead of the ID elements
+    #[serde(default, rename = "uniqueid")]
+    unique_ids: Vec<UniqueId>,
+}
+
+#[derive(Debug, Deserialize)]
+struct UniqueId {
+    #[serde(rename = "@type")]
+    kind: Option<String>,
+    #[serde(rename = "$text")]
+    value: Option<String>,
+}
+
+fn non_empty(value: Option<String>) -> Option<String> {
+    value.map(|v| v.trim().to_string()).filter(|v| !v.is_empty())
+}
+
+impl From<MovieNfo> for Metadata {
+    fn from(nfo: MovieNfo) -> Self {
+        // Some scrapers leave <year> empty and only fill in the premiere date
+        let year = non_empty(nfo.year)
+            .or_else(|| non_empty(nfo.premiered).and_then(|p| p.get(..4).map(str::to_string)))
+            .and_then(|y| y.parse().ok());
+        let unique_id = |kind: &str| {
+            nfo.unique_ids
+                .iter()
+                .find(|id| id.kind.as_deref().is_some_and(|k| k.eq_ignore_ascii_case(kind)))
+                .and_then(|id| non_empty(id.value.clone()))
+        };
+        let imdb_id = unique_id("imdb").or_else(|| non_empty(nfo.imdbid.clone()));
+        let tmdb_id = unique_id("tmdb").or_else(|| non_empty(nfo.tmdbid.clone())).and_then(|id| id.parse().ok());
+        Self {
+            title: non_empty(nfo.title),
+            year,
+            genres: nfo.genres.into_iter().filter_map(|g| non_empty(Some(g))).collect(),
+            runtime: non_empty(nfo.runtime).and_then(|r| r.parse().ok()),
+            plot: non_empty(nfo.plot),
+            imdb_id,
+            tmdb_id,
+        }
+    }
+}
+
+impl Metadata {
+    pub(crate) fn parse(xml: &str) -> anyhow::Result<Self> {
+        let nfo: MovieNfo = quick_xml::de::from_str(xml)?;
+        Ok(nfo.into())
+    }
+
+    /// Reads `movie.nfo` from a movie folder. Missing or malformed files are not an error,
+    /// the movie is simply shown without metadata.
+    pub(crate) async fn load<T: AsRef<Path>>(movie_path: T) -> Option<Self> {
+        let nfo_path = movie_path.as_ref().join("movie.nfo");
+        let xml = tokio::fs::read_to_string(&nfo_path).await.ok()?;
+        match Self::parse(&xml) {
+            Ok(metadata) => Some(metadata),
+            Err(e) => {
+                tracing::warn!("failed to parse {}: {}", nfo_path.display(), e);
+                None
+            }
+        }
+    }
+}
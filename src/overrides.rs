@@ -0,0 +1,54 @@
+use std::path::Path;
+
+use maud::{Markup, PreEscaped};
+use minijinja::{Environment, ErrorKind, Value};
+
+/// Names of the page fragments that can be replaced with a template of the same name.
+pub(crate) const OVERRIDABLE: &[&str] = &["header.html", "movie.html"];
+
+/// Jinja templates replacing parts of the built-in pages, read from `--templates-dir`.
+#[derive(Debug)]
+pub struct TemplateOverrides {
+    env: Environment<'static>,
+}
+
+impl TemplateOverrides {
+    /// Compiles the overridable templates found in `dir`, failing on syntax errors so they show
+    /// up on startup rather than on every page view.
+    pub async fn load<T: AsRef<Path>>(dir: T) -> anyhow::Result<Self> {
+        let dir = dir.as_ref();
+        let mut env = Environment::new();
+        for name in OVERRIDABLE {
+            let path = dir.join(name);
+            let source = match tokio::fs::read_to_string(&path).await {
+                Ok(source) => source,
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+                Err(e) => anyhow::bail!("failed to read template {}: {}", path.display(), e),
+            };
+            env.add_template_owned(*name, source)
+                .map_err(|e| anyhow::anyhow!("invalid template {}: {}", path.display(), e))?;
+            tracing::info!("overriding {} with {}", name, path.display());
+        }
+        Ok(Self { env })
+    }
+
+    /// Renders the override of `name`, or `None` to use the built-in markup. Render errors are
+    /// logged and fall back to the built-in markup too.
+    pub(crate) fn render(&self, name: &str, context: Value) -> Option<Markup> {
+        let template = match self.env.get_template(name) {
+            Ok(template) => template,
+            Err(e) if e.kind() == ErrorKind::TemplateNotFound => return None,
+            Err(e) => {
+                tracing::warn!("failed to load template {}: {}", name, e);
+                return None;
+            }
+        };
+        match template.render(context) {
+            Ok(html) => Some(PreEscaped(html)),
+            Err(e) => {
+                tracing::warn!("failed to render template {}: {}", name, e);
+                None
+            }
+        }
+    }
+}
@@ -0,0 +1,89 @@
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::SystemTime;
+
+use axum::body::Bytes;
+use lru::LruCache;
+
+use crate::collection::Error;
+
+/// Poster files kept in memory, least recently served first out once they take more than
+/// `max_bytes`. Keyed by path and modification time, so replaced posters are read again.
+#[derive(Debug)]
+pub struct PosterCache {
+    max_bytes: usize,
+    entries: Mutex<Entries>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+#[derive(Debug)]
+struct Entries {
+    posters: LruCache<(PathBuf, SystemTime), Bytes>,
+    bytes: usize,
+}
+
+impl PosterCache {
+    pub fn new(max_bytes: usize) -> Self {
+        Self {
+            max_bytes,
+            entries: Mutex::new(Entries { posters: LruCache::unbounded(), bytes: 0 }),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        }
+    }
+
+    pub(crate) fn enabled(&self) -> bool {
+        self.max_bytes > 0
+    }
+
+    /// The contents of the poster at `path`, from memory if it hasn't changed since.
+    pub(crate) async fn read(&self, path: &Path, modified: SystemTime) -> Result<Bytes, Error> {
+        let key = (path.to_path_buf(), modified);
+        if let Some(poster) = self.entries.lock().unwrap().posters.get(&key) {
+            self.hits.fetch_add(1, Ordering::Relaxed);
+            return Ok(poster.clone());
+        }
+        self.misses.fetch_add(1, Ordering::Relaxed);
+        let poster = Bytes::from(tokio::fs::read(path).await?);
+        if poster.len() <= self.max_bytes {
+            let mut entries = self.entries.lock().unwrap();
+            entries.bytes += poster.len();
+            if let Some(replaced) = entries.posters.put(key, poster.clone()) {
+                entries.bytes -= replaced.len();
+            }
+            while entries.bytes > self.max_bytes {
+                let Some((_, evicted)) = entries.posters.pop_lru() else { break };
+                entries.bytes -= evicted.len();
+            }
+        }
+        Ok(poster)
+    }
+
+    /// Counters and sizes in the Prometheus text format.
+    pub(crate) fn metrics(&self) -> String {
+        let (count, bytes) = {
+            let entries = self.entries.lock().unwrap();
+            (entries.posters.len(), entries.bytes)
+        };
+        format!(
+            "# HELP tagrs_poster_cache_hits_total Posters served from memory.\n\
+             # TYPE tagrs_poster_cache_hits_total counter\n\
+             tagrs_poster_cache_hits_total {}\n\
+             # HELP tagrs_poster_cache_misses_total Posters read from disk.\n\
+             # TYPE tagrs_poster_cache_misses_total counter\n\
+             tagrs_poster_cache_misses_total {}\n\
+             # HELP tagrs_poster_cache_entries Posters held in memory.\n\
+             # TYPE tagrs_poster_cache_entries gauge\n\
+             tagrs_poster_cache_entries {}\n\
+             # HELP tagrs_poster_cache_bytes Size of the posters held in memory.\n\
+             # TYPE tagrs_poster_cache_bytes gauge\n\
+             tagrs_poster_cache_bytes {}\n",
+            self.hits.load(Ordering::Relaxed),
+            self.misses.load(Ordering::Relaxed),
+            count,
+            bytes,
+        )
+    }
+}
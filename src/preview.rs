@@ -0,0 +1,65 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use crate::collection::{tag_library_name, Collection, Movie};
+use crate::jellyfin_api::{MediaFolders, User};
+
+/// What a library the user may access shows of the collection.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum LibraryContent<'a> {
+    /// The library of a tag, by its key
+    Tag(&'a str),
+    /// A library of the whole movie directory
+    AllMovies,
+    /// Not a library of this collection, e.g. music or TV shows
+    Other,
+}
+
+/// The movies of the collection a Jellyfin user can see, going by the libraries they may access.
+/// Parental controls may hide some of them still.
+#[derive(Debug)]
+pub(crate) struct LibraryPreview<'a> {
+    pub(crate) libraries: Vec<(&'a MediaFolders, LibraryContent<'a>)>,
+    /// Each visible movie with the names of the libraries showing it, by folder name
+    pub(crate) movies: Vec<(&'a Movie, Vec<&'a str>)>,
+}
+
+impl<'a> LibraryPreview<'a> {
+    pub(crate) fn new(collection: &'a Collection, user: &User, folders: &'a [MediaFolders]) -> Self {
+        let accessible = user.accessible_folders(folders);
+        let libraries: Vec<_> = folders
+            .iter()
+            .filter(|folder| accessible.contains(&folder.id))
+            .map(|folder| (folder, library_content(collection, folder)))
+            .collect();
+        let mut movies: BTreeMap<&str, (&Movie, Vec<&str>)> = BTreeMap::new();
+        for (folder, content) in &libraries {
+            let shown: Vec<&Movie> = match content {
+                LibraryContent::Tag(key) => {
+                    collection.tags[*key].movies.iter().filter_map(|hash| collection.movies.get(hash)).collect()
+                }
+                LibraryContent::AllMovies => collection.movies.values().collect(),
+                LibraryContent::Other => continue,
+            };
+            for movie in shown {
+                movies.entry(&movie.name).or_insert_with(|| (movie, Vec::new())).1.push(&folder.name);
+            }
+        }
+        Self { libraries, movies: movies.into_values().collect() }
+    }
+}
+
+/// Matches a library to a tag by the directory it shows, or else by the name tagrs gives tag
+/// libraries, since Jellyfin may report the path of its own library definition instead.
+pub(crate) fn library_content<'a>(collection: &'a Collection, folder: &MediaFolders) -> LibraryContent<'a> {
+    let path = Path::new(&folder.path);
+    if path == collection.path_mappings.to_jellyfin(&collection.movie_dir) {
+        return LibraryContent::AllMovies;
+    }
+    collection
+        .tags
+        .keys()
+        .find(|key| path == collection.path_mappings.to_jellyfin(&collection.tag_dir.join(key)))
+        .or_else(|| collection.tags.keys().find(|key| folder.name == tag_library_name(key)))
+        .map_or(LibraryContent::Other, |key| LibraryContent::Tag(key))
+}
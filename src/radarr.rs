@@ -0,0 +1,205 @@
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use reqwest::Method;
+use serde::Deserialize;
+use tokio::sync::RwLock;
+
+use crate::collection::{Error, Movie};
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RadarrMovie {
+    id: u64,
+    path: String,
+    monitored: bool,
+    has_file: bool,
+    quality_profile_id: u64,
+    movie_file: Option<RadarrMovieFile>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct RadarrMovieFile {
+    quality: RadarrQualityModel,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct RadarrQualityModel {
+    quality: RadarrQuality,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct RadarrQuality {
+    name: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct QualityProfile {
+    id: u64,
+    name: String,
+}
+
+/// What Radarr knows about a movie folder.
+#[derive(Debug, Clone)]
+pub(crate) struct RadarrEntry {
+    pub(crate) id: u64,
+    pub(crate) monitored: bool,
+    pub(crate) has_file: bool,
+    /// Name of the quality profile
+    pub(crate) profile: String,
+    /// Quality of the current file, e.g. `Bluray-1080p`
+    pub(crate) quality: Option<String>,
+}
+
+/// Radarr movies keyed by folder name.
+pub(crate) type RadarrIndex = HashMap<String, RadarrEntry>;
+
+#[derive(Clone)]
+pub struct RadarrClient {
+    base_url: String,
+    api_key: String,
+    client: reqwest::Client,
+}
+
+impl std::fmt::Debug for RadarrClient {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RadarrClient")
+            .field("base_url", &self.base_url)
+            .field("api_key", &"<redacted>")
+            .finish()
+    }
+}
+
+impl RadarrClient {
+    /// Talks to Radarr through `client`, which may be shared with the Jellyfin servers.
+    pub fn new(mut base_url: String, api_key: String, client: reqwest::Client) -> Self {
+        if base_url.ends_with('/') {
+            base_url.pop();
+        }
+        Self { base_url, api_key, client }
+    }
+
+    async fn request<T: serde::de::DeserializeOwned>(
+        &self,
+        method: Method,
+        path: &str,
+        body: Option<serde_json::Value>,
+    ) -> Result<T, Error> {
+        let url = format!("{}/api/v3{}", self.base_url, path);
+        let mut request = self.client.request(method, url).header("X-Api-Key", &self.api_key);
+        if let Some(body) = body {
+            request = request.json(&body);
+        }
+        let resp = request.send().await.map_err(|e| Error::Radarr(e.to_string()))?;
+        let status = resp.status();
+        if !status.is_success() {
+            return Err(Error::Radarr(format!("{} {}: {}", path, status, resp.text().await.unwrap_or_default())));
+        }
+        resp.json().await.map_err(|e| Error::Radarr(e.to_string()))
+    }
+
+    #[tracing::instrument]
+    async fn get_movies(&self) -> Result<Vec<RadarrMovie>, Error> {
+        self.request(Method::GET, "/movie", None).await
+    }
+
+    #[tracing::instrument]
+    async fn get_quality_profiles(&self) -> Result<Vec<QualityProfile>, Error> {
+        self.request(Method::GET, "/qualityprofile", None).await
+    }
+
+    /// Asks Radarr to search its indexers for a better release of the movie.
+    #[tracing::instrument]
+    async fn search_movie(&self, movie_id: u64) -> Result<(), Error> {
+        let body = serde_json::json!({ "name": "MoviesSearch", "movieIds": [movie_id] });
+        let _: serde_json::Value = self.request(Method::POST, "/command", Some(body)).await?;
+        Ok(())
+    }
+}
+
+/// Radarr's view of the collection, fetched on first use and kept until the next reload.
+/// A failed fetch isn't retried for a while, so pages don't each wait for an unreachable Radarr.
+#[derive(Debug)]
+pub struct Radarr {
+    client: RadarrClient,
+    index: RwLock<Option<Cached>>,
+}
+
+#[derive(Debug)]
+enum Cached {
+    Index(Arc<RadarrIndex>),
+    Failed { at: Instant, error: String },
+}
+
+impl Cached {
+    /// The index, the recent failure, or `None` once it's time to ask Radarr again.
+    fn get(&self) -> Option<Result<Arc<RadarrIndex>, Error>> {
+        match self {
+            Cached::Index(index) => Some(Ok(index.clone())),
+            Cached::Failed { at, error } if at.elapsed() < Radarr::RETRY_AFTER => Some(Err(Error::Radarr(error.clone()))),
+            Cached::Failed { .. } => None,
+        }
+    }
+}
+
+impl Radarr {
+    /// How long a failed fetch is reported instead of asking Radarr again
+    const RETRY_AFTER: Duration = Duration::from_secs(60);
+
+    pub fn new(client: RadarrClient) -> Self {
+        Self { client, index: RwLock::new(None) }
+    }
+
+    pub(crate) async fn index(&self) -> Result<Arc<RadarrIndex>, Error> {
+        if let Some(result) = self.index.read().await.as_ref().and_then(Cached::get) {
+            return result;
+        }
+        let mut cached = self.index.write().await;
+        // another request may have fetched it while this one waited for the lock
+        if let Some(result) = cached.as_ref().and_then(Cached::get) {
+            return result;
+        }
+        let index = self.fetch().await;
+        *cached = Some(match &index {
+            Ok(index) => Cached::Index(index.clone()),
+            Err(Error::Radarr(error)) => Cached::Failed { at: Instant::now(), error: error.clone() },
+            Err(e) => Cached::Failed { at: Instant::now(), error: e.to_string() },
+        });
+        index
+    }
+
+    async fn fetch(&self) -> Result<Arc<RadarrIndex>, Error> {
+        let (movies, profiles) = tokio::try_join!(self.client.get_movies(), self.client.get_quality_profiles())?;
+        let profiles: HashMap<u64, String> = profiles.into_iter().map(|p| (p.id, p.name)).collect();
+        let index: RadarrIndex = movies
+            .into_iter()
+            .filter_map(|movie| {
+                // Radarr reports paths as seen by its own host, so only the folder name is
+                // comparable
+                let folder = Path::new(&movie.path).file_name()?.to_string_lossy().to_string();
+                let entry = RadarrEntry {
+                    id: movie.id,
+                    monitored: movie.monitored,
+                    has_file: movie.has_file,
+                    profile: profiles.get(&movie.quality_profile_id).cloned().unwrap_or_default(),
+                    quality: movie.movie_file.map(|f| f.quality.quality.name),
+                };
+                Some((folder, entry))
+            })
+            .collect();
+        Ok(Arc::new(index))
+    }
+
+    pub(crate) async fn invalidate(&self) {
+        *self.index.write().await = None;
+    }
+
+    /// Triggers a search for `movie`, failing with `NotFound` if Radarr doesn't know it.
+    pub(crate) async fn search(&self, movie: &Movie) -> Result<(), Error> {
+        let index = self.index().await?;
+        let entry = index.get(&movie.name).ok_or(Error::NotFound)?;
+        self.client.search_movie(entry.id).await
+    }
+}
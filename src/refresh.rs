@@ -0,0 +1,42 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use tokio::sync::mpsc;
+
+use crate::jellyfin_api::JellyfinServers;
+
+/// Debounces Jellyfin library scans: a scan starts once no further changes have been requested
+/// for `delay`, so a burst of tag toggles results in a single scan.
+#[derive(Debug)]
+pub struct LibraryRefresh {
+    tx: mpsc::UnboundedSender<()>,
+}
+
+impl LibraryRefresh {
+    pub fn spawn(servers: Arc<JellyfinServers>, delay: Duration) -> Self {
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        tokio::spawn(async move {
+            while rx.recv().await.is_some() {
+                loop {
+                    match tokio::time::timeout(delay, rx.recv()).await {
+                        Ok(Some(())) => continue,
+                        Ok(None) => return,
+                        Err(_) => break,
+                    }
+                }
+                for (name, server) in servers.iter() {
+                    if let Err(e) = server.refresh().await {
+                        tracing::warn!("automatic library scan of {} failed: {}", name, e);
+                    }
+                }
+            }
+        });
+        Self { tx }
+    }
+
+    pub(crate) fn schedule(&self) {
+        if self.tx.send(()).is_err() {
+            tracing::warn!("library refresh task is no longer running");
+        }
+    }
+}
@@ -0,0 +1,108 @@
+use std::convert::Infallible;
+use std::future::Future;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::time::Duration;
+
+use axum::response::sse::{Event, KeepAlive, Sse};
+use chrono::{DateTime, Utc};
+use futures::Stream;
+use serde::Serialize;
+use tokio::sync::watch;
+
+use crate::collection::{Error, ScanProgress};
+
+/// How often the counts of a running reload are published.
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Runs collection reloads one at a time in the background, reporting their progress at
+/// `/reload/status` and as server-sent events at `/reload/events`.
+#[derive(Debug, Clone)]
+pub struct Reloads {
+    status: Arc<watch::Sender<ReloadStatus>>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, utoipa::ToSchema)]
+pub(crate) struct ReloadStatus {
+    running: bool,
+    /// Movies scanned by the running or last reload
+    movies: usize,
+    /// Tags scanned by the running or last reload
+    tags: usize,
+    started_at: Option<DateTime<Utc>>,
+    finished_at: Option<DateTime<Utc>>,
+    /// Why the last reload failed
+    error: Option<String>,
+}
+
+impl Default for Reloads {
+    fn default() -> Self {
+        Self { status: Arc::new(watch::channel(ReloadStatus::default()).0) }
+    }
+}
+
+impl Reloads {
+    pub(crate) fn status(&self) -> ReloadStatus {
+        self.status.borrow().clone()
+    }
+
+    /// Runs `reload` with a fresh progress counter, unless a reload is running already. Returns
+    /// the result of `reload`, or `None` if it was skipped.
+    pub(crate) async fn run<F, Fut>(&self, reload: F) -> Option<Result<(), Error>>
+    where
+        F: FnOnce(Arc<ScanProgress>) -> Fut,
+        Fut: Future<Output = Result<(), Error>>,
+    {
+        let started = self.status.send_if_modified(|status| {
+            if status.running {
+                return false;
+            }
+            *status = ReloadStatus { running: true, started_at: Some(Utc::now()), ..ReloadStatus::default() };
+            true
+        });
+        if !started {
+            return None;
+        }
+        let progress = Arc::new(ScanProgress::default());
+        let reload = reload(progress.clone());
+        tokio::pin!(reload);
+        let mut ticker = tokio::time::interval(PROGRESS_INTERVAL);
+        let result = loop {
+            tokio::select! {
+                result = &mut reload => break result,
+                _ = ticker.tick() => {
+                    self.status.send_if_modified(|status| status.count(&progress));
+                }
+            }
+        };
+        self.status.send_modify(|status| {
+            status.count(&progress);
+            status.running = false;
+            status.finished_at = Some(Utc::now());
+            status.error = result.as_ref().err().map(|e| e.to_string());
+        });
+        Some(result)
+    }
+
+    /// Event stream of the reload status, starting with the current one.
+    pub(crate) fn subscribe(&self) -> Sse<impl Stream<Item = Result<Event, Infallible>>> {
+        let mut rx = self.status.subscribe();
+        rx.mark_changed();
+        let stream = futures::stream::unfold(rx, |mut rx| async move {
+            rx.changed().await.ok()?;
+            let event = Event::default().event("progress").json_data(&*rx.borrow_and_update()).ok()?;
+            Some((Ok(event), rx))
+        });
+        Sse::new(stream).keep_alive(KeepAlive::default())
+    }
+}
+
+impl ReloadStatus {
+    /// Takes the counts of `progress`, returning whether they changed.
+    fn count(&mut self, progress: &ScanProgress) -> bool {
+        let counts = (progress.movies.load(Ordering::Relaxed), progress.tags.load(Ordering::Relaxed));
+        let changed = counts != (self.movies, self.tags);
+        (self.movies, self.tags) = counts;
+        changed
+    }
+}
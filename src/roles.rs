@@ -0,0 +1,99 @@
+use axum::extract::{Request, State};
+use axum::http::{Method, StatusCode};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+
+use crate::config::RolesConfig;
+use crate::{Actor, AppState};
+
+/// What a user may do, each role including everything the ones before it may.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    /// Browse movies, tags and statistics
+    Viewer,
+    /// Also tag movies, create tags and change posters
+    Tagger,
+    /// Also manage Jellyfin users, delete tags and reload the collection
+    Admin,
+}
+
+/// Paths at or below which everything, even viewing, needs the admin role.
+const ADMIN_PATHS: &[&str] = &["/user-libraries", "/users", "/user", "/presets", "/sessions", "/devices", "/tasks", "/audit", "/activity", "/reload", "/jellyfin", "/snapshots", "/tags/hidden", "/trakt"];
+
+/// Whether `path` is `parent` or below it, matching whole path segments only.
+fn is_below(path: &str, parent: &str) -> bool {
+    path.strip_prefix(parent).is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+}
+
+/// Changes anyone may make, since they only set a cookie.
+const PREFERENCE_PATHS: &[&str] = &["/server", "/theme"];
+
+/// Paths only reading even when posted to.
+const QUERY_PATHS: &[&str] = &["/graphql"];
+
+/// Paths called by other services rather than users, which check a token of their own.
+const HOOK_PATHS: &[&str] = &["/hooks/"];
+
+/// The role needed for a request.
+fn required_role(method: &Method, path: &str) -> Role {
+    let read_only = matches!(*method, Method::GET | Method::HEAD | Method::OPTIONS);
+    if ADMIN_PATHS.iter().any(|parent| is_below(path, parent)) {
+        Role::Admin
+    } else if path.starts_with("/tag/") && (method == Method::DELETE || path.contains("/merge/")) {
+        // deleting a tag, also by merging it, may delete its Jellyfin library too
+        Role::Admin
+    } else if read_only || PREFERENCE_PATHS.contains(&path) || QUERY_PATHS.contains(&path) || HOOK_PATHS.iter().any(|prefix| path.starts_with(prefix)) {
+        Role::Viewer
+    } else {
+        Role::Tagger
+    }
+}
+
+impl RolesConfig {
+    /// The role of `user` from the config, falling back to the default role.
+    fn configured(&self, user: &str) -> Role {
+        self.users.get(user).copied().unwrap_or(self.default)
+    }
+}
+
+/// Whether `user` is an administrator of the default Jellyfin server. Lookup failures deny.
+async fn is_jellyfin_admin(state: &AppState, user: &str) -> bool {
+    let Some((server, api)) = state.jellyfin.default_server() else {
+        return false;
+    };
+    match state.jellyfin_cache.users(server, api.as_ref()).await {
+        Ok(users) => users.iter().any(|u| u.name == user && u.is_admin()),
+        Err(e) => {
+            tracing::warn!("failed to look up {} on {}: {}", user, server, e);
+            false
+        }
+    }
+}
+
+/// The role of `actor`, asking Jellyfin whether they are an administrator if configured to.
+pub(crate) async fn role(state: &AppState, actor: &Actor) -> Role {
+    let role = state.roles.configured(actor.name());
+    let jellyfin_admin = role < Role::Admin
+        && state.roles.jellyfin_admins
+        && !state.roles.users.contains_key(actor.name())
+        && is_jellyfin_admin(state, actor.name()).await;
+    if jellyfin_admin {
+        Role::Admin
+    } else {
+        role
+    }
+}
+
+/// Middleware rejecting requests the user's role doesn't allow with 403 Forbidden.
+pub(crate) async fn authorize(State(state): State<AppState>, actor: Actor, request: Request, next: Next) -> Response {
+    let required = required_role(request.method(), request.uri().path());
+    // only ask Jellyfin when the configured role isn't enough anyway
+    let configured = state.roles.configured(actor.name());
+    let role = if configured >= required { configured } else { role(&state, &actor).await };
+    if role < required {
+        tracing::info!("{} ({:?}) may not {} {}", actor.name(), role, request.method(), request.uri().path());
+        return (StatusCode::FORBIDDEN, format!("{:?} role required", required)).into_response();
+    }
+    next.run(request).await
+}
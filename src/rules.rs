@@ -0,0 +1,90 @@
+use std::collections::BTreeSet;
+
+use serde::Deserialize;
+
+use crate::collection::{Collection, Error, Movie, PathnameHash};
+use crate::media_info::Resolution;
+use crate::views::Filter;
+
+/// Tags movies matching a filter. Rules only ever add tags, so removing a tag by hand sticks
+/// until the movie is tagged again.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Rule {
+    pub(crate) filter: Filter,
+    /// Tag name or alias. `{genre}` in it stands for each `.nfo` genre of the movie.
+    pub(crate) tag: String,
+}
+
+/// Placeholder in [`Rule::tag`] replaced with the genres of a movie.
+const GENRE_PLACEHOLDER: &str = "{genre}";
+
+impl Rule {
+    /// Tags the rule gives `movie` if it matches, resolved through tag aliases so that e.g. the
+    /// genre "Science Fiction" can land in an existing `sci-fi` tag.
+    fn tags(&self, collection: &Collection, movie: &Movie) -> Vec<String> {
+        let names = if self.tag.contains(GENRE_PLACEHOLDER) {
+            let genres = movie.metadata.as_ref().map(|m| m.genres.as_slice()).unwrap_or_default();
+            genres.iter().map(|genre| self.tag.replace(GENRE_PLACEHOLDER, genre)).collect()
+        } else {
+            vec![self.tag.clone()]
+        };
+        names
+            .into_iter()
+            .map(|name| collection.resolve_tag(&name).map(str::to_string).unwrap_or(name))
+            .collect()
+    }
+}
+
+/// A tag a rule gives a movie.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) struct Change {
+    pub(crate) tag: String,
+    pub(crate) movie_name: String,
+    pub(crate) movie: PathnameHash,
+}
+
+/// What running `rules` would change, by tag and movie name.
+pub(crate) fn plan(
+    rules: &[Rule],
+    collection: &Collection,
+    resolution: impl Fn(&Movie) -> Option<Resolution>,
+) -> Vec<Change> {
+    let mut changes = BTreeSet::new();
+    for rule in rules {
+        for movie in collection.movies.values() {
+            let mut tags = rule.tags(collection, movie);
+            tags.retain(|tag| !collection.tags.get(tag).is_some_and(|tag| tag.movies.contains(&movie.hash)));
+            if tags.is_empty() || !rule.filter.matches(collection, movie, resolution(movie)) {
+                continue;
+            }
+            for tag in tags {
+                changes.insert(Change { tag, movie_name: movie.name.clone(), movie: movie.hash });
+            }
+        }
+    }
+    changes.into_iter().collect()
+}
+
+/// Links the movies of `changes` into their tags, creating missing tags. Returns the changes
+/// made and the tags created; movies tagged or gone since planning are skipped.
+pub(crate) async fn apply(collection: &mut Collection, changes: Vec<Change>) -> Result<(Vec<Change>, Vec<String>), Error> {
+    let mut applied = Vec::new();
+    let mut created = Vec::new();
+    for change in changes {
+        let Some(movie) = collection.movies.get(&change.movie).cloned() else {
+            continue;
+        };
+        match collection.tags.get(&change.tag) {
+            Some(tag) if tag.movies.contains(&movie.hash) => continue,
+            Some(_) => {}
+            None => {
+                collection.create_tag(&change.tag).await?;
+                created.push(change.tag.clone());
+            }
+        }
+        collection.toggle_tag(&change.tag, &movie).await?;
+        applied.push(change);
+    }
+    Ok((applied, created))
+}
@@ -0,0 +1,206 @@
+use chrono::{DateTime, Datelike, Duration, Local, NaiveDate, NaiveTime, TimeZone, Timelike};
+use serde::Deserialize;
+
+use crate::collection::Error;
+use crate::{audit, trakt, updates, AppState};
+
+/// Name recorded in the audit log for changes made by scheduled jobs.
+const SCHEDULER_ACTOR: &str = "scheduler";
+
+/// Days searched for the next run, enough for a February 29th schedule.
+const MAX_DAYS_AHEAD: i64 = 366 * 8;
+
+/// A job run whenever its cron expression matches.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ScheduledJob {
+    pub(crate) job: Job,
+    pub(crate) cron: Cron,
+}
+
+/// Everything that can be scheduled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Job {
+    /// Rescan the movie and tag directories
+    Reload,
+    /// Log problems with the collection, like tag links to missing movies
+    Doctor,
+    /// Forget the cached Jellyfin item index and Radarr details
+    RefreshCaches,
+    /// Start a new audit log file
+    RotateAuditLog,
+    /// Sync the configured Trakt lists
+    SyncTrakt,
+}
+
+/// A five field cron expression, `minute hour day-of-month month day-of-week`, in local time.
+/// Fields take `*`, numbers, ranges like `1-5`, lists like `1,15` and steps like `*/10`.
+/// Sunday is 0 or 7.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "String")]
+pub struct Cron {
+    source: String,
+    minutes: u64,
+    hours: u64,
+    days: u64,
+    months: u64,
+    weekdays: u64,
+    /// Whether day-of-month and day-of-week were both restricted, in which case either matches
+    either_day: bool,
+}
+
+impl TryFrom<String> for Cron {
+    type Error = String;
+
+    fn try_from(source: String) -> Result<Self, Self::Error> {
+        let invalid = |e: String| format!("invalid cron expression {:?}: {}", source, e);
+        let fields: Vec<&str> = source.split_whitespace().collect();
+        let [minutes, hours, days, months, weekdays] = fields[..] else {
+            return Err(invalid("expected 5 fields".to_string()));
+        };
+        let either_day = !days.starts_with('*') && !weekdays.starts_with('*');
+        let mut weekdays = field(weekdays, 0, 7).map_err(invalid)?;
+        // Sunday may be written as 7
+        if weekdays & (1 << 7) != 0 {
+            weekdays |= 1;
+        }
+        Ok(Cron {
+            minutes: field(minutes, 0, 59).map_err(invalid)?,
+            hours: field(hours, 0, 23).map_err(invalid)?,
+            days: field(days, 1, 31).map_err(invalid)?,
+            months: field(months, 1, 12).map_err(invalid)?,
+            weekdays,
+            either_day,
+            source,
+        })
+    }
+}
+
+/// Parses one field into a bit set of the values it matches.
+fn field(field: &str, min: u32, max: u32) -> Result<u64, String> {
+    let mut bits = 0;
+    for part in field.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => (range, step.parse().map_err(|_| format!("invalid step in {}", part))?),
+            None => (part, 1),
+        };
+        let (start, end) = match range {
+            "*" => (min, max),
+            _ => match range.split_once('-') {
+                Some((start, end)) => (value(start, min, max)?, value(end, min, max)?),
+                // `5/15` runs from 5 to the end
+                None if step > 1 => (value(range, min, max)?, max),
+                None => (value(range, min, max)?, value(range, min, max)?),
+            },
+        };
+        if step == 0 || start > end {
+            return Err(format!("invalid range {}", part));
+        }
+        for v in (start..=end).step_by(step) {
+            bits |= 1 << v;
+        }
+    }
+    Ok(bits)
+}
+
+fn value(value: &str, min: u32, max: u32) -> Result<u32, String> {
+    match value.parse() {
+        Ok(v) if (min..=max).contains(&v) => Ok(v),
+        _ => Err(format!("{} is not between {} and {}", value, min, max)),
+    }
+}
+
+impl Cron {
+    fn matches_day(&self, date: NaiveDate) -> bool {
+        let day = self.days & (1 << date.day()) != 0;
+        let weekday = self.weekdays & (1 << date.weekday().num_days_from_sunday()) != 0;
+        let either = if self.either_day { day || weekday } else { day && weekday };
+        either && self.months & (1 << date.month()) != 0
+    }
+
+    /// The first matching minute after `after`. Times skipped by a daylight saving change don't
+    /// run.
+    pub(crate) fn next_after(&self, after: DateTime<Local>) -> Option<DateTime<Local>> {
+        let next_minute = after.naive_local() + Duration::minutes(1);
+        let start = next_minute.date().and_hms_opt(next_minute.hour(), next_minute.minute(), 0)?;
+        for day in 0..MAX_DAYS_AHEAD {
+            let date = start.date() + Duration::days(day);
+            if !self.matches_day(date) {
+                continue;
+            }
+            for hour in (0..24).filter(|h| self.hours & (1 << h) != 0) {
+                for minute in (0..60).filter(|m| self.minutes & (1 << m) != 0) {
+                    let candidate = date.and_time(NaiveTime::from_hms_opt(hour, minute, 0)?);
+                    if candidate < start {
+                        continue;
+                    }
+                    if let Some(next) = Local.from_local_datetime(&candidate).earliest() {
+                        return Some(next);
+                    }
+                }
+            }
+        }
+        None
+    }
+}
+
+/// Starts a task per job waiting for its next run. A failed run is logged and the job runs
+/// again at its next time.
+pub(crate) fn spawn(state: AppState, jobs: Vec<ScheduledJob>) {
+    for scheduled in jobs {
+        let state = state.clone();
+        tokio::spawn(async move {
+            while let Some(next) = scheduled.cron.next_after(Local::now()) {
+                tracing::debug!("next {:?} at {}", scheduled.job, next);
+                let wait = (next - Local::now()).to_std().unwrap_or_default();
+                tokio::time::sleep(wait).await;
+                tracing::info!("running scheduled {:?} ({})", scheduled.job, scheduled.cron.source);
+                if let Err(e) = run(&state, scheduled.job).await {
+                    tracing::warn!("scheduled {:?} failed: {}", scheduled.job, e);
+                }
+            }
+            tracing::warn!("{:?} ({}) never runs", scheduled.job, scheduled.cron.source);
+        });
+    }
+}
+
+async fn run(state: &AppState, job: Job) -> Result<(), Error> {
+    match job {
+        Job::Reload => {
+            let reloaded = state.reloads.run(|progress| async move { state.rescan_collection(&progress).await });
+            match reloaded.await {
+                Some(result) => result?,
+                None => {
+                    tracing::info!("skipping the scheduled reload, one is running already");
+                    return Ok(());
+                }
+            }
+            state.audit.record(SCHEDULER_ACTOR, audit::Action::Reload).await?;
+        }
+        Job::Doctor => {
+            let collection = state.collection.read().await;
+            let problems = collection.problems();
+            for problem in &problems {
+                tracing::warn!("doctor: {}", problem);
+            }
+            tracing::info!("doctor found {} problems", problems.len());
+        }
+        Job::RefreshCaches => {
+            state.artwork.invalidate().await;
+            state.jellyfin_cache.invalidate_all();
+            if let Some(radarr) = &state.radarr {
+                radarr.invalidate().await;
+            }
+        }
+        Job::RotateAuditLog => state.audit.rotate().await?,
+        Job::SyncTrakt => {
+            let trakt = state.trakt.as_ref().ok_or_else(|| anyhow::anyhow!("Trakt isn't configured"))?;
+            let reports = trakt::sync(state, trakt, SCHEDULER_ACTOR).await?;
+            if reports.iter().any(|report| !report.tagged.is_empty()) {
+                state.updates.notify(updates::Update::Collection);
+            }
+        }
+    }
+    Ok(())
+}
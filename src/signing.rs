@@ -0,0 +1,59 @@
+use hmac::{Hmac, Mac};
+use sha2::{Digest, Sha256};
+
+/// Key signing the cookies tagrs reads back, so visitors can't hand it values it never set.
+#[derive(Clone)]
+pub struct CookieKey([u8; 32]);
+
+impl CookieKey {
+    /// A key derived from `secret`, which keeps cookies valid across restarts.
+    pub fn new(secret: &str) -> Self {
+        Self(Sha256::digest(secret.as_bytes()).into())
+    }
+
+    /// A key of its own for this process, invalidating cookies signed before a restart.
+    pub fn random() -> Self {
+        Self(rand::random())
+    }
+
+    fn mac(&self, value: &str) -> Hmac<Sha256> {
+        let mut mac = Hmac::<Sha256>::new_from_slice(&self.0).expect("HMAC takes keys of any length");
+        mac.update(value.as_bytes());
+        mac
+    }
+
+    /// `value` followed by its signature.
+    pub(crate) fn sign(&self, value: &str) -> String {
+        format!("{}.{}", value, hex::encode(self.mac(value).finalize().into_bytes()))
+    }
+
+    /// The value of a cookie set with [`CookieKey::sign`], or `None` if it was changed.
+    pub(crate) fn verify<'a>(&self, signed: &'a str) -> Option<&'a str> {
+        let (value, signature) = signed.rsplit_once('.')?;
+        self.mac(value).verify_slice(&hex::decode(signature).ok()?).ok()?;
+        Some(value)
+    }
+}
+
+impl std::fmt::Debug for CookieKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("CookieKey(<redacted>)")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn verify_accepts_only_what_was_signed_with_the_same_key() {
+        let key = CookieKey::new("secret");
+        let signed = key.sign("per_page=25&group=a.b");
+        assert_eq!(key.verify(&signed), Some("per_page=25&group=a.b"));
+        assert_eq!(CookieKey::new("secret").verify(&signed), Some("per_page=25&group=a.b"));
+
+        assert_eq!(CookieKey::new("other").verify(&signed), None);
+        assert_eq!(key.verify(&signed.replace("25", "0")), None);
+        assert_eq!(key.verify("per_page=0"), None);
+    }
+}
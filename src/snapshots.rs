@@ -0,0 +1,127 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::collection::{Collection, Error};
+
+/// Directory in the tag directory holding the snapshots, one JSON file each.
+pub(crate) const SNAPSHOT_DIR: &str = ".tagrs-snapshots";
+
+/// The movies of every tag at one point in time, by folder name.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct Snapshot {
+    #[serde(skip)]
+    pub(crate) id: String,
+    pub(crate) created: DateTime<Utc>,
+    pub(crate) tags: BTreeMap<String, BTreeSet<String>>,
+}
+
+/// What restoring a snapshot changed.
+#[derive(Debug, Default)]
+pub(crate) struct Restored {
+    pub(crate) created_tags: Vec<String>,
+    pub(crate) added: Vec<(String, String)>,
+    pub(crate) removed: Vec<(String, String)>,
+    /// Movies the snapshot tagged that are no longer in the collection
+    pub(crate) missing: BTreeSet<String>,
+}
+
+impl Snapshot {
+    pub(crate) fn take(collection: &Collection) -> Self {
+        let created = Utc::now();
+        let tags = collection
+            .tags
+            .iter()
+            .map(|(key, tag)| {
+                let movies = tag.movies.iter().filter_map(|hash| collection.movies.get(hash)).map(|m| m.name.clone());
+                (key.clone(), movies.collect())
+            })
+            .collect();
+        Snapshot { id: created.format("%Y%m%dT%H%M%S%3fZ").to_string(), created, tags }
+    }
+
+    pub(crate) fn links(&self) -> usize {
+        self.tags.values().map(BTreeSet::len).sum()
+    }
+
+    fn path(tag_dir: &Path, id: &str) -> PathBuf {
+        tag_dir.join(SNAPSHOT_DIR).join(format!("{}.json", id))
+    }
+
+    pub(crate) async fn save(&self, tag_dir: &Path) -> Result<(), Error> {
+        tokio::fs::create_dir_all(tag_dir.join(SNAPSHOT_DIR)).await?;
+        tokio::fs::write(Self::path(tag_dir, &self.id), serde_json::to_vec_pretty(self)?).await?;
+        Ok(())
+    }
+
+    pub(crate) async fn load(tag_dir: &Path, id: &str) -> Result<Self, Error> {
+        if id.is_empty() || id.contains(['/', '\\', '.']) {
+            return Err(Error::NotFound);
+        }
+        let contents = match tokio::fs::read(Self::path(tag_dir, id)).await {
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Err(Error::NotFound),
+            contents => contents?,
+        };
+        let mut snapshot: Snapshot = serde_json::from_slice(&contents)?;
+        snapshot.id = id.to_string();
+        Ok(snapshot)
+    }
+
+    /// Every snapshot, newest first. Unreadable files are skipped.
+    pub(crate) async fn list(tag_dir: &Path) -> Result<Vec<Self>, Error> {
+        let mut snapshots = Vec::new();
+        let mut entries = match tokio::fs::read_dir(tag_dir.join(SNAPSHOT_DIR)).await {
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(snapshots),
+            entries => entries?,
+        };
+        while let Some(entry) = entries.next_entry().await? {
+            let name = entry.file_name().to_string_lossy().to_string();
+            let Some(id) = name.strip_suffix(".json") else {
+                continue;
+            };
+            match Self::load(tag_dir, id).await {
+                Ok(snapshot) => snapshots.push(snapshot),
+                Err(e) => tracing::warn!("skipping snapshot {}: {}", name, e),
+            }
+        }
+        snapshots.sort_by_key(|snapshot| std::cmp::Reverse(snapshot.created));
+        Ok(snapshots)
+    }
+
+    /// Links and unlinks movies until every tag holds the movies it held in the snapshot,
+    /// recreating deleted tags. Tags made since keep their directory but lose their movies.
+    pub(crate) async fn restore(&self, collection: &mut Collection) -> Result<Restored, Error> {
+        let mut restored = Restored::default();
+        let by_name: BTreeMap<String, _> = collection.movies.values().map(|m| (m.name.clone(), m.clone())).collect();
+        for (key, names) in &self.tags {
+            if !collection.tags.contains_key(key) {
+                collection.create_tag(key).await?;
+                restored.created_tags.push(key.clone());
+            }
+            for name in names {
+                let Some(movie) = by_name.get(name) else {
+                    restored.missing.insert(name.clone());
+                    continue;
+                };
+                if !collection.tags[key].movies.contains(&movie.hash) {
+                    collection.toggle_tag(key, movie).await?;
+                    restored.added.push((key.clone(), name.clone()));
+                }
+            }
+        }
+        let keys: Vec<String> = collection.tags.keys().cloned().collect();
+        for key in keys {
+            let wanted = self.tags.get(&key);
+            for movie in by_name.values() {
+                let tagged = collection.tags[&key].movies.contains(&movie.hash);
+                if tagged && !wanted.is_some_and(|names| names.contains(&movie.name)) {
+                    collection.toggle_tag(&key, movie).await?;
+                    restored.removed.push((key.clone(), movie.name.clone()));
+                }
+            }
+        }
+        Ok(restored)
+    }
+}
@@ -0,0 +1,9 @@
+const updates = new EventSource(document.currentScript.dataset.url);
+// Refreshes the movie list when the collection changes on the server.
+updates.addEventListener("collection", () => {
+  htmx.trigger(document.body, "collection-changed");
+});
+// Refreshes the activity feed when a change is recorded.
+updates.addEventListener("activity", () => {
+  htmx.trigger(document.body, "activity-changed");
+});
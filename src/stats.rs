@@ -0,0 +1,202 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use chrono::{DateTime, Utc};
+use futures::future::try_join_all;
+use futures::StreamExt;
+use tokio::sync::RwLock;
+
+use crate::collection::{Collection, Error};
+use crate::jellyfin_api::JellyfinClient;
+
+/// How many movie folders are measured at once.
+const MEASURE_CONCURRENCY: usize = 4;
+
+#[derive(Debug, Clone)]
+pub(crate) struct MovieViews {
+    pub(crate) name: String,
+    pub(crate) plays: u32,
+    pub(crate) viewers: usize,
+    pub(crate) last_played: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct UserActivity {
+    pub(crate) name: String,
+    pub(crate) movies: usize,
+    pub(crate) plays: u32,
+    pub(crate) last_played: Option<DateTime<Utc>>,
+}
+
+/// Play counts aggregated over every Jellyfin user.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct PlaybackStats {
+    /// Most played first
+    pub(crate) movies: Vec<MovieViews>,
+    /// Most active first
+    pub(crate) users: Vec<UserActivity>,
+    /// Movies in the collection nobody has played, by folder name
+    pub(crate) unwatched: Vec<String>,
+}
+
+impl PlaybackStats {
+    /// Gathers every user's played movies. `movie_names` are the collection's folder names, used
+    /// to find movies nobody has watched.
+    pub(crate) async fn collect(api: &JellyfinClient, movie_names: Vec<String>) -> Result<Self, Error> {
+        let users = api.get_users().await?;
+        let played = try_join_all(users.iter().map(|user| api.get_played_movies(&user.id))).await?;
+
+        let mut movies: HashMap<String, MovieViews> = HashMap::new();
+        let mut activity = Vec::with_capacity(users.len());
+        for (user, items) in users.iter().zip(played) {
+            let mut user_activity = UserActivity {
+                name: user.name.clone(),
+                movies: 0,
+                plays: 0,
+                last_played: None,
+            };
+            for item in items {
+                let Some(user_data) = item.user_data.as_ref().filter(|d| d.played || d.play_count > 0) else {
+                    continue;
+                };
+                let plays = user_data.play_count.max(1);
+                let name = item.folder_name().unwrap_or(&item.name).to_string();
+                let views = movies.entry(name.clone()).or_insert_with(|| MovieViews {
+                    name,
+                    plays: 0,
+                    viewers: 0,
+                    last_played: None,
+                });
+                views.plays += plays;
+                views.viewers += 1;
+                views.last_played = views.last_played.max(user_data.last_played_date);
+                user_activity.movies += 1;
+                user_activity.plays += plays;
+                user_activity.last_played = user_activity.last_played.max(user_data.last_played_date);
+            }
+            activity.push(user_activity);
+        }
+
+        let mut unwatched: Vec<String> = movie_names
+            .into_iter()
+            .filter(|name| !movies.contains_key(name))
+            .collect();
+        unwatched.sort();
+        let mut movies: Vec<MovieViews> = movies.into_values().collect();
+        movies.sort_by(|a, b| b.plays.cmp(&a.plays).then_with(|| a.name.cmp(&b.name)));
+        activity.sort_by(|a, b| b.plays.cmp(&a.plays).then_with(|| a.name.cmp(&b.name)));
+        Ok(Self {
+            movies,
+            users: activity,
+            unwatched,
+        })
+    }
+}
+
+/// Total size of the files in a movie folder, as of the folder's modification time.
+#[derive(Debug, Clone, Copy)]
+struct FolderSize {
+    modified: Option<SystemTime>,
+    bytes: u64,
+}
+
+/// Sizes of movie folders, measured the first time they're asked for and again once the folder
+/// was modified. Files replaced within subfolders go unnoticed until the folder itself changes.
+#[derive(Debug, Default)]
+pub(crate) struct FolderSizes {
+    sizes: RwLock<HashMap<PathBuf, FolderSize>>,
+}
+
+impl FolderSizes {
+    pub(crate) async fn size(&self, folder: &Path) -> std::io::Result<u64> {
+        let modified = tokio::fs::metadata(folder).await?.modified().ok();
+        if let Some(size) = self.sizes.read().await.get(folder) {
+            if modified.is_some() && size.modified == modified {
+                return Ok(size.bytes);
+            }
+        }
+        let bytes = folder_bytes(folder).await?;
+        self.sizes.write().await.insert(folder.to_path_buf(), FolderSize { modified, bytes });
+        Ok(bytes)
+    }
+}
+
+/// Adds up the files below `folder`. Symlinks aren't followed, they point at data stored
+/// elsewhere.
+async fn folder_bytes(folder: &Path) -> std::io::Result<u64> {
+    let mut bytes = 0;
+    let mut pending = vec![folder.to_path_buf()];
+    while let Some(dir) = pending.pop() {
+        let mut entries = tokio::fs::read_dir(&dir).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            let metadata = entry.metadata().await?;
+            if metadata.is_dir() {
+                pending.push(entry.path());
+            } else if metadata.is_file() {
+                bytes += metadata.len();
+            }
+        }
+    }
+    Ok(bytes)
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct TagUsage {
+    pub(crate) key: String,
+    pub(crate) movies: usize,
+    pub(crate) bytes: u64,
+}
+
+/// Storage taken by the movies of each tag. A movie with several tags counts towards each.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct DiskUsage {
+    /// Largest first
+    pub(crate) tags: Vec<TagUsage>,
+    /// Size of the whole collection
+    pub(crate) total: u64,
+}
+
+impl DiskUsage {
+    /// Measures the movie folders not measured yet. Folders that can't be read count as empty.
+    pub(crate) async fn collect(sizes: &FolderSizes, collection: &RwLock<Collection>) -> Self {
+        let (folders, tags): (Vec<_>, Vec<_>) = {
+            let collection = collection.read().await;
+            let folders = collection.movies.iter().map(|(hash, movie)| (*hash, movie.path.clone())).collect();
+            let tags = collection
+                .tags
+                .iter()
+                .map(|(key, tag)| (key.clone(), tag.movies.iter().copied().collect::<Vec<_>>()))
+                .collect();
+            (folders, tags)
+        };
+        let movie_sizes: HashMap<_, u64> = futures::stream::iter(folders)
+            .map(|(hash, path)| async move {
+                let bytes = sizes.size(&path).await.unwrap_or_else(|e| {
+                    tracing::warn!("failed to measure {}: {}", path.display(), e);
+                    0
+                });
+                (hash, bytes)
+            })
+            .buffer_unordered(MEASURE_CONCURRENCY)
+            .collect()
+            .await;
+
+        let mut tags: Vec<TagUsage> = tags
+            .into_iter()
+            .map(|(key, movies)| {
+                let sizes: Vec<u64> = movies.iter().filter_map(|hash| movie_sizes.get(hash).copied()).collect();
+                TagUsage {
+                    key,
+                    movies: sizes.len(),
+                    bytes: sizes.iter().sum(),
+                }
+            })
+            .collect();
+        tags.sort_by(|a, b| b.bytes.cmp(&a.bytes).then_with(|| a.key.cmp(&b.key)));
+        Self {
+            tags,
+            total: movie_sizes.values().sum(),
+        }
+    }
+}
@@ -0,0 +1,660 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::Ordering;
+use std::sync::Mutex;
+use std::time::Instant;
+
+use futures::future::try_join_all;
+use futures::StreamExt;
+use tokio::fs::read_dir;
+
+use crate::collection::{
+    path_hash, Error, LinkMode, Movie, PathnameHash, ScanProgress, Tag, TagMeta, Tags, ID_FILE,
+    MAX_RATING, NOTE_FILE, POSTER_CANDIDATES, RATING_FILE, SCAN_CONCURRENCY,
+};
+use crate::config::PathMappings;
+use crate::nfo::Metadata;
+
+/// Where the movie folders and tag directories of a [`crate::Collection`] live. The collection
+/// keeps what it scanned in memory and goes through this for every change, so it can run
+/// against something other than the filesystem, like [`MemoryStorage`] in tests.
+#[axum::async_trait]
+pub(crate) trait Storage: std::fmt::Debug + Send + Sync {
+    fn movie_dir(&self) -> &Path;
+
+    fn tag_dir(&self) -> &Path;
+
+    /// Paths of all movie folders.
+    async fn movie_folders(&self) -> anyhow::Result<Vec<PathBuf>>;
+
+    async fn load_movie(&self, path: PathBuf) -> anyhow::Result<Movie>;
+
+    /// Every tag, keyed by its path relative to the tag directory, counting them in `progress`.
+    async fn load_tags(&self, progress: &ScanProgress) -> anyhow::Result<Tags>;
+
+    /// Puts the movie folder named `movie` into `tag`.
+    async fn link(&self, tag: &str, movie: &str, link_mode: LinkMode, path_mappings: &PathMappings) -> Result<(), Error>;
+
+    /// Takes the movie folder named `movie` out of `tag`.
+    async fn unlink(&self, tag: &str, movie: &str) -> Result<(), Error>;
+
+    async fn create_tag(&self, tag: &str) -> Result<(), Error>;
+
+    /// Deletes `tag` along with its links.
+    async fn delete_tag(&self, tag: &str) -> Result<(), Error>;
+
+    /// Removes what is left of a group whose last tag was deleted.
+    async fn remove_group(&self, group: &str) -> Result<(), Error>;
+
+    /// Stores the note of the movie folder named `movie`, or removes it if `None`.
+    async fn save_note(&self, movie: &str, note: Option<&str>) -> Result<(), Error>;
+
+    /// Stores the rating of the movie folder named `movie`, or removes it if `None`.
+    async fn save_rating(&self, movie: &str, rating: Option<u8>) -> Result<(), Error>;
+}
+
+/// Movie folders and tag directories on disk, tags linking to movies with symlinks or mirrored
+/// folders.
+#[derive(Debug)]
+pub(crate) struct FsStorage {
+    movie_dir: PathBuf,
+    tag_dir: PathBuf,
+}
+
+impl FsStorage {
+    pub(crate) async fn new(movie_dir: &Path, tag_dir: &Path) -> anyhow::Result<Self> {
+        Ok(Self {
+            movie_dir: tokio::fs::canonicalize(movie_dir).await?,
+            tag_dir: tokio::fs::canonicalize(tag_dir).await?,
+        })
+    }
+
+    /// Loads a top level tag directory, along with the tags nested in it if it's a group.
+    async fn load_tag_dir(name: String, path: PathBuf, movie_dir: &Path) -> anyhow::Result<Vec<(String, Tag)>> {
+        let mut tags = Vec::new();
+        let (tagged, nested) = Self::scan_tag_dir(&path, movie_dir).await?;
+        // an empty directory is an empty tag, not an empty group
+        if nested.is_empty() || !tagged.is_empty() {
+            let meta = TagMeta::load(&path).await;
+            tags.push((name.clone(), Tag { name: name.clone(), group: None, movies: tagged, meta }));
+        }
+        let nested_tags = try_join_all(nested.into_iter().map(|nested_name| {
+            let (group, tag_path) = (name.clone(), path.join(&nested_name));
+            async move {
+                let (tagged, deeper) = Self::scan_tag_dir(&tag_path, movie_dir).await?;
+                if !deeper.is_empty() {
+                    tracing::warn!("ignoring tag directories nested deeper than {}/{}: {:?}", group, nested_name, deeper);
+                }
+                let meta = TagMeta::load(&tag_path).await;
+                let key = format!("{}/{}", group, nested_name);
+                anyhow::Ok((key, Tag { name: nested_name, group: Some(group), movies: tagged, meta }))
+            }
+        }))
+        .await?;
+        tags.extend(nested_tags);
+        Ok(tags)
+    }
+
+    /// Splits the entries of a tag directory into tagged movies and nested tag directories.
+    /// Hardlink and copy modes mirror the movie folder instead of linking to it, so a folder
+    /// named after a movie is a tagged movie.
+    async fn scan_tag_dir(dir: &Path, movie_dir: &Path) -> anyhow::Result<(HashSet<PathnameHash>, Vec<String>)> {
+        let mut tagged = HashSet::new();
+        let mut nested = Vec::new();
+        let mut entries = read_dir(dir).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            let file_type = entry.file_type().await?;
+            if file_type.is_symlink() {
+                tagged.insert(path_hash(entry.path())?);
+            } else if file_type.is_dir() {
+                let name = entry.file_name();
+                // a mirrored folder carries the movie's ID file even after the movie is renamed
+                let is_movie = tokio::fs::try_exists(movie_dir.join(&name)).await?
+                    || tokio::fs::try_exists(entry.path().join(ID_FILE)).await?;
+                if is_movie {
+                    tagged.insert(path_hash(entry.path())?);
+                } else {
+                    nested.push(name.to_string_lossy().to_string());
+                }
+            }
+        }
+        Ok((tagged, nested))
+    }
+}
+
+#[axum::async_trait]
+impl Storage for FsStorage {
+    fn movie_dir(&self) -> &Path {
+        &self.movie_dir
+    }
+
+    fn tag_dir(&self) -> &Path {
+        &self.tag_dir
+    }
+
+    #[tracing::instrument(skip_all)]
+    async fn movie_folders(&self) -> anyhow::Result<Vec<PathBuf>> {
+        let mut movie_paths = Vec::new();
+        let mut entries = read_dir(&self.movie_dir).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            if entry.file_type().await?.is_dir() {
+                movie_paths.push(entry.path());
+            }
+        }
+        Ok(movie_paths)
+    }
+
+    async fn load_movie(&self, path: PathBuf) -> anyhow::Result<Movie> {
+        let name = path.file_name().unwrap_or_default().to_string_lossy().to_string();
+        let hash = path_hash(&path)?;
+        let mut poster = None;
+        for (file_name, content_type) in POSTER_CANDIDATES {
+            let poster_path = path.join(file_name);
+            if tokio::fs::try_exists(&poster_path).await? {
+                poster = Some((poster_path, *content_type));
+                break;
+            }
+        }
+        let (poster_path, poster_content_type) = poster
+            .map(|(poster_path, content_type)| (Some(poster_path), content_type))
+            .unwrap_or((None, "image/jpeg"));
+        let metadata = Metadata::load(&path).await;
+        let note = tokio::fs::read_to_string(path.join(NOTE_FILE)).await.ok().filter(|note| !note.trim().is_empty());
+        let rating = tokio::fs::read_to_string(path.join(RATING_FILE))
+            .await
+            .ok()
+            .and_then(|rating| rating.trim().parse().ok())
+            .filter(|rating| (1..=MAX_RATING).contains(rating));
+        let folder = tokio::fs::metadata(&path).await?;
+        Ok(Movie {
+            name,
+            hash,
+            path,
+            poster_path,
+            poster_content_type,
+            metadata,
+            note,
+            rating,
+            created: folder.created().ok(),
+            modified: folder.modified().ok(),
+        })
+    }
+
+    /// A directory whose subdirectories aren't movies is a group, and each of those
+    /// subdirectories is a tag in that group. Only one level of nesting is supported.
+    #[tracing::instrument(skip_all)]
+    async fn load_tags(&self, progress: &ScanProgress) -> anyhow::Result<Tags> {
+        let start = Instant::now();
+        let mut tag_dirs = Vec::new();
+        let mut entries = read_dir(&self.tag_dir).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            // dot directories hold tagrs' own files, and tags can't start with a dot; the movie
+            // directory may be nested in the tag directory
+            let hidden = entry.file_name().to_string_lossy().starts_with('.');
+            if !entry.file_type().await?.is_dir() || hidden || entry.path() == self.movie_dir {
+                continue;
+            }
+            let name = entry
+                .file_name()
+                .to_str()
+                .ok_or(anyhow::anyhow!("Invalid tag directory name"))?
+                .to_string();
+            tag_dirs.push((name, entry.path()));
+        }
+        let loaded: Vec<_> = futures::stream::iter(tag_dirs)
+            .map(|(name, path)| Self::load_tag_dir(name, path, &self.movie_dir))
+            .buffer_unordered(SCAN_CONCURRENCY)
+            .inspect(|loaded| {
+                if let Ok(tags) = loaded {
+                    progress.tags.fetch_add(tags.len(), Ordering::Relaxed);
+                }
+            })
+            .collect()
+            .await;
+        let mut tags = HashMap::new();
+        for tag_dir_tags in loaded {
+            tags.extend(tag_dir_tags?);
+        }
+        tracing::info!("loaded {} tags in {:?}", tags.len(), start.elapsed());
+        Ok(tags)
+    }
+
+    // spans time the filesystem calls, which can be slow on network mounts
+    #[tracing::instrument(skip(self, path_mappings))]
+    async fn link(&self, tag: &str, movie: &str, link_mode: LinkMode, path_mappings: &PathMappings) -> Result<(), Error> {
+        let tag_path = self.tag_dir.join(tag).join(movie);
+        let movie_path = self.movie_dir.join(movie);
+        tracing::debug!("linking {} to {} ({:?})", movie_path.display(), tag_path.display(), link_mode);
+        link(link_mode, &movie_path, &tag_path, path_mappings).await
+    }
+
+    #[tracing::instrument(skip(self))]
+    async fn unlink(&self, tag: &str, movie: &str) -> Result<(), Error> {
+        let tag_path = self.tag_dir.join(tag).join(movie);
+        tracing::debug!("unlinking {}", tag_path.display());
+        unlink(&tag_path, &self.movie_dir.join(movie)).await
+    }
+
+    #[tracing::instrument(skip(self))]
+    async fn create_tag(&self, tag: &str) -> Result<(), Error> {
+        tokio::fs::create_dir_all(self.tag_dir.join(tag)).await?;
+        Ok(())
+    }
+
+    /// Movie folders are never touched, whatever the link mode.
+    #[tracing::instrument(skip(self))]
+    async fn delete_tag(&self, tag: &str) -> Result<(), Error> {
+        let tag_path = self.tag_dir.join(tag);
+        let mut entries = read_dir(&tag_path).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            if entry.file_name() == TagMeta::FILE_NAME {
+                tokio::fs::remove_file(entry.path()).await?;
+            } else {
+                unlink(&entry.path(), &self.movie_dir.join(entry.file_name())).await?;
+            }
+        }
+        tokio::fs::remove_dir(&tag_path).await?;
+        Ok(())
+    }
+
+    /// An empty group directory would come back as an empty tag.
+    async fn remove_group(&self, group: &str) -> Result<(), Error> {
+        tokio::fs::remove_dir(self.tag_dir.join(group)).await?;
+        Ok(())
+    }
+
+    #[tracing::instrument(skip(self, note))]
+    async fn save_note(&self, movie: &str, note: Option<&str>) -> Result<(), Error> {
+        write_sidecar(&self.movie_dir.join(movie).join(NOTE_FILE), note).await
+    }
+
+    #[tracing::instrument(skip(self))]
+    async fn save_rating(&self, movie: &str, rating: Option<u8>) -> Result<(), Error> {
+        let rating = rating.map(|rating| rating.to_string());
+        write_sidecar(&self.movie_dir.join(movie).join(RATING_FILE), rating.as_deref()).await
+    }
+}
+
+/// Writes a file tagrs keeps in a movie folder, or removes it if `contents` is `None`.
+async fn write_sidecar(path: &Path, contents: Option<&str>) -> Result<(), Error> {
+    match contents {
+        Some(contents) => tokio::fs::write(path, contents).await?,
+        None => match tokio::fs::remove_file(path).await {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e.into()),
+            _ => {}
+        },
+    }
+    Ok(())
+}
+
+/// Puts a movie into a tag directory. Symlinks point where Jellyfin sees the movie folder, as
+/// that's who follows them.
+async fn link(link_mode: LinkMode, movie_path: &Path, tag_path: &Path, path_mappings: &PathMappings) -> Result<(), Error> {
+    match link_mode {
+        LinkMode::Symlink => platform::link_dir(&path_mappings.to_jellyfin(movie_path), tag_path).await?,
+        LinkMode::Hardlink | LinkMode::Copy => {
+            let source = movie_path.to_path_buf();
+            let target = tag_path.to_path_buf();
+            let hardlink = link_mode == LinkMode::Hardlink;
+            let result = tokio::task::spawn_blocking(move || mirror_dir(&source, &target, hardlink))
+                .await
+                .map_err(anyhow::Error::from)?;
+            if let Err(e) = result {
+                // don't leave a half-copied folder behind that would show up as tagged
+                if let Err(cleanup) = tokio::fs::remove_dir_all(tag_path).await {
+                    tracing::warn!("failed to clean up {}: {}", tag_path.display(), cleanup);
+                }
+                return Err(e.into());
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Recreates the directory tree at `source` under `target`, hard linking or copying files.
+fn mirror_dir(source: &Path, target: &Path, hardlink: bool) -> std::io::Result<()> {
+    std::fs::create_dir(target)?;
+    for entry in std::fs::read_dir(source)? {
+        let entry = entry?;
+        let file_type = entry.file_type()?;
+        let target = target.join(entry.file_name());
+        if file_type.is_dir() {
+            mirror_dir(&entry.path(), &target, hardlink)?;
+        } else if file_type.is_symlink() {
+            platform::copy_symlink(&entry.path(), &target)?;
+        } else if hardlink {
+            std::fs::hard_link(entry.path(), &target)?;
+        } else {
+            std::fs::copy(entry.path(), &target)?;
+        }
+    }
+    Ok(())
+}
+
+/// Removes a movie's entry from a tag directory, whichever link mode created it. Symlinks are
+/// unlinked and mirrored folders deleted; the movie folder itself is never touched.
+async fn unlink(tag_path: &Path, movie_path: &Path) -> Result<(), Error> {
+    let metadata = tokio::fs::symlink_metadata(tag_path).await?;
+    if metadata.is_symlink() {
+        platform::remove_link(tag_path).await?;
+        return Ok(());
+    }
+    if !metadata.is_dir() {
+        tokio::fs::remove_file(tag_path).await?;
+        return Ok(());
+    }
+    let (tag_path, movie_path) = (
+        tokio::fs::canonicalize(tag_path).await?,
+        tokio::fs::canonicalize(movie_path).await?,
+    );
+    if tag_path == movie_path {
+        return Err(Error::InvalidPath(format!(
+            "refusing to delete the movie folder {}",
+            movie_path.display()
+        )));
+    }
+    tokio::fs::remove_dir_all(&tag_path).await?;
+    Ok(())
+}
+
+/// Filesystem linking primitives, which differ between Unix and Windows.
+mod platform {
+    use std::path::Path;
+
+    #[cfg(unix)]
+    pub(super) async fn link_dir(target: &Path, link: &Path) -> std::io::Result<()> {
+        tokio::fs::symlink(target, link).await
+    }
+
+    /// Creates a directory symlink, which needs developer mode or elevated privileges, falling
+    /// back to a junction which any user can create.
+    #[cfg(windows)]
+    pub(super) async fn link_dir(target: &Path, link: &Path) -> std::io::Result<()> {
+        let target = strip_verbatim_prefix(target);
+        match tokio::fs::symlink_dir(&target, link).await {
+            Ok(()) => Ok(()),
+            Err(e) if e.raw_os_error() == Some(ERROR_PRIVILEGE_NOT_HELD) => {
+                tracing::debug!("no symlink privilege, creating a junction for {}", link.display());
+                create_junction(&target, link).await
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    #[cfg(windows)]
+    pub(super) async fn create_junction(target: &Path, link: &Path) -> std::io::Result<()> {
+        let (target, link) = (strip_verbatim_prefix(target), link.to_path_buf());
+        tokio::task::spawn_blocking(move || junction::create(&target, &link))
+            .await
+            .map_err(std::io::Error::other)?
+    }
+
+    #[cfg(windows)]
+    const ERROR_PRIVILEGE_NOT_HELD: i32 = 1314;
+
+    /// `canonicalize` returns `\\?\C:\...` paths on Windows, which junctions and most
+    /// programs reading the link can't handle, so store plain drive letter paths instead.
+    #[cfg(windows)]
+    fn strip_verbatim_prefix(path: &Path) -> std::path::PathBuf {
+        let path_str = path.to_string_lossy();
+        match path_str.strip_prefix(r"\\?\") {
+            Some(rest) if !rest.starts_with("UNC\\") => rest.into(),
+            _ => path.to_path_buf(),
+        }
+    }
+
+    #[cfg(unix)]
+    pub(super) async fn remove_link(link: &Path) -> std::io::Result<()> {
+        tokio::fs::remove_file(link).await
+    }
+
+    /// Directory symlinks and junctions are removed like directories on Windows, which leaves
+    /// the directory they point to untouched.
+    #[cfg(windows)]
+    pub(super) async fn remove_link(link: &Path) -> std::io::Result<()> {
+        match tokio::fs::remove_dir(link).await {
+            Ok(()) => Ok(()),
+            Err(_) => tokio::fs::remove_file(link).await,
+        }
+    }
+
+    #[cfg(unix)]
+    pub(super) fn copy_symlink(source: &Path, target: &Path) -> std::io::Result<()> {
+        std::os::unix::fs::symlink(std::fs::read_link(source)?, target)
+    }
+
+    #[cfg(windows)]
+    pub(super) fn copy_symlink(source: &Path, target: &Path) -> std::io::Result<()> {
+        let link_target = std::fs::read_link(source)?;
+        if std::fs::metadata(source)?.is_dir() {
+            std::os::windows::fs::symlink_dir(link_target, target)
+        } else {
+            std::os::windows::fs::symlink_file(link_target, target)
+        }
+    }
+}
+
+/// Movies and tags held in memory only, for exercising a collection without touching the disk.
+/// Movies have no posters, metadata or video files.
+#[derive(Debug)]
+pub struct MemoryStorage {
+    movie_dir: PathBuf,
+    tag_dir: PathBuf,
+    movies: Vec<String>,
+    /// Names of the movies in each tag
+    tags: Mutex<BTreeMap<String, BTreeSet<String>>>,
+    /// Notes by movie name
+    notes: Mutex<HashMap<String, String>>,
+    /// Ratings by movie name
+    ratings: Mutex<HashMap<String, u8>>,
+}
+
+impl MemoryStorage {
+    /// Holds the movie folders named `movies` and the empty tags `tags`, which may be grouped
+    /// like `genre/horror`.
+    pub fn new(movies: &[&str], tags: &[&str]) -> Self {
+        Self {
+            movie_dir: PathBuf::from("/movies"),
+            tag_dir: PathBuf::from("/tags"),
+            movies: movies.iter().map(|movie| movie.to_string()).collect(),
+            tags: Mutex::new(tags.iter().map(|tag| (tag.to_string(), BTreeSet::new())).collect()),
+            notes: Mutex::default(),
+            ratings: Mutex::default(),
+        }
+    }
+}
+
+#[axum::async_trait]
+impl Storage for MemoryStorage {
+    fn movie_dir(&self) -> &Path {
+        &self.movie_dir
+    }
+
+    fn tag_dir(&self) -> &Path {
+        &self.tag_dir
+    }
+
+    async fn movie_folders(&self) -> anyhow::Result<Vec<PathBuf>> {
+        Ok(self.movies.iter().map(|movie| self.movie_dir.join(movie)).collect())
+    }
+
+    async fn load_movie(&self, path: PathBuf) -> anyhow::Result<Movie> {
+        let name = path.file_name().unwrap_or_default().to_string_lossy().to_string();
+        Ok(Movie {
+            hash: path_hash(&path)?,
+            note: self.notes.lock().unwrap().get(&name).cloned(),
+            rating: self.ratings.lock().unwrap().get(&name).copied(),
+            name,
+            path,
+            poster_path: None,
+            poster_content_type: POSTER_CANDIDATES[0].1,
+            metadata: None,
+            created: None,
+            modified: None,
+        })
+    }
+
+    async fn load_tags(&self, progress: &ScanProgress) -> anyhow::Result<Tags> {
+        let stored = self.tags.lock().unwrap();
+        let mut tags = HashMap::new();
+        for (key, movies) in stored.iter() {
+            let (group, name) = match key.split_once('/') {
+                Some((group, name)) => (Some(group.to_string()), name),
+                None => (None, key.as_str()),
+            };
+            let movies = movies.iter().map(path_hash).collect::<anyhow::Result<HashSet<PathnameHash>>>()?;
+            tags.insert(key.clone(), Tag { name: name.to_string(), group, movies, meta: TagMeta::default() });
+        }
+        progress.tags.fetch_add(tags.len(), Ordering::Relaxed);
+        Ok(tags)
+    }
+
+    async fn link(&self, tag: &str, movie: &str, _: LinkMode, _: &PathMappings) -> Result<(), Error> {
+        let mut tags = self.tags.lock().unwrap();
+        if !tags.get_mut(tag).ok_or(Error::NotFound)?.insert(movie.to_string()) {
+            return Err(std::io::Error::from(std::io::ErrorKind::AlreadyExists).into());
+        }
+        Ok(())
+    }
+
+    async fn unlink(&self, tag: &str, movie: &str) -> Result<(), Error> {
+        let mut tags = self.tags.lock().unwrap();
+        if !tags.get_mut(tag).ok_or(Error::NotFound)?.remove(movie) {
+            return Err(std::io::Error::from(std::io::ErrorKind::NotFound).into());
+        }
+        Ok(())
+    }
+
+    async fn create_tag(&self, tag: &str) -> Result<(), Error> {
+        self.tags.lock().unwrap().entry(tag.to_string()).or_default();
+        Ok(())
+    }
+
+    async fn delete_tag(&self, tag: &str) -> Result<(), Error> {
+        self.tags.lock().unwrap().remove(tag).ok_or(Error::NotFound)?;
+        Ok(())
+    }
+
+    async fn remove_group(&self, _: &str) -> Result<(), Error> {
+        Ok(())
+    }
+
+    async fn save_note(&self, movie: &str, note: Option<&str>) -> Result<(), Error> {
+        let mut notes = self.notes.lock().unwrap();
+        match note {
+            Some(note) => notes.insert(movie.to_string(), note.to_string()),
+            None => notes.remove(movie),
+        };
+        Ok(())
+    }
+
+    async fn save_rating(&self, movie: &str, rating: Option<u8>) -> Result<(), Error> {
+        let mut ratings = self.ratings.lock().unwrap();
+        match rating {
+            Some(rating) => ratings.insert(movie.to_string(), rating),
+            None => ratings.remove(movie),
+        };
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn movie_folder(root: &Path) -> PathBuf {
+        let movie = root.join("movies").join("Up (2009)");
+        std::fs::create_dir_all(&movie).unwrap();
+        std::fs::write(movie.join("movie.mkv"), "video").unwrap();
+        std::fs::create_dir(root.join("kids")).unwrap();
+        movie
+    }
+
+    fn is_link(path: &Path) -> bool {
+        std::fs::symlink_metadata(path).is_ok_and(|metadata| metadata.is_symlink())
+    }
+
+    #[tokio::test]
+    async fn symlink_links_and_unlinks_the_movie_folder() {
+        let root = tempfile::tempdir().unwrap();
+        let movie = movie_folder(root.path());
+        let tagged = root.path().join("kids").join("Up (2009)");
+
+        link(LinkMode::Symlink, &movie, &tagged, &PathMappings::default()).await.unwrap();
+        assert!(is_link(&tagged));
+        assert_eq!(std::fs::read_to_string(tagged.join("movie.mkv")).unwrap(), "video");
+
+        unlink(&tagged, &movie).await.unwrap();
+        assert!(!tagged.exists() && !is_link(&tagged));
+        assert!(movie.join("movie.mkv").exists());
+    }
+
+    #[tokio::test]
+    async fn hardlink_mirrors_the_movie_folder_and_unlink_removes_only_the_mirror() {
+        let root = tempfile::tempdir().unwrap();
+        let movie = movie_folder(root.path());
+        let tagged = root.path().join("kids").join("Up (2009)");
+
+        link(LinkMode::Hardlink, &movie, &tagged, &PathMappings::default()).await.unwrap();
+        assert!(!is_link(&tagged));
+        assert_eq!(std::fs::read_to_string(tagged.join("movie.mkv")).unwrap(), "video");
+
+        unlink(&tagged, &movie).await.unwrap();
+        assert!(!tagged.exists());
+        assert!(movie.join("movie.mkv").exists());
+    }
+
+    #[tokio::test]
+    async fn unlink_refuses_to_delete_the_movie_folder() {
+        let root = tempfile::tempdir().unwrap();
+        let movie = movie_folder(root.path());
+
+        assert!(matches!(unlink(&movie, &movie).await, Err(Error::InvalidPath(_))));
+        assert!(movie.join("movie.mkv").exists());
+    }
+
+    #[tokio::test]
+    async fn remove_link_leaves_the_target_untouched() {
+        let root = tempfile::tempdir().unwrap();
+        let movie = movie_folder(root.path());
+        let tagged = root.path().join("kids").join("Up (2009)");
+
+        platform::link_dir(&movie, &tagged).await.unwrap();
+        platform::remove_link(&tagged).await.unwrap();
+        assert!(!is_link(&tagged));
+        assert!(movie.join("movie.mkv").exists());
+    }
+
+    #[test]
+    fn copy_symlink_points_where_the_source_does() {
+        let root = tempfile::tempdir().unwrap();
+        let movie = movie_folder(root.path());
+        let source = root.path().join("source");
+        let copy = root.path().join("copy");
+        #[cfg(unix)]
+        std::os::unix::fs::symlink(&movie, &source).unwrap();
+        #[cfg(windows)]
+        std::os::windows::fs::symlink_dir(&movie, &source).unwrap();
+
+        platform::copy_symlink(&source, &copy).unwrap();
+        assert!(is_link(&copy));
+        assert_eq!(std::fs::read_link(&copy).unwrap(), movie);
+    }
+
+    /// Without the symlink privilege, which CI runners have, directories are linked with junctions.
+    #[cfg(windows)]
+    #[tokio::test]
+    async fn junctions_link_and_unlink_the_movie_folder() {
+        let root = tempfile::tempdir().unwrap();
+        let movie = movie_folder(root.path()).canonicalize().unwrap();
+        let tagged = root.path().join("kids").join("Up (2009)");
+
+        platform::create_junction(&movie, &tagged).await.unwrap();
+        assert!(junction::exists(&tagged).unwrap());
+        assert_eq!(std::fs::read_to_string(tagged.join("movie.mkv")).unwrap(), "video");
+
+        platform::remove_link(&tagged).await.unwrap();
+        assert!(!tagged.exists());
+        assert!(movie.join("movie.mkv").exists());
+    }
+}
@@ -0,0 +1,97 @@
+//! Running as a systemd service: telling systemd when tagrs is ready with `Type=notify`, and
+//! serving on a socket systemd opened for it with socket activation.
+
+/// First file descriptor passed by socket activation, see sd_listen_fds(3).
+#[cfg(unix)]
+const LISTEN_FDS_START: std::os::fd::RawFd = 3;
+
+/// A listening socket handed over by systemd.
+#[derive(Debug)]
+pub enum ActivatedSocket {
+    Tcp(std::net::TcpListener),
+    #[cfg(unix)]
+    Unix(std::os::unix::net::UnixListener),
+}
+
+/// Takes the first socket systemd passed, if tagrs was started by socket activation. Further
+/// sockets are ignored.
+#[cfg(unix)]
+pub fn activated_socket() -> anyhow::Result<Option<ActivatedSocket>> {
+    use std::os::fd::{FromRawFd, IntoRawFd};
+
+    let env = |name| std::env::var(name).ok().and_then(|value| value.parse::<u32>().ok());
+    // the variables are meant for this process only, not its children like ffprobe
+    if env("LISTEN_PID") != Some(std::process::id()) {
+        return Ok(None);
+    }
+    match env("LISTEN_FDS").unwrap_or(0) {
+        0 => return Ok(None),
+        1 => {}
+        count => tracing::warn!("systemd passed {} sockets, only serving the first", count),
+    }
+    // SAFETY: systemd hands the sockets to this process as the descriptors from 3 on, and
+    // nothing else in tagrs takes ownership of them
+    let tcp = unsafe { std::net::TcpListener::from_raw_fd(LISTEN_FDS_START) };
+    // getting the address of a unix socket as an IP address fails
+    let socket = if tcp.local_addr().is_ok() {
+        tcp.set_nonblocking(true)?;
+        ActivatedSocket::Tcp(tcp)
+    } else {
+        // SAFETY: the descriptor was just released by the TCP listener
+        let unix = unsafe { std::os::unix::net::UnixListener::from_raw_fd(tcp.into_raw_fd()) };
+        unix.local_addr().map_err(|_| anyhow::anyhow!("the socket passed by systemd is neither TCP nor a unix socket"))?;
+        unix.set_nonblocking(true)?;
+        ActivatedSocket::Unix(unix)
+    };
+    Ok(Some(socket))
+}
+
+#[cfg(not(unix))]
+pub fn activated_socket() -> anyhow::Result<Option<ActivatedSocket>> {
+    Ok(None)
+}
+
+/// Tells systemd that tagrs is serving, for units with `Type=notify`. Without a notification
+/// socket, e.g. when not run by systemd, this does nothing.
+pub fn notify_ready() {
+    if let Err(e) = notify("READY=1") {
+        tracing::warn!("failed to notify systemd: {}", e);
+    }
+}
+
+/// Sends `state` to the socket in `NOTIFY_SOCKET`, see sd_notify(3).
+#[cfg(unix)]
+fn notify(state: &str) -> std::io::Result<()> {
+    use std::os::unix::net::UnixDatagram;
+
+    let Some(path) = std::env::var_os("NOTIFY_SOCKET") else {
+        return Ok(());
+    };
+    let socket = UnixDatagram::unbound()?;
+    // a leading @ names a socket in the abstract namespace
+    if let Some(name) = path.as_encoded_bytes().strip_prefix(b"@") {
+        #[cfg(any(target_os = "linux", target_os = "android"))]
+        {
+            #[cfg(target_os = "android")]
+            use std::os::android::net::SocketAddrExt;
+            #[cfg(target_os = "linux")]
+            use std::os::linux::net::SocketAddrExt;
+
+            let addr = std::os::unix::net::SocketAddr::from_abstract_name(name)?;
+            socket.send_to_addr(state.as_bytes(), &addr)?;
+            return Ok(());
+        }
+        #[cfg(not(any(target_os = "linux", target_os = "android")))]
+        {
+            let _ = name;
+            return Err(std::io::Error::new(std::io::ErrorKind::Unsupported, "abstract sockets are Linux only"));
+        }
+    }
+    socket.send_to(state.as_bytes(), path)?;
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn notify(_state: &str) -> std::io::Result<()> {
+    Ok(())
+}
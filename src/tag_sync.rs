@@ -0,0 +1,143 @@
+use std::collections::{BTreeSet, HashMap};
+
+use serde::Deserialize;
+
+use crate::collection::{Collection, Error, PathnameHash};
+use crate::jellyfin_api::Item;
+
+/// Which way tags are copied between tagrs and the `Tags` of Jellyfin items.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SyncDirection {
+    #[default]
+    Off,
+    /// Set the tags of Jellyfin items from tagrs
+    Push,
+    /// Import the tags of Jellyfin items as tagrs tags
+    Pull,
+    Both,
+}
+
+impl SyncDirection {
+    pub(crate) fn push(self) -> bool {
+        matches!(self, SyncDirection::Push | SyncDirection::Both)
+    }
+
+    pub(crate) fn pull(self) -> bool {
+        matches!(self, SyncDirection::Pull | SyncDirection::Both)
+    }
+}
+
+/// Which side wins when a movie carries a tagrs tag on one side only. Jellyfin tags tagrs
+/// doesn't have are never a conflict and are kept on Jellyfin and imported when pulling.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ConflictPolicy {
+    /// A tag on either side is added to the other
+    #[default]
+    Union,
+    /// Tags missing in tagrs are removed from Jellyfin
+    Tagrs,
+    /// Tags missing in Jellyfin are removed from tagrs
+    Jellyfin,
+}
+
+/// A tag added to or removed from a movie by pulling.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Pull {
+    pub(crate) tag: String,
+    pub(crate) movie_name: String,
+    pub(crate) movie: PathnameHash,
+    pub(crate) add: bool,
+}
+
+/// New tags for a Jellyfin item.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Push {
+    pub(crate) item_id: String,
+    pub(crate) movie_name: String,
+    pub(crate) tags: Vec<String>,
+}
+
+/// Compares the movies with the Jellyfin `items` showing them, matched by folder name, and
+/// returns what needs to change on each side.
+pub(crate) fn plan(
+    collection: &Collection,
+    items: &[Item],
+    direction: SyncDirection,
+    conflict: ConflictPolicy,
+) -> (Vec<Pull>, Vec<Push>) {
+    let mut by_folder: HashMap<&str, Vec<&Item>> = HashMap::new();
+    for item in items {
+        if let Some(folder) = item.folder_name() {
+            by_folder.entry(folder).or_default().push(item);
+        }
+    }
+    let mut pulls = Vec::new();
+    let mut pushes = Vec::new();
+    for movie in collection.movies.values() {
+        let Some(items) = by_folder.get(movie.name.as_str()) else {
+            continue;
+        };
+        let tagrs: BTreeSet<&str> = movie.tags(collection).into_iter().map(|(key, _)| key).collect();
+        // Jellyfin tags named like an alias count as the aliased tag
+        let jellyfin: BTreeSet<&str> = items
+            .iter()
+            .flat_map(|item| item.tags.iter().map(|tag| collection.resolve_tag(tag).unwrap_or(tag)))
+            .collect();
+        let foreign = jellyfin.iter().copied().filter(|tag| !collection.tags.contains_key(*tag));
+        let wanted: BTreeSet<&str> = match conflict {
+            ConflictPolicy::Union => tagrs.union(&jellyfin).copied().collect(),
+            ConflictPolicy::Tagrs => tagrs.iter().copied().chain(foreign).collect(),
+            ConflictPolicy::Jellyfin => jellyfin.clone(),
+        };
+        if direction.pull() {
+            let pull = |tag: &str, add| Pull {
+                tag: tag.to_string(),
+                movie_name: movie.name.clone(),
+                movie: movie.hash,
+                add,
+            };
+            pulls.extend(wanted.difference(&tagrs).map(|tag| pull(tag, true)));
+            pulls.extend(tagrs.difference(&wanted).map(|tag| pull(tag, false)));
+        }
+        if direction.push() {
+            for item in items {
+                let current: BTreeSet<&str> = item.tags.iter().map(String::as_str).collect();
+                if current != wanted {
+                    pushes.push(Push {
+                        item_id: item.id.clone(),
+                        movie_name: movie.name.clone(),
+                        tags: wanted.iter().map(|tag| tag.to_string()).collect(),
+                    });
+                }
+            }
+        }
+    }
+    (pulls, pushes)
+}
+
+/// Applies pulled tags to the collection, creating missing tags. Returns the changes made and
+/// the tags created; Jellyfin tags that aren't valid tag names are skipped.
+pub(crate) async fn apply(collection: &mut Collection, pulls: Vec<Pull>) -> Result<(Vec<Pull>, Vec<String>), Error> {
+    let mut applied = Vec::new();
+    let mut created = Vec::new();
+    for pull in pulls {
+        let Some(movie) = collection.movies.get(&pull.movie).cloned() else {
+            continue;
+        };
+        if !collection.tags.contains_key(&pull.tag) {
+            if let Err(e) = collection.create_tag(&pull.tag).await {
+                tracing::warn!("not importing Jellyfin tag {:?}: {}", pull.tag, e);
+                continue;
+            }
+            created.push(pull.tag.clone());
+        }
+        let tagged = collection.tags[&pull.tag].movies.contains(&movie.hash);
+        if tagged != pull.add {
+            collection.toggle_tag(&pull.tag, &movie).await?;
+            applied.push(pull);
+        }
+    }
+    Ok((applied, created))
+}
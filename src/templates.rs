@@ -1,142 +1,523 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::sync::Arc;
+
 use maud::{html, Markup, DOCTYPE};
+use minijinja::Value;
+use percent_encoding::{utf8_percent_encode, NON_ALPHANUMERIC};
+
+use crate::assets::static_url;
+use crate::audit::Event;
+use crate::i18n::{lang, t, tf, tm};
+use crate::config::{Preset, TraktListConfig};
+use crate::collection::{tag_library_name, Collection, Error, Movie, MovieFile, Tag, MAX_RATING};
+use crate::media_info::{MediaInfo, MediaInfoMap, Resolution};
+use crate::preview::{LibraryContent, LibraryPreview};
+use crate::radarr::{RadarrEntry, RadarrIndex};
+use crate::import;
+use crate::rules::{Change, Rule};
+use crate::snapshots::{Restored, Snapshot};
+use crate::stats::{DiskUsage, PlaybackStats};
+use crate::tag_sync::SyncDirection;
+use crate::trakt::{Connection, LastSync};
+use crate::watched::PlayedFolders;
+use crate::theme::{self, url, ColorScheme, PageTheme};
+use crate::jellyfin_api::{Device, JellyfinServers, MediaFolders, ParentalRating, RemoteImage, ScheduledTask, Session, SystemInfo, User, TICKS_PER_SECOND, UNRATED_ITEM_TYPES};
+use crate::{DiskSort, MovieFilter, Paging, Sort, Subset, TagSort, UserFilter};
 
-use crate::collection::{Collection, Error, Movie};
-use crate::jellyfin_api::{MediaFolders, User};
-use crate::Paging;
+/// Joins the paging and filter query strings of the movie list.
+fn list_query(paging: Paging, filter: &MovieFilter) -> String {
+    match filter.query() {
+        filter_query if filter_query.is_empty() => paging.query(),
+        filter_query => format!("{}&{}", paging.query(), filter_query),
+    }
+}
 
 pub const MISSING_POSTER: &[u8] = include_bytes!("static/missing_poster.jpg");
 
+/// Per-movie details from optional integrations, shown on movie cards.
 #[derive(Debug, Default, Clone)]
-struct PageOptions {
+pub struct MovieSources {
+    pub(crate) media: Arc<MediaInfoMap>,
+    /// Missing when Radarr isn't configured or couldn't be reached
+    pub(crate) radarr: Option<Arc<RadarrIndex>>,
+    /// Missing without a Jellyfin server or when it couldn't be reached
+    pub(crate) watched: Option<Arc<PlayedFolders>>,
+}
+
+impl MovieSources {
+    pub(crate) fn media_info(&self, movie: &Movie) -> Option<&MediaInfo> {
+        self.media.get(&movie.path)
+    }
+
+    pub(crate) fn radarr(&self, movie: &Movie) -> Option<&RadarrEntry> {
+        self.radarr.as_ref()?.get(&movie.name)
+    }
+
+    /// Whether the movie was played, if that's known.
+    pub(crate) fn watched(&self, collection: &Collection, movie: &Movie) -> Option<bool> {
+        let played = self.watched.as_ref()?;
+        Some(played.contains(&collection.path_mappings.to_jellyfin(&movie.path)))
+    }
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct PageOptions {
     controls: Option<Markup>,
     footer: Option<Markup>,
 }
 
+/// Drop-down picking a light or dark color scheme, or following the system.
+fn color_scheme_picker(selected: Option<ColorScheme>) -> Markup {
+    html! {
+        form .color-scheme hx-post=(url("/theme")) hx-trigger="change" {
+            select name="scheme" aria-label=(t("theme.color_scheme")) {
+                option value="auto" selected[selected.is_none()] { (t("theme.auto")) }
+                @for scheme in ColorScheme::ALL {
+                    option value=(scheme.as_str()) selected[selected == Some(scheme)] {
+                        (t(&format!("theme.{}", scheme.as_str())))
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Page header, replaced by `header.html` from the templates directory if there is one.
+fn header(title: &str, theme: &PageTheme, controls: Option<Markup>) -> Markup {
+    let nav = html! {
+        a href=(url("/")) { (t("nav.movies")) }
+        a href=(url("/?sort=added")) { (t("nav.recent")) }
+        a href=(url("/tags")) { (t("nav.tags")) }
+        @for view in &theme.views {
+            a href={(url("/view/")) (utf8_percent_encode(view, NON_ALPHANUMERIC))} { (view) }
+        }
+        a href=(url("/random")) { (t("nav.random")) }
+        @if theme.admin && theme.jellyfin {
+            a href=(url("/user-libraries")) { (t("nav.user_libraries")) }
+            a href=(url("/presets")) { (t("nav.presets")) }
+        }
+        a href=(url("/rules")) { (t("nav.rules")) }
+        a href=(url("/import")) { (t("nav.import")) }
+        @if theme.admin && theme.trakt {
+            a href=(url("/trakt")) { (t("nav.trakt")) }
+        }
+        @if theme.admin {
+            a href=(url("/snapshots")) { (t("nav.snapshots")) }
+        }
+        @if theme.admin && theme.jellyfin {
+            a href=(url("/sessions")) { (t("nav.sessions")) }
+            a href=(url("/tasks")) { (t("nav.tasks")) }
+        }
+        a href=(url("/stats")) { (t("nav.statistics")) }
+        @if theme.admin {
+            a href=(url("/activity")) { (t("nav.activity")) }
+            a href=(url("/audit")) { (t("nav.audit")) }
+        }
+        @if theme.admin && theme.jellyfin {
+            button .secondary hx-post=(url("/jellyfin/refresh")) hx-swap="outerHTML" { (t("nav.scan_jellyfin")) }
+        }
+        @if theme.jellyfin {
+            span #server-status hx-get=(url("/server/status")) hx-trigger="load" hx-swap="outerHTML" {}
+        }
+    };
+    let controls = controls.unwrap_or_else(|| html! { div {} });
+    if let Some(overrides) = &theme.overrides {
+        let context = minijinja::context! {
+            title => title,
+            logo => theme.logo.then(|| url("/theme/logo")),
+            nav => Value::from_safe_string(nav.0.clone()),
+            color_scheme => Value::from_safe_string(color_scheme_picker(theme.scheme).0),
+            controls => Value::from_safe_string(controls.0.clone()),
+        };
+        if let Some(header) = overrides.render("header.html", context) {
+            return header;
+        }
+    }
+    html! {
+        header {
+            @if theme.logo { img .logo src=(url("/theme/logo")) alt=""; }
+            h1 { (title) }
+            (nav)
+            (color_scheme_picker(theme.scheme))
+            (controls)
+        }
+    }
+}
+
 pub fn page(title: &str, content: Markup, options: PageOptions) -> Markup {
+    let theme = theme::current();
     html! {
         (DOCTYPE)
-        html {
+        html lang=(lang()) data-theme=[theme.scheme.map(|s| s.as_str())] {
             head {
                 title { (title) }
-                link rel="stylesheet" href="/static/reset.css";
-                link rel="stylesheet" href="/static/pico.min.css";
-                link rel="stylesheet" href="/static/main.css";
-                script src="/static/htmx.min.js" {}
+                link rel="stylesheet" href=(static_url("reset.css"));
+                link rel="stylesheet" href=(static_url("pico.min.css"));
+                link rel="stylesheet" href=(static_url("main.css"));
+                @if theme.custom_css { link rel="stylesheet" href=(url("/theme/custom.css")); }
+                // swap error responses too, they are retargeted to #errors
+                meta name="htmx-config" content=r#"{"responseHandling":[{"code":"204","swap":false},{"code":"[23]..","swap":true},{"code":"[45]..","swap":true,"error":true}]}"#;
+                script src=(static_url("htmx.min.js")) {}
+                script src=(static_url("updates.js")) data-url=(url("/updates")) defer {}
             }
             body {
-                header {
-                    h1 { (title) }
-                    a href="/" { "Movie Tagger" }
-                    a href="/user-libraries" { "User Libraries" }
-                    @if let Some(c) = options.controls { (c) } @else { div {} }
-                }
+                (header(title, &theme, options.controls))
                 main { (content) }
+                div #errors aria-live="polite" {}
                 footer { @if let Some(f) = options.footer { (f) } }
             }
         }
     }
 }
 
-pub fn paging_controls(url: &str, paging: Paging, total_items: usize) -> Markup {
+pub fn paging_controls(url: &str, paging: Paging, filter: &MovieFilter, total_items: usize) -> Markup {
     let per_page_options = [10, 25, 50, 100];
     let last_page = paging.last_page(total_items);
     let prev_page = paging.page.saturating_sub(1).max(1);
-    let next_page = (paging.page + 1).min(last_page);
+    let next_page = paging.page.saturating_add(1).min(last_page);
+    let page_query = |page: usize| list_query(paging.with_page(page), filter);
+    let mut page_vals = serde_json::json!({"per_page": paging.per_page, "sort": paging.sort.as_str()});
+    if let (Some(vals), Ok(serde_json::Value::Object(filter))) = (page_vals.as_object_mut(), serde_json::to_value(filter)) {
+        vals.extend(filter.into_iter().filter(|(_, value)| !value.is_null()));
+    }
     html! {
         div .paging role="group" {
             div .spacer {}
+            select name="sort" {
+                @for sort in Sort::ALL {
+                    @let query = list_query(Paging { sort, ..paging }.with_page(1), filter);
+                    option
+                        hx-get={(url) "?" (query)}
+                        hx-push-url={"?" (query)}
+                        hx-target="main"
+                        value=(sort.as_str())
+                        selected[sort == paging.sort]
+                        { (t(&format!("sort.{}", sort.as_str()))) }
+                }
+            }
             select name="per_page" {
                 @for per_page in per_page_options {
+                    @let query = list_query(Paging { per_page, ..paging }.with_page(1), filter);
                     option
-                        hx-get={(url) "?page=1&per_page=" (per_page)}
-                        hx-push-url={"?page=1&per_page=" (per_page)}
+                        hx-get={(url) "?" (query)}
+                        hx-push-url={"?" (query)}
                         hx-target="main"
                         value=(per_page)
                         selected[per_page == paging.per_page]
                         { (per_page) }
                 }
             }
+            @let mode_query = list_query(Paging { scroll: !paging.scroll, ..paging }.with_page(1), filter);
             button
-                .secondary
-                hx-get=(url)
-                hx-target="main"
-                hx-push-url={"?page=1&per_page=" (paging.per_page)}
-                name="page"
-                value="1"
-                disabled[paging.page == 1]
-                { "<<" }
-            button
-                .secondary
-                hx-get=(url)
-                hx-target="main"
-                hx-push-url={"?page=" (prev_page) "&per_page=" (paging.per_page)}
-                name="page"
-                value=(prev_page)
-                disabled[paging.page == 1]
-                { "<" }
-            input
-                .num-select
-                hx-get=(url)
-                hx-trigger="changed click"
+                .secondary.outline
+                hx-get={(url) "?" (mode_query)}
+                hx-push-url={"?" (mode_query)}
                 hx-target="main"
-                type="number"
-                name="page"
-                value=(paging.page)
-                min="1"
-                max=(last_page);
-            button
-                type="submit"
-                .secondary
-                hx-get=(url)
-                hx-target="main"
-                hx-push-url={"?page=" (next_page) "&per_page=" (paging.per_page)}
-                name="page"
-                value=(next_page)
-                disabled[paging.page == last_page]
-                { ">" }
-            button
-                type="submit"
-                .secondary
-                hx-get=(url)
+                { @if paging.scroll { (t("paging.pages")) } @else { (t("paging.infinite_scroll")) } }
+            @if !paging.scroll {
+                button
+                    .secondary
+                    hx-get={(url) "?" (page_query(1))}
+                    hx-target="main"
+                    hx-push-url={"?" (page_query(1))}
+                    disabled[paging.page == 1]
+                    { "<<" }
+                button
+                    .secondary
+                    hx-get={(url) "?" (page_query(prev_page))}
+                    hx-target="main"
+                    hx-push-url={"?" (page_query(prev_page))}
+                    disabled[paging.page == 1]
+                    { "<" }
+                input
+                    .num-select
+                    hx-get=(url)
+                    hx-trigger="changed click"
+                    hx-target="main"
+                    hx-vals=(page_vals.to_string())
+                    type="number"
+                    name="page"
+                    value=(paging.page)
+                    min="1"
+                    max=(last_page);
+                button
+                    type="submit"
+                    .secondary
+                    hx-get={(url) "?" (page_query(next_page))}
+                    hx-target="main"
+                    hx-push-url={"?" (page_query(next_page))}
+                    disabled[paging.page == last_page]
+                    { ">" }
+                button
+                    type="submit"
+                    .secondary
+                    hx-get={(url) "?" (page_query(last_page))}
+                    hx-target="main"
+                    hx-push-url={"?" (page_query(last_page))}
+                    disabled[paging.page == last_page]
+                    { ">>" }
+            }
+        }
+    }
+}
+
+/// Drop-downs limiting the movie list to a subset of movies and tag buttons to a tag group.
+fn filter_controls(collection: &Collection, sources: &MovieSources, paging: Paging, filter: &MovieFilter) -> Markup {
+    let option = |updated: MovieFilter, label: &str| {
+        let query = list_query(paging.with_page(1), &updated);
+        html! {
+            option
+                hx-get={(url(&updated.list_url())) "?" (query)}
+                hx-push-url={(url(&updated.page_url())) "?" (query)}
                 hx-target="main"
-                hx-push-url={"?page=" (last_page) "&per_page=" (paging.per_page)}
-                name="page"
-                value=(last_page)
-                disabled[paging.page == last_page]
-                { ">>" }
+                selected[&updated == filter]
+                { (label) }
+        }
+    };
+    let with_subset = |subset| MovieFilter { tag: None, view: None, filter: subset, ..filter.clone() };
+    let with_tag = |tag: &str| MovieFilter { tag: Some(tag.to_string()), view: None, filter: None, ..filter.clone() };
+    let with_group = |group: Option<&str>| MovieFilter { group: group.map(str::to_string), ..filter.clone() };
+    let with_resolution = |resolution| MovieFilter { resolution, ..filter.clone() };
+    let mut tags: Vec<_> = collection.tags.iter().filter(|(_, tag)| listed(tag)).collect();
+    tags.sort_by_key(|(key, _)| *key);
+    let groups = collection.groups();
+    html! {
+        div .filters role="group" {
+            select name="filter" aria-label=(t("filter.movies")) {
+                (option(with_subset(None), &t("filter.all_movies")))
+                (option(with_subset(Some(Subset::Untagged)), &t("filter.untagged_movies")))
+                @if sources.watched.is_some() {
+                    (option(with_subset(Some(Subset::Unwatched)), &t("filter.unwatched_movies")))
+                }
+                optgroup label=(t("filter.tagged")) {
+                    @for (key, _) in tags {
+                        (option(with_tag(key), key))
+                    }
+                }
+            }
+            @if !groups.is_empty() {
+                select name="group" aria-label=(t("filter.tag_group")) {
+                    (option(with_group(None), &t("filter.all_tags")))
+                    @for group in groups {
+                        (option(with_group(Some(group)), group))
+                    }
+                }
+            }
+            @if !sources.media.is_empty() {
+                select name="resolution" aria-label=(t("filter.resolution")) {
+                    (option(with_resolution(None), &t("filter.any_resolution")))
+                    @for resolution in Resolution::ALL {
+                        (option(with_resolution(Some(resolution)), resolution.as_str()))
+                    }
+                }
+            }
         }
     }
 }
 
-pub fn movie_list(collection: &Collection, paging: Paging) -> Markup {
-    let mut sorted_movies: Vec<_> = collection
-        .movies
-        .values()
-        .collect();
-    sorted_movies.sort_by_key(|m| &m.name);
+/// Movies matching the filter, in display order.
+fn list_movies<'a>(collection: &'a Collection, sources: &MovieSources, paging: Paging, filter: &MovieFilter) -> Vec<&'a Movie> {
+    let resolution = |movie: &Movie| sources.media_info(movie).and_then(MediaInfo::resolution);
+    let mut sorted_movies: Vec<_> = match filter.filter {
+        Some(Subset::Untagged) => collection.untagged(),
+        // without Jellyfin nothing is known to be watched
+        Some(Subset::Unwatched) => collection
+            .movies
+            .values()
+            .filter(|movie| sources.watched(collection, movie) != Some(true))
+            .collect(),
+        None => collection.movies.values().collect(),
+    };
+    if let Some(tag) = filter.tag.as_ref().and_then(|tag| collection.tags.get(tag)) {
+        sorted_movies.retain(|movie| tag.movies.contains(&movie.hash));
+    }
+    if let Some(view) = &filter.view {
+        sorted_movies.retain(|movie| view.filter.matches(collection, movie, resolution(movie)));
+    }
+    if let Some(wanted) = filter.resolution {
+        sorted_movies.retain(|movie| resolution(movie) == Some(wanted));
+    }
+    match paging.sort {
+        Sort::Name => sorted_movies.sort_by_key(|m| &m.name),
+        // movies without a known year go last
+        Sort::Year => sorted_movies.sort_by_key(|m| (m.year().is_none(), m.year(), &m.name)),
+        // unprobed movies go last
+        Sort::Resolution => sorted_movies.sort_by_key(|m| (std::cmp::Reverse(resolution(m)), &m.name)),
+        Sort::Added => sorted_movies.sort_by_key(|m| (std::cmp::Reverse(m.added()), &m.name)),
+        // unrated movies go last
+        Sort::Rating => sorted_movies.sort_by_key(|m| (std::cmp::Reverse(m.rating), &m.name)),
+    }
+    sorted_movies
+}
+
+/// The next `per_page` of `movies`, followed by an element loading the chunk after it once it
+/// is scrolled into view.
+fn scroll_chunk(collection: &Collection, sources: &MovieSources, paging: Paging, filter: &MovieFilter, movies: &[&Movie]) -> Markup {
+    let chunk = &movies[..movies.len().min(paging.per_page)];
+    let next_url = match chunk.last() {
+        Some(last) if movies.len() > chunk.len() => {
+            Some(format!("{}?after={}&{}", url(&filter.list_url()), last.id(), list_query(paging.with_page(1), filter)))
+        }
+        _ => None,
+    };
+    html! {
+        @for m in chunk {
+            (movie(collection, m, sources, filter))
+        }
+        @if let Some(next_url) = next_url {
+            div .scroll-sentinel hx-get=(next_url) hx-trigger="revealed" hx-swap="outerHTML" {
+                p aria-busy="true" { (t("paging.loading")) }
+            }
+        }
+    }
+}
+
+pub fn movie_list(collection: &Collection, sources: &MovieSources, paging: Paging, filter: &MovieFilter) -> Markup {
+    let sorted_movies = list_movies(collection, sources, paging, filter);
+    let total = sorted_movies.len();
+    // swaps in the list again when the page is told the collection changed
+    let refresh = html! {
+        div hx-get={(url(&filter.list_url())) "?" (list_query(paging, filter))} hx-trigger="collection-changed from:body" hx-target="main" {}
+    };
+    if paging.scroll {
+        return html! {
+            (refresh)
+            (filter_controls(collection, sources, paging, filter))
+            (paging_controls(&url(&filter.list_url()), paging, filter, total))
+            div #movie-list {
+                (scroll_chunk(collection, sources, paging, filter, &sorted_movies))
+            }
+        };
+    }
     let sorted_movies = sorted_movies.into_iter()
         .skip(paging.offset())
         .take(paging.per_page);
     html! {
-        (paging_controls("/movies", paging, collection.movies.len()))
+        (refresh)
+        (filter_controls(collection, sources, paging, filter))
+        (paging_controls(&url(&filter.list_url()), paging, filter, total))
         div #movie-list {
             @for m in sorted_movies {
-                (movie(collection, m))
+                (movie(collection, m, sources, filter))
             }
         }
-        (paging_controls("/movies", paging, collection.movies.len()))
+        (paging_controls(&url(&filter.list_url()), paging, filter, total))
     }
 }
 
-pub fn index(collection: &Collection, paging: Paging) -> Markup {
+/// The chunk of movies following the movie `after` in infinite scroll mode. Empty if that movie
+/// is gone, e.g. after a reload.
+pub fn movies_after(collection: &Collection, sources: &MovieSources, paging: Paging, filter: &MovieFilter, after: &str) -> Markup {
+    let sorted_movies = list_movies(collection, sources, paging, filter);
+    match sorted_movies.iter().position(|m| m.id() == after) {
+        Some(position) => scroll_chunk(collection, sources, paging, filter, &sorted_movies[position + 1..]),
+        None => html! {},
+    }
+}
+
+pub fn index(collection: &Collection, sources: &MovieSources, paging: Paging, filter: &MovieFilter) -> Markup {
+    let controls = html! {
+        form .new-tag hx-post=(url("/tags")) {
+            fieldset role="group" {
+                input type="text" name="name" placeholder=(t("movies.new_tag")) required;
+                button type="submit" { (t("movies.create_tag")) }
+            }
+        }
+        form method="post" action=(url("/reload")) {
+            button type="submit" { (t("movies.reload")) }
+        }
+    };
+    page(
+        &t("nav.movies"),
+        movie_list(collection, sources, paging, filter),
+        PageOptions {
+            controls: Some(controls),
+            footer: None,
+        },
+    )
+}
+
+/// Movies carrying a single tag, with the same controls as the index.
+pub fn tag_page(
+    collection: &Collection,
+    sources: &MovieSources,
+    tag_name: &str,
+    paging: Paging,
+    filter: &MovieFilter,
+    tag_libraries: bool,
+) -> Markup {
+    let tag = filter.tag.as_deref().unwrap_or_default();
+    let mut confirm = tf("tag.confirm_delete", &[("tag", &tag)]);
+    if tag_libraries {
+        confirm.push_str(&tf("tag.confirm_delete_library", &[("library", &tag_library_name(tag))]));
+    }
     let controls = html! {
-        form method="post" action="/reload" {
-            button type="submit" { "Reload" }
+        a href={(url("/random?tag=")) (utf8_percent_encode(tag, NON_ALPHANUMERIC))} role="button" .secondary.outline { (t("tag.random")) }
+        @if theme::current().jellyfin {
+            button .secondary.outline hx-post={(url(&filter.page_url())) "/collection"} hx-swap="outerHTML" {
+                (t("tag.export_collection"))
+            }
+        }
+        button .secondary hx-delete=(url(&filter.page_url())) hx-confirm=(confirm) { (t("tag.delete")) }
+    };
+    page(
+        &tf("tag.title", &[("tag", &tag_name)]),
+        movie_list(collection, sources, paging, filter),
+        PageOptions {
+            controls: Some(controls),
+            footer: None,
+        },
+    )
+}
+
+/// Page shown when navigating to a URL fails.
+pub fn error_page(status: u16, reason: Option<&str>, message: &str) -> Markup {
+    let title = match reason {
+        Some(reason) => format!("{} {}", status, reason),
+        None => status.to_string(),
+    };
+    let content = html! {
+        article .error-page {
+            h2 { (title) }
+            p { (message) }
+            a href=(url("/")) role="button" { (t("errors.back")) }
         }
     };
+    page(&t("errors.title"), content, PageOptions::default())
+}
+
+/// Message added to `#errors` when an htmx request fails.
+pub fn error_message(message: &str) -> Markup {
+    html! {
+        div .error-message role="alert" {
+            span { (message) }
+            button .secondary.outline aria-label=(t("errors.dismiss")) onclick="this.parentElement.remove()" { "×" }
+        }
+    }
+}
+
+/// Replaces the export button once a tag was exported as a Jellyfin collection.
+pub fn collection_exported(collection: &str, added: usize, missing: usize) -> Markup {
+    html! {
+        small .exported {
+            (tf("tag.exported", &[("count", &added), ("collection", &collection)]))
+            @if missing > 0 {
+                " " (tf("tag.not_in_jellyfin", &[("count", &missing)]))
+            }
+        }
+    }
+}
+
+/// Movies in a saved view, with the same controls as the index.
+pub fn view_page(collection: &Collection, sources: &MovieSources, paging: Paging, filter: &MovieFilter) -> Markup {
+    let (name, expression) = filter.view.as_ref().map(|v| (v.name.as_str(), v.filter.as_str())).unwrap_or_default();
+    let controls = html! {
+        code .view-filter { (expression) }
+    };
     page(
-        "Movie Tagger",
-        movie_list(collection, paging),
+        &tf("view.title", &[("view", &name)]),
+        movie_list(collection, sources, paging, filter),
         PageOptions {
             controls: Some(controls),
             footer: None,
@@ -144,42 +525,495 @@ pub fn index(collection: &Collection, paging: Paging) -> Markup {
     )
 }
 
-pub fn movie(collection: &Collection, movie: &Movie) -> Markup {
-    let tags = collection.tags.iter().map(|(name, tag_movies)| {
+/// Every tag sized by how many movies carry it, in the same order as on movie cards.
+pub fn tag_cloud(collection: &Collection) -> Markup {
+    let mut tag_counts = collection.tag_counts();
+    let any_hidden = tag_counts.iter().any(|(_, tag, _)| tag.meta.hidden);
+    tag_counts.retain(|(_, tag, _)| listed(tag));
+    tag_counts.sort_by_cached_key(|(key, tag, _)| tag.sort_key(key));
+    let max = tag_counts.iter().map(|(_, _, count)| *count).max().unwrap_or(0).max(1);
+    let content = html! {
+        @if tag_counts.is_empty() {
+            p { (t("tags.empty")) }
+        }
+        ul .tag-cloud {
+            @for (key, tag, count) in &tag_counts {
+                @let size = 0.9 + 1.3 * *count as f64 / max as f64;
+                li {
+                    a href={(url("/tag/")) (utf8_percent_encode(key, NON_ALPHANUMERIC))}
+                        style={"font-size: " (format!("{:.2}", size)) "rem"}
+                        title=[&tag.meta.description]
+                    {
+                        (tag.display_name()) " " small .count { (count) }
+                    }
+                }
+            }
+        }
+        @if any_hidden {
+            @let show = !theme::show_hidden_tags();
+            form .hidden-tags hx-post=(url("/tags/hidden")) hx-vals=(serde_json::json!({ "show": show })) {
+                button .secondary.outline type="submit" {
+                    @if show { (t("tags.show_hidden")) } @else { (t("tags.hide_hidden")) }
+                }
+            }
+        }
+        @if theme::is_admin() && tag_counts.len() > 1 {
+            (merge_tags_form(&tag_counts))
+        }
+    };
+    page(&t("nav.tags"), content, PageOptions::default())
+}
+
+/// Whether `tag` is listed on pages: hidden tags only for admins who asked to see them.
+fn listed(tag: &Tag) -> bool {
+    !tag.meta.hidden || theme::show_hidden_tags()
+}
+
+/// Picks two of the listed `tags` to merge, posting to `/tag/<from>/merge/<into>`.
+fn merge_tags_form(tags: &[(&str, &Tag, usize)]) -> Markup {
+    let options = html! {
+        @for (key, tag, _) in tags {
+            option value=(key) { (tag.display_name()) }
+        }
+    };
+    html! {
+        form .merge-tags
+            hx-post=(url("/tag/"))
+            hx-confirm=(t("tags.confirm_merge"))
+            hx-on--config-request="event.detail.path += encodeURIComponent(this.elements.from.value) + '/merge/' + encodeURIComponent(this.elements.into.value)"
+        {
+            h3 { (t("tags.merge")) }
+            p { small { (t("tags.merge_help")) } }
+            fieldset role="group" {
+                select name="from" aria-label=(t("tags.merge_from")) required { (options) }
+                select name="into" aria-label=(t("tags.merge_into")) required { (options) }
+                button type="submit" { (t("tags.merge_button")) }
+            }
+        }
+    }
+}
+
+pub fn movie(collection: &Collection, movie: &Movie, sources: &MovieSources, filter: &MovieFilter) -> Markup {
+    let media_info = sources.media_info(movie);
+    let radarr = sources.radarr(movie);
+    let watched = sources.watched(collection, movie);
+    // ungrouped tags first, then each group in name order
+    let mut groups: BTreeMap<Option<&str>, Vec<(&String, &Tag)>> = BTreeMap::new();
+    for (key, tag) in &collection.tags {
+        if (filter.group.is_some() && tag.group != filter.group) || !listed(tag) {
+            continue;
+        }
+        groups.entry(tag.group.as_deref()).or_default().push((key, tag));
+    }
+    for tags in groups.values_mut() {
+        tags.sort_by_cached_key(|(key, tag)| tag.sort_key(key));
+    }
+    // keep the group filter when the card is swapped after toggling a tag
+    let toggle_query = match filter.query() {
+        query if query.is_empty() => query,
+        query => format!("?{}", query),
+    };
+    let tag_button = |key: &str, tag: &Tag| {
+        let tagged = tag.movies.contains(&movie.hash);
         let mut tag_classes = vec!["tag"];
-        if !tag_movies.contains(&movie.hash) {
+        if !tagged {
             tag_classes.push("secondary")
         };
+        let tag_path = utf8_percent_encode(key, NON_ALPHANUMERIC);
+        let tag_url = url(&format!("/movie/{}/tag/{}{}", movie.id(), tag_path, toggle_query));
+        // PUT/DELETE rather than a toggle, so clicking a stale card can't undo someone else's change
         html! {
             button
-                hx-post=(format!("/movie/{}/tag/{}", movie.id(), name))
+                hx-put=[(!tagged).then_some(&tag_url)]
+                hx-delete=[tagged.then_some(&tag_url)]
                 hx-target={"#movie-" (movie.id())}
                 hx-swap="outerHTML"
                 class=(tag_classes.join(" "))
-                { (name) }
+                style=[tag.meta.color.as_ref().map(|color| format!("--tag-color: {}", color))]
+                title=[&tag.meta.description]
+                { (tag.display_name()) " " small .count { (tag.movies.len()) } }
         }
-    });
-    let poster_url = format!("/movie/{}/poster.jpg", movie.id());
+    };
+    let poster_url = url(&format!("/movie/{}/poster.jpg?w=300", movie.id()));
+    let jellyfin_url = theme::current().jellyfin.then(|| url(&format!("/movie/{}/jellyfin", movie.id())));
+    let tag_groups = html! {
+        @for (group, tags) in &groups {
+            div .tag-group data-group=[group] {
+                @if let Some(group) = group { small .tag-group-name { (group) } }
+                @for (key, tag) in tags { (tag_button(key, tag)) }
+            }
+        }
+    };
+    if let Some(overrides) = &theme::current().overrides {
+        let tags: Vec<_> = groups
+            .values()
+            .flatten()
+            .map(|(key, tag)| {
+                serde_json::json!({
+                    "key": key,
+                    "name": tag.display_name(),
+                    "group": tag.group,
+                    "color": tag.meta.color,
+                    "description": tag.meta.description,
+                    "tagged": tag.movies.contains(&movie.hash),
+                    "count": tag.movies.len(),
+                    "toggle_url": url(&format!("/movie/{}/tag/{}{}", movie.id(), utf8_percent_encode(key, NON_ALPHANUMERIC), toggle_query)),
+                })
+            })
+            .collect();
+        let metadata = movie.metadata.as_ref();
+        let context = minijinja::context! {
+            id => movie.id(),
+            name => &movie.name,
+            title => movie.title(),
+            year => movie.year(),
+            runtime => metadata.and_then(|m| m.runtime),
+            genres => metadata.map(|m| m.genres.clone()).unwrap_or_default(),
+            plot => metadata.and_then(|m| m.plot.clone()),
+            resolution => media_info.and_then(MediaInfo::resolution).map(|r| r.as_str()),
+            radarr_profile => radarr.map(|entry| entry.profile.clone()),
+            radarr_monitored => radarr.map(|entry| entry.monitored),
+            watched => watched,
+            poster_url => &poster_url,
+            details_url => url(&format!("/movie/{}/details", movie.id())),
+            jellyfin_url => &jellyfin_url,
+            tags => Value::from(minijinja::value::Serde(tags)),
+            tag_buttons => Value::from_safe_string(tag_groups.0.clone()),
+            rating => movie.rating,
+            rating_control => Value::from_safe_string(rating(movie).0),
+        };
+        if let Some(card) = overrides.render("movie.html", context) {
+            return card;
+        }
+    }
     html! {
         article .movie id={"movie-" (movie.id())} {
-            header { h2 { (movie.name) } }
-            img src=(poster_url) alt=(format!("{} poster", movie.name)) {}
-            footer .tags { @for tag in tags { (tag) } }
+            header {
+                h2 { a href={(url("/movie/")) (movie.id()) "/details"} { (movie.name) } }
+                @if movie.metadata.is_some() || media_info.is_some() || radarr.is_some() || watched == Some(true) {
+                    p .metadata title=[movie.metadata.as_ref().and_then(|m| m.plot.as_ref())] {
+                        @if let Some(metadata) = &movie.metadata {
+                            @if let Some(year) = metadata.year { span .year { (year) } }
+                            @if let Some(runtime) = metadata.runtime { span .runtime { (tf("movies.runtime", &[("minutes", &runtime)])) } }
+                            @if !metadata.genres.is_empty() { span .genres { (metadata.genres.join(", ")) } }
+                        }
+                        @if let Some(resolution) = media_info.and_then(MediaInfo::resolution) {
+                            span .resolution { (resolution.as_str()) }
+                        }
+                        @if let Some(entry) = radarr {
+                            span .radarr.monitored[entry.monitored] title=(t("movies.radarr_profile")) {
+                                (entry.profile) @if !entry.monitored { (t("movies.unmonitored")) }
+                            }
+                        }
+                        @if watched == Some(true) {
+                            span .watched { (t("movies.watched")) }
+                        }
+                    }
+                }
+            }
+            img src=(poster_url) alt=(tf("movies.poster", &[("title", &movie.title())])) {}
+            (rating(movie))
+            @if let Some(jellyfin_url) = &jellyfin_url {
+                a .open-in-jellyfin href=(jellyfin_url) target="_blank" rel="noopener" { (t("movies.open_in_jellyfin")) }
+            }
+            footer .tags { (tag_groups) }
         }
     }
 }
 
-pub fn user_libraries_page(users: &[User], folders: &[MediaFolders]) -> Result<Markup, Error> {
+pub fn movie_details(
+    collection: &Collection,
+    movie: &Movie,
+    files: &[MovieFile],
+    media_info: Option<&MediaInfo>,
+    radarr: Option<&RadarrEntry>,
+) -> Markup {
+    let added = movie.added().map(chrono::DateTime::<chrono::Utc>::from);
+    let tags = movie.tags(collection);
     let content = html! {
-        @for user in users {
-            (user_libraries_entry(user, folders)?)
+        article .movie-details {
+            div {
+                (details_poster(movie, None))
+                @if theme::current().jellyfin {
+                    button .secondary.outline hx-get={(url("/movie/")) (movie.id()) "/posters"} hx-target="#poster-candidates" {
+                        (t("details.find_poster"))
+                    }
+                    a role="button" .secondary.outline href={(url("/movie/")) (movie.id()) "/jellyfin"} target="_blank" rel="noopener" {
+                        (t("movies.open_in_jellyfin"))
+                    }
+                }
+            }
+            div {
+                @if let Some(plot) = movie.metadata.as_ref().and_then(|m| m.plot.as_ref()) {
+                    p { (plot) }
+                }
+                table {
+                    tbody {
+                        tr { th { (t("details.folder")) } td { code { (movie.path.display()) } } }
+                        tr { th { (t("details.added")) } td { (format_date(added)) } }
+                        @if let Some(info) = media_info {
+                            tr {
+                                th { (t("details.video")) }
+                                td {
+                                    @if let (Some(width), Some(height)) = (info.width, info.height) {
+                                        (width) "×" (height) " "
+                                    }
+                                    @if let Some(resolution) = info.resolution() { "(" (resolution.as_str()) ") " }
+                                    (info.video_codec.as_deref().unwrap_or("-"))
+                                }
+                            }
+                            tr { th { (t("details.audio")) } td { (info.audio_codecs.join(", ")) } }
+                            @if let Some(duration) = info.duration {
+                                tr { th { (t("details.duration")) } td { (format_ticks(duration.as_secs() * TICKS_PER_SECOND)) } }
+                            }
+                            tr { th { (t("details.probed_file")) } td { (info.file) } }
+                        }
+                        @if let Some(entry) = radarr {
+                            tr {
+                                th { (t("details.radarr")) }
+                                td {
+                                    (entry.profile) ", "
+                                    @if entry.monitored { (t("details.monitored")) } @else { (t("details.unmonitored")) }
+                                    @if let Some(quality) = &entry.quality { (tf("details.current_file", &[("quality", quality)])) }
+                                    @else if !entry.has_file { (t("details.no_file")) }
+                                    " "
+                                    button .secondary.outline hx-post={(url("/movie/")) (movie.id()) "/radarr/search"} hx-swap="outerHTML" {
+                                        (t("details.search_upgrade"))
+                                    }
+                                }
+                            }
+                        }
+                        tr { th { (t("details.rating")) } td { (rating(movie)) } }
+                        tr {
+                            th { (t("details.tags")) }
+                            td {
+                                @if tags.is_empty() { "-" }
+                                @for (key, tag) in &tags {
+                                    a .tag href={(url("/tag/")) (utf8_percent_encode(key, NON_ALPHANUMERIC))} { (tag.display_name()) } " "
+                                }
+                            }
+                        }
+                    }
+                }
+                (movie_note(movie, false))
+                div #poster-candidates {}
+                h3 { (t("details.video_files")) }
+                @if files.is_empty() {
+                    p { (t("details.no_video_files")) }
+                } @else {
+                    table {
+                        thead { tr { th { (t("details.file")) } th { (t("details.size")) } } }
+                        tbody {
+                            @for file in files {
+                                tr { td { (file.name) } td { (format_size(file.size)) } }
+                            }
+                        }
+                    }
+                }
+            }
         }
     };
-    Ok(page("User Libraries", content, Default::default()))
+    page(movie.title(), content, PageOptions::default())
+}
+
+fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} {}", bytes, UNITS[0])
+    } else {
+        format!("{:.1} {}", size, UNITS[unit])
+    }
 }
 
-pub fn user_libraries_entry(user: &User, folders: &[MediaFolders]) -> Result<Markup, Error> {
-    let user_folders: Vec<String> = user.enabled_folders()?;
+/// Poster on the details page. `version` busts the browser cache after a new one was picked.
+pub fn details_poster(movie: &Movie, version: Option<&str>) -> Markup {
+    let mut src = url(&format!("/movie/{}/poster.jpg", movie.id()));
+    if let Some(version) = version {
+        src.push_str(&format!("?v={}", version));
+    }
+    html! {
+        img #poster src=(src) alt=(tf("movies.poster", &[("title", &movie.title())])) {}
+    }
+}
+
+/// Stars rating a movie when clicked. Clicking the current rating clears it.
+pub fn rating(movie: &Movie) -> Markup {
+    let rating = movie.rating.unwrap_or(0);
+    html! {
+        div .rating role="group" aria-label=(t("movies.rating")) {
+            @for stars in 1..=MAX_RATING {
+                button .star.active[stars <= rating]
+                    hx-put={(url("/movie/")) (movie.id()) "/rating"}
+                    hx-vals=(serde_json::json!({ "rating": if stars == rating { 0 } else { stars } }))
+                    hx-target="closest .rating"
+                    hx-swap="outerHTML"
+                    title=(tf("movies.rate", &[("stars", &stars)]))
+                    aria-pressed=(stars == rating)
+                    { "★" }
+            }
+        }
+    }
+}
+
+/// Note of a movie, saved from the details page. `saved` confirms a change was stored.
+pub fn movie_note(movie: &Movie, saved: bool) -> Markup {
+    html! {
+        form #movie-note hx-put={(url("/movie/")) (movie.id()) "/note"} hx-swap="outerHTML" {
+            label {
+                (t("details.note"))
+                textarea name="note" rows="3" placeholder=(t("details.note_placeholder")) {
+                    (movie.note.as_deref().unwrap_or_default())
+                }
+            }
+            button .secondary.outline type="submit" { (t("details.save_note")) }
+            @if saved {
+                " " small { (t("details.note_saved")) }
+            }
+        }
+    }
+}
+
+/// Posters from Jellyfin's metadata providers, each saved as the movie's poster when clicked.
+pub fn poster_candidates(movie: &Movie, images: &[RemoteImage]) -> Markup {
+    html! {
+        h3 { (t("details.poster_candidates")) }
+        @if images.is_empty() {
+            p { (t("details.no_poster_candidates")) }
+        }
+        div .poster-candidates {
+            @for image in images {
+                figure {
+                    button .outline
+                        hx-post={(url("/movie/")) (movie.id()) "/poster"}
+                        hx-vals=(serde_json::json!({ "url": image.url }).to_string())
+                        hx-target="#poster"
+                        hx-swap="outerHTML"
+                        title=(t("details.use_poster"))
+                    {
+                        img src=(image.thumbnail_url.as_deref().unwrap_or(&image.url)) alt="" loading="lazy";
+                    }
+                    figcaption {
+                        small {
+                            (image.provider_name.as_deref().unwrap_or("-"))
+                            @if let (Some(width), Some(height)) = (image.width, image.height) { ", " (width) "×" (height) }
+                            @if let Some(language) = &image.language { ", " (language) }
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+pub fn radarr_search_started() -> Markup {
+    html! {
+        button .secondary.outline disabled { (t("details.search_started")) }
+    }
+}
+
+pub fn scan_started() -> Markup {
+    html! {
+        button .secondary disabled { (t("nav.scan_started")) }
+    }
+}
+
+/// Drop-down for switching between Jellyfin servers, if more than one is configured.
+pub fn server_picker(servers: &JellyfinServers, selected: &str) -> Option<Markup> {
+    if servers.len() < 2 {
+        return None;
+    }
+    Some(html! {
+        form .server-picker hx-post=(url("/server")) hx-trigger="change" {
+            select name="server" aria-label=(t("users.server")) {
+                @for (name, _) in servers.iter() {
+                    option value=(name) selected[name == selected] { (name) }
+                }
+            }
+        }
+    })
+}
+
+/// `users` are every user, for picking one to copy libraries from, and `shown` the ones
+/// matching `filter`.
+pub fn user_libraries_page(
+    users: &[User],
+    shown: &[&User],
+    filter: &UserFilter,
+    folders: &[MediaFolders],
+    presets: &[Preset],
+    server_picker: Option<Markup>,
+    stale: bool,
+) -> Result<Markup, Error> {
+    let content = html! {
+        @if stale {
+            p .stale role="alert" { (t("users.stale")) }
+        }
+        form .new-user hx-post=(url("/users")) hx-target="#user-list" hx-swap="beforeend" hx-on--after-request="this.reset()" {
+            fieldset role="group" {
+                input type="text" name="name" placeholder=(t("users.new_user")) required;
+                input type="password" name="password" placeholder=(t("users.password"));
+                button type="submit" { (t("users.create_user")) }
+            }
+        }
+        form #batch-form hx-post=(url("/users/libraries")) hx-target="#batch-results" {
+            fieldset role="group" {
+                select name="action" aria-label=(t("users.batch_action")) {
+                    option value="grant" { (t("users.grant")) }
+                    option value="revoke" { (t("users.revoke")) }
+                }
+                select name="folder" aria-label=(t("users.library")) {
+                    @for folder in folders { option value=(folder.id) { (folder.name) } }
+                }
+                button type="submit" { (t("users.apply_to_selected")) }
+            }
+        }
+        div #batch-results {}
+        form .user-filter
+            hx-get=(url("/user-libraries"))
+            hx-trigger="input delay:300ms, search"
+            hx-target="#user-list"
+            hx-select="#user-list"
+            hx-swap="outerHTML"
+            hx-push-url="true"
+        {
+            fieldset role="group" {
+                input type="search" name="q" value=(filter.q) placeholder=(t("users.search")) aria-label=(t("users.search"));
+            }
+            label { input type="checkbox" name="hide_disabled" value="true" checked[filter.hide_disabled]; (t("users.hide_disabled")) }
+            label { input type="checkbox" name="hide_admins" value="true" checked[filter.hide_admins]; (t("users.hide_admins")) }
+        }
+        div #user-list
+            hx-get={(url("/user-libraries")) @if !filter.query().is_empty() { "?" (filter.query()) }}
+            hx-select="#user-list"
+            hx-swap="outerHTML"
+            hx-trigger="users-changed from:body"
+        {
+            datalist #user-names {
+                @for user in users { option value=(user.name) {} }
+            }
+            @if shown.is_empty() {
+                p { (t("users.no_matches")) }
+            }
+            @for user in shown {
+                (user_libraries_entry(user, folders, presets)?)
+            }
+        }
+    };
+    let options = PageOptions {
+        controls: server_picker,
+        footer: None,
+    };
+    Ok(page(&t("nav.user_libraries"), content, options))
+}
+
+pub fn user_libraries_entry(user: &User, folders: &[MediaFolders], presets: &[Preset]) -> Result<Markup, Error> {
+    let user_folders = user.accessible_folders(folders);
     let folder_buttons = folders.iter().map(|folder| {
         let mut classes = vec![];
         if !user_folders.contains(&folder.id) {
@@ -190,16 +1024,877 @@ pub fn user_libraries_entry(user: &User, folders: &[MediaFolders]) -> Result<Mar
             button
                 id=(input_id)
                 class=(classes.join(" "))
-                hx-post=(format!("/user/{}/library/{}", user.id, folder.id))
+                hx-post=(url(&format!("/user/{}/library/{}", user.id, folder.id)))
                 hx-target=(format!("#user-{}", user.id))
                 hx-swap="outerHTML"
                 { (folder.name) }
         }
     });
     Ok(html! {
-        div .user-library.grid id=(format!("user-{}", user.id)) {
-            h2 { (user.name) }
+        div .user-library.grid.disabled[user.is_disabled()] id=(format!("user-{}", user.id)) {
+            h2 {
+                input type="checkbox" name="user_ids" value=(user.id) form="batch-form" aria-label=(t("users.select"));
+                (user.name)
+            }
+            button
+                class=[(!user.has_all_folders()).then_some("secondary")]
+                title=(t("users.all_libraries_hint"))
+                hx-post=(url(&format!("/user/{}/all-libraries", user.id)))
+                hx-target=(format!("#user-{}", user.id))
+                hx-swap="outerHTML"
+                { (t("users.all_libraries")) }
             @for folder in folder_buttons { (folder) }
+            @if !presets.is_empty() {
+                form .apply-preset
+                    hx-post=(url(&format!("/user/{}/preset", user.id)))
+                    hx-trigger="change"
+                    hx-target=(format!("#user-{}", user.id))
+                    hx-swap="outerHTML"
+                {
+                    select name="preset" aria-label=(t("users.apply_preset")) {
+                        option value="" selected disabled { (t("users.apply_preset_placeholder")) }
+                        @for preset in presets { option value=(preset.name) { (preset.name) } }
+                    }
+                }
+            }
+            form .copy-libraries
+                hx-post=(url(&format!("/user/{}/copy", user.id)))
+                hx-target=(format!("#user-{}", user.id))
+                hx-swap="outerHTML"
+            {
+                fieldset role="group" {
+                    input type="text" name="source" list="user-names" placeholder=(t("users.copy_from")) aria-label=(t("users.copy_from_label")) required;
+                    button .outline type="submit" { (t("users.copy")) }
+                }
+            }
+            button .outline.toggle-disabled
+                hx-post=(url(&format!("/user/{}/disabled", user.id)))
+                hx-target=(format!("#user-{}", user.id))
+                hx-swap="outerHTML"
+                { @if user.is_disabled() { (t("users.enable")) } @else { (t("users.disable")) } }
+            button .outline.contrast.delete-user
+                hx-delete=(url(&format!("/user/{}", user.id)))
+                hx-confirm=(tf("users.confirm_delete", &[("user", &user.name)]))
+                hx-target=(format!("#user-{}", user.id))
+                hx-swap="outerHTML"
+                { (t("users.delete")) }
+            a .preview-user.outline role="button" href=(url(&format!("/user/{}/preview", user.id))) { (t("users.preview")) }
+            details .parental {
+                summary { (t("users.parental_controls")) }
+                div hx-get=(url(&format!("/user/{}/parental", user.id))) hx-trigger="toggle from:closest details once" {
+                    (t("users.loading"))
+                }
+            }
         }
     })
 }
+
+pub fn tasks_page(tasks: &[ScheduledTask], server_picker: Option<Markup>) -> Markup {
+    page(&t("nav.tasks"), task_list(tasks), PageOptions { controls: server_picker, footer: None })
+}
+
+/// Visible tasks by category, refreshing itself quickly while one is running so its progress
+/// can be followed.
+pub fn task_list(tasks: &[ScheduledTask]) -> Markup {
+    let mut categories: BTreeMap<&str, Vec<&ScheduledTask>> = BTreeMap::new();
+    for task in tasks.iter().filter(|task| !task.is_hidden) {
+        categories.entry(&task.category).or_default().push(task);
+    }
+    for tasks in categories.values_mut() {
+        tasks.sort_by_key(|task| &task.name);
+    }
+    let running = tasks.iter().any(ScheduledTask::is_running);
+    let poll = if running { "every 2s" } else { "every 30s" };
+    html! {
+        div #tasks hx-get=(url("/tasks/list")) hx-trigger=(poll) hx-swap="outerHTML" {
+            @for (category, tasks) in &categories {
+                h2 { (category) }
+                table .tasks {
+                    thead {
+                        tr {
+                            th { (t("tasks.task")) }
+                            th { (t("tasks.last_run")) }
+                            th { (t("tasks.state")) }
+                            th {}
+                        }
+                    }
+                    tbody {
+                        @for task in tasks {
+                            tr {
+                                td {
+                                    (task.name)
+                                    @if let Some(description) = &task.description { br; small { (description) } }
+                                }
+                                td {
+                                    @if let Some(result) = &task.last_execution_result {
+                                        (format_date(result.end_time_utc)) " "
+                                        small class=[(result.status != "Completed").then_some("failed")] { (result.status) }
+                                    } @else {
+                                        "-"
+                                    }
+                                }
+                                td {
+                                    @if task.is_running() {
+                                        @let progress = task.current_progress_percentage.unwrap_or(0.0);
+                                        progress value=(format!("{:.0}", progress)) max="100" {}
+                                        small { (format!("{:.0}", progress)) " %" }
+                                    } @else {
+                                        (t("tasks.idle"))
+                                    }
+                                }
+                                td {
+                                    button .outline
+                                        hx-post=(url(&format!("/tasks/{}/run", task.id)))
+                                        hx-target="#tasks"
+                                        hx-swap="outerHTML"
+                                        disabled[task.is_running()]
+                                        { (t("tasks.run")) }
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Version and state of a Jellyfin server in the page header, or a note that it can't be
+/// reached if `info` is missing.
+pub fn server_status(server: &str, info: Option<&SystemInfo>) -> Markup {
+    let Some(info) = info else {
+        return html! {
+            span #server-status .server-status.unreachable role="status" title=(server) { (t("server.unreachable")) }
+        };
+    };
+    let attention = info.has_pending_restart || info.is_shutting_down;
+    html! {
+        details #server-status .server-status.dropdown.attention[attention] {
+            summary {
+                (info.product_name.as_deref().unwrap_or("Jellyfin")) " " (info.version)
+                @if attention { " ⚠" }
+            }
+            ul {
+                li { (tf("server.name", &[("name", &info.server_name)])) }
+                @if let Some(os) = &info.operating_system_display_name {
+                    li { (tf("server.os", &[("os", os)])) }
+                }
+                @if info.is_shutting_down {
+                    li { (t("server.shutting_down")) }
+                } @else if info.has_pending_restart {
+                    li { (t("server.pending_restart")) }
+                }
+                @if info.has_update_available {
+                    li { (t("server.update_available")) }
+                }
+            }
+        }
+    }
+}
+
+/// Takes the place of the stop button once the client was told to stop.
+pub fn playback_stopped() -> Markup {
+    html! { small { (t("sessions.stopped")) } }
+}
+
+/// The movies `user` can see, with the libraries showing each.
+pub fn user_preview(collection: &Collection, user: &User, preview: &LibraryPreview) -> Markup {
+    let policy = &user.policy;
+    let content = html! {
+        section .user-preview {
+            @if user.is_disabled() {
+                p role="alert" { (t("preview.disabled")) }
+            }
+            p { (tf("preview.summary", &[("visible", &preview.movies.len()), ("total", &collection.movies.len())])) }
+            @if policy.max_parental_rating.is_some() || !policy.block_unrated_items.is_empty() {
+                p { small { (t("preview.parental_controls")) } }
+            }
+            h2 { (t("preview.libraries")) }
+            @if preview.libraries.is_empty() {
+                p { (t("preview.no_libraries")) }
+            } @else {
+                ul {
+                    @for (folder, content) in &preview.libraries {
+                        li {
+                            (folder.name) " "
+                            small {
+                                @match content {
+                                    LibraryContent::Tag(key) => {
+                                        a href={(url("/tag/")) (utf8_percent_encode(key, NON_ALPHANUMERIC))} { (tf("preview.tag_library", &[("tag", key)])) }
+                                    }
+                                    LibraryContent::AllMovies => (t("preview.all_movies")),
+                                    LibraryContent::Other => (t("preview.other_library")),
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+            @if !preview.movies.is_empty() {
+                h2 { (t("preview.movies")) }
+                table {
+                    thead { tr { th { (t("preview.movie")) } th { (t("preview.through")) } } }
+                    tbody {
+                        @for (movie, libraries) in &preview.movies {
+                            tr {
+                                td { a href={(url("/movie/")) (movie.id()) "/details"} { (movie.name) } }
+                                td { (libraries.join(", ")) }
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    };
+    page(&tf("preview.title", &[("user", &user.name)]), content, PageOptions::default())
+}
+
+/// Outcome of a batch library change, one line per selected user.
+pub fn batch_results(folder: &MediaFolders, grant: bool, results: &[(&str, Result<(), Error>)]) -> Markup {
+    let key = if grant { "users.granted" } else { "users.revoked" };
+    html! {
+        article .batch-results {
+            @if results.is_empty() { p { (t("users.no_users_selected")) } }
+            ul {
+                @for (user, result) in results {
+                    @match result {
+                        Ok(()) => li { (tf(key, &[("library", &folder.name), ("user", user)])) },
+                        Err(e) => li .error { (user) ": " (e) },
+                    }
+                }
+            }
+        }
+    }
+}
+
+pub fn presets_page(presets: &[Preset], folders: &[MediaFolders], server_picker: Option<Markup>) -> Markup {
+    let content = html! {
+        @if presets.is_empty() {
+            p { (tm("presets.none", "section", html! { code { "[[presets]]" } })) }
+        }
+        @for preset in presets {
+            @let (ids, unknown) = preset.resolve(folders);
+            section .preset {
+                h2 { (preset.name) }
+                ul {
+                    @for folder in folders.iter().filter(|f| ids.contains(&f.id)) { li { (folder.name) } }
+                    @for entry in &unknown { li .unknown title=(t("presets.unknown_library")) { del { (entry) } } }
+                }
+            }
+        }
+    };
+    page(&t("nav.presets"), content, PageOptions { controls: server_picker, footer: None })
+}
+
+/// The tagging rules, the tags running them would add, and the ones just added if they ran.
+pub fn rules_page(rules: &[Rule], pending: &[Change], applied: Option<&[Change]>) -> Markup {
+    let changes_table = |changes: &[Change]| html! {
+        table {
+            thead { tr { th { (t("rules.movie")) } th { (t("rules.tag")) } } }
+            tbody {
+                @for change in changes {
+                    tr {
+                        td { a href={(url("/movie/")) (change.movie.id()) "/details"} { (change.movie_name) } }
+                        td { (change.tag) }
+                    }
+                }
+            }
+        }
+    };
+    let content = html! {
+        @if rules.is_empty() {
+            p { (tm("rules.none", "section", html! { code { "[[rules]]" } })) }
+        } @else {
+            table .rules {
+                thead { tr { th { (t("rules.filter")) } th { (t("rules.tag")) } } }
+                tbody {
+                    @for rule in rules {
+                        tr { td { code { (rule.filter.as_str()) } } td { (rule.tag) } }
+                    }
+                }
+            }
+        }
+        @if let Some(applied) = applied {
+            section {
+                h2 { (tf("rules.applied", &[("count", &applied.len())])) }
+                @if !applied.is_empty() { (changes_table(applied)) }
+            }
+        }
+        @if !rules.is_empty() {
+            section {
+                h2 { (t("rules.pending")) }
+                @if pending.is_empty() {
+                    p { (t("rules.no_pending")) }
+                } @else {
+                    (changes_table(pending))
+                    form method="post" action=(url("/rules/run")) {
+                        button type="submit" { (tf("rules.apply", &[("count", &pending.len())])) }
+                    }
+                }
+            }
+        }
+    };
+    page(&t("nav.rules"), content, PageOptions::default())
+}
+
+/// Form for tagging the movies of a CSV file, and what the last import changed.
+pub fn import_page(imported: Option<(&import::Plan, &[Change])>) -> Markup {
+    let content = html! {
+        @if let Some((plan, applied)) = imported {
+            section .imported {
+                h2 { (tf("import.applied", &[("count", &applied.len())])) }
+                @if !applied.is_empty() {
+                    ul {
+                        @for change in applied {
+                            li { a href={(url("/movie/")) (change.movie.id()) "/details"} { (change.movie_name) } }
+                        }
+                    }
+                }
+                @if !plan.already_tagged.is_empty() {
+                    p { (tf("import.already_tagged", &[("count", &plan.already_tagged.len())])) }
+                }
+                @if !plan.unmatched.is_empty() {
+                    h3 { (tf("import.unmatched", &[("count", &plan.unmatched.len())])) }
+                    table {
+                        thead { tr { th { (t("import.title")) } th { (t("import.year")) } } }
+                        tbody {
+                            @for row in &plan.unmatched {
+                                tr { td { (row.title) } td { @if let Some(year) = row.year { (year) } } }
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        form .import method="post" action=(url("/import/letterboxd")) enctype="multipart/form-data" {
+            p { (t("import.help")) }
+            label {
+                (t("import.file"))
+                input type="file" name="file" accept=".csv,text/csv" required;
+            }
+            label {
+                (t("import.tag"))
+                input type="text" name="tag" value="watchlist" required;
+            }
+            button type="submit" { (t("import.submit")) }
+        }
+    };
+    page(&t("nav.import"), content, PageOptions::default())
+}
+
+/// Whether tagrs is connected to Trakt, with the code to enter while connecting.
+pub fn trakt_connection(connection: &Connection) -> Markup {
+    match connection {
+        Connection::Disconnected => html! {
+            section #trakt-connection {
+                p { (t("trakt.disconnected")) }
+                button hx-post=(url("/trakt/connect")) hx-target="#trakt-connection" hx-swap="outerHTML" { (t("trakt.connect")) }
+            }
+        },
+        Connection::Waiting { user_code, verification_url } => html! {
+            section #trakt-connection hx-get=(url("/trakt/connection")) hx-trigger="every 5s" hx-swap="outerHTML" {
+                p aria-busy="true" {
+                    (tm("trakt.enter_code", "url", html! { a href=(verification_url) target="_blank" { (verification_url) } }))
+                }
+                p .user-code { (user_code) }
+            }
+        },
+        Connection::Connected { expires } => html! {
+            section #trakt-connection {
+                p { (tf("trakt.connected", &[("expires", &expires.format("%Y-%m-%d"))])) }
+                button .secondary hx-post=(url("/trakt/connect")) hx-target="#trakt-connection" hx-swap="outerHTML" { (t("trakt.reconnect")) }
+            }
+        },
+    }
+}
+
+fn sync_direction(sync: SyncDirection) -> String {
+    match sync {
+        SyncDirection::Off => t("trakt.off"),
+        SyncDirection::Pull => t("trakt.pull"),
+        SyncDirection::Push => t("trakt.push"),
+        SyncDirection::Both => t("trakt.both"),
+    }
+}
+
+/// The Trakt connection, the lists synced with tags and what the last sync changed.
+pub fn trakt_page(lists: &[TraktListConfig], connection: &Connection, last_sync: Option<&LastSync>) -> Markup {
+    let content = html! {
+        (trakt_connection(connection))
+        section {
+            h2 { (t("trakt.lists")) }
+            @if lists.is_empty() {
+                p { (tm("trakt.no_lists", "section", html! { code { "[[trakt.lists]]" } })) }
+            } @else {
+                table {
+                    thead { tr { th { (t("trakt.list")) } th { (t("trakt.tag")) } th { (t("trakt.sync")) } } }
+                    tbody {
+                        @for list in lists {
+                            tr { td { (list.list) } td { (list.tag) } td { (sync_direction(list.sync)) } }
+                        }
+                    }
+                }
+                @if matches!(connection, Connection::Connected { .. }) {
+                    form method="post" action=(url("/trakt/sync")) {
+                        button type="submit" { (t("trakt.sync_now")) }
+                    }
+                }
+            }
+        }
+        @if let Some(last_sync) = last_sync {
+            section .trakt-sync {
+                h2 { (tf("trakt.last_sync", &[("finished", &last_sync.finished.format("%Y-%m-%d %H:%M:%S UTC"))])) }
+                @for report in &last_sync.lists {
+                    article {
+                        header { strong { (report.list) } " → " (report.tag) }
+                        @if let Some(error) = &report.error {
+                            p .error { (error) }
+                        } @else {
+                            p {
+                                (tf("trakt.report", &[
+                                    ("tagged", &report.tagged.len()),
+                                    ("added", &report.added),
+                                    ("removed", &report.removed),
+                                ]))
+                            }
+                            @if !report.tagged.is_empty() {
+                                details {
+                                    summary { (tf("trakt.tagged", &[("count", &report.tagged.len())])) }
+                                    ul { @for name in &report.tagged { li { (name) } } }
+                                }
+                            }
+                            @if !report.unmatched.is_empty() {
+                                details {
+                                    summary { (tf("trakt.unmatched", &[("count", &report.unmatched.len())])) }
+                                    ul { @for title in &report.unmatched { li { (title) } } }
+                                }
+                            }
+                            @if !report.without_ids.is_empty() {
+                                details {
+                                    summary { (tf("trakt.without_ids", &[("count", &report.without_ids.len())])) }
+                                    ul { @for name in &report.without_ids { li { (name) } } }
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    };
+    page(&t("nav.trakt"), content, PageOptions::default())
+}
+
+/// Saved tag snapshots, newest first, and what restoring one just changed.
+pub fn snapshots_page(snapshots: &[Snapshot], restored: Option<(&Snapshot, &Restored)>) -> Markup {
+    let taken = |snapshot: &Snapshot| snapshot.created.format("%Y-%m-%d %H:%M:%S UTC").to_string();
+    let content = html! {
+        @if let Some((snapshot, restored)) = restored {
+            section .restored {
+                p {
+                    (tf("snapshots.restored", &[
+                        ("taken", &taken(snapshot)),
+                        ("added", &restored.added.len()),
+                        ("removed", &restored.removed.len()),
+                        ("created", &restored.created_tags.len()),
+                    ]))
+                }
+                @if !restored.missing.is_empty() {
+                    p { (t("snapshots.missing")) }
+                    ul { @for name in &restored.missing { li { (name) } } }
+                }
+            }
+        }
+        form method="post" action=(url("/snapshots")) {
+            button type="submit" { (t("snapshots.take")) }
+        }
+        @if snapshots.is_empty() {
+            p { (t("snapshots.none")) }
+        } @else {
+            table .snapshots {
+                thead {
+                    tr { th { (t("snapshots.taken")) } th { (t("snapshots.tags")) } th { (t("snapshots.links")) } th {} }
+                }
+                tbody {
+                    @for snapshot in snapshots {
+                        tr {
+                            td { (taken(snapshot)) }
+                            td { (snapshot.tags.len()) }
+                            td { (snapshot.links()) }
+                            td {
+                                form method="post" action={(url("/snapshots/")) (snapshot.id) "/restore"}
+                                    onsubmit={"return confirm(" (serde_json::to_string(&tf("snapshots.confirm_restore", &[("taken", &taken(snapshot))])).unwrap_or_default()) ")"}
+                                {
+                                    button .secondary.outline type="submit" { (t("snapshots.restore")) }
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    };
+    page(&t("nav.snapshots"), content, PageOptions::default())
+}
+
+pub fn parental_controls(user: &User, ratings: &[ParentalRating]) -> Markup {
+    let max_rating = user.policy.max_parental_rating;
+    let selected_rating = ratings.iter().position(|r| r.value.is_some() && r.value == max_rating);
+    html! {
+        form .parental-controls
+            hx-post=(url(&format!("/user/{}/parental", user.id)))
+            hx-trigger="change"
+            hx-swap="outerHTML"
+        {
+            label {
+                (t("parental.max_rating"))
+                select name="max_parental_rating" {
+                    option value="" selected[max_rating.is_none()] { (t("parental.no_limit")) }
+                    @for (i, rating) in ratings.iter().enumerate() {
+                        @if let Some(value) = rating.value {
+                            option value=(value) selected[selected_rating == Some(i)] { (rating.name) }
+                        }
+                    }
+                }
+            }
+            fieldset {
+                legend { (t("parental.block_unrated")) }
+                @for item_type in UNRATED_ITEM_TYPES {
+                    label {
+                        input
+                            type="checkbox"
+                            name="block_unrated_items"
+                            value=(item_type)
+                            checked[user.policy.block_unrated_items.iter().any(|i| i == item_type)];
+                        (item_type)
+                    }
+                }
+            }
+        }
+    }
+}
+
+pub fn audit_page(events: &[Event], log_path: Option<&Path>) -> Markup {
+    let content = html! {
+        @if events.is_empty() {
+            p { (t("audit.empty")) }
+        } @else {
+            table .audit {
+                thead { tr { th { (t("audit.when")) } th { (t("audit.who")) } th { (t("audit.what")) } } }
+                tbody {
+                    @for event in events {
+                        tr {
+                            td { (event.timestamp.format("%Y-%m-%d %H:%M:%S UTC")) }
+                            td { (event.actor) }
+                            td { (event.action) }
+                        }
+                    }
+                }
+            }
+        }
+    };
+    let footer = log_path.map(|path| html! { small { (tm("audit.full_history", "path", html! { code { (path.display()) } })) } });
+    page(&t("nav.audit"), content, PageOptions { controls: None, footer })
+}
+
+pub fn activity_page(events: &[Event]) -> Markup {
+    page(&t("nav.activity"), activity_feed(events), PageOptions { controls: None, footer: None })
+}
+
+/// Recent events as a timeline, newest first and split by day, that reloads itself when
+/// `/updates` announces new activity.
+pub fn activity_feed(events: &[Event]) -> Markup {
+    html! {
+        div #activity hx-get=(url("/activity/feed")) hx-trigger="activity-changed from:body" hx-swap="outerHTML" {
+            @if events.is_empty() {
+                p { (t("activity.empty")) }
+            }
+            @for day in events.chunk_by(|a, b| a.timestamp.date_naive() == b.timestamp.date_naive()) {
+                section .activity-day {
+                    @let date = day[0].timestamp.date_naive();
+                    h3 { time datetime=(date) { (date.format("%Y-%m-%d")) } }
+                    ol .activity {
+                        @for event in day {
+                            li {
+                                time datetime=(event.timestamp.to_rfc3339()) title=(event.timestamp.format("%Y-%m-%d %H:%M:%S UTC")) {
+                                    (event.timestamp.format("%H:%M"))
+                                }
+                                " " strong { (event.actor) } " " (event.action)
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn format_ticks(ticks: u64) -> String {
+    let seconds = ticks / TICKS_PER_SECOND;
+    format!("{}:{:02}:{:02}", seconds / 3600, seconds / 60 % 60, seconds % 60)
+}
+
+pub fn sessions_page(sessions: &[Session], devices: &[Device], server_picker: Option<Markup>) -> Markup {
+    let content = html! {
+        @if sessions.is_empty() {
+            p { (t("sessions.empty")) }
+        } @else {
+            table .sessions {
+                thead {
+                    tr {
+                        th { (t("sessions.user")) }
+                        th { (t("sessions.device")) }
+                        th { (t("sessions.playing")) }
+                        th { (t("sessions.progress")) }
+                        th { (t("sessions.playback")) }
+                        th {}
+                    }
+                }
+                tbody {
+                    @for session in sessions {
+                        tr id={"session-" (session.id)} {
+                            td { (session.user_name.as_deref().unwrap_or("-")) }
+                            td {
+                                (session.device_name.as_deref().unwrap_or("-"))
+                                @if let Some(client) = &session.client { br; small { (client) } }
+                            }
+                            @if let Some(item) = &session.now_playing_item {
+                                td {
+                                    @if let Some(series) = &item.series_name { (series) ": " }
+                                    (item.name)
+                                }
+                                td {
+                                    @let position = session.play_state.as_ref().and_then(|p| p.position_ticks).unwrap_or(0);
+                                    (format_ticks(position))
+                                    @if let Some(runtime) = item.run_time_ticks { " / " (format_ticks(runtime)) }
+                                    @if session.play_state.as_ref().is_some_and(|p| p.is_paused) { (t("sessions.paused")) }
+                                }
+                                td {
+                                    (session.play_state.as_ref().and_then(|p| p.play_method.as_deref()).unwrap_or("-"))
+                                    @if let Some(transcode) = &session.transcoding_info {
+                                        br;
+                                        small {
+                                            (transcode.video_codec.as_deref().unwrap_or("?")) "/" (transcode.audio_codec.as_deref().unwrap_or("?"))
+                                            @if let Some(bitrate) = transcode.bitrate { " @ " (bitrate / 1000) " kbps" }
+                                            @if !transcode.transcode_reasons.is_empty() { " (" (transcode.transcode_reasons.join(", ")) ")" }
+                                        }
+                                    }
+                                }
+                                td {
+                                    button .outline.contrast
+                                        hx-post=(url(&format!("/sessions/{}/stop", session.id)))
+                                        hx-confirm=(tf("sessions.confirm_stop", &[("user", &session.user_name.as_deref().unwrap_or("-")), ("item", &item.name)]))
+                                        hx-swap="outerHTML"
+                                        { (t("sessions.stop")) }
+                                }
+                            } @else {
+                                td colspan="4" { small { (t("sessions.idle")) } }
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        h2 { (t("sessions.devices")) }
+        @if devices.is_empty() {
+            p { (t("sessions.no_devices")) }
+        } @else {
+            table .devices {
+                thead {
+                    tr {
+                        th { (t("sessions.device")) }
+                        th { (t("sessions.app")) }
+                        th { (t("sessions.last_user")) }
+                        th { (t("sessions.last_active")) }
+                        th {}
+                    }
+                }
+                tbody {
+                    @for device in devices {
+                        tr {
+                            td { (device.name) }
+                            td {
+                                (device.app_name.as_deref().unwrap_or("-"))
+                                @if let Some(version) = &device.app_version { " " small { (version) } }
+                            }
+                            td { (device.last_user_name.as_deref().unwrap_or("-")) }
+                            td { (format_date(device.date_last_activity)) }
+                            td {
+                                button .outline.contrast
+                                    hx-delete=(url(&format!("/devices/{}", utf8_percent_encode(&device.id, NON_ALPHANUMERIC))))
+                                    hx-confirm=(tf("sessions.confirm_revoke", &[("device", &device.name)]))
+                                    hx-target="closest tr"
+                                    hx-swap="outerHTML"
+                                    { (t("sessions.revoke")) }
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    };
+    page(&t("nav.sessions"), content, PageOptions { controls: server_picker, footer: None })
+}
+
+const TOP_ENTRIES: usize = 25;
+
+fn format_date(date: Option<chrono::DateTime<chrono::Utc>>) -> String {
+    date.map_or_else(|| "-".to_string(), |d| d.format("%Y-%m-%d").to_string())
+}
+
+pub fn stats_page(collection: &Collection, tag_sort: TagSort, server_picker: Option<Markup>) -> Markup {
+    let untagged = collection.untagged().len();
+    let missing_posters = collection.missing_posters();
+    let mut tag_counts = collection.tag_counts();
+    match tag_sort {
+        TagSort::Name => tag_counts.sort_by_key(|(key, _, _)| *key),
+        TagSort::Count => tag_counts.sort_by(|a, b| b.2.cmp(&a.2).then(a.0.cmp(b.0))),
+    }
+    let content = html! {
+        section .stats {
+            h2 { (t("stats.collection")) }
+            table {
+                tbody {
+                    tr { th { (t("stats.movies")) } td { (collection.movies.len()) } }
+                    tr { th { (t("stats.tags")) } td { (collection.tags.len()) } }
+                    tr { th { (t("stats.tagged_movies")) } td { (collection.movies.len() - untagged) } }
+                    tr { th { (t("stats.untagged_movies")) } td { a href=(url("/?filter=untagged")) { (untagged) } } }
+                    tr { th { (t("stats.without_poster")) } td { (missing_posters.len()) } }
+                }
+            }
+        }
+        section .stats {
+            h2 { (t("stats.movies_per_tag")) }
+            table {
+                thead {
+                    tr {
+                        th { a href="?tag_sort=name" aria-current=[(tag_sort == TagSort::Name).then_some("true")] { (t("stats.tag")) } }
+                        th { a href="?tag_sort=count" aria-current=[(tag_sort == TagSort::Count).then_some("true")] { (t("stats.movies")) } }
+                    }
+                }
+                tbody {
+                    @for (key, _, count) in &tag_counts {
+                        tr {
+                            td { a href={(url("/tag/")) (utf8_percent_encode(key, NON_ALPHANUMERIC))} { (key) } }
+                            td { (count) }
+                        }
+                    }
+                }
+            }
+        }
+        @if !missing_posters.is_empty() {
+            section .stats {
+                h2 { (tf("stats.missing_posters", &[("count", &missing_posters.len())])) }
+                ul { @for movie in &missing_posters { li { (movie.name) } } }
+            }
+        }
+        div hx-get=(url("/stats/disk")) hx-trigger="load" hx-swap="outerHTML" {
+            p aria-busy="true" { (t("stats.loading_disk_usage")) }
+        }
+        @if theme::current().jellyfin {
+            div hx-get=(url("/stats/playback")) hx-trigger="load" hx-swap="outerHTML" {
+                p aria-busy="true" { (t("stats.loading_playback")) }
+            }
+        }
+    };
+    page(&t("nav.statistics"), content, PageOptions { controls: server_picker, footer: None })
+}
+
+/// Disk usage section of the statistics page, loaded separately since measuring movie folders
+/// takes a while the first time.
+pub fn disk_usage(usage: &DiskUsage, sort: DiskSort) -> Markup {
+    let mut tags: Vec<_> = usage.tags.iter().collect();
+    if sort == DiskSort::Name {
+        tags.sort_by(|a, b| a.key.cmp(&b.key));
+    }
+    let largest = usage.tags.first().map_or(0, |tag| tag.bytes).max(1);
+    let sort_link = |value: DiskSort, query: &str, label: &str| {
+        html! {
+            a href="#" hx-get={(url("/stats/disk")) "?sort=" (query)}
+                hx-target="closest section" hx-swap="outerHTML"
+                aria-current=[(sort == value).then_some("true")] { (label) }
+        }
+    };
+    html! {
+        section .stats .disk-usage {
+            h2 { (tf("stats.disk_usage", &[("total", &format_size(usage.total))])) }
+            table {
+                thead {
+                    tr {
+                        th { (sort_link(DiskSort::Name, "name", &t("stats.tag"))) }
+                        th { (t("stats.movies")) }
+                        th { (sort_link(DiskSort::Size, "size", &t("stats.size"))) }
+                        th {}
+                    }
+                }
+                tbody {
+                    @for tag in tags {
+                        tr {
+                            td { a href={(url("/tag/")) (utf8_percent_encode(&tag.key, NON_ALPHANUMERIC))} { (tag.key) } }
+                            td { (tag.movies) }
+                            td { (format_size(tag.bytes)) }
+                            td .bar {
+                                span style={"width: " (tag.bytes as f64 / largest as f64 * 100.0) "%"} {}
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Playback sections of the statistics page, loaded separately since they query Jellyfin.
+pub fn playback_stats(playback: &PlaybackStats) -> Markup {
+    html! {
+        section .stats {
+            h2 { (t("stats.most_watched")) }
+            table {
+                thead {
+                    tr {
+                        th { (t("stats.movie")) }
+                        th { (t("stats.plays")) }
+                        th { (t("stats.viewers")) }
+                        th { (t("stats.last_played")) }
+                    }
+                }
+                tbody {
+                    @for movie in playback.movies.iter().take(TOP_ENTRIES) {
+                        tr {
+                            td { (movie.name) }
+                            td { (movie.plays) }
+                            td { (movie.viewers) }
+                            td { (format_date(movie.last_played)) }
+                        }
+                    }
+                }
+            }
+        }
+        section .stats {
+            h2 { (t("stats.most_active")) }
+            table {
+                thead {
+                    tr {
+                        th { (t("stats.user")) }
+                        th { (t("stats.movies_watched")) }
+                        th { (t("stats.plays")) }
+                        th { (t("stats.last_played")) }
+                    }
+                }
+                tbody {
+                    @for user in &playback.users {
+                        tr {
+                            td { (user.name) }
+                            td { (user.movies) }
+                            td { (user.plays) }
+                            td { (format_date(user.last_played)) }
+                        }
+                    }
+                }
+            }
+        }
+        section .stats {
+            h2 { (tf("stats.never_watched", &[("count", &playback.unwatched.len())])) }
+            ul { @for name in &playback.unwatched { li { (name) } } }
+        }
+    }
+}
@@ -0,0 +1,111 @@
+use std::sync::Arc;
+
+use axum::extract::{Request, State};
+use axum::middleware::Next;
+use axum::response::Response;
+
+use crate::overrides::TemplateOverrides;
+use crate::roles::{self, Role};
+use crate::{cookie, Actor, AppState};
+
+pub(crate) const SCHEME_COOKIE: &str = "color_scheme";
+
+/// Set by admins to see tags marked hidden.
+pub(crate) const HIDDEN_TAGS_COOKIE: &str = "show_hidden_tags";
+
+/// Color scheme picked in the header. Without one, pico follows the system preference.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColorScheme {
+    Light,
+    Dark,
+}
+
+impl ColorScheme {
+    pub const ALL: [ColorScheme; 2] = [ColorScheme::Light, ColorScheme::Dark];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ColorScheme::Light => "light",
+            ColorScheme::Dark => "dark",
+        }
+    }
+
+    pub(crate) fn from_name(value: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|scheme| scheme.as_str() == value)
+    }
+}
+
+/// How pages of the current request are styled.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct PageTheme {
+    pub(crate) custom_css: bool,
+    pub(crate) logo: bool,
+    pub(crate) scheme: Option<ColorScheme>,
+    pub(crate) overrides: Option<Arc<TemplateOverrides>>,
+    /// Names of the saved views linked in the header
+    pub(crate) views: Vec<String>,
+    /// Prefix of every URL when served below a path by a reverse proxy, like `/tagrs`
+    pub(crate) base_path: String,
+    /// Whether a Jellyfin server is configured, showing the pages managing it
+    pub(crate) jellyfin: bool,
+    /// Whether Trakt is configured, linking its page
+    pub(crate) trakt: bool,
+    /// Whether tags marked hidden are shown, for admins who asked to
+    pub(crate) show_hidden_tags: bool,
+    /// Whether the visitor is an admin, linking the pages only admins may open
+    pub(crate) admin: bool,
+}
+
+tokio::task_local! {
+    static PAGE_THEME: PageTheme;
+}
+
+/// Middleware passing the configured branding and the visitor's color scheme to the templates.
+pub(crate) async fn apply_theme(State(state): State<AppState>, actor: Actor, request: Request, next: Next) -> Response {
+    let admin = roles::role(&state, &actor).await >= Role::Admin;
+    let show_hidden_tags = admin && cookie(request.headers(), HIDDEN_TAGS_COOKIE) == Some("true");
+    let theme = PageTheme {
+        custom_css: state.theme.custom_css.is_some(),
+        logo: state.theme.logo.is_some(),
+        scheme: cookie(request.headers(), SCHEME_COOKIE).and_then(ColorScheme::from_name),
+        overrides: state.overrides.clone(),
+        views: state.views.iter().map(|view| view.name.clone()).collect(),
+        base_path: state.base_path.clone(),
+        jellyfin: !state.jellyfin.is_empty(),
+        trakt: state.trakt.is_some(),
+        show_hidden_tags,
+        admin,
+    };
+    PAGE_THEME.scope(theme, next.run(request)).await
+}
+
+pub(crate) fn current() -> PageTheme {
+    PAGE_THEME.try_with(PageTheme::clone).unwrap_or_default()
+}
+
+/// Whether the current request asked for hidden tags and may see them.
+pub(crate) fn show_hidden_tags() -> bool {
+    PAGE_THEME.try_with(|theme| theme.show_hidden_tags).unwrap_or_default()
+}
+
+/// Whether the current request was made by an admin.
+pub(crate) fn is_admin() -> bool {
+    PAGE_THEME.try_with(|theme| theme.admin).unwrap_or_default()
+}
+
+/// `path` below the base path of the current request.
+pub(crate) fn url(path: &str) -> String {
+    PAGE_THEME.try_with(|theme| format!("{}{}", theme.base_path, path)).unwrap_or_else(|_| path.to_string())
+}
+
+/// Content type of a logo, from its file extension.
+pub(crate) fn image_content_type(path: &std::path::Path) -> &'static str {
+    match path.extension().and_then(|ext| ext.to_str()).map(str::to_lowercase).as_deref() {
+        Some("png") => "image/png",
+        Some("jpg" | "jpeg") => "image/jpeg",
+        Some("gif") => "image/gif",
+        Some("webp") => "image/webp",
+        Some("svg") => "image/svg+xml",
+        _ => "application/octet-stream",
+    }
+}
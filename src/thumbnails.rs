@@ -0,0 +1,56 @@
+use std::path::{Path, PathBuf};
+
+use sha1::{Digest, Sha1};
+
+use crate::collection::Error;
+
+const MIN_WIDTH: u32 = 16;
+const MAX_WIDTH: u32 = 1000;
+
+#[derive(Debug, Clone)]
+pub struct Thumbnailer {
+    cache_dir: PathBuf,
+}
+
+impl Thumbnailer {
+    pub fn new<T: AsRef<Path>>(cache_dir: T) -> Self {
+        Self {
+            cache_dir: cache_dir.as_ref().to_path_buf(),
+        }
+    }
+
+    fn cache_path(&self, source: &Path, width: u32) -> PathBuf {
+        let mut hasher = Sha1::new();
+        hasher.update(source.as_os_str().as_encoded_bytes());
+        let name = format!("{}-w{}.jpg", hex::encode(hasher.finalize()), width);
+        self.cache_dir.join(name)
+    }
+
+    /// Returns the path of a JPEG thumbnail of `source` scaled to `width`, generating it if the
+    /// cached copy is missing or older than the source image.
+    pub(crate) async fn thumbnail(&self, source: &Path, width: u32) -> Result<PathBuf, Error> {
+        let width = width.clamp(MIN_WIDTH, MAX_WIDTH);
+        let cache_path = self.cache_path(source, width);
+        let source_modified = tokio::fs::metadata(source).await?.modified()?;
+        if let Ok(cached) = tokio::fs::metadata(&cache_path).await {
+            if cached.modified()? >= source_modified {
+                return Ok(cache_path);
+            }
+        }
+        tokio::fs::create_dir_all(&self.cache_dir).await?;
+        let source = source.to_path_buf();
+        let target = cache_path.clone();
+        tokio::task::spawn_blocking(move || -> anyhow::Result<()> {
+            tracing::debug!("generating {}px thumbnail for {}", width, source.display());
+            let image = image::ImageReader::open(&source)?.with_guessed_format()?.decode()?;
+            let thumbnail = image.thumbnail(width, u32::MAX).into_rgb8();
+            let tmp = target.with_extension(format!("{}.tmp", uuid::Uuid::new_v4()));
+            thumbnail.save_with_format(&tmp, image::ImageFormat::Jpeg)?;
+            std::fs::rename(&tmp, &target)?;
+            Ok(())
+        })
+        .await
+        .map_err(anyhow::Error::from)??;
+        Ok(cache_path)
+    }
+}
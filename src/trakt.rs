@@ -0,0 +1,459 @@
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use reqwest::{Method, StatusCode};
+use serde::{Deserialize, Serialize};
+use tokio::sync::{Mutex, RwLock};
+
+use crate::audit::{self, AuditLog};
+use crate::collection::{Error, PathnameHash};
+use crate::config::{TraktConfig, TraktListConfig};
+use crate::import::Matcher;
+use crate::rules::Change;
+use crate::AppState;
+
+const API_URL: &str = "https://api.trakt.tv";
+
+/// List name standing for the user's watchlist, which has endpoints of its own.
+const WATCHLIST: &str = "watchlist";
+
+/// How long before it expires the access token is refreshed.
+const REFRESH_MARGIN: chrono::Duration = chrono::Duration::days(1);
+
+/// Tokens as returned by Trakt, and saved to the token file as is.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Token {
+    access_token: String,
+    refresh_token: String,
+    /// Seconds since the epoch
+    created_at: i64,
+    /// Seconds from `created_at`
+    expires_in: i64,
+}
+
+impl Token {
+    fn expires(&self) -> DateTime<Utc> {
+        DateTime::from_timestamp(self.created_at + self.expires_in, 0).unwrap_or_default()
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct DeviceCode {
+    device_code: String,
+    user_code: String,
+    verification_url: String,
+    /// Seconds until the code expires
+    expires_in: u64,
+    /// Seconds to wait between polls
+    interval: u64,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct Ids {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    trakt: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    imdb: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tmdb: Option<u64>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ListItem {
+    movie: Option<TraktMovie>,
+}
+
+#[derive(Debug, Deserialize)]
+struct TraktMovie {
+    title: String,
+    year: Option<u16>,
+    ids: Ids,
+}
+
+/// How far connecting tagrs to a Trakt account got.
+#[derive(Debug, Clone)]
+pub(crate) enum Connection {
+    Disconnected,
+    /// Waiting for the user to enter `user_code` at `verification_url`
+    Waiting { user_code: String, verification_url: String },
+    Connected { expires: DateTime<Utc> },
+}
+
+/// What syncing one list changed.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct ListReport {
+    pub(crate) list: String,
+    pub(crate) tag: String,
+    /// Movies given the tag because they're on the list
+    pub(crate) tagged: Vec<String>,
+    /// Tagged movies put on the list
+    pub(crate) added: usize,
+    /// Movies taken off the list since they're no longer tagged
+    pub(crate) removed: usize,
+    /// Titles on the list that aren't in the collection
+    pub(crate) unmatched: Vec<String>,
+    /// Tagged movies that couldn't be put on the list, since their `.nfo` has no IMDb or TMDB ID
+    pub(crate) without_ids: Vec<String>,
+    pub(crate) error: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct LastSync {
+    pub(crate) finished: DateTime<Utc>,
+    pub(crate) lists: Vec<ListReport>,
+}
+
+/// A connection to a Trakt account through the OAuth device flow, syncing lists of the account
+/// with tags.
+pub struct Trakt {
+    client_id: String,
+    client_secret: String,
+    token_file: PathBuf,
+    pub(crate) lists: Vec<TraktListConfig>,
+    client: reqwest::Client,
+    /// Locked while refreshing so only one request refreshes an expired token
+    token: Mutex<Option<Token>>,
+    waiting: RwLock<Option<(String, String)>>,
+    /// Held while syncing, so scheduled and manual syncs don't run at once
+    syncing: Mutex<()>,
+    pub(crate) last_sync: RwLock<Option<LastSync>>,
+}
+
+impl std::fmt::Debug for Trakt {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Trakt")
+            .field("client_id", &self.client_id)
+            .field("client_secret", &"<redacted>")
+            .field("token_file", &self.token_file)
+            .field("lists", &self.lists)
+            .finish()
+    }
+}
+
+impl Trakt {
+    /// Sets up the client from the config, reading the token saved by an earlier connection.
+    pub async fn load(config: &TraktConfig) -> anyhow::Result<Self> {
+        let token = match tokio::fs::read(&config.token_file).await {
+            Ok(json) => Some(
+                serde_json::from_slice(&json)
+                    .map_err(|e| anyhow::anyhow!("invalid Trakt token file {}: {}", config.token_file.display(), e))?,
+            ),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+            Err(e) => anyhow::bail!("failed to read Trakt token file {}: {}", config.token_file.display(), e),
+        };
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_secs(30))
+            .user_agent(concat!("tagrs/", env!("CARGO_PKG_VERSION")))
+            .build()
+            .expect("failed to initialize the HTTP client");
+        Ok(Self {
+            client_id: config.client_id.clone(),
+            client_secret: config.client_secret().await?,
+            token_file: config.token_file.clone(),
+            lists: config.lists.clone(),
+            client,
+            token: Mutex::new(token),
+            waiting: RwLock::new(None),
+            syncing: Mutex::new(()),
+            last_sync: RwLock::new(None),
+        })
+    }
+
+    pub(crate) async fn connection(&self) -> Connection {
+        if let Some((user_code, verification_url)) = self.waiting.read().await.clone() {
+            return Connection::Waiting { user_code, verification_url };
+        }
+        match &*self.token.lock().await {
+            Some(token) => Connection::Connected { expires: token.expires() },
+            None => Connection::Disconnected,
+        }
+    }
+
+    /// Starts the device flow, polling Trakt in the background until the user entered the code
+    /// shown by [`Trakt::connection`] or it expired.
+    pub(crate) async fn connect(self: &Arc<Self>, audit: Arc<AuditLog>, actor: String) -> Result<(), Error> {
+        let body = serde_json::json!({ "client_id": self.client_id });
+        let resp = self.oauth("/oauth/device/code", body).await?;
+        if !resp.status().is_success() {
+            return Err(Error::Trakt(format!("requesting a device code failed with {}", resp.status())));
+        }
+        let code: DeviceCode = json(resp).await?;
+        *self.waiting.write().await = Some((code.user_code.clone(), code.verification_url.clone()));
+        let trakt = self.clone();
+        tokio::spawn(async move {
+            match trakt.poll_device_token(&code).await {
+                Ok(()) => {
+                    tracing::info!("connected to Trakt");
+                    if let Err(e) = audit.record(&actor, audit::Action::TraktConnected).await {
+                        tracing::warn!("failed to record the Trakt connection: {}", e);
+                    }
+                }
+                Err(e) => tracing::warn!("failed to connect to Trakt: {}", e),
+            }
+            *trakt.waiting.write().await = None;
+        });
+        Ok(())
+    }
+
+    async fn poll_device_token(&self, code: &DeviceCode) -> Result<(), Error> {
+        let body = serde_json::json!({
+            "code": code.device_code,
+            "client_id": self.client_id,
+            "client_secret": self.client_secret,
+        });
+        let deadline = tokio::time::Instant::now() + Duration::from_secs(code.expires_in);
+        let mut interval = Duration::from_secs(code.interval.max(1));
+        while tokio::time::Instant::now() < deadline {
+            tokio::time::sleep(interval).await;
+            let resp = self.oauth("/oauth/device/token", body.clone()).await?;
+            match resp.status() {
+                StatusCode::OK => {
+                    let token: Token = json(resp).await?;
+                    self.save_token(&token).await?;
+                    *self.token.lock().await = Some(token);
+                    return Ok(());
+                }
+                // not entered yet
+                StatusCode::BAD_REQUEST => {}
+                StatusCode::TOO_MANY_REQUESTS => interval += Duration::from_secs(1),
+                StatusCode::IM_A_TEAPOT => return Err(Error::Trakt("the user denied access".to_string())),
+                status => return Err(Error::Trakt(format!("device code rejected with {}", status))),
+            }
+        }
+        Err(Error::Trakt("the code expired before it was entered".to_string()))
+    }
+
+    async fn oauth(&self, path: &str, body: serde_json::Value) -> Result<reqwest::Response, Error> {
+        self.client
+            .post(format!("{}{}", API_URL, path))
+            .json(&body)
+            .send()
+            .await
+            .map_err(|e| Error::Trakt(e.to_string()))
+    }
+
+    async fn save_token(&self, token: &Token) -> Result<(), Error> {
+        if let Some(dir) = self.token_file.parent() {
+            tokio::fs::create_dir_all(dir).await?;
+        }
+        let mut options = tokio::fs::OpenOptions::new();
+        options.write(true).create(true).truncate(true);
+        #[cfg(unix)]
+        options.mode(0o600);
+        let mut file = options.open(&self.token_file).await?;
+        tokio::io::AsyncWriteExt::write_all(&mut file, &serde_json::to_vec(token)?).await?;
+        Ok(())
+    }
+
+    /// The access token, refreshed first if it's about to expire.
+    async fn access_token(&self) -> Result<String, Error> {
+        let mut token = self.token.lock().await;
+        let Some(current) = token.as_ref() else {
+            return Err(Error::Trakt("not connected to Trakt yet".to_string()));
+        };
+        if current.expires() - REFRESH_MARGIN > Utc::now() {
+            return Ok(current.access_token.clone());
+        }
+        let body = serde_json::json!({
+            "refresh_token": current.refresh_token,
+            "client_id": self.client_id,
+            "client_secret": self.client_secret,
+            "redirect_uri": "urn:ietf:wg:oauth:2.0:oob",
+            "grant_type": "refresh_token",
+        });
+        let resp = self.oauth("/oauth/token", body).await?;
+        if !resp.status().is_success() {
+            return Err(Error::Trakt(format!("refreshing the access token failed with {}, connect again", resp.status())));
+        }
+        let refreshed: Token = json(resp).await?;
+        self.save_token(&refreshed).await?;
+        let access_token = refreshed.access_token.clone();
+        *token = Some(refreshed);
+        Ok(access_token)
+    }
+
+    async fn request<T: serde::de::DeserializeOwned>(
+        &self,
+        method: Method,
+        path: &str,
+        body: Option<serde_json::Value>,
+    ) -> Result<T, Error> {
+        let mut request = self
+            .client
+            .request(method, format!("{}{}", API_URL, path))
+            .header("trakt-api-version", "2")
+            .header("trakt-api-key", &self.client_id)
+            .bearer_auth(self.access_token().await?);
+        if let Some(body) = body {
+            request = request.json(&body);
+        }
+        let resp = request.send().await.map_err(|e| Error::Trakt(e.to_string()))?;
+        let status = resp.status();
+        if !status.is_success() {
+            return Err(Error::Trakt(format!("{} {}: {}", path, status, resp.text().await.unwrap_or_default())));
+        }
+        json(resp).await
+    }
+
+    #[tracing::instrument(skip(self))]
+    async fn list_movies(&self, list: &str) -> Result<Vec<TraktMovie>, Error> {
+        let path = if list == WATCHLIST {
+            "/users/me/watchlist/movies".to_string()
+        } else {
+            format!("/users/me/lists/{}/items/movie", list)
+        };
+        let items: Vec<ListItem> = self.request(Method::GET, &path, None).await?;
+        Ok(items.into_iter().filter_map(|item| item.movie).collect())
+    }
+
+    /// Adds the movies to `list`, or removes them with `remove`.
+    #[tracing::instrument(skip(self, movies), fields(movies = movies.len()))]
+    async fn change_list(&self, list: &str, movies: Vec<Ids>, remove: bool) -> Result<(), Error> {
+        if movies.is_empty() {
+            return Ok(());
+        }
+        let mut path = if list == WATCHLIST {
+            "/sync/watchlist".to_string()
+        } else {
+            format!("/users/me/lists/{}/items", list)
+        };
+        if remove {
+            path.push_str("/remove");
+        }
+        let movies: Vec<_> = movies.into_iter().map(|ids| serde_json::json!({ "ids": ids })).collect();
+        let _: serde_json::Value = self.request(Method::POST, &path, Some(serde_json::json!({ "movies": movies }))).await?;
+        Ok(())
+    }
+}
+
+async fn json<T: serde::de::DeserializeOwned>(resp: reqwest::Response) -> Result<T, Error> {
+    resp.json().await.map_err(|e| Error::Trakt(e.to_string()))
+}
+
+/// Syncs every configured list. A failing list is reported and the others still synced.
+pub(crate) async fn sync(state: &AppState, trakt: &Trakt, actor: &str) -> Result<Vec<ListReport>, Error> {
+    let _syncing = trakt.syncing.lock().await;
+    let mut reports = Vec::with_capacity(trakt.lists.len());
+    for list in &trakt.lists {
+        let report = match sync_list(state, trakt, list, actor).await {
+            Ok(report) => {
+                let action = audit::Action::TraktListSynced {
+                    list: report.list.clone(),
+                    tag: report.tag.clone(),
+                    tagged: report.tagged.len(),
+                    added: report.added,
+                    removed: report.removed,
+                };
+                state.audit.record(actor, action).await?;
+                report
+            }
+            Err(e) => {
+                tracing::warn!("failed to sync Trakt list {}: {}", list.list, e);
+                ListReport { list: list.list.clone(), tag: list.tag.clone(), error: Some(e.to_string()), ..ListReport::default() }
+            }
+        };
+        reports.push(report);
+    }
+    *trakt.last_sync.write().await = Some(LastSync { finished: Utc::now(), lists: reports.clone() });
+    Ok(reports)
+}
+
+#[tracing::instrument(skip_all, fields(list = %list.list, tag = %list.tag))]
+async fn sync_list(state: &AppState, trakt: &Trakt, list: &TraktListConfig, actor: &str) -> Result<ListReport, Error> {
+    let items = trakt.list_movies(&list.list).await?;
+    let mut report = ListReport { list: list.list.clone(), ..ListReport::default() };
+
+    let (tag, on_list) = {
+        let collection = state.collection.read().await;
+        let tag = collection.resolve_tag(&list.tag).unwrap_or(&list.tag).to_string();
+        let matcher = Matcher::new(&collection);
+        let mut by_id = HashMap::new();
+        for movie in collection.movies.values() {
+            let Some(metadata) = &movie.metadata else {
+                continue;
+            };
+            if let Some(imdb) = &metadata.imdb_id {
+                by_id.insert(imdb.clone(), movie.hash);
+            }
+            if let Some(tmdb) = metadata.tmdb_id {
+                by_id.insert(format!("tmdb:{}", tmdb), movie.hash);
+            }
+        }
+        // list items by the movie they are, IDs being more reliable than titles
+        let mut on_list: HashMap<PathnameHash, Ids> = HashMap::new();
+        for item in items {
+            let by_ids = item
+                .ids
+                .imdb
+                .as_ref()
+                .and_then(|imdb| by_id.get(imdb))
+                .or_else(|| item.ids.tmdb.and_then(|tmdb| by_id.get(&format!("tmdb:{}", tmdb))))
+                .copied();
+            match by_ids.or_else(|| matcher.find(&item.title, item.year).map(|movie| movie.hash)) {
+                Some(hash) => {
+                    on_list.insert(hash, item.ids);
+                }
+                None => report.unmatched.push(match item.year {
+                    Some(year) => format!("{} ({})", item.title, year),
+                    None => item.title,
+                }),
+            }
+        }
+        (tag, on_list)
+    };
+    report.tag = tag.clone();
+
+    if list.sync.pull() {
+        let changes = {
+            let collection = state.collection.read().await;
+            let tagged = collection.tags.get(&tag).map(|tag| &tag.movies);
+            on_list
+                .keys()
+                .filter(|hash| !tagged.is_some_and(|movies| movies.contains(hash)))
+                .filter_map(|hash| collection.movies.get(hash))
+                .map(|movie| Change { tag: tag.clone(), movie_name: movie.name.clone(), movie: movie.hash })
+                .collect()
+        };
+        report.tagged = state.apply_changes(actor, changes).await?.into_iter().map(|change| change.movie_name).collect();
+        report.tagged.sort();
+    }
+
+    if list.sync.push() {
+        let (add, remove) = {
+            let collection = state.collection.read().await;
+            let tagged: HashSet<PathnameHash> = collection.tags.get(&tag).map(|tag| tag.movies.clone()).unwrap_or_default();
+            let mut add = Vec::new();
+            for movie in tagged.iter().filter(|hash| !on_list.contains_key(hash)).filter_map(|hash| collection.movies.get(hash)) {
+                let metadata = movie.metadata.as_ref();
+                let ids = Ids {
+                    trakt: None,
+                    imdb: metadata.and_then(|m| m.imdb_id.clone()),
+                    tmdb: metadata.and_then(|m| m.tmdb_id),
+                };
+                if ids.imdb.is_none() && ids.tmdb.is_none() {
+                    report.without_ids.push(movie.name.clone());
+                } else {
+                    add.push(ids);
+                }
+            }
+            // syncing both ways only adds, since without remembering earlier syncs a movie untagged
+            // in tagrs can't be told from one just put on the list. Movies not in the collection
+            // always stay on the list.
+            let remove: Vec<Ids> = if list.sync.pull() {
+                Vec::new()
+            } else {
+                on_list.iter().filter(|(hash, _)| !tagged.contains(hash)).map(|(_, ids)| ids.clone()).collect()
+            };
+            (add, remove)
+        };
+        report.without_ids.sort();
+        report.added = add.len();
+        report.removed = remove.len();
+        trakt.change_list(&list.list, add, false).await?;
+        trakt.change_list(&list.list, remove, true).await?;
+    }
+    Ok(report)
+}
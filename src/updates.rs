@@ -0,0 +1,56 @@
+use std::convert::Infallible;
+
+use axum::response::sse::{Event, KeepAlive, Sse};
+use futures::Stream;
+use tokio::sync::broadcast;
+
+/// Tells open pages about changes made behind their back, as server-sent events at `/updates`.
+#[derive(Debug, Clone)]
+pub struct Updates {
+    tx: broadcast::Sender<Update>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Update {
+    /// Movies or tags were reloaded
+    Collection,
+    /// A change was recorded in the audit log
+    Activity,
+}
+
+impl Update {
+    fn as_str(self) -> &'static str {
+        match self {
+            Update::Collection => "collection",
+            Update::Activity => "activity",
+        }
+    }
+}
+
+impl Default for Updates {
+    fn default() -> Self {
+        Self { tx: broadcast::channel(16).0 }
+    }
+}
+
+impl Updates {
+    pub(crate) fn notify(&self, update: Update) {
+        // nobody listening is fine
+        let _ = self.tx.send(update);
+    }
+
+    /// Event stream for one page. Pages that fell behind only miss repeats of the same news.
+    pub(crate) fn subscribe(&self) -> Sse<impl Stream<Item = Result<Event, Infallible>>> {
+        let rx = self.tx.subscribe();
+        let stream = futures::stream::unfold(rx, |mut rx| async move {
+            loop {
+                match rx.recv().await {
+                    Ok(update) => return Some((Ok(Event::default().event(update.as_str()).data("")), rx)),
+                    Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                    Err(broadcast::error::RecvError::Closed) => return None,
+                }
+            }
+        });
+        Sse::new(stream).keep_alive(KeepAlive::default())
+    }
+}
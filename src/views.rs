@@ -0,0 +1,275 @@
+use std::cmp::Ordering;
+
+use serde::Deserialize;
+
+use crate::collection::{Collection, Movie};
+use crate::media_info::Resolution;
+
+/// A named movie list defined by a filter expression, shown at `/view/:name`.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "ViewConfig")]
+pub struct View {
+    pub(crate) name: String,
+    pub(crate) filter: Filter,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ViewConfig {
+    name: String,
+    filter: Filter,
+}
+
+impl TryFrom<ViewConfig> for View {
+    type Error = String;
+
+    fn try_from(config: ViewConfig) -> Result<Self, Self::Error> {
+        if config.name.is_empty() || config.name.contains('/') {
+            return Err(format!("invalid view name: {:?}", config.name));
+        }
+        Ok(View { name: config.name, filter: config.filter })
+    }
+}
+
+/// A filter expression selecting movies, used by views and tagging rules.
+///
+/// Expressions combine terms with `and`, `or`, `not` and parentheses, where adjacent terms are
+/// joined with `and`:
+///
+/// - `tag:kids`, `tag:"genre/sci fi"`: carries the tag
+/// - `group:genre`: carries any tag of the group
+/// - `untagged`: carries no tag
+/// - `poster`: has poster artwork in its folder
+/// - `resolution:4k`: probed resolution, one of `sd`, `720p`, `1080p` and `4k`
+/// - `year:1999`, `year<1990`, `year>=2000`: release year, which movies without one never match
+/// - `title:alien`: title contains the text, ignoring case
+/// - `genre:animation`: one of the `.nfo` genres, ignoring case
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "String")]
+pub struct Filter {
+    source: String,
+    expr: Expr,
+}
+
+impl TryFrom<String> for Filter {
+    type Error = String;
+
+    fn try_from(source: String) -> Result<Self, Self::Error> {
+        let expr = parse(&source).map_err(|e| format!("invalid filter {:?}: {}", source, e))?;
+        Ok(Filter { source, expr })
+    }
+}
+
+impl Filter {
+    /// The expression as written in the config.
+    pub(crate) fn as_str(&self) -> &str {
+        &self.source
+    }
+
+    /// Whether `movie` matches. `resolution` is the movie's probed resolution.
+    pub(crate) fn matches(&self, collection: &Collection, movie: &Movie, resolution: Option<Resolution>) -> bool {
+        self.expr.matches(collection, movie, resolution)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Expr {
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+    Not(Box<Expr>),
+    Tag(String),
+    Group(String),
+    Untagged,
+    Poster,
+    Resolution(Resolution),
+    Year(Vec<Ordering>, u16),
+    Rating(Vec<Ordering>, u8),
+    Title(String),
+    Genre(String),
+    Note(String),
+}
+
+impl Expr {
+    fn matches(&self, collection: &Collection, movie: &Movie, resolution: Option<Resolution>) -> bool {
+        let tagged = |tag: &crate::collection::Tag| tag.movies.contains(&movie.hash);
+        match self {
+            Expr::And(a, b) => a.matches(collection, movie, resolution) && b.matches(collection, movie, resolution),
+            Expr::Or(a, b) => a.matches(collection, movie, resolution) || b.matches(collection, movie, resolution),
+            Expr::Not(a) => !a.matches(collection, movie, resolution),
+            Expr::Tag(name) => collection.resolve_tag(name).and_then(|key| collection.tags.get(key)).is_some_and(tagged),
+            Expr::Group(name) => collection.tags.values().any(|tag| tag.group.as_ref() == Some(name) && tagged(tag)),
+            Expr::Untagged => !collection.tags.values().any(tagged),
+            Expr::Poster => movie.poster_path.is_some(),
+            Expr::Resolution(wanted) => resolution == Some(*wanted),
+            Expr::Year(orderings, year) => movie.year().is_some_and(|y| orderings.contains(&y.cmp(year))),
+            Expr::Rating(orderings, rating) => movie.rating.is_some_and(|r| orderings.contains(&r.cmp(rating))),
+            Expr::Title(text) => movie.title().to_lowercase().contains(text),
+            Expr::Note(text) => movie.note.as_ref().is_some_and(|note| note.to_lowercase().contains(text)),
+            Expr::Genre(genre) => movie
+                .metadata
+                .as_ref()
+                .is_some_and(|m| m.genres.iter().any(|g| g.to_lowercase() == *genre)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    Open,
+    Close,
+    Word(String),
+}
+
+/// Splits an expression into parentheses and words. Double quotes keep spaces and parentheses
+/// inside a word.
+fn tokenize(input: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut word = String::new();
+    let mut quoted = false;
+    for c in input.chars() {
+        match c {
+            '"' => quoted = !quoted,
+            _ if quoted => word.push(c),
+            '(' | ')' | ' ' | '\t' | '\n' => {
+                if !word.is_empty() {
+                    tokens.push(Token::Word(std::mem::take(&mut word)));
+                }
+                match c {
+                    '(' => tokens.push(Token::Open),
+                    ')' => tokens.push(Token::Close),
+                    _ => {}
+                }
+            }
+            _ => word.push(c),
+        }
+    }
+    if quoted {
+        return Err("unterminated quote".to_string());
+    }
+    if !word.is_empty() {
+        tokens.push(Token::Word(word));
+    }
+    Ok(tokens)
+}
+
+fn parse(input: &str) -> Result<Expr, String> {
+    let tokens = tokenize(input)?;
+    let mut parser = Parser { tokens: &tokens, pos: 0 };
+    let expr = parser.or()?;
+    match parser.peek() {
+        None => Ok(expr),
+        Some(token) => Err(format!("unexpected {:?}", token)),
+    }
+}
+
+/// Recursive descent parser, `or` binding weaker than `and` binding weaker than `not`.
+struct Parser<'a> {
+    tokens: &'a [Token],
+    pos: usize,
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<&Token> {
+        self.pos += 1;
+        self.tokens.get(self.pos - 1)
+    }
+
+    fn is_keyword(&self, keyword: &str) -> bool {
+        matches!(self.peek(), Some(Token::Word(word)) if word.eq_ignore_ascii_case(keyword))
+    }
+
+    fn or(&mut self) -> Result<Expr, String> {
+        let mut expr = self.and()?;
+        while self.is_keyword("or") {
+            self.pos += 1;
+            expr = Expr::Or(Box::new(expr), Box::new(self.and()?));
+        }
+        Ok(expr)
+    }
+
+    fn and(&mut self) -> Result<Expr, String> {
+        let mut expr = self.unary()?;
+        loop {
+            if self.is_keyword("and") {
+                self.pos += 1;
+            } else if self.is_keyword("or") || matches!(self.peek(), None | Some(Token::Close)) {
+                return Ok(expr);
+            }
+            expr = Expr::And(Box::new(expr), Box::new(self.unary()?));
+        }
+    }
+
+    fn unary(&mut self) -> Result<Expr, String> {
+        if self.is_keyword("not") {
+            self.pos += 1;
+            return Ok(Expr::Not(Box::new(self.unary()?)));
+        }
+        match self.next() {
+            Some(Token::Open) => {
+                let expr = self.or()?;
+                match self.next() {
+                    Some(Token::Close) => Ok(expr),
+                    _ => Err("missing )".to_string()),
+                }
+            }
+            Some(Token::Word(word)) => term(word),
+            Some(Token::Close) => Err("unexpected )".to_string()),
+            None => Err("unexpected end of filter".to_string()),
+        }
+    }
+}
+
+/// Parses a single term like `untagged`, `tag:kids` or `year>=2000`.
+fn term(word: &str) -> Result<Expr, String> {
+    let key_len = word.find(|c: char| !c.is_ascii_alphabetic()).unwrap_or(word.len());
+    let (key, rest) = word.split_at(key_len);
+    let key = key.to_ascii_lowercase();
+    if rest.is_empty() {
+        return match key.as_str() {
+            "untagged" => Ok(Expr::Untagged),
+            "poster" => Ok(Expr::Poster),
+            _ => Err(format!("unknown term {}", word)),
+        };
+    }
+    let (orderings, value) = [
+        ("<=", vec![Ordering::Less, Ordering::Equal]),
+        (">=", vec![Ordering::Greater, Ordering::Equal]),
+        ("<", vec![Ordering::Less]),
+        (">", vec![Ordering::Greater]),
+        (":", vec![Ordering::Equal]),
+        ("=", vec![Ordering::Equal]),
+    ]
+    .into_iter()
+    .find_map(|(op, orderings)| Some((orderings, rest.strip_prefix(op)?)))
+    .ok_or_else(|| format!("missing operator in {}", word))?;
+    if value.is_empty() {
+        return Err(format!("missing value in {}", word));
+    }
+    let equality = orderings == [Ordering::Equal];
+    match key.as_str() {
+        "year" => {
+            let year = value.parse().map_err(|_| format!("invalid year in {}", word))?;
+            Ok(Expr::Year(orderings, year))
+        }
+        "rating" => {
+            let rating = value.parse().map_err(|_| format!("invalid rating in {}", word))?;
+            Ok(Expr::Rating(orderings, rating))
+        }
+        _ if !equality => Err(format!("{} can only be compared with :", key)),
+        "tag" => Ok(Expr::Tag(value.to_string())),
+        "group" => Ok(Expr::Group(value.to_string())),
+        "title" => Ok(Expr::Title(value.to_lowercase())),
+        "genre" => Ok(Expr::Genre(value.to_lowercase())),
+        "note" => Ok(Expr::Note(value.to_lowercase())),
+        "resolution" => Resolution::ALL
+            .into_iter()
+            .find(|resolution| resolution.as_str().eq_ignore_ascii_case(value))
+            .map(Expr::Resolution)
+            .ok_or_else(|| format!("unknown resolution {}", value)),
+        _ => Err(format!("unknown term {}", word)),
+    }
+}
@@ -0,0 +1,94 @@
+use std::collections::HashSet;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use tokio::sync::RwLock;
+
+use crate::collection::Error;
+use crate::jellyfin_api::JellyfinClient;
+
+/// Folders of the movies a user has played, as Jellyfin sees them.
+pub(crate) type PlayedFolders = HashSet<PathBuf>;
+
+/// Which movies one Jellyfin user has watched, for the watched badge and the unwatched filter.
+/// Fetched on first use and reused for a while, or until Jellyfin reports a playback.
+#[derive(Debug)]
+pub(crate) struct Watched {
+    api: Arc<JellyfinClient>,
+    /// Name of the user, or `None` for the first administrator
+    user: Option<String>,
+    ttl: Duration,
+    played: RwLock<Option<Cached>>,
+}
+
+#[derive(Debug)]
+enum Cached {
+    Played { at: Instant, played: Arc<PlayedFolders> },
+    Failed { at: Instant, error: String },
+}
+
+impl Cached {
+    /// The played movies, the recent failure, or `None` once it's time to ask Jellyfin again.
+    fn get(&self, ttl: Duration) -> Option<Result<Arc<PlayedFolders>, Error>> {
+        match self {
+            Cached::Played { at, played } if at.elapsed() < ttl => Some(Ok(played.clone())),
+            Cached::Failed { at, error } if at.elapsed() < Watched::RETRY_AFTER => Some(Err(Error::Jellyfin(error.clone()))),
+            _ => None,
+        }
+    }
+}
+
+impl Watched {
+    /// How long a failed fetch is reported instead of asking Jellyfin again
+    const RETRY_AFTER: Duration = Duration::from_secs(30);
+
+    pub(crate) fn new(api: Arc<JellyfinClient>, user: Option<String>, ttl: Duration) -> Self {
+        Self { api, user, ttl, played: RwLock::new(None) }
+    }
+
+    pub(crate) async fn played(&self) -> Result<Arc<PlayedFolders>, Error> {
+        if let Some(result) = self.played.read().await.as_ref().and_then(|cached| cached.get(self.ttl)) {
+            return result;
+        }
+        let mut cached = self.played.write().await;
+        // another request may have fetched them while this one waited for the lock
+        if let Some(result) = cached.as_ref().and_then(|cached| cached.get(self.ttl)) {
+            return result;
+        }
+        let played = self.fetch().await;
+        *cached = Some(match &played {
+            Ok(played) => Cached::Played { at: Instant::now(), played: played.clone() },
+            Err(Error::Jellyfin(error)) => Cached::Failed { at: Instant::now(), error: error.clone() },
+            Err(e) => Cached::Failed { at: Instant::now(), error: e.to_string() },
+        });
+        played
+    }
+
+    async fn fetch(&self) -> Result<Arc<PlayedFolders>, Error> {
+        let users = self.api.get_users().await?;
+        let user = match &self.user {
+            Some(name) => users.iter().find(|user| user.name.eq_ignore_ascii_case(name)),
+            None => users.iter().find(|user| user.is_admin() && !user.is_disabled()),
+        };
+        let Some(user) = user else {
+            return Err(Error::Jellyfin(match &self.user {
+                Some(name) => format!("no Jellyfin user named {}", name),
+                None => "no Jellyfin administrator to take watched movies from".to_string(),
+            }));
+        };
+        let played: PlayedFolders = self
+            .api
+            .get_played_movies(&user.id)
+            .await?
+            .iter()
+            .filter(|item| item.user_data.as_ref().is_some_and(|data| data.played || data.play_count > 0))
+            .filter_map(|item| Some(item.folder()?.to_path_buf()))
+            .collect();
+        Ok(Arc::new(played))
+    }
+
+    pub(crate) async fn invalidate(&self) {
+        *self.played.write().await = None;
+    }
+}
@@ -0,0 +1,98 @@
+use std::time::Duration;
+
+use chrono::Utc;
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use tokio::sync::mpsc;
+
+use crate::audit::{Action, Event};
+use crate::config::WebhookConfig;
+
+/// Attempts per event before it is dropped, waiting twice as long after each failure.
+const ATTEMPTS: u32 = 4;
+const FIRST_RETRY: Duration = Duration::from_secs(1);
+
+/// Posts events as JSON to the configured webhooks from background tasks, one per webhook so a
+/// slow endpoint doesn't hold up the others. Events reach each webhook in order.
+#[derive(Debug, Default)]
+pub struct Webhooks {
+    hooks: Vec<Hook>,
+}
+
+#[derive(Debug)]
+struct Hook {
+    events: Vec<String>,
+    tx: mpsc::UnboundedSender<Event>,
+}
+
+impl Webhooks {
+    pub async fn spawn(configs: Vec<WebhookConfig>) -> anyhow::Result<Self> {
+        let client = reqwest::Client::builder().timeout(Duration::from_secs(10)).build()?;
+        let mut hooks = Vec::new();
+        for config in configs {
+            let secret = config.secret().await?;
+            let (tx, mut rx) = mpsc::unbounded_channel::<Event>();
+            let client = client.clone();
+            let url = config.url.clone();
+            tokio::spawn(async move {
+                while let Some(event) = rx.recv().await {
+                    deliver(&client, &url, secret.as_deref(), &event).await;
+                }
+            });
+            hooks.push(Hook { events: config.events, tx });
+        }
+        Ok(Self { hooks })
+    }
+
+    /// Queues `action` by `actor` for every webhook subscribed to it.
+    pub(crate) fn send(&self, actor: &str, action: &Action) {
+        let name = action_name(action);
+        for hook in &self.hooks {
+            if !hook.events.is_empty() && !hook.events.contains(&name) {
+                continue;
+            }
+            let event = Event { timestamp: Utc::now(), actor: actor.to_string(), action: action.clone() };
+            if hook.tx.send(event).is_err() {
+                tracing::warn!("webhook task is no longer running");
+            }
+        }
+    }
+}
+
+/// The `action` field of the serialized action, like `tag_added`.
+fn action_name(action: &Action) -> String {
+    serde_json::to_value(action)
+        .ok()
+        .and_then(|value| value.get("action")?.as_str().map(str::to_string))
+        .unwrap_or_default()
+}
+
+async fn deliver(client: &reqwest::Client, url: &str, secret: Option<&str>, event: &Event) {
+    let body = match serde_json::to_vec(event) {
+        Ok(body) => body,
+        Err(e) => return tracing::warn!("failed to encode webhook event: {}", e),
+    };
+    let signature = secret.map(|secret| {
+        let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC takes keys of any length");
+        mac.update(&body);
+        format!("sha256={}", hex::encode(mac.finalize().into_bytes()))
+    });
+    let mut retry = FIRST_RETRY;
+    for attempt in 1..=ATTEMPTS {
+        let mut request = client.post(url).header("content-type", "application/json").body(body.clone());
+        if let Some(signature) = &signature {
+            request = request.header("x-tagrs-signature", signature);
+        }
+        match request.send().await.and_then(|resp| resp.error_for_status()) {
+            Ok(_) => return,
+            Err(e) if attempt == ATTEMPTS => {
+                tracing::warn!("giving up on webhook {} after {} attempts: {}", url, ATTEMPTS, e)
+            }
+            Err(e) => {
+                tracing::debug!("webhook {} failed, retrying in {:?}: {}", url, retry, e);
+                tokio::time::sleep(retry).await;
+                retry *= 2;
+            }
+        }
+    }
+}
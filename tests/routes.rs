@@ -0,0 +1,576 @@
+mod support;
+
+use axum::http::StatusCode;
+use serde_json::json;
+use std::time::Duration;
+use support::{json_request, memory_app, movie_id, request, send, Fixture, MockJellyfin};
+use tagrs::{router, AppState, AuditLog, Collection, JellyfinServers, Preset, Role, RolesConfig, Rule, ServerKind, WebhookConfig, Webhooks};
+use tower::ServiceExt;
+
+#[tokio::test]
+async fn toggle_tag_links_and_unlinks_the_movie() {
+    let fixture = Fixture::new(&["Heat (1995)", "Up (2009)"], &["kids"]);
+    let app = fixture.app(None).await;
+    let id = movie_id(&app, "Up (2009)").await;
+
+    let (status, body) = send(&app, request("POST", &format!("/movie/{}/tag/kids", id))).await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    assert!(fixture.is_tagged("Up (2009)", "kids"));
+    assert!(!fixture.is_tagged("Heat (1995)", "kids"));
+
+    let (status, _) = send(&app, request("POST", &format!("/movie/{}/tag/kids", id))).await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(!fixture.is_tagged("Up (2009)", "kids"));
+}
+
+#[tokio::test]
+async fn set_tag_conflicts_when_already_tagged() {
+    let fixture = Fixture::new(&["Up (2009)"], &["kids"]);
+    let app = fixture.app(None).await;
+    let id = movie_id(&app, "Up (2009)").await;
+
+    let (status, _) = send(&app, request("PUT", &format!("/movie/{}/tag/kids", id))).await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, _) = send(&app, request("PUT", &format!("/movie/{}/tag/kids", id))).await;
+    assert_eq!(status, StatusCode::CONFLICT);
+    assert!(fixture.is_tagged("Up (2009)", "kids"));
+}
+
+#[tokio::test]
+async fn toggle_tag_of_unknown_tag_is_not_found() {
+    let fixture = Fixture::new(&["Up (2009)"], &["kids"]);
+    let app = fixture.app(None).await;
+    let id = movie_id(&app, "Up (2009)").await;
+
+    let (status, _) = send(&app, request("POST", &format!("/movie/{}/tag/horror", id))).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn tag_aliases_resolve_to_the_first_tag_listing_them() {
+    let fixture = Fixture::new(&["Alien (1979)"], &["sci-fi", "space"]);
+    std::fs::write(fixture.tag_dir().join("sci-fi/.tagrs.toml"), r#"aliases = ["Science Fiction"]"#).unwrap();
+    std::fs::write(fixture.tag_dir().join("space/.tagrs.toml"), r#"aliases = ["science fiction", "Outer Space"]"#).unwrap();
+    let app = fixture.app(None).await;
+    let id = movie_id(&app, "Alien (1979)").await;
+
+    let (status, body) = send(&app, request("PUT", &format!("/movie/{}/tag/SCIENCE%20FICTION", id))).await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    assert!(fixture.is_tagged("Alien (1979)", "sci-fi"));
+    let (status, body) = send(&app, request("GET", "/api/v1/tags")).await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    let tags: serde_json::Value = serde_json::from_str(&body).unwrap();
+    let space = tags.as_array().unwrap().iter().find(|t| t["name"] == "space").unwrap();
+    assert_eq!(space["aliases"], json!(["Outer Space"]));
+}
+
+#[tokio::test]
+async fn set_tags_replaces_the_tags_of_a_movie() {
+    let app = memory_app(&["Up (2009)"], &["kids", "genre/animation", "genre/horror"]).await;
+    let id = movie_id(&app, "Up (2009)").await;
+    let set = |tags: serde_json::Value| json_request("PUT", &format!("/movie/{}/tags", id), json!({ "tags": tags }));
+
+    let (status, body) = send(&app, set(json!(["kids", "genre/horror"]))).await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    let (status, body) = send(&app, set(json!(["genre/animation"]))).await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    let movie: serde_json::Value = serde_json::from_str(&body).unwrap();
+    assert_eq!(movie["tags"], json!(["genre/animation"]));
+
+    let (status, _) = send(&app, set(json!(["genre/comedy"]))).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn merge_tag_moves_movies_and_deletes_the_source() {
+    let fixture = Fixture::new(&["Alien (1979)", "Dune (2021)"], &["sci-fi", "scifi"]);
+    let app = fixture.app(None).await;
+    let alien = movie_id(&app, "Alien (1979)").await;
+    let dune = movie_id(&app, "Dune (2021)").await;
+    for uri in [format!("/movie/{}/tag/sci-fi", alien), format!("/movie/{}/tag/scifi", alien), format!("/movie/{}/tag/scifi", dune)] {
+        let (status, body) = send(&app, request("PUT", &uri)).await;
+        assert_eq!(status, StatusCode::OK, "{}", body);
+    }
+
+    let (status, body) = send(&app, request("POST", "/tag/scifi/merge/sci-fi")).await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    assert!(fixture.is_tagged("Alien (1979)", "sci-fi"));
+    assert!(fixture.is_tagged("Dune (2021)", "sci-fi"));
+    assert!(!fixture.tag_dir().join("scifi").exists());
+
+    let (status, _) = send(&app, request("POST", "/tag/sci-fi/merge/sci-fi")).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn tag_routes_accept_aliases() {
+    let fixture = Fixture::new(&["Alien (1979)"], &["sci-fi", "scifi", "space"]);
+    std::fs::write(fixture.tag_dir().join("sci-fi/.tagrs.toml"), r#"aliases = ["Science Fiction"]"#).unwrap();
+    std::fs::write(fixture.tag_dir().join("scifi/.tagrs.toml"), r#"aliases = ["SF"]"#).unwrap();
+    std::fs::write(fixture.tag_dir().join("space/.tagrs.toml"), r#"aliases = ["Outer Space"]"#).unwrap();
+    let app = fixture.app(None).await;
+    let alien = movie_id(&app, "Alien (1979)").await;
+    let (status, body) = send(&app, request("PUT", &format!("/movie/{}/tag/scifi", alien))).await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+
+    let (status, _) = send(&app, request("POST", "/tag/Science%20Fiction/merge/sci-fi")).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert!(fixture.tag_dir().join("sci-fi").exists());
+
+    let (status, body) = send(&app, request("POST", "/tag/SF/merge/Science%20Fiction")).await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    assert!(fixture.is_tagged("Alien (1979)", "sci-fi"));
+    assert!(!fixture.tag_dir().join("scifi").exists());
+
+    let (status, body) = send(&app, request("DELETE", "/tag/Outer%20Space")).await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    assert!(!fixture.tag_dir().join("space").exists());
+}
+
+#[tokio::test]
+async fn set_rating_rates_and_clears_a_movie() {
+    let app = memory_app(&["Up (2009)"], &[]).await;
+    let id = movie_id(&app, "Up (2009)").await;
+    let set = |rating: serde_json::Value| json_request("PUT", &format!("/movie/{}/rating", id), json!({ "rating": rating }));
+
+    let (status, body) = send(&app, set(json!(4))).await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    let movie: serde_json::Value = serde_json::from_str(&body).unwrap();
+    assert_eq!(movie["rating"], json!(4));
+
+    for rating in [0, 6] {
+        let (status, _) = send(&app, set(json!(rating))).await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY, "{}", rating);
+    }
+    let (_, body) = send(&app, request("GET", "/api/v1/movies")).await;
+    assert!(body.contains(r#""rating":4"#), "{}", body);
+
+    let (status, body) = send(&app, set(json!(null))).await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    let movie: serde_json::Value = serde_json::from_str(&body).unwrap();
+    assert_eq!(movie["rating"], json!(null));
+}
+
+#[tokio::test]
+async fn audited_actions_are_sent_to_webhooks() {
+    let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel::<serde_json::Value>();
+    let hook = axum::Router::new().route(
+        "/",
+        axum::routing::post(move |axum::Json(event): axum::Json<serde_json::Value>| async move {
+            tx.send(event).unwrap();
+        }),
+    );
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}/", listener.local_addr().unwrap());
+    tokio::spawn(async move { axum::serve(listener, hook).await.unwrap() });
+    let config = WebhookConfig { url, secret: None, secret_file: None, events: vec![] };
+    let audit = AuditLog::default().sending_to(Webhooks::spawn(vec![config]).await.unwrap());
+    let collection = Collection::in_memory(&["Up (2009)"], &[]).await.unwrap();
+    let app = router(AppState::new(collection, JellyfinServers::default()).with_audit_log(audit)).unwrap();
+    let id = movie_id(&app, "Up (2009)").await;
+
+    let (status, body) = send(&app, json_request("PUT", &format!("/movie/{}/rating", id), json!({ "rating": 4 }))).await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    let event = tokio::time::timeout(Duration::from_secs(5), rx.recv()).await.unwrap().unwrap();
+    assert_eq!(event["action"], "rating_changed");
+    assert_eq!(event["movie"], "Up (2009)");
+}
+
+#[tokio::test]
+async fn toggle_user_library_grants_and_revokes_a_library() {
+    let jellyfin = MockJellyfin::start().await;
+    let fixture = Fixture::new(&["Up (2009)"], &["kids"]);
+    let app = fixture.app(Some(&jellyfin)).await;
+
+    let (status, body) = send(&app, request("POST", "/user/u2/library/f2")).await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    assert_eq!(jellyfin.policy("u2")["EnabledFolders"], json!(["f1", "f2"]));
+
+    let (status, _) = send(&app, request("POST", "/user/u2/library/f1")).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(jellyfin.policy("u2")["EnabledFolders"], json!(["f2"]));
+    assert_eq!(jellyfin.policy("u2")["EnableAllFolders"], json!(false));
+}
+
+#[tokio::test]
+async fn toggle_user_library_keeps_changes_made_in_jellyfin() {
+    let jellyfin = MockJellyfin::start().await;
+    let fixture = Fixture::new(&["Up (2009)"], &["kids"]);
+    let state = fixture.state(Some(&jellyfin)).await.with_jellyfin_cache_ttl(Duration::from_secs(60));
+    let app = router(state).unwrap();
+    let (status, _) = send(&app, request("GET", "/user-libraries")).await;
+    assert_eq!(status, StatusCode::OK);
+
+    jellyfin.set_enabled_folders("u2", &[]);
+    let (status, body) = send(&app, request("POST", "/user/u2/library/f2")).await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    assert_eq!(jellyfin.policy("u2")["EnabledFolders"], json!(["f2"]));
+}
+
+#[tokio::test]
+async fn apply_preset_with_unknown_libraries_is_rejected() {
+    let jellyfin = MockJellyfin::start().await;
+    let fixture = Fixture::new(&["Up (2009)"], &["kids"]);
+    let preset = Preset { name: "family".to_string(), folders: vec!["kids".to_string(), "Movis".to_string()] };
+    let app = router(fixture.state(Some(&jellyfin)).await.with_presets(vec![preset])).unwrap();
+    let apply = axum::http::Request::builder()
+        .method("POST")
+        .uri("/user/u2/preset")
+        .header("content-type", "application/x-www-form-urlencoded")
+        .body(axum::body::Body::from("preset=family"))
+        .unwrap();
+
+    let (status, body) = send(&app, apply).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert!(body.contains("Movis"), "{}", body);
+    assert_eq!(jellyfin.policy("u2")["EnabledFolders"], json!(["f1"]));
+}
+
+#[tokio::test]
+async fn request_id_is_returned_and_passed_to_jellyfin() {
+    let jellyfin = MockJellyfin::start().await;
+    let fixture = Fixture::new(&["Up (2009)"], &["kids"]);
+    let app = fixture.app(Some(&jellyfin)).await;
+
+    let mut proxied = request("POST", "/user/u2/library/f2");
+    proxied.headers_mut().insert("x-request-id", "proxy-42".parse().unwrap());
+    let response = app.clone().oneshot(proxied).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()["x-request-id"], "proxy-42");
+    assert!(jellyfin.received().iter().all(|r| r.request_id.as_deref() == Some("proxy-42")));
+
+    let response = app.clone().oneshot(request("GET", "/tags")).await.unwrap();
+    assert!(!response.headers()["x-request-id"].is_empty());
+}
+
+#[tokio::test]
+async fn toggle_user_library_restricts_a_user_seeing_everything() {
+    let jellyfin = MockJellyfin::start().await;
+    let fixture = Fixture::new(&["Up (2009)"], &["kids"]);
+    let app = fixture.app(Some(&jellyfin)).await;
+
+    let (status, body) = send(&app, request("POST", "/user/u1/library/f1")).await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    assert_eq!(jellyfin.policy("u1")["EnableAllFolders"], json!(false));
+    assert_eq!(jellyfin.policy("u1")["EnabledFolders"], json!(["f2"]));
+    let posted: Vec<_> = jellyfin.received().into_iter().filter(|r| r.method == "POST").collect();
+    assert_eq!(posted.len(), 1);
+    assert_eq!(posted[0].path, "/Users/u1/Policy");
+}
+
+#[tokio::test]
+async fn emby_servers_are_asked_below_emby_with_their_token_header() {
+    let emby = MockJellyfin::start().await;
+    let fixture = Fixture::new(&["Up (2009)"], &["kids"]);
+    let app = router(fixture.state_of_kind(Some(&emby), ServerKind::Emby).await).unwrap();
+
+    let (status, body) = send(&app, request("POST", "/user/u2/library/f2")).await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    assert_eq!(emby.policy("u2")["EnabledFolders"], json!(["f1", "f2"]));
+    let received = emby.received();
+    assert!(received.iter().all(|r| r.path.starts_with("/emby/") && r.emby_token.as_deref() == Some("test-key")));
+}
+
+#[tokio::test]
+async fn toggle_user_library_of_unknown_user_is_not_found() {
+    let jellyfin = MockJellyfin::start().await;
+    let fixture = Fixture::new(&[], &[]);
+    let app = fixture.app(Some(&jellyfin)).await;
+
+    let (status, _) = send(&app, request("POST", "/user/nobody/library/f1")).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn activity_is_only_shown_to_admins() {
+    let fixture = Fixture::new(&["Up (2009)"], &["kids"]);
+    let roles = RolesConfig { default: Role::Viewer, ..RolesConfig::default() };
+    let app = router(fixture.state(None).await.with_roles(roles)).unwrap();
+
+    for uri in ["/activity", "/activity/feed", "/audit"] {
+        let (status, _) = send(&app, request("GET", uri)).await;
+        assert_eq!(status, StatusCode::FORBIDDEN, "{}", uri);
+    }
+    let (status, _) = send(&app, request("GET", "/tags")).await;
+    assert_eq!(status, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn remote_user_is_only_believed_when_trusted() {
+    let fixture = Fixture::new(&["Up (2009)"], &["kids"]);
+    let roles = RolesConfig { default: Role::Viewer, users: [("alice".to_string(), Role::Admin)].into(), ..RolesConfig::default() };
+    let as_alice = || {
+        let mut request = request("GET", "/audit");
+        request.headers_mut().insert("Remote-User", "alice".parse().unwrap());
+        request
+    };
+
+    let app = router(fixture.state(None).await.with_roles(roles.clone())).unwrap();
+    let (status, _) = send(&app, as_alice()).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+
+    let state = fixture.state(None).await.with_roles(roles).with_user_header("Remote-User".parse().unwrap());
+    let (status, _) = send(&router(state).unwrap(), as_alice()).await;
+    assert_eq!(status, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn merging_is_only_offered_to_admins_between_listed_tags() {
+    let fixture = Fixture::new(&["Up (2009)"], &["kids", "family", "secret"]);
+    std::fs::write(fixture.tag_dir().join("secret").join(".tagrs.toml"), "hidden = true\n").unwrap();
+
+    let (_, body) = send(&fixture.app(None).await, request("GET", "/tags")).await;
+    assert!(body.contains("merge-tags"), "{}", body);
+    assert!(body.contains(r#"value="family""#) && !body.contains(r#"value="secret""#), "{}", body);
+
+    let roles = RolesConfig { default: Role::Tagger, ..RolesConfig::default() };
+    let app = router(fixture.state(None).await.with_roles(roles)).unwrap();
+    let (_, body) = send(&app, request("GET", "/tags")).await;
+    assert!(!body.contains("merge-tags"), "{}", body);
+}
+
+#[tokio::test]
+async fn snapshots_are_neither_linked_nor_open_to_taggers() {
+    let fixture = Fixture::new(&["Up (2009)"], &["kids"]);
+    let roles = RolesConfig { default: Role::Tagger, ..RolesConfig::default() };
+    let app = router(fixture.state(None).await.with_roles(roles)).unwrap();
+
+    for (method, uri) in [("GET", "/snapshots"), ("POST", "/snapshots")] {
+        let (status, _) = send(&app, request(method, uri)).await;
+        assert_eq!(status, StatusCode::FORBIDDEN, "{} {}", method, uri);
+    }
+    let (status, body) = send(&app, request("GET", "/tags")).await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(!body.contains(r#"href="/snapshots""#) && !body.contains(r#"href="/audit""#), "{}", body);
+}
+
+#[tokio::test]
+async fn open_in_jellyfin_looks_up_items_once_per_cache_period() {
+    let jellyfin = MockJellyfin::start().await;
+    let fixture = Fixture::new(&["Up (2009)"], &[]);
+    jellyfin.add_movie("item-up", &fixture.movie_dir().canonicalize().unwrap().join("Up (2009)/movie.mkv"));
+    let state = fixture.state(Some(&jellyfin)).await.with_jellyfin_cache_ttl(Duration::from_secs(60));
+    let app = router(state).unwrap();
+    let id = movie_id(&app, "Up (2009)").await;
+
+    for _ in 0..2 {
+        let response = app.clone().oneshot(request("GET", &format!("/movie/{}/jellyfin", id))).await.unwrap();
+        assert!(response.status().is_redirection(), "{}", response.status());
+        assert!(response.headers()["location"].to_str().unwrap().ends_with("item-up"));
+    }
+    let fetched = jellyfin.received().into_iter().filter(|r| r.path == "/Items").count();
+    assert_eq!(fetched, 1);
+}
+
+#[tokio::test]
+async fn jellyfin_artwork_finds_items_in_the_same_cache() {
+    let jellyfin = MockJellyfin::start().await;
+    let fixture = Fixture::new(&["Up (2009)"], &[]);
+    jellyfin.add_movie("item-up", &fixture.movie_dir().canonicalize().unwrap().join("Up (2009)/movie.mkv"));
+    let state = fixture.state(Some(&jellyfin)).await.with_jellyfin_cache_ttl(Duration::from_secs(60));
+    let app = router(state).unwrap();
+    let id = movie_id(&app, "Up (2009)").await;
+
+    let response = app.clone().oneshot(request("GET", &format!("/movie/{}/jellyfin", id))).await.unwrap();
+    assert!(response.status().is_redirection(), "{}", response.status());
+    send(&app, request("GET", &format!("/movie/{}/poster.jpg", id))).await;
+
+    let received = jellyfin.received();
+    assert_eq!(received.iter().filter(|r| r.path == "/Items").count(), 1);
+    assert!(received.iter().any(|r| r.path == "/Items/item-up/Images/Primary"), "{:?}", received);
+}
+
+#[tokio::test]
+async fn jellyfin_routes_are_missing_without_a_server() {
+    let fixture = Fixture::new(&["Up (2009)"], &["kids"]);
+    let app = fixture.app(None).await;
+
+    let (status, _) = send(&app, request("POST", "/user/u2/library/f1")).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn disk_usage_is_served_without_jellyfin() {
+    let fixture = Fixture::new(&["Up (2009)"], &["kids"]);
+    let app = fixture.app(None).await;
+
+    let (status, body) = send(&app, request("GET", "/stats/disk")).await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+}
+
+#[tokio::test]
+async fn undecodable_posters_are_served_unscaled() {
+    let fixture = Fixture::new(&["Up (2009)"], &[]);
+    std::fs::write(fixture.movie_dir().join("Up (2009)/poster.jpg"), b"not a jpeg").unwrap();
+    let app = fixture.app(None).await;
+    let id = movie_id(&app, "Up (2009)").await;
+
+    let (status, body) = send(&app, request("GET", &format!("/movie/{}/poster.jpg?w=200", id))).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body, "not a jpeg");
+}
+
+#[tokio::test]
+async fn out_of_range_page_sizes_are_not_used() {
+    let app = memory_app(&["Heat (1995)", "Up (2009)"], &[]).await;
+
+    let response = app.clone().oneshot(request("GET", "/?per_page=0")).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let cookie = response.headers()["set-cookie"].to_str().unwrap();
+    assert!(cookie.starts_with("list_view=per_page=1&"), "{}", cookie);
+
+    let (status, _) = send(&app, request("GET", &format!("/?per_page={}&page={}", usize::MAX, usize::MAX))).await;
+    assert_eq!(status, StatusCode::OK);
+
+    let mut remembered = request("GET", "/");
+    remembered.headers_mut().insert("cookie", "list_view=per_page%3D0".parse().unwrap());
+    let (status, _) = send(&app, remembered).await;
+    assert_eq!(status, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn list_preferences_are_only_read_back_when_signed() {
+    let app = memory_app(&["Heat (1995)", "Up (2009)"], &[]).await;
+    let remembered = |cookie: &str| {
+        let mut request = request("GET", "/");
+        request.headers_mut().insert("cookie", cookie.parse().unwrap());
+        request
+    };
+    let set_cookie = |response: &axum::response::Response| {
+        let cookie = response.headers()["set-cookie"].to_str().unwrap();
+        cookie.split(';').next().unwrap().to_string()
+    };
+
+    let response = app.clone().oneshot(request("GET", "/?per_page=10")).await.unwrap();
+    let cookie = set_cookie(&response);
+    let response = app.clone().oneshot(remembered(&cookie)).await.unwrap();
+    assert!(set_cookie(&response).starts_with("list_view=per_page=10&"), "{}", set_cookie(&response));
+
+    let forged = cookie.replace("per_page=10", "per_page=25");
+    let response = app.clone().oneshot(remembered(&forged)).await.unwrap();
+    assert!(set_cookie(&response).starts_with("list_view=per_page=50&"), "{}", set_cookie(&response));
+}
+
+#[tokio::test]
+async fn restoring_a_snapshot_brings_back_the_tags_it_took() {
+    let fixture = Fixture::new(&["Heat (1995)", "Up (2009)"], &["kids"]);
+    let app = fixture.app(None).await;
+    let id = movie_id(&app, "Up (2009)").await;
+    let toggle = || request("POST", &format!("/movie/{}/tag/kids", id));
+
+    send(&app, toggle()).await;
+    let (status, _) = send(&app, request("POST", "/snapshots")).await;
+    assert_eq!(status, StatusCode::SEE_OTHER);
+    let (_, body) = send(&app, request("GET", "/snapshots")).await;
+    let restore = body.split(r#"action=""#).find_map(|rest| rest.split('"').next().filter(|action| action.ends_with("/restore")));
+    let restore = restore.unwrap_or_else(|| panic!("no snapshot to restore in {}", body)).to_string();
+
+    send(&app, toggle()).await;
+    assert!(!fixture.is_tagged("Up (2009)", "kids"));
+    let (status, body) = send(&app, request("POST", &restore)).await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    assert!(fixture.is_tagged("Up (2009)", "kids"));
+    assert!(!fixture.is_tagged("Heat (1995)", "kids"));
+}
+
+#[tokio::test]
+async fn rules_are_previewed_before_being_applied() {
+    let fixture = Fixture::new(&["Alien (1979)", "Up (2009)"], &[]);
+    let rule: Rule = serde_json::from_value(json!({ "filter": "title:alien", "tag": "classics" })).unwrap();
+    let app = router(fixture.state(None).await.with_rules(vec![rule])).unwrap();
+
+    let (status, body) = send(&app, request("GET", "/rules")).await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(body.contains("Alien (1979)") && !body.contains("Up (2009)"), "{}", body);
+    assert!(!fixture.is_tagged("Alien (1979)", "classics"));
+
+    let (status, body) = send(&app, request("POST", "/rules/run")).await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    assert!(fixture.is_tagged("Alien (1979)", "classics"));
+    assert!(!fixture.is_tagged("Up (2009)", "classics"));
+}
+
+#[tokio::test]
+async fn import_tags_the_listed_movies() {
+    let fixture = Fixture::new(&["Alien (1979)", "The Thing (1982)", "Up (2009)"], &[]);
+    let app = fixture.app(None).await;
+    let csv = "Date,Name,Year\n2024-01-01,Alien,1979\n2024-01-02,Thing,1982\n2024-01-03,Heat,1995\n";
+    let body = format!(
+        "--form\r\nContent-Disposition: form-data; name=\"tag\"\r\n\r\nwatchlist\r\n\
+         --form\r\nContent-Disposition: form-data; name=\"file\"; filename=\"watchlist.csv\"\r\nContent-Type: text/csv\r\n\r\n{}\r\n\
+         --form--\r\n",
+        csv
+    );
+    let upload = axum::http::Request::builder()
+        .method("POST")
+        .uri("/import/letterboxd")
+        .header("content-type", "multipart/form-data; boundary=form")
+        .body(axum::body::Body::from(body))
+        .unwrap();
+
+    let (status, body) = send(&app, upload).await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    assert!(fixture.is_tagged("Alien (1979)", "watchlist"));
+    assert!(fixture.is_tagged("The Thing (1982)", "watchlist"));
+    assert!(!fixture.is_tagged("Up (2009)", "watchlist"));
+    assert!(body.contains(&format!(r#"href="/movie/{}/details""#, movie_id(&app, "Alien (1979)").await)), "{}", body);
+    assert!(body.contains("Heat"), "{}", body);
+}
+
+#[tokio::test]
+async fn each_role_is_refused_what_the_next_may_do() {
+    let fixture = Fixture::new(&["Up (2009)"], &["kids"]);
+    let app_for = |role: Role| {
+        let fixture = &fixture;
+        async move {
+            let roles = RolesConfig { default: role, ..RolesConfig::default() };
+            router(fixture.state(None).await.with_roles(roles)).unwrap()
+        }
+    };
+    let viewer = app_for(Role::Viewer).await;
+    let tagger = app_for(Role::Tagger).await;
+    let id = movie_id(&viewer, "Up (2009)").await;
+    let tag = format!("/movie/{}/tag/kids", id);
+
+    let (status, _) = send(&viewer, request("GET", "/")).await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, _) = send(&viewer, request("POST", &tag)).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    assert!(!fixture.is_tagged("Up (2009)", "kids"));
+
+    let (status, _) = send(&tagger, request("POST", &tag)).await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, _) = send(&tagger, request("DELETE", "/tag/kids")).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    assert!(fixture.tag_dir().join("kids").exists());
+}
+
+#[tokio::test]
+async fn graphql_lists_the_movies_of_a_tag() {
+    let fixture = Fixture::new(&["Heat (1995)", "Up (2009)"], &["kids"]);
+    let app = fixture.app(None).await;
+    let id = movie_id(&app, "Up (2009)").await;
+    send(&app, request("POST", &format!("/movie/{}/tag/kids", id))).await;
+
+    let query = json!({ "query": r#"{ tag(name: "kids") { name movies { id name } } }"# });
+    let (status, body) = send(&app, json_request("POST", "/graphql", query)).await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    let response: serde_json::Value = serde_json::from_str(&body).unwrap();
+    assert_eq!(response["data"]["tag"], json!({ "name": "kids", "movies": [{ "id": id, "name": "Up (2009)" }] }), "{}", body);
+}
+
+#[tokio::test]
+async fn api_and_graphql_list_pinned_tags_first() {
+    let fixture = Fixture::new(&["Up (2009)"], &["drama", "kids", "new"]);
+    std::fs::write(fixture.tag_dir().join("new").join(".tagrs.toml"), "pinned = 1\n").unwrap();
+    let app = fixture.app(None).await;
+
+    let (_, body) = send(&app, request("GET", "/api/v1/tags")).await;
+    let tags: Vec<serde_json::Value> = serde_json::from_str(&body).unwrap();
+    let names: Vec<&str> = tags.iter().map(|tag| tag["name"].as_str().unwrap()).collect();
+    assert_eq!(names, ["new", "drama", "kids"]);
+
+    let (_, body) = send(&app, json_request("POST", "/graphql", json!({ "query": "{ tags { name } }" }))).await;
+    let response: serde_json::Value = serde_json::from_str(&body).unwrap();
+    assert_eq!(response["data"]["tags"], json!([{ "name": "new" }, { "name": "drama" }, { "name": "kids" }]), "{}", body);
+}
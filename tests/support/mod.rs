@@ -0,0 +1,268 @@
+//! Test support: a Jellyfin stand-in served in-process, and movie and tag directories to run
+//! tagrs against.
+
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+use axum::body::Body;
+use axum::extract::{Path as PathExtractor, State};
+use axum::http::{Request, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use serde_json::{json, Value};
+use tagrs::jellyfin_api::JellyfinClient;
+use tagrs::{router, AppState, Collection, JellyfinServers, ServerKind};
+use tempfile::TempDir;
+use tower::ServiceExt;
+
+/// A request received by the mock Jellyfin server.
+#[derive(Debug, Clone)]
+pub struct Received {
+    pub method: String,
+    pub path: String,
+    pub request_id: Option<String>,
+    /// The API key as Emby clients send it
+    pub emby_token: Option<String>,
+}
+
+#[derive(Debug, Default)]
+struct MockState {
+    users: Vec<Value>,
+    folders: Vec<Value>,
+    movies: Vec<Value>,
+    received: Vec<Received>,
+}
+
+/// The endpoints of Jellyfin that `JellyfinClient` uses for users and libraries, answered from
+/// memory on a local port.
+#[derive(Debug, Clone)]
+pub struct MockJellyfin {
+    url: String,
+    state: Arc<Mutex<MockState>>,
+}
+
+impl MockJellyfin {
+    /// Starts a server with an admin seeing every library and a user restricted to "kids".
+    pub async fn start() -> Self {
+        let state = Arc::new(Mutex::new(MockState {
+            users: vec![
+                user("u1", "alice", true, &[]),
+                user("u2", "kid", false, &["f1"]),
+            ],
+            folders: vec![
+                json!({ "Id": "f1", "Name": "kids", "Path": "/media/tags/kids", "CollectionType": "movies", "Etag": "" }),
+                json!({ "Id": "f2", "Name": "Movies", "Path": "/media/movies", "CollectionType": "movies", "Etag": "" }),
+            ],
+            movies: Vec::new(),
+            received: Vec::new(),
+        }));
+        let api = Router::new()
+            .route("/Users", get(users))
+            .route("/Users/:id", get(user_by_id))
+            .route("/Users/:id/Policy", post(set_policy))
+            .route("/Library/MediaFolders", get(media_folders))
+            .route("/Items", get(movies))
+            .route("/Library/Refresh", post(|| async { StatusCode::NO_CONTENT }));
+        // Emby serves the same API below /emby
+        let app = Router::new()
+            .merge(api.clone())
+            .nest("/emby", api)
+            .fallback(|| async { StatusCode::NOT_FOUND })
+            .layer(axum::middleware::from_fn_with_state(state.clone(), record))
+            .with_state(state.clone());
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        Self { url, state }
+    }
+
+    pub fn client(&self) -> JellyfinClient {
+        JellyfinClient::new(self.url.clone(), "test-key".to_string())
+    }
+
+    /// Requests received so far, oldest first.
+    pub fn received(&self) -> Vec<Received> {
+        self.state.lock().unwrap().received.clone()
+    }
+
+    /// The current policy of the user with `id`.
+    pub fn policy(&self, id: &str) -> Value {
+        let state = self.state.lock().unwrap();
+        let user = state.users.iter().find(|u| u["Id"] == id).expect("unknown user");
+        user["Policy"].clone()
+    }
+
+    /// Lists a movie item with `id` whose video file is at `path`.
+    pub fn add_movie(&self, id: &str, path: &std::path::Path) {
+        let movie = json!({ "Id": id, "Name": id, "Path": path });
+        self.state.lock().unwrap().movies.push(movie);
+    }
+
+    /// Changes the libraries of the user with `id` behind the back of tagrs, like an
+    /// administrator using Jellyfin directly.
+    pub fn set_enabled_folders(&self, id: &str, folders: &[&str]) {
+        let mut state = self.state.lock().unwrap();
+        let user = state.users.iter_mut().find(|u| u["Id"] == id).expect("unknown user");
+        user["Policy"]["EnabledFolders"] = json!(folders);
+    }
+}
+
+fn user(id: &str, name: &str, all_folders: bool, folders: &[&str]) -> Value {
+    json!({
+        "Id": id,
+        "Name": name,
+        "Policy": {
+            "IsAdministrator": all_folders,
+            "IsDisabled": false,
+            "EnableAllFolders": all_folders,
+            "EnabledFolders": folders,
+        },
+    })
+}
+
+async fn record(
+    State(state): State<Arc<Mutex<MockState>>>,
+    request: Request<Body>,
+    next: axum::middleware::Next,
+) -> Response {
+    let headers = request.headers();
+    let header = |name: &str| headers.get(name).and_then(|value| value.to_str().ok()).map(str::to_string);
+    let received = Received {
+        method: request.method().to_string(),
+        path: request.uri().path().to_string(),
+        request_id: header("x-request-id"),
+        emby_token: header("x-emby-token"),
+    };
+    state.lock().unwrap().received.push(received);
+    next.run(request).await
+}
+
+async fn users(State(state): State<Arc<Mutex<MockState>>>) -> Json<Vec<Value>> {
+    Json(state.lock().unwrap().users.clone())
+}
+
+async fn user_by_id(State(state): State<Arc<Mutex<MockState>>>, PathExtractor(id): PathExtractor<String>) -> Response {
+    match state.lock().unwrap().users.iter().find(|u| u["Id"] == id) {
+        Some(user) => Json(user.clone()).into_response(),
+        None => StatusCode::NOT_FOUND.into_response(),
+    }
+}
+
+async fn set_policy(
+    State(state): State<Arc<Mutex<MockState>>>,
+    PathExtractor(id): PathExtractor<String>,
+    Json(policy): Json<Value>,
+) -> StatusCode {
+    match state.lock().unwrap().users.iter_mut().find(|u| u["Id"] == id) {
+        Some(user) => {
+            user["Policy"] = policy;
+            StatusCode::NO_CONTENT
+        }
+        None => StatusCode::NOT_FOUND,
+    }
+}
+
+async fn media_folders(State(state): State<Arc<Mutex<MockState>>>) -> Json<Value> {
+    let folders = state.lock().unwrap().folders.clone();
+    Json(json!({ "Items": folders, "TotalRecordCount": folders.len(), "StartIndex": 0 }))
+}
+
+async fn movies(State(state): State<Arc<Mutex<MockState>>>) -> Json<Value> {
+    let movies = state.lock().unwrap().movies.clone();
+    Json(json!({ "Items": movies }))
+}
+
+/// Movie and tag directories in a temporary directory, removed when dropped.
+pub struct Fixture {
+    dir: TempDir,
+}
+
+impl Fixture {
+    /// Creates `movies` with a folder and video file each, and empty tag directories `tags`.
+    pub fn new(movies: &[&str], tags: &[&str]) -> Self {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir_all(dir.path().join("movies")).unwrap();
+        std::fs::create_dir_all(dir.path().join("tags")).unwrap();
+        for movie in movies {
+            let path = dir.path().join("movies").join(movie);
+            std::fs::create_dir_all(&path).unwrap();
+            std::fs::write(path.join("movie.mkv"), b"").unwrap();
+        }
+        for tag in tags {
+            std::fs::create_dir_all(dir.path().join("tags").join(tag)).unwrap();
+        }
+        Self { dir }
+    }
+
+    pub fn movie_dir(&self) -> PathBuf {
+        self.dir.path().join("movies")
+    }
+
+    pub fn tag_dir(&self) -> PathBuf {
+        self.dir.path().join("tags")
+    }
+
+    /// Whether `movie` is linked into `tag`.
+    pub fn is_tagged(&self, movie: &str, tag: &str) -> bool {
+        self.tag_dir().join(tag).join(movie).exists()
+    }
+
+    /// The router of tagrs serving this collection, using `jellyfin` if given.
+    pub async fn app(&self, jellyfin: Option<&MockJellyfin>) -> Router {
+        router(self.state(jellyfin).await).unwrap()
+    }
+
+    /// The state of tagrs serving this collection, for tests configuring it further.
+    pub async fn state(&self, jellyfin: Option<&MockJellyfin>) -> AppState {
+        self.state_of_kind(jellyfin, ServerKind::Jellyfin).await
+    }
+
+    /// The state of tagrs serving this collection, talking to `server` as a server of `kind`.
+    pub async fn state_of_kind(&self, server: Option<&MockJellyfin>, kind: ServerKind) -> AppState {
+        let collection = Collection::new(self.movie_dir(), self.tag_dir()).await.unwrap();
+        let mut servers = JellyfinServers::default();
+        if let Some(server) = server {
+            servers.insert("default".to_string(), kind, server.client()).unwrap();
+        }
+        AppState::new(collection, servers)
+    }
+}
+
+/// The router of tagrs serving a collection held in memory only, without Jellyfin.
+pub async fn memory_app(movies: &[&str], tags: &[&str]) -> Router {
+    let collection = Collection::in_memory(movies, tags).await.unwrap();
+    router(AppState::new(collection, JellyfinServers::default())).unwrap()
+}
+
+/// Sends `request` to `app`, returning the status and body.
+pub async fn send(app: &Router, request: Request<Body>) -> (StatusCode, String) {
+    let response = app.clone().oneshot(request).await.unwrap();
+    let status = response.status();
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    (status, String::from_utf8_lossy(&body).into_owned())
+}
+
+pub fn request(method: &str, uri: &str) -> Request<Body> {
+    Request::builder().method(method).uri(uri).body(Body::empty()).unwrap()
+}
+
+/// A request sending `body` as JSON.
+pub fn json_request(method: &str, uri: &str, body: Value) -> Request<Body> {
+    Request::builder()
+        .method(method)
+        .uri(uri)
+        .header("content-type", "application/json")
+        .body(Body::from(body.to_string()))
+        .unwrap()
+}
+
+/// The ID tagrs gives the movie in folder `name`, looked up through the JSON API.
+pub async fn movie_id(app: &Router, name: &str) -> String {
+    let (status, body) = send(app, request("GET", "/api/v1/movies")).await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    let movies: Value = serde_json::from_str(&body).unwrap();
+    let movies = movies.as_array().expect("movie list");
+    let movie = movies.iter().find(|m| m["name"] == name).unwrap_or_else(|| panic!("no movie {}", name));
+    movie["id"].as_str().unwrap().to_string()
+}